@@ -0,0 +1,70 @@
+/*!
+*   `didwebvh-driver`: Universal Resolver driver for did:webvh, behind the
+*   `driver-bin` feature.
+*
+*   ```text
+*   cargo install didwebvh-rs --features driver-bin
+*   didwebvh-driver
+*   curl http://localhost:8080/1.0/identifiers/did:webvh:...
+*   ```
+*
+*   Configured from the environment:
+*
+*   | Variable | Default | Meaning |
+*   |----------|---------|---------|
+*   | `DIDWEBVH_LISTEN` | `0.0.0.0:8080` | Address to listen on |
+*   | `DIDWEBVH_TIMEOUT` | `10` | Network timeout in seconds |
+*   | `DIDWEBVH_MAX_RESPONSE_BYTES` | `204800` | Size limit for each downloaded file |
+*   | `DIDWEBVH_FOLLOW_CANONICAL_ID` | `false` | Resolve a migrated DID at its new location |
+*/
+
+use didwebvh_rs::{driver::ResolverDriver, resolve::ResolveOptions};
+use std::{process::ExitCode, str::FromStr, time::Duration};
+
+/// Read `name` from the environment, `default` if unset.
+fn env_or<T: FromStr>(name: &str, default: T) -> Result<T, String> {
+    match std::env::var(name) {
+        Ok(value) => value
+            .parse()
+            .map_err(|_| format!("{name} has an invalid value: {value}")),
+        Err(_) => Ok(default),
+    }
+}
+
+fn options() -> Result<(String, ResolveOptions), String> {
+    let defaults = ResolveOptions::default();
+    let listen = env_or("DIDWEBVH_LISTEN", "0.0.0.0:8080".to_string())?;
+    let options = ResolveOptions {
+        timeout: Some(Duration::from_secs(env_or("DIDWEBVH_TIMEOUT", 10)?)),
+        max_response_bytes: env_or("DIDWEBVH_MAX_RESPONSE_BYTES", defaults.max_response_bytes)?,
+        follow_canonical_id: env_or("DIDWEBVH_FOLLOW_CANONICAL_ID", false)?,
+        ..defaults
+    };
+    Ok((listen, options))
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let (listen, options) = match options() {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("error: {e}");
+            return ExitCode::from(2);
+        }
+    };
+    let listener = match tokio::net::TcpListener::bind(&listen).await {
+        Ok(listener) => listener,
+        Err(e) => {
+            eprintln!("error: couldn't listen on {listen}: {e}");
+            return ExitCode::FAILURE;
+        }
+    };
+    eprintln!("didwebvh-driver listening on {listen}");
+
+    let router = ResolverDriver::new().options(options).router();
+    if let Err(e) = axum::serve(listener, router).await {
+        eprintln!("error: {e}");
+        return ExitCode::FAILURE;
+    }
+    ExitCode::SUCCESS
+}
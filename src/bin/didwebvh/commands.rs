@@ -0,0 +1,544 @@
+//! Subcommand implementations.
+
+use crate::{
+    CreateArgs, DeactivateArgs, ExportWebArgs, Failure, InspectArgs, KeyTypeArg, LintArgs,
+    LogFiles, MigrateArgs, Report, ResolveArgs, RotateArgs, UpdateArgs, WitnessArgs,
+};
+use ahash::HashMap;
+use chrono::Utc;
+use didwebvh_rs::{
+    lint::{LintCode, LintLevel, LintOptions, lint_with},
+    log_entry_state::LogEntryValidationStatus,
+    prelude::*,
+    update::UpdateDIDResult,
+    witness::{Witness, WitnessVerifyOptions},
+};
+use serde_json::{Value, json};
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+
+type Result<T> = std::result::Result<T, Failure>;
+
+pub(crate) async fn create(args: CreateArgs) -> Result<Report> {
+    let log = path_str(&args.log)?;
+    if args.log.exists() && !args.force {
+        return Err(Failure::Error(format!(
+            "{log} already exists (use --force to overwrite)"
+        )));
+    }
+
+    let key = match (&args.key, &args.key_out) {
+        (Some(path), _) => read_key(path)?,
+        (None, Some(out)) => generate_key(args.key_type, out)?,
+        (None, None) => return Err(Failure::Error("--key or --key-out is required".into())),
+    };
+    let public_key = public_key(&key)?;
+
+    let document = match &args.document {
+        Some(path) => read_json(path)?,
+        None => json!({
+            "id": "{DID}",
+            "@context": ["https://www.w3.org/ns/did/v1"],
+            "verificationMethod": [{
+                "id": "{DID}#key-0",
+                "type": "Multikey",
+                "publicKeyMultibase": public_key,
+                "controller": "{DID}"
+            }],
+            "authentication": ["{DID}#key-0"],
+            "assertionMethod": ["{DID}#key-0"],
+        }),
+    };
+
+    let (witnesses, witness_secrets) = witness_config(&args.witnesses, Some(1))?;
+    let parameters = Parameters {
+        update_keys: Some(Arc::new(vec![Multibase::new(public_key)])),
+        portable: args.portable.then_some(true),
+        ttl: args.ttl,
+        watchers: (!args.watchers.is_empty()).then(|| Arc::new(args.watchers.clone())),
+        next_key_hashes: multibases(&args.next_key_hashes).map(Arc::new),
+        witness: witnesses.map(Arc::new),
+        ..Default::default()
+    };
+
+    let config = CreateDIDConfig::builder()
+        .address(&args.address)
+        .authorization_key(key)
+        .did_document(document)
+        .parameters(parameters)
+        .witness_secrets(witness_secrets)
+        .also_known_as_web(args.also_known_as_web)
+        .also_known_as_scid(args.also_known_as_scid)
+        .build()?;
+    let result = create_did(config).await?;
+
+    let witness_path = witness_path(&args.log, args.witness_proofs.as_deref());
+    if result.witness_proofs().get_total_count() > 0 {
+        result
+            .witness_proofs()
+            .save_to_file(&path_str(&witness_path)?)?;
+    }
+    if args.force && args.log.exists() {
+        // save_to_file never starts a log over another one
+        std::fs::remove_file(&args.log)?;
+    }
+    result.log_entry().save_to_file(&log)?;
+
+    let version_id = result.log_entry().get_version_id().to_string();
+    Ok(Report {
+        text: format!(
+            "Created {}\n  versionId: {version_id}\n  log: {log}",
+            result.did()
+        ),
+        json: json!({
+            "did": result.did(),
+            "versionId": version_id,
+            "log": log,
+            "keyFile": args.key_out,
+        }),
+    })
+}
+
+pub(crate) async fn update(args: UpdateArgs) -> Result<Report> {
+    let state = load_for_update(&args.files.log, args.files.witness_proofs.as_deref()).await?;
+    let (witnesses, witness_secrets) = witness_config(&args.witnesses, None)?;
+
+    let mut builder = UpdateDIDConfig::builder()
+        .state(state)
+        .signing_key(read_key(&args.key)?)
+        .witness_secrets(witness_secrets);
+    if let Some(path) = &args.document {
+        builder = builder.document(read_json(path)?);
+    }
+    if let Some(ttl) = args.ttl {
+        builder = builder.ttl(ttl);
+    }
+    if !args.watchers.is_empty() {
+        builder = builder.watchers(args.watchers.clone());
+    }
+    if let Some(hashes) = multibases(&args.next_key_hashes) {
+        builder = builder.next_key_hashes(hashes);
+    }
+    if let Some(witnesses) = witnesses {
+        builder = builder.witness(witnesses);
+    }
+    if args.disable_portability {
+        builder = builder.disable_portability();
+    }
+
+    let result = update_did(builder.build()?).await?;
+    save_update(&result, &args.files, "Updated")
+}
+
+pub(crate) async fn rotate(args: RotateArgs) -> Result<Report> {
+    let state = load_for_update(&args.files.log, args.files.witness_proofs.as_deref()).await?;
+    let (witnesses, witness_secrets) = witness_config(&args.witnesses, None)?;
+    let new_key = match (&args.new_key, &args.new_key_out) {
+        (Some(path), _) => read_key(path)?,
+        (None, Some(out)) => generate_key(args.key_type, out)?,
+        (None, None) => {
+            return Err(Failure::Error(
+                "--new-key or --new-key-out is required".into(),
+            ));
+        }
+    };
+
+    let mut builder = UpdateDIDConfig::builder()
+        .state(state)
+        .signing_key(read_key(&args.key)?)
+        .update_keys(vec![Multibase::new(public_key(&new_key)?)])
+        .witness_secrets(witness_secrets);
+    if let Some(hashes) = multibases(&args.next_key_hashes) {
+        builder = builder.next_key_hashes(hashes);
+    }
+    if let Some(witnesses) = witnesses {
+        builder = builder.witness(witnesses);
+    }
+
+    let result = update_did(builder.build()?).await?;
+    let mut report = save_update(&result, &args.files, "Rotated keys of")?;
+    report.json["keyFile"] = json!(args.new_key_out.or(args.new_key));
+    Ok(report)
+}
+
+pub(crate) async fn migrate(args: MigrateArgs) -> Result<Report> {
+    let state = load_for_update(&args.files.log, args.files.witness_proofs.as_deref()).await?;
+    let (witnesses, witness_secrets) = witness_config(&args.witnesses, None)?;
+    let mut builder = UpdateDIDConfig::builder()
+        .state(state)
+        .signing_key(read_key(&args.key)?)
+        .migrate_to(&args.to)
+        .witness_secrets(witness_secrets);
+    if let Some(witnesses) = witnesses {
+        builder = builder.witness(witnesses);
+    }
+
+    let result = update_did(builder.build()?).await?;
+    let mut report = save_update(&result, &args.files, "Migrated to")?;
+    if let Some(migration) = result.migration_report() {
+        report.json["rewritten"] = json!(migration.rewritten.len());
+        report.json["untouched"] = json!(migration.untouched.len());
+    }
+    Ok(report)
+}
+
+pub(crate) async fn deactivate(args: DeactivateArgs) -> Result<Report> {
+    let state = load_for_update(&args.files.log, args.files.witness_proofs.as_deref()).await?;
+    let (_, witness_secrets) = witness_config(&args.witnesses, None)?;
+    let config = UpdateDIDConfig::builder()
+        .state(state)
+        .signing_key(read_key(&args.key)?)
+        .deactivate(true)
+        .witness_secrets(witness_secrets)
+        .build()?;
+
+    let result = update_did(config).await?;
+    save_update(&result, &args.files, "Deactivated")
+}
+
+pub(crate) async fn resolve(args: ResolveArgs) -> Result<Report> {
+    let options = ResolveOptions {
+        timeout: Some(Duration::from_secs(args.timeout)),
+        ..Default::default()
+    };
+    let mut state = DIDWebVHState::default();
+    let (entry, metadata) = state.resolve_owned(&args.did, options).await?;
+    let document = entry.get_did_document()?;
+    Ok(Report {
+        text: serde_json::to_string_pretty(&document)?,
+        json: json!({ "didDocument": document, "didDocumentMetadata": metadata }),
+    })
+}
+
+pub(crate) fn verify(args: InspectArgs) -> Result<Report> {
+    let mut state = load(&args.log, args.witness_proofs.as_deref())?;
+    let entries = state.log_entries().len();
+    let outcome = state.validate().and_then(|report| {
+        let ok_until = report.ok_until.clone();
+        report.assert_complete().map(|()| ok_until)
+    });
+    let did = current_did(&state).unwrap_or_default();
+
+    match outcome {
+        Ok(version_id) => Ok(Report {
+            text: format!(
+                "OK: {did}\n  {entries} entries, latest {}{}",
+                version_id,
+                if state.deactivated() {
+                    " (deactivated)"
+                } else {
+                    ""
+                }
+            ),
+            json: json!({
+                "valid": true,
+                "did": did,
+                "entries": entries,
+                "versionId": version_id,
+                "deactivated": state.deactivated(),
+            }),
+        }),
+        Err(e) => Err(Failure::Invalid(Report {
+            text: format!("INVALID: {e}"),
+            json: json!({ "valid": false, "did": did, "entries": entries, "error": e.to_string() }),
+        })),
+    }
+}
+
+pub(crate) fn inspect(args: InspectArgs) -> Result<Report> {
+    let mut state = load(&args.log, args.witness_proofs.as_deref())?;
+    // Keep going on failure: the statuses show where the log breaks
+    let error = state
+        .validate()
+        .and_then(|report| report.assert_complete())
+        .err();
+    let diagnostics = state.witness_diagnostics(&WitnessVerifyOptions::new());
+
+    let mut text = String::new();
+    let mut entries = Vec::new();
+    for entry in state.log_entries() {
+        let witnesses = diagnostics
+            .entries
+            .iter()
+            .find(|w| w.version_id == entry.get_version_id());
+        let status = match entry.validation_status {
+            LogEntryValidationStatus::Ok => "ok",
+            LogEntryValidationStatus::NotValidated => "not validated",
+            _ => "invalid",
+        };
+        text.push_str(&format!(
+            "{}  {}  {status}\n  id: {}\n  parameters: {}\n",
+            entry.get_version_id(),
+            entry.log_entry.get_version_time_string(),
+            entry
+                .get_state()
+                .get("id")
+                .and_then(Value::as_str)
+                .unwrap_or("?"),
+            serde_json::to_string(&entry.log_entry.get_parameters())?,
+        ));
+        if let Some(w) = witnesses
+            && w.threshold.is_some()
+        {
+            text.push_str(&format!(
+                "  witnesses: {}/{} valid\n",
+                w.valid_count(),
+                w.threshold.unwrap_or_default()
+            ));
+        }
+        entries.push(json!({
+            "versionId": entry.get_version_id(),
+            "versionTime": entry.log_entry.get_version_time_string(),
+            "id": entry.get_state().get("id"),
+            "parameters": entry.log_entry.get_parameters(),
+            "status": status,
+            "witnesses": witnesses.map(|w| json!({
+                "threshold": w.threshold,
+                "valid": w.valid_count(),
+                "missing": w.missing().collect::<Vec<_>>(),
+            })),
+        }));
+    }
+    let next_signatures: Vec<Value> = diagnostics
+        .next_signatures
+        .iter()
+        .map(|s| json!({ "witness": s.witness, "versionId": s.version_id }))
+        .collect();
+    for s in &diagnostics.next_signatures {
+        text.push_str(&format!(
+            "needs witness proof: {} for {}\n",
+            s.witness, s.version_id
+        ));
+    }
+    if let Some(e) = &error {
+        text.push_str(&format!("INVALID: {e}\n"));
+    }
+
+    Ok(Report {
+        text: text.trim_end().to_string(),
+        json: json!({
+            "did": current_did(&state),
+            "entries": entries,
+            "nextSignatures": next_signatures,
+            "error": error.map(|e| e.to_string()),
+        }),
+    })
+}
+
+pub(crate) fn export_web(args: ExportWebArgs) -> Result<Report> {
+    let state = load_valid(&args.log, args.witness_proofs.as_deref())?;
+    let web_did = state.to_web_did()?;
+    let text = serde_json::to_string_pretty(&web_did)?;
+    match &args.out {
+        Some(out) => {
+            std::fs::write(out, &text)
+                .map_err(|e| format!("Couldn't write {}: {e}", out.display()))?;
+            Ok(Report {
+                text: format!("Wrote {}", out.display()),
+                json: json!({ "id": web_did.get("id"), "out": out }),
+            })
+        }
+        None => Ok(Report {
+            text,
+            json: web_did,
+        }),
+    }
+}
+
+pub(crate) fn lint(args: LintArgs) -> Result<Report> {
+    let state = load_valid(&args.log, args.witness_proofs.as_deref())?;
+    let mut options = LintOptions::new();
+    for code in &args.deny {
+        options = if code == "all" {
+            options.deny_all()
+        } else {
+            options.deny(code.parse::<LintCode>()?)
+        };
+    }
+    for code in &args.allow {
+        options = options.allow(code.parse::<LintCode>()?);
+    }
+    let lints = lint_with(&state, &options)?;
+
+    let findings: Vec<Value> = lints
+        .findings
+        .iter()
+        .map(|f| {
+            json!({
+                "code": f.code.as_str(),
+                "level": if f.level == LintLevel::Deny { "error" } else { "warning" },
+                "versionId": f.version_id,
+                "message": f.message,
+            })
+        })
+        .collect();
+    let mut text: Vec<String> = lints.findings.iter().map(ToString::to_string).collect();
+    if text.is_empty() {
+        text.push("No lint findings".to_string());
+    }
+    let report = Report {
+        text: text.join("\n"),
+        json: json!({ "errors": lints.has_errors(), "findings": findings }),
+    };
+    if lints.has_errors() {
+        Err(Failure::Invalid(report))
+    } else {
+        Ok(report)
+    }
+}
+
+fn save_update(result: &UpdateDIDResult, files: &LogFiles, action: &str) -> Result<Report> {
+    let log = path_str(&files.log)?;
+    let state = result.state();
+    if state.witness_proofs().get_total_count() > 0 {
+        let path = witness_path(&files.log, files.witness_proofs.as_deref());
+        state.witness_proofs().save_to_file(&path_str(&path)?)?;
+    }
+    state.append_log_file(&log)?;
+
+    let version_id = result.log_entry().get_version_id().to_string();
+    Ok(Report {
+        text: format!("{action} {}\n  versionId: {version_id}", result.did()),
+        json: json!({ "did": result.did(), "versionId": version_id, "log": log }),
+    })
+}
+
+/// Load a DID's files without validating them.
+fn load(log: &Path, witness_proofs: Option<&Path>) -> Result<DIDWebVHState> {
+    let mut state = DIDWebVHState::default();
+    state.load_log_entries_from_file(&path_str(log)?)?;
+    let witness_path = witness_path(log, witness_proofs);
+    if witness_proofs.is_some() || witness_path.exists() {
+        let raw = std::fs::read_to_string(&witness_path)
+            .map_err(|e| format!("Couldn't read {}: {e}", witness_path.display()))?;
+        state.set_witness_proofs(DIDWebVHState::parse_witness_proofs(&raw)?);
+    }
+    Ok(state)
+}
+
+/// Load a DID's files; every entry must validate.
+fn load_valid(log: &Path, witness_proofs: Option<&Path>) -> Result<DIDWebVHState> {
+    let mut state = load(log, witness_proofs)?;
+    state.validate()?.assert_complete()?;
+    Ok(state)
+}
+
+/// Load a DID to append to it. versionTime has one-second resolution and
+/// must increase, so wait out the second of the last entry: scripts run
+/// commands back to back.
+async fn load_for_update(log: &Path, witness_proofs: Option<&Path>) -> Result<DIDWebVHState> {
+    let state = load_valid(log, witness_proofs)?;
+    if let Some(last) = state.log_entries().last() {
+        let next = last.log_entry.get_version_time() + chrono::Duration::seconds(1);
+        if let Ok(wait) = (next - Utc::now().fixed_offset()).to_std() {
+            tokio::time::sleep(wait).await;
+        }
+    }
+    Ok(state)
+}
+
+fn witness_path(log: &Path, given: Option<&Path>) -> PathBuf {
+    given.map_or_else(|| log.with_file_name("did-witness.json"), Path::to_path_buf)
+}
+
+fn current_did(state: &DIDWebVHState) -> Option<String> {
+    state
+        .log_entries()
+        .last()
+        .and_then(|e| e.get_state().get("id"))
+        .and_then(Value::as_str)
+        .map(ToString::to_string)
+}
+
+/// Witness configuration from `--witness-threshold`, and the witness secrets
+/// from `--witness-key`.
+fn witness_config(
+    args: &WitnessArgs,
+    default_threshold: Option<u32>,
+) -> Result<(Option<Witnesses>, HashMap<String, Secret>)> {
+    let mut secrets = HashMap::default();
+    for path in &args.witness_keys {
+        let secret = read_key(path)?;
+        let did = secret
+            .id
+            .split_once('#')
+            .map_or(secret.id.as_str(), |(did, _)| did)
+            .to_string();
+        secrets.insert(did, secret);
+    }
+
+    let threshold = args
+        .witness_threshold
+        .or(default_threshold.filter(|_| !secrets.is_empty()));
+    let witnesses = threshold.map(|threshold| {
+        let mut ids: Vec<&String> = secrets.keys().collect();
+        ids.sort();
+        Witnesses::Value {
+            threshold,
+            witnesses: ids
+                .into_iter()
+                .map(|id| Witness {
+                    id: Multibase::new(id.clone()),
+                })
+                .collect(),
+        }
+    });
+    Ok((witnesses, secrets))
+}
+
+fn multibases(values: &[String]) -> Option<Vec<Multibase>> {
+    (!values.is_empty()).then(|| values.iter().cloned().map(Multibase::new).collect())
+}
+
+fn read_key(path: &Path) -> Result<Secret> {
+    let text = std::fs::read_to_string(path)
+        .map_err(|e| format!("Couldn't read key file {}: {e}", path.display()))?;
+    Ok(serde_json::from_str(&text)
+        .map_err(|e| format!("Couldn't parse key file {}: {e}", path.display()))?)
+}
+
+/// Generate a `did:key` secret and write it to `out`, readable only by the
+/// owner on Unix.
+fn generate_key(key_type: KeyTypeArg, out: &Path) -> Result<Secret> {
+    if out.exists() {
+        return Err(Failure::Error(format!(
+            "{} already exists; refusing to overwrite a key",
+            out.display()
+        )));
+    }
+    let (_, secret) = generate_did_key(key_type.into())?;
+    let json = serde_json::to_string_pretty(&secret)?;
+
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    let mut file = options
+        .open(out)
+        .map_err(|e| format!("Couldn't create {}: {e}", out.display()))?;
+    std::io::Write::write_all(&mut file, json.as_bytes())
+        .map_err(|e| format!("Couldn't write {}: {e}", out.display()))?;
+    Ok(secret)
+}
+
+fn public_key(secret: &Secret) -> Result<String> {
+    Ok(secret
+        .get_public_keymultibase()
+        .map_err(|e| format!("Couldn't get public key of {}: {e}", secret.id))?)
+}
+
+fn read_json(path: &Path) -> Result<Value> {
+    let text = std::fs::read_to_string(path)
+        .map_err(|e| format!("Couldn't read {}: {e}", path.display()))?;
+    Ok(serde_json::from_str(&text)
+        .map_err(|e| format!("Couldn't parse {}: {e}", path.display()))?)
+}
+
+fn path_str(path: &Path) -> Result<String> {
+    path.to_str()
+        .map(ToString::to_string)
+        .ok_or_else(|| Failure::Error(format!("Path isn't valid UTF-8: {}", path.display())))
+}
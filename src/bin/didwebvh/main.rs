@@ -0,0 +1,484 @@
+/*!
+*   `didwebvh`: non-interactive command line for did:webvh, behind the `bin`
+*   feature.
+*
+*   ```text
+*   cargo install didwebvh-rs --features bin
+*   didwebvh create --address https://example.com/dids/alice --key-out alice.key.json
+*   didwebvh update --log did.jsonl --key alice.key.json --ttl 600
+*   didwebvh verify did.jsonl --json
+*   ```
+*
+*   Every subcommand reads its inputs from flags, or from a JSON config file
+*   given with `--config <file>` whose keys are the subcommand's long flag
+*   names (`{"address": "...", "witness-key": ["w1.json", "w2.json"]}`).
+*   A flag given on the command line replaces the file's value for it,
+*   repeatable flags included.
+*
+*   With `--json` the result is printed to stdout as a single JSON document
+*   (`{"error": ...}` on failure), otherwise as text.
+*
+*   Exit codes:
+*
+*   | Code | Meaning |
+*   |------|---------|
+*   | 0 | Success |
+*   | 1 | The operation failed (bad input, I/O, signing, network) |
+*   | 2 | Invalid command line |
+*   | 3 | The DID log didn't verify |
+*/
+
+mod commands;
+
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use didwebvh_rs::prelude::KeyType;
+use serde_json::Value;
+use std::{collections::HashSet, io::Write, path::PathBuf, process::ExitCode};
+
+#[derive(Parser, Debug)]
+#[command(
+    name = "didwebvh",
+    version,
+    about = "Create, update and verify did:webvh DIDs"
+)]
+struct Cli {
+    /// Print the result as JSON.
+    #[arg(long, global = true)]
+    json: bool,
+
+    /// Read flags for the subcommand from a JSON file.
+    #[arg(long, global = true, value_name = "FILE")]
+    config: Option<PathBuf>,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Create a new DID and write its first log entry.
+    Create(CreateArgs),
+    /// Append a log entry changing the document or parameters.
+    Update(UpdateArgs),
+    /// Replace the authorization keys (`updateKeys`).
+    Rotate(RotateArgs),
+    /// Move the DID to a new domain or path.
+    Migrate(MigrateArgs),
+    /// Permanently deactivate the DID.
+    Deactivate(DeactivateArgs),
+    /// Resolve a DID over the network.
+    Resolve(ResolveArgs),
+    /// Verify a local did.jsonl (and did-witness.json).
+    Verify(InspectArgs),
+    /// Show every entry of a local did.jsonl and its witness status.
+    Inspect(InspectArgs),
+    /// Write the did:web equivalent (did.json) of a local did.jsonl.
+    ExportWeb(ExportWebArgs),
+    /// Report SHOULD-level recommendations a local did.jsonl doesn't follow.
+    Lint(LintArgs),
+}
+
+/// Key types `--key-type` accepts.
+#[derive(Clone, Copy, Debug, Default, ValueEnum)]
+enum KeyTypeArg {
+    #[default]
+    #[value(name = "ed25519")]
+    Ed25519,
+    #[value(name = "p-256")]
+    P256,
+    #[value(name = "p-384")]
+    P384,
+    #[value(name = "secp256k1")]
+    Secp256k1,
+}
+
+impl From<KeyTypeArg> for KeyType {
+    fn from(arg: KeyTypeArg) -> Self {
+        match arg {
+            KeyTypeArg::Ed25519 => KeyType::Ed25519,
+            KeyTypeArg::P256 => KeyType::P256,
+            KeyTypeArg::P384 => KeyType::P384,
+            KeyTypeArg::Secp256k1 => KeyType::Secp256k1,
+        }
+    }
+}
+
+/// Location of an existing DID's files.
+#[derive(Args, Debug)]
+struct LogFiles {
+    /// The DID's log.
+    #[arg(long, default_value = "did.jsonl")]
+    log: PathBuf,
+
+    /// The DID's witness proofs. Default: `did-witness.json` next to the log.
+    #[arg(long, value_name = "FILE")]
+    witness_proofs: Option<PathBuf>,
+}
+
+/// Witness keys and configuration.
+#[derive(Args, Debug)]
+struct WitnessArgs {
+    /// Key file of a witness, to sign witness proofs. Repeat for each witness.
+    #[arg(long = "witness-key", value_name = "FILE")]
+    witness_keys: Vec<PathBuf>,
+
+    /// Set the witnesses to those given by `--witness-key`, with this threshold.
+    #[arg(long)]
+    witness_threshold: Option<u32>,
+}
+
+#[derive(Args, Debug)]
+#[allow(clippy::struct_excessive_bools)] // independent command line switches
+struct CreateArgs {
+    /// URL the DID is hosted at, e.g. `https://example.com/dids/alice`.
+    #[arg(long)]
+    address: String,
+
+    /// Authorization key file. Without it a key is generated (see `--key-out`).
+    #[arg(long, value_name = "FILE", conflicts_with = "key_out")]
+    key: Option<PathBuf>,
+
+    /// Where to write the generated authorization key.
+    #[arg(long, value_name = "FILE", required_unless_present = "key")]
+    key_out: Option<PathBuf>,
+
+    /// Type of generated keys.
+    #[arg(long, value_enum, default_value_t)]
+    key_type: KeyTypeArg,
+
+    /// DID Document template (JSON). `{DID}` is replaced with the new DID.
+    /// Default: a document with the authorization key as `{DID}#key-0`.
+    #[arg(long, value_name = "FILE")]
+    document: Option<PathBuf>,
+
+    /// Allow the DID to move to another address later.
+    #[arg(long)]
+    portable: bool,
+
+    /// Cache TTL in seconds.
+    #[arg(long)]
+    ttl: Option<u32>,
+
+    /// Watcher URL. Repeat for each watcher.
+    #[arg(long = "watcher", value_name = "URL")]
+    watchers: Vec<String>,
+
+    /// Pre-rotation: hash of a future authorization key. Repeatable.
+    #[arg(long = "next-key-hash", value_name = "HASH")]
+    next_key_hashes: Vec<String>,
+
+    #[command(flatten)]
+    witnesses: WitnessArgs,
+
+    /// Add the did:web equivalent to `alsoKnownAs`.
+    #[arg(long)]
+    also_known_as_web: bool,
+
+    /// Add the did:scid:vh equivalent to `alsoKnownAs`.
+    #[arg(long)]
+    also_known_as_scid: bool,
+
+    /// Where to write the log.
+    #[arg(long, default_value = "did.jsonl")]
+    log: PathBuf,
+
+    /// Where to write witness proofs. Default: `did-witness.json` next to the log.
+    #[arg(long, value_name = "FILE")]
+    witness_proofs: Option<PathBuf>,
+
+    /// Overwrite an existing log.
+    #[arg(long)]
+    force: bool,
+}
+
+#[derive(Args, Debug)]
+struct UpdateArgs {
+    #[command(flatten)]
+    files: LogFiles,
+
+    /// Authorization key file.
+    #[arg(long, value_name = "FILE")]
+    key: PathBuf,
+
+    /// New DID Document (JSON).
+    #[arg(long, value_name = "FILE")]
+    document: Option<PathBuf>,
+
+    /// New cache TTL in seconds.
+    #[arg(long)]
+    ttl: Option<u32>,
+
+    /// Replace the watchers. Repeat for each watcher.
+    #[arg(long = "watcher", value_name = "URL")]
+    watchers: Vec<String>,
+
+    /// Pre-rotation: replace `nextKeyHashes`. Repeatable.
+    #[arg(long = "next-key-hash", value_name = "HASH")]
+    next_key_hashes: Vec<String>,
+
+    /// Turn portability off (it can't be turned back on).
+    #[arg(long)]
+    disable_portability: bool,
+
+    #[command(flatten)]
+    witnesses: WitnessArgs,
+}
+
+#[derive(Args, Debug)]
+struct RotateArgs {
+    #[command(flatten)]
+    files: LogFiles,
+
+    /// Current authorization key file (or the pre-rotated key).
+    #[arg(long, value_name = "FILE")]
+    key: PathBuf,
+
+    /// New authorization key file. Without it a key is generated (see `--new-key-out`).
+    #[arg(long, value_name = "FILE", conflicts_with = "new_key_out")]
+    new_key: Option<PathBuf>,
+
+    /// Where to write the generated authorization key.
+    #[arg(long, value_name = "FILE", required_unless_present = "new_key")]
+    new_key_out: Option<PathBuf>,
+
+    /// Type of generated keys.
+    #[arg(long, value_enum, default_value_t)]
+    key_type: KeyTypeArg,
+
+    /// Pre-rotation: replace `nextKeyHashes`. Repeatable.
+    #[arg(long = "next-key-hash", value_name = "HASH")]
+    next_key_hashes: Vec<String>,
+
+    #[command(flatten)]
+    witnesses: WitnessArgs,
+}
+
+#[derive(Args, Debug)]
+struct MigrateArgs {
+    #[command(flatten)]
+    files: LogFiles,
+
+    /// Authorization key file.
+    #[arg(long, value_name = "FILE")]
+    key: PathBuf,
+
+    /// New URL (`https://new.example.com/alice`) or path (`/dids/alice`).
+    #[arg(long, value_name = "ADDRESS")]
+    to: String,
+
+    #[command(flatten)]
+    witnesses: WitnessArgs,
+}
+
+#[derive(Args, Debug)]
+struct DeactivateArgs {
+    #[command(flatten)]
+    files: LogFiles,
+
+    /// Authorization key file.
+    #[arg(long, value_name = "FILE")]
+    key: PathBuf,
+
+    #[command(flatten)]
+    witnesses: WitnessArgs,
+}
+
+#[derive(Args, Debug)]
+struct ResolveArgs {
+    /// DID (or DID URL with `versionId` / `versionTime` / `versionNumber`).
+    did: String,
+
+    /// Network timeout in seconds.
+    #[arg(long, default_value_t = 10)]
+    timeout: u64,
+}
+
+#[derive(Args, Debug)]
+struct InspectArgs {
+    /// The DID's log.
+    log: PathBuf,
+
+    /// The DID's witness proofs. Default: `did-witness.json` next to the log.
+    #[arg(long, value_name = "FILE")]
+    witness_proofs: Option<PathBuf>,
+}
+
+#[derive(Args, Debug)]
+struct ExportWebArgs {
+    /// The DID's log.
+    log: PathBuf,
+
+    /// The DID's witness proofs. Default: `did-witness.json` next to the log.
+    #[arg(long, value_name = "FILE")]
+    witness_proofs: Option<PathBuf>,
+
+    /// Where to write did.json. Default: stdout.
+    #[arg(long, value_name = "FILE")]
+    out: Option<PathBuf>,
+}
+
+#[derive(Args, Debug)]
+struct LintArgs {
+    /// The DID's log.
+    log: PathBuf,
+
+    /// The DID's witness proofs. Default: `did-witness.json` next to the log.
+    #[arg(long, value_name = "FILE")]
+    witness_proofs: Option<PathBuf>,
+
+    /// Fail (exit code 3) on this lint code, or `all`. Repeatable.
+    #[arg(long, value_name = "CODE")]
+    deny: Vec<String>,
+
+    /// Don't report this lint code. Repeatable; applied after `--deny`.
+    #[arg(long, value_name = "CODE")]
+    allow: Vec<String>,
+}
+
+/// Result of a subcommand: JSON for `--json`, text otherwise.
+struct Report {
+    json: Value,
+    text: String,
+}
+
+/// Why a subcommand failed, and the exit code it maps to.
+enum Failure {
+    Error(String),
+    Invalid(Report),
+}
+
+impl<E: std::fmt::Display> From<E> for Failure {
+    fn from(e: E) -> Self {
+        Failure::Error(e.to_string())
+    }
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let cli = match expand_config(std::env::args().collect()).and_then(|args| {
+        Cli::try_parse_from(args).map_err(|e| {
+            let _ = e.print();
+            e.exit_code()
+        })
+    }) {
+        Ok(cli) => cli,
+        Err(code) => return ExitCode::from(u8::try_from(code).unwrap_or(2)),
+    };
+
+    let result = match cli.command {
+        Command::Create(args) => commands::create(args).await,
+        Command::Update(args) => commands::update(args).await,
+        Command::Rotate(args) => commands::rotate(args).await,
+        Command::Migrate(args) => commands::migrate(args).await,
+        Command::Deactivate(args) => commands::deactivate(args).await,
+        Command::Resolve(args) => commands::resolve(args).await,
+        Command::Verify(args) => commands::verify(args),
+        Command::Inspect(args) => commands::inspect(args),
+        Command::ExportWeb(args) => commands::export_web(args),
+        Command::Lint(args) => commands::lint(args),
+    };
+
+    match result {
+        Ok(report) => {
+            print_report(&report, cli.json);
+            ExitCode::SUCCESS
+        }
+        Err(Failure::Invalid(report)) => {
+            print_report(&report, cli.json);
+            ExitCode::from(3)
+        }
+        Err(Failure::Error(message)) => {
+            if cli.json {
+                println!("{}", serde_json::json!({ "error": message }));
+            } else {
+                eprintln!("error: {message}");
+            }
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn print_report(report: &Report, json: bool) {
+    let out = if json {
+        serde_json::to_string_pretty(&report.json).unwrap_or_default()
+    } else {
+        report.text.clone()
+    };
+    // Ignore a closed pipe (`didwebvh inspect ... | head`)
+    let _ = writeln!(std::io::stdout(), "{out}");
+}
+
+/// Insert the flags from `--config <file>` right after the subcommand. A flag
+/// given on the command line replaces the file's value for it entirely, so
+/// single-valued flags aren't given twice and repeatable ones
+/// (`--witness-key`) aren't merged.
+fn expand_config(args: Vec<String>) -> Result<Vec<String>, i32> {
+    let mut out = Vec::with_capacity(args.len());
+    let mut config = None;
+    let mut iter = args.into_iter();
+    while let Some(arg) = iter.next() {
+        if arg == "--config" {
+            config = iter.next();
+        } else if let Some(path) = arg.strip_prefix("--config=") {
+            config = Some(path.to_string());
+        } else {
+            out.push(arg);
+        }
+    }
+    let Some(path) = config else {
+        return Ok(out);
+    };
+    let given: HashSet<&str> = out
+        .iter()
+        .filter_map(|arg| arg.strip_prefix("--"))
+        .map(|flag| flag.split_once('=').map_or(flag, |(name, _)| name))
+        .collect();
+
+    let flags = std::fs::read_to_string(&path)
+        .map_err(|e| format!("Couldn't read config file {path}: {e}"))
+        .and_then(|text| {
+            serde_json::from_str::<Value>(&text)
+                .map_err(|e| format!("Couldn't parse config file {path}: {e}"))
+        })
+        .and_then(|value| config_flags(&value, &given).map_err(|e| format!("{path}: {e}")))
+        .map_err(|e| {
+            eprintln!("error: {e}");
+            2
+        })?;
+
+    // The subcommand is the first argument that isn't a flag
+    let at = out
+        .iter()
+        .skip(1)
+        .position(|a| !a.starts_with('-'))
+        .map_or(out.len(), |i| i + 2);
+    out.splice(at..at, flags);
+    Ok(out)
+}
+
+fn config_flags(config: &Value, given: &HashSet<&str>) -> Result<Vec<String>, String> {
+    let Value::Object(map) = config else {
+        return Err("config file must be a JSON object".to_string());
+    };
+    let mut flags = Vec::new();
+    for (name, value) in map {
+        if given.contains(name.as_str()) {
+            continue;
+        }
+        let flag = format!("--{name}");
+        let values = match value {
+            Value::Array(values) => values.clone(),
+            value => vec![value.clone()],
+        };
+        for value in values {
+            match value {
+                Value::Bool(true) => flags.push(flag.clone()),
+                Value::Bool(false) | Value::Null => {}
+                Value::String(s) => flags.extend([flag.clone(), s]),
+                Value::Number(n) => flags.extend([flag.clone(), n.to_string()]),
+                _ => return Err(format!("unsupported value for \"{name}\"")),
+            }
+        }
+    }
+    Ok(flags)
+}
@@ -39,6 +39,7 @@ use crate::{
     did_key::generate_did_key,
     log_entry::LogEntry,
     log_entry_state::LogEntryState,
+    migrate::{MigrationPreview, migration_target, preview_migration},
     parameters::Parameters,
     url::WebVHURL,
     witness::{Witness, Witnesses},
@@ -48,7 +49,6 @@ use console::style;
 use dialoguer::{Confirm, Input, MultiSelect, Select, theme::ColorfulTheme};
 use serde_json::Value;
 use std::sync::Arc;
-use url::Url;
 
 // ─────────────────────── Public types ───────────────────────
 
@@ -538,8 +538,8 @@ async fn do_migrate(
         "\t{}",
         style(
             "The DID's SCID will remain the same, and the previous URL will be \
-             added to alsoKnownAs. All references in the DID document will be \
-             rewritten to the new domain."
+             added to alsoKnownAs. DID identifiers in the document will be \
+             rewritten; service endpoints are left unchanged."
         )
         .color256(69)
     );
@@ -561,38 +561,39 @@ async fn do_migrate(
         input
     };
 
-    let new_url = Url::parse(&new_url_str)
-        .map_err(|e| DIDWebVHError::DIDError(format!("Invalid URL format: {e}")))?;
-
-    let mut new_did_url = WebVHURL::parse_url(&new_url)?;
-    new_did_url.scid = did_url.scid.clone();
+    let new_did_url = migration_target(&did_url, &new_url_str)?;
 
     println!(
         "\n{} {}\n",
         style("New DID:").color256(69),
-        style(&new_did_url.to_string()).color256(141)
+        style(&new_did_url.to_did_base()).color256(141)
     );
 
     if !prompt_confirm("Migrate to this new URL?", true)? {
         return Err(DIDWebVHError::DIDError("Migration aborted".to_string()));
     }
 
-    // Modify the DID Document - replace old DID with new DID
-    let did_doc: String = serde_json::to_string(last_entry.get_state())
-        .map_err(|e| DIDWebVHError::DIDError(format!("Failed to serialize DID doc: {e}")))?;
-    let new_did_doc = did_doc.replace(&did_url.to_string(), &new_did_url.to_string());
-    let mut new_did_doc: Value = serde_json::from_str(&new_did_doc)
-        .map_err(|e| DIDWebVHError::DIDError(format!("Failed to parse new DID doc: {e}")))?;
-
-    // Add previous DID to alsoKnownAs
-    if let Some(alias) = new_did_doc.get_mut("alsoKnownAs") {
-        if let Some(arr) = alias.as_array_mut() {
-            arr.push(Value::String(did.clone()));
-        }
-    } else if let Some(obj) = new_did_doc.as_object_mut() {
-        obj.insert(
-            "alsoKnownAs".to_string(),
-            Value::Array(vec![Value::String(did)]),
+    // Rewrite identifier positions only; service endpoints are left alone
+    let MigrationPreview {
+        document: new_did_doc,
+        report,
+    } = preview_migration(webvh_state, &new_url_str)?;
+
+    for rewritten in &report.rewritten {
+        println!(
+            "{} {} {} {}",
+            style("Rewrote").color256(69),
+            style(&rewritten.path).color256(45),
+            style("→").color256(69),
+            style(&rewritten.new).color256(141)
+        );
+    }
+    for untouched in &report.untouched {
+        println!(
+            "{} {} {}",
+            style("Left unchanged").color256(214),
+            style(&untouched.path).color256(45),
+            style(&untouched.value).color256(214)
         );
     }
 
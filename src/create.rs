@@ -5,14 +5,15 @@
 */
 
 use crate::{
-    DIDWebVHError, DIDWebVHState, Signer, ensure_object_mut,
+    DIDWebVHError, DIDWebVHState, EntryOptions, Signer, ensure_object_mut,
     log_entry::{LogEntry, LogEntryMethods},
     log_entry_state::LogEntryState,
     parameters::Parameters,
+    proof_options,
     url::WebVHURL,
     witness::{Witnesses, proofs::WitnessProofCollection},
 };
-use affinidi_data_integrity::{DataIntegrityProof, SignOptions};
+use affinidi_data_integrity::DataIntegrityProof;
 use affinidi_secrets_resolver::secrets::Secret;
 use ahash::HashMap;
 use serde_json::{Value, json};
@@ -43,6 +44,10 @@ pub struct CreateDIDConfig<A: Signer = Secret, W: Signer = Secret> {
     /// rapid create-then-update sequence stays strictly increasing and not in the
     /// future (versionTime serializes at second granularity).
     pub version_time: Option<chrono::DateTime<chrono::FixedOffset>>,
+    /// Stamp the entry's proof with its versionTime instead of the signing
+    /// clock, so the same inputs always give the same `did.jsonl`. Default:
+    /// `false`.
+    pub deterministic: bool,
 }
 
 /// Builder for constructing a [`CreateDIDConfig`].
@@ -69,6 +74,7 @@ pub struct CreateDIDConfigBuilder<A: Signer = Secret, W: Signer = Secret> {
     also_known_as_web: bool,
     also_known_as_scid: bool,
     version_time: Option<chrono::DateTime<chrono::FixedOffset>>,
+    deterministic: bool,
 }
 
 impl<A: Signer, W: Signer> CreateDIDConfigBuilder<A, W> {
@@ -82,6 +88,7 @@ impl<A: Signer, W: Signer> CreateDIDConfigBuilder<A, W> {
             also_known_as_web: false,
             also_known_as_scid: false,
             version_time: None,
+            deterministic: false,
         }
     }
 
@@ -145,6 +152,13 @@ impl<A: Signer, W: Signer> CreateDIDConfigBuilder<A, W> {
         self
     }
 
+    /// Stamp proofs with the entry's versionTime instead of the signing clock,
+    /// for reproducible output. Defaults to `false`.
+    pub fn deterministic(mut self, deterministic: bool) -> Self {
+        self.deterministic = deterministic;
+        self
+    }
+
     /// Build the [`CreateDIDConfig`], returning an error if required fields are missing.
     pub fn build(self) -> Result<CreateDIDConfig<A, W>, DIDWebVHError> {
         let address = self
@@ -196,6 +210,7 @@ impl<A: Signer, W: Signer> CreateDIDConfigBuilder<A, W> {
             also_known_as_web: self.also_known_as_web,
             also_known_as_scid: self.also_known_as_scid,
             version_time: self.version_time,
+            deterministic: self.deterministic,
         })
     }
 }
@@ -303,11 +318,15 @@ pub async fn create_did<A: Signer, W: Signer>(
     })?;
 
     let log_entry_state = didwebvh
-        .create_log_entry(
+        .create_log_entry_with(
             config.version_time,
             &config.did_document,
             &config.parameters,
             signing_key,
+            EntryOptions {
+                deterministic: config.deterministic,
+                ..Default::default()
+            },
         )
         .await?;
 
@@ -328,11 +347,12 @@ pub async fn create_did<A: Signer, W: Signer>(
 
     // Sign witness proofs
     let mut witness_proofs = WitnessProofCollection::default();
-    sign_witness_proofs(
+    sign_witness_proofs_with(
         &mut witness_proofs,
         log_entry_state,
         &active_witnesses,
         &config.witness_secrets,
+        config.deterministic,
     )
     .await?;
 
@@ -470,6 +490,19 @@ pub async fn sign_witness_proofs<W: Signer>(
     log_entry: &LogEntryState,
     witnesses: &Option<Arc<Witnesses>>,
     witness_secrets: &HashMap<String, W>,
+) -> Result<bool, DIDWebVHError> {
+    sign_witness_proofs_with(witness_proofs, log_entry, witnesses, witness_secrets, false).await
+}
+
+/// [`sign_witness_proofs`], stamping each proof's `created` with the entry's
+/// versionTime instead of the signing clock when `deterministic` is set.
+#[allow(clippy::implicit_hasher, reason = "see sign_witness_proofs")]
+pub(crate) async fn sign_witness_proofs_with<W: Signer>(
+    witness_proofs: &mut WitnessProofCollection,
+    log_entry: &LogEntryState,
+    witnesses: &Option<Arc<Witnesses>>,
+    witness_secrets: &HashMap<String, W>,
+    deterministic: bool,
 ) -> Result<bool, DIDWebVHError> {
     let Some(witnesses) = witnesses else {
         return Ok(false);
@@ -503,7 +536,7 @@ pub async fn sign_witness_proofs<W: Signer>(
         let proof = DataIntegrityProof::sign(
             &json!({"versionId": log_entry.get_version_id()}),
             secret,
-            SignOptions::new(),
+            proof_options(log_entry.get_version_time(), deterministic),
         )
         .await
         .map_err(|e| {
@@ -731,6 +764,7 @@ mod tests {
             also_known_as_web: false,
             also_known_as_scid: false,
             version_time: None,
+            deterministic: false,
         };
 
         assert!(create_did(config).await.is_err());
@@ -8,6 +8,7 @@
 
 use affinidi_did_common::{DID, KeyMaterialFormat};
 use affinidi_secrets_resolver::secrets::{KeyType, Secret};
+use sha2::{Digest, Sha256};
 
 use crate::DIDWebVHError;
 
@@ -26,19 +27,60 @@ use crate::DIDWebVHError;
 pub fn generate_did_key(key_type: KeyType) -> Result<(String, Secret), DIDWebVHError> {
     match key_type {
         #[cfg(feature = "experimental-pqc")]
-        KeyType::MlDsa44 => Ok(did_key_from_secret(Secret::generate_ml_dsa_44(None, None))),
+        KeyType::MlDsa44 => did_key_from_secret(Secret::generate_ml_dsa_44(None, None)),
         #[cfg(feature = "experimental-pqc")]
-        KeyType::MlDsa65 => Ok(did_key_from_secret(Secret::generate_ml_dsa_65(None, None))),
+        KeyType::MlDsa65 => did_key_from_secret(Secret::generate_ml_dsa_65(None, None)),
         #[cfg(feature = "experimental-pqc")]
-        KeyType::MlDsa87 => Ok(did_key_from_secret(Secret::generate_ml_dsa_87(None, None))),
+        KeyType::MlDsa87 => did_key_from_secret(Secret::generate_ml_dsa_87(None, None)),
         #[cfg(feature = "experimental-pqc")]
-        KeyType::SlhDsaSha2_128s => Ok(did_key_from_secret(Secret::generate_slh_dsa_sha2_128s(
-            None,
-        ))),
+        KeyType::SlhDsaSha2_128s => did_key_from_secret(Secret::generate_slh_dsa_sha2_128s(None)),
         _ => generate_did_key_via_did_common(key_type),
     }
 }
 
+/// Derive a `did:key` from `seed` and `label` and return `(did, secret)`,
+/// like [`generate_did_key`] but reproducible: the same seed and label always
+/// give the same key, different labels give unrelated keys.
+///
+/// Meant for tests and golden files, where a fresh random key on every run
+/// changes every signature. The key's secret bytes are
+/// `SHA-256(seed || 0x00 || label)`, so anyone holding the seed holds every
+/// derived key: never use a derived key for a real DID.
+///
+/// Supports Ed25519, X25519, P-256 and secp256k1 (and ML-DSA with
+/// `experimental-pqc`).
+pub fn derive_did_key(
+    key_type: KeyType,
+    seed: &[u8],
+    label: &str,
+) -> Result<(String, Secret), DIDWebVHError> {
+    let key_seed: [u8; 32] = Sha256::new()
+        .chain_update(seed)
+        .chain_update([0])
+        .chain_update(label.as_bytes())
+        .finalize()
+        .into();
+    let error = |e| DIDWebVHError::DIDError(format!("did:key derivation failed: {e}"));
+    let secret = match key_type {
+        KeyType::Ed25519 => Secret::generate_ed25519(None, Some(&key_seed)),
+        KeyType::X25519 => Secret::generate_x25519(None, Some(&key_seed)).map_err(error)?,
+        KeyType::P256 => Secret::generate_p256(None, Some(&key_seed)).map_err(error)?,
+        KeyType::Secp256k1 => Secret::generate_secp256k1(None, Some(&key_seed)).map_err(error)?,
+        #[cfg(feature = "experimental-pqc")]
+        KeyType::MlDsa44 => Secret::generate_ml_dsa_44(None, Some(&key_seed)),
+        #[cfg(feature = "experimental-pqc")]
+        KeyType::MlDsa65 => Secret::generate_ml_dsa_65(None, Some(&key_seed)),
+        #[cfg(feature = "experimental-pqc")]
+        KeyType::MlDsa87 => Secret::generate_ml_dsa_87(None, Some(&key_seed)),
+        _ => {
+            return Err(DIDWebVHError::DIDError(format!(
+                "{key_type:?} keys can't be derived from a seed"
+            )));
+        }
+    };
+    did_key_from_secret(secret)
+}
+
 /// Classical suites (Ed25519, X25519, P-256/384, secp256k1): delegate to
 /// `did-common`'s built-in generator, which handles multicodec + JWK
 /// construction for us.
@@ -61,16 +103,34 @@ fn generate_did_key_via_did_common(key_type: KeyType) -> Result<(String, Secret)
     Ok((did.to_string(), secret))
 }
 
-/// PQC suites: `Secret::generate_*` already has the public bytes; we just
-/// need to build the `did:key:{mb}` URI and set `secret.id` to the full
-/// `did:key:{mb}#{mb}` verification-method URL so the `Signer` impl works
-/// straight away.
-#[cfg(feature = "experimental-pqc")]
-fn did_key_from_secret(mut secret: Secret) -> (String, Secret) {
+/// PQC suites and derived keys: `Secret::generate_*` already has the public
+/// bytes; we just need to build the `did:key:{mb}` URI and set `secret.id` to
+/// the full `did:key:{mb}#{mb}` verification-method URL so the `Signer` impl
+/// works straight away.
+fn did_key_from_secret(mut secret: Secret) -> Result<(String, Secret), DIDWebVHError> {
     let mb = secret
         .get_public_keymultibase()
-        .expect("generate_* produced a Secret with decodable public bytes");
+        .map_err(|e| DIDWebVHError::DIDError(format!("did:key public key failed: {e}")))?;
     let did = format!("did:key:{mb}");
     secret.id = format!("{did}#{mb}");
-    (did, secret)
+    Ok((did, secret))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn derived_keys_are_reproducible() {
+        for key_type in [KeyType::Ed25519, KeyType::P256, KeyType::Secp256k1] {
+            let (did, secret) = derive_did_key(key_type, b"golden", "auth-1").unwrap();
+            let (again, _) = derive_did_key(key_type, b"golden", "auth-1").unwrap();
+            let (other, _) = derive_did_key(key_type, b"golden", "auth-2").unwrap();
+            assert_eq!(did, again);
+            assert_ne!(did, other);
+            let mb = secret.get_public_keymultibase().unwrap();
+            assert_eq!(secret.id, format!("did:key:{mb}#{mb}"));
+        }
+        assert!(derive_did_key(KeyType::P384, b"golden", "auth-1").is_err());
+    }
 }
@@ -0,0 +1,1156 @@
+/*!
+*   Declarative DID specs: describe the DID you want, let the library work out
+*   the log entries that get you there.
+*
+*   A [`DidSpec`] lists the desired address, authorization and pre-rotation
+*   keys, verification methods, services, `alsoKnownAs`, witnesses, watchers,
+*   `ttl` and portability. Keys are named: the spec refers to them by a
+*   reference that is looked up in a map of [`Secret`]s passed to
+*   [`reconcile`], so the spec file itself holds no key material and can be
+*   kept in version control.
+*
+*   [`reconcile`] compares a spec with the current [`DIDWebVHState`] and
+*   returns a [`Plan`]: a [`CreateDIDConfig`] for a DID that doesn't exist
+*   yet, or the shortest sequence of [`PlannedUpdate`]s that reaches the spec.
+*   Each planned update describes its changes for review (the "plan" half of
+*   a plan/apply workflow) and turns into an [`UpdateDIDConfig`] with
+*   [`PlannedUpdate::config`]; [`apply_updates`] runs them all.
+*
+*   Specs are read from JSON, YAML (`yaml` feature) or TOML (`toml` feature):
+*
+*   ```yaml
+*   address: https://example.com/dids/alice
+*   portable: true
+*   ttl: 3600
+*   updateKeys: [auth-1]
+*   nextKeys: [auth-2]
+*   verificationMethods:
+*     - id: key-0
+*       key: signing
+*       relationships: [authentication, assertionMethod]
+*   services:
+*     - id: website
+*       type: LinkedDomains
+*       serviceEndpoint: https://example.com
+*   witnesses:
+*     threshold: 1
+*     witnesses: [witness-1]
+*   ```
+*
+*   ```ignore
+*   use didwebvh_rs::did_spec::{DidSpec, Plan, apply_updates, reconcile};
+*
+*   let spec = DidSpec::load("alice.yaml")?;
+*   match reconcile(&spec, Some(&state), &keys)? {
+*       Plan::Create(config) => { create_did(*config).await?; }
+*       Plan::Update(steps) => {
+*           for step in &steps {
+*               println!("{}", step.changes().join(", "));
+*           }
+*           let state = apply_updates(state, &steps).await?;
+*       }
+*   }
+*   ```
+*
+*   Fields left out of the spec (`ttl`, `watchers`, `witnesses`) are not
+*   managed: whatever the log currently says is kept. An empty list turns the
+*   feature off. `alsoKnownAs` entries the library maintains itself (the
+*   earlier `did:webvh` locations of a migrated DID) are always kept.
+*/
+
+use crate::{
+    DIDWebVHError, DIDWebVHState, Multibase,
+    create::CreateDIDConfig,
+    log_entry::LogEntryMethods,
+    migrate::{migration_target, preview_migration},
+    parameters::Parameters,
+    update::{UpdateDIDConfig, update_did},
+    url::WebVHURL,
+    witness::{Witness, Witnesses},
+};
+use affinidi_secrets_resolver::secrets::Secret;
+use ahash::{HashMap, HashSet};
+use chrono::{DateTime, Duration, FixedOffset, Timelike, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value, json};
+use std::{collections::HashMap as StdHashMap, hash::BuildHasher, path::Path, sync::Arc};
+
+/// Desired state of a DID.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct DidSpec {
+    /// Where the DID is hosted: an `https://` URL, or a path (`/dids/alice`)
+    /// on the current domain for an existing DID. Changing it migrates the DID.
+    pub address: String,
+
+    /// References of the keys allowed to update the DID (`updateKeys`).
+    pub update_keys: Vec<String>,
+
+    /// References of the pre-rotation keys whose hashes are committed in
+    /// `nextKeyHashes`. Empty turns pre-rotation off.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub next_keys: Vec<String>,
+
+    /// JSON-LD contexts of the DID Document. Default: DID v1 and CID v1.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub context: Option<Vec<String>>,
+
+    /// Verification methods in the DID Document.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub verification_methods: Vec<SpecVerificationMethod>,
+
+    /// Services in the DID Document, as JSON objects. A relative `id`
+    /// (`website` or `#website`) is expanded to `{DID}#website`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub services: Vec<Value>,
+
+    /// `alsoKnownAs` of the DID Document.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub also_known_as: Vec<String>,
+
+    /// Witness configuration. `None` leaves the current witnesses alone.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub witnesses: Option<SpecWitnesses>,
+
+    /// Watcher URLs. `None` leaves the current watchers alone.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub watchers: Option<Vec<String>>,
+
+    /// Cache TTL in seconds. `None` leaves the current TTL alone.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ttl: Option<u32>,
+
+    /// Can the DID move to another address? Can only be turned off after
+    /// creation.
+    #[serde(default)]
+    pub portable: bool,
+
+    /// Deactivate the DID. Permanent.
+    #[serde(default)]
+    pub deactivated: bool,
+}
+
+/// A verification method in a [`DidSpec`].
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct SpecVerificationMethod {
+    /// Fragment of the verification method id (`key-0` becomes `{DID}#key-0`).
+    pub id: String,
+
+    /// Verification method type. Default: `Multikey`.
+    #[serde(rename = "type", default, skip_serializing_if = "Option::is_none")]
+    pub type_: Option<String>,
+
+    /// Reference of the key backing the method.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key: Option<String>,
+
+    /// Public key, for a method whose private key isn't at hand. Used when
+    /// `key` isn't set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub public_key_multibase: Option<String>,
+
+    /// Verification relationships the method is listed under.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub relationships: Vec<VerificationRelationship>,
+}
+
+/// DID Document verification relationships.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum VerificationRelationship {
+    /// `authentication`
+    Authentication,
+    /// `assertionMethod`
+    AssertionMethod,
+    /// `keyAgreement`
+    KeyAgreement,
+    /// `capabilityInvocation`
+    CapabilityInvocation,
+    /// `capabilityDelegation`
+    CapabilityDelegation,
+}
+
+impl VerificationRelationship {
+    const ALL: [Self; 5] = [
+        Self::Authentication,
+        Self::AssertionMethod,
+        Self::KeyAgreement,
+        Self::CapabilityInvocation,
+        Self::CapabilityDelegation,
+    ];
+
+    /// Property name in the DID Document.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Authentication => "authentication",
+            Self::AssertionMethod => "assertionMethod",
+            Self::KeyAgreement => "keyAgreement",
+            Self::CapabilityInvocation => "capabilityInvocation",
+            Self::CapabilityDelegation => "capabilityDelegation",
+        }
+    }
+}
+
+/// Witness configuration in a [`DidSpec`].
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct SpecWitnesses {
+    /// Number of witness proofs needed per log entry.
+    pub threshold: u32,
+
+    /// Witnesses, as key references or `did:key` DIDs. Empty turns witnessing
+    /// off.
+    pub witnesses: Vec<String>,
+}
+
+impl DidSpec {
+    /// Parse a JSON spec.
+    pub fn from_json_str(spec: &str) -> Result<Self, DIDWebVHError> {
+        serde_json::from_str(spec)
+            .map_err(|e| DIDWebVHError::SpecError(format!("Invalid JSON DID spec: {e}")))
+    }
+
+    /// Parse a YAML spec.
+    #[cfg(feature = "yaml")]
+    pub fn from_yaml_str(spec: &str) -> Result<Self, DIDWebVHError> {
+        serde_yaml_ng::from_str(spec)
+            .map_err(|e| DIDWebVHError::SpecError(format!("Invalid YAML DID spec: {e}")))
+    }
+
+    /// Parse a TOML spec.
+    #[cfg(feature = "toml")]
+    pub fn from_toml_str(spec: &str) -> Result<Self, DIDWebVHError> {
+        toml::from_str(spec)
+            .map_err(|e| DIDWebVHError::SpecError(format!("Invalid TOML DID spec: {e}")))
+    }
+
+    /// Read a spec file, picking the format from its extension (`.json`,
+    /// `.yaml` / `.yml`, `.toml`).
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, DIDWebVHError> {
+        let path = path.as_ref();
+        let spec = std::fs::read_to_string(path).map_err(|e| {
+            DIDWebVHError::SpecError(format!("Couldn't read {}: {e}", path.display()))
+        })?;
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("json") => Self::from_json_str(&spec),
+            #[cfg(feature = "yaml")]
+            Some("yaml" | "yml") => Self::from_yaml_str(&spec),
+            #[cfg(feature = "toml")]
+            Some("toml") => Self::from_toml_str(&spec),
+            _ => Err(DIDWebVHError::SpecError(format!(
+                "Unsupported DID spec format: {} (JSON, or YAML / TOML with the `yaml` / \
+                 `toml` features)",
+                path.display()
+            ))),
+        }
+    }
+
+    /// Render the DID Document the spec describes for `did`. Pass `{DID}` to
+    /// get a template for [`CreateDIDConfig`].
+    pub fn document<S: BuildHasher>(
+        &self,
+        did: &str,
+        keys: &StdHashMap<String, Secret, S>,
+    ) -> Result<Value, DIDWebVHError> {
+        let context = self.context.clone().unwrap_or_else(|| {
+            vec![
+                "https://www.w3.org/ns/did/v1".to_string(),
+                "https://www.w3.org/ns/cid/v1".to_string(),
+            ]
+        });
+        let mut document = Map::new();
+        document.insert("@context".to_string(), json!(context));
+        document.insert("id".to_string(), json!(did));
+
+        let mut methods = Vec::with_capacity(self.verification_methods.len());
+        for vm in &self.verification_methods {
+            let public_key = match (&vm.key, &vm.public_key_multibase) {
+                (Some(key), _) => public_key(key, keys)?.to_string(),
+                (None, Some(public_key)) => public_key.clone(),
+                (None, None) => {
+                    return Err(DIDWebVHError::SpecError(format!(
+                        "Verification method {} needs a key or publicKeyMultibase",
+                        vm.id
+                    )));
+                }
+            };
+            methods.push(json!({
+                "id": fragment_id(did, &vm.id),
+                "type": vm.type_.as_deref().unwrap_or("Multikey"),
+                "controller": did,
+                "publicKeyMultibase": public_key,
+            }));
+        }
+        if !methods.is_empty() {
+            document.insert("verificationMethod".to_string(), Value::Array(methods));
+        }
+        for relationship in VerificationRelationship::ALL {
+            let ids: Vec<Value> = self
+                .verification_methods
+                .iter()
+                .filter(|vm| vm.relationships.contains(&relationship))
+                .map(|vm| Value::String(fragment_id(did, &vm.id)))
+                .collect();
+            if !ids.is_empty() {
+                document.insert(relationship.as_str().to_string(), Value::Array(ids));
+            }
+        }
+
+        if !self.services.is_empty() {
+            let mut services = self.services.clone();
+            for service in &mut services {
+                let Some(service) = service.as_object_mut() else {
+                    return Err(DIDWebVHError::SpecError(
+                        "Services must be JSON objects".to_string(),
+                    ));
+                };
+                if let Some(Value::String(id)) = service.get("id") {
+                    let id = fragment_id(did, id);
+                    service.insert("id".to_string(), Value::String(id));
+                }
+            }
+            document.insert("service".to_string(), Value::Array(services));
+        }
+        if !self.also_known_as.is_empty() {
+            document.insert("alsoKnownAs".to_string(), json!(self.also_known_as));
+        }
+        Ok(Value::Object(document))
+    }
+}
+
+/// What it takes to bring a DID in line with its [`DidSpec`].
+pub enum Plan {
+    /// The DID doesn't exist yet.
+    Create(Box<CreateDIDConfig>),
+    /// Log entries to append, in order. Empty when the DID matches the spec.
+    Update(Vec<PlannedUpdate>),
+}
+
+impl Plan {
+    /// Does the DID already match the spec?
+    pub fn is_empty(&self) -> bool {
+        matches!(self, Plan::Update(steps) if steps.is_empty())
+    }
+}
+
+/// One log entry of a [`Plan`].
+#[derive(Clone, Debug, Default)]
+pub struct PlannedUpdate {
+    changes: Vec<String>,
+    signing_key: Option<Secret>,
+    document: Option<Value>,
+    update_keys: Option<Vec<Multibase>>,
+    next_key_hashes: Option<Vec<Multibase>>,
+    witness: Option<Witnesses>,
+    watchers: Option<Vec<String>>,
+    ttl: Option<u32>,
+    disable_portability: bool,
+    deactivate: bool,
+    migrate_to: Option<String>,
+    witness_secrets: HashMap<String, Secret>,
+}
+
+impl PlannedUpdate {
+    /// Human-readable list of what this entry changes.
+    pub fn changes(&self) -> &[String] {
+        &self.changes
+    }
+
+    /// Does this entry move the DID?
+    pub fn is_migration(&self) -> bool {
+        self.migrate_to.is_some()
+    }
+
+    /// Does this entry deactivate the DID?
+    pub fn is_deactivation(&self) -> bool {
+        self.deactivate
+    }
+
+    /// [`UpdateDIDConfig`] appending this entry to `state`, which must be the
+    /// state the previous step of the plan left behind.
+    pub fn config(&self, state: DIDWebVHState) -> Result<UpdateDIDConfig, DIDWebVHError> {
+        let mut builder = UpdateDIDConfig::builder()
+            .state(state)
+            .witness_secrets(self.witness_secrets.clone());
+        if let Some(key) = &self.signing_key {
+            builder = builder.signing_key(key.clone());
+        }
+        if let Some(document) = &self.document {
+            builder = builder.document(document.clone());
+        }
+        if let Some(keys) = &self.update_keys {
+            builder = builder.update_keys(keys.clone());
+        }
+        if let Some(hashes) = &self.next_key_hashes {
+            builder = builder.next_key_hashes(hashes.clone());
+        }
+        if let Some(witness) = &self.witness {
+            builder = builder.witness(witness.clone());
+        }
+        if let Some(watchers) = &self.watchers {
+            builder = builder.watchers(watchers.clone());
+        }
+        if let Some(ttl) = self.ttl {
+            builder = builder.ttl(ttl);
+        }
+        if self.disable_portability {
+            builder = builder.disable_portability();
+        }
+        if let Some(address) = &self.migrate_to {
+            builder = builder.migrate_to(address);
+        }
+        builder.deactivate(self.deactivate).build()
+    }
+}
+
+/// Work out how to bring the DID in `state` in line with `spec`.
+///
+/// `state` is `None` (or empty) for a DID that doesn't exist yet, otherwise a
+/// validated state. `keys` maps the key references used in the spec to their
+/// secrets; the key signing each entry is picked from it.
+pub fn reconcile<S: BuildHasher>(
+    spec: &DidSpec,
+    state: Option<&DIDWebVHState>,
+    keys: &StdHashMap<String, Secret, S>,
+) -> Result<Plan, DIDWebVHError> {
+    match state {
+        Some(state) if !state.log_entries().is_empty() => {
+            plan_update(spec, state, keys).map(Plan::Update)
+        }
+        _ => plan_create(spec, keys).map(|config| Plan::Create(Box::new(config))),
+    }
+}
+
+/// Append the entries of a [`Plan::Update`] to `state`.
+///
+/// Entries are stamped one second apart and no later than now (versionTime
+/// has one-second resolution and must strictly increase), so the last entry
+/// of `state` must be at least `steps.len()` seconds old.
+pub async fn apply_updates(
+    mut state: DIDWebVHState,
+    steps: &[PlannedUpdate],
+) -> Result<DIDWebVHState, DIDWebVHError> {
+    let Some(last) = state.log_entries().last() else {
+        return Err(DIDWebVHError::LogEntryError(
+            "Can't apply updates to an empty DID state".to_string(),
+        ));
+    };
+    let last_time = last.log_entry.get_version_time();
+    let now = Utc::now()
+        .fixed_offset()
+        .with_nanosecond(0)
+        .unwrap_or_default();
+    let steps_back = i64::try_from(steps.len()).unwrap_or(i64::MAX) - 1;
+    if steps_back >= 0 && now - Duration::seconds(steps_back) <= last_time {
+        return Err(DIDWebVHError::LogEntryError(format!(
+            "The last log entry ({last_time}) is too recent to append {} entries; retry in a \
+             few seconds",
+            steps.len()
+        )));
+    }
+
+    for (i, step) in steps.iter().enumerate() {
+        let mut config = step.config(state)?;
+        config.version_time = Some(version_time(now, steps_back, i));
+        state = update_did(config).await?.into_state();
+    }
+    Ok(state)
+}
+
+fn version_time(now: DateTime<FixedOffset>, steps_back: i64, step: usize) -> DateTime<FixedOffset> {
+    now - Duration::seconds(steps_back - i64::try_from(step).unwrap_or(steps_back))
+}
+
+fn plan_create<S: BuildHasher>(
+    spec: &DidSpec,
+    keys: &StdHashMap<String, Secret, S>,
+) -> Result<CreateDIDConfig, DIDWebVHError> {
+    if spec.deactivated {
+        return Err(DIDWebVHError::SpecError(
+            "A DID can't be deactivated when it is created".to_string(),
+        ));
+    }
+    let authorization_keys = spec
+        .update_keys
+        .iter()
+        .map(|key| signer(key, keys))
+        .collect::<Result<Vec<_>, _>>()?;
+    let witnesses = match &spec.witnesses {
+        Some(witnesses) => witness_config(witnesses, keys)?,
+        None => Witnesses::Empty {},
+    };
+    let witness_secrets = witness_secrets(keys, &witnesses, None);
+
+    let parameters = Parameters {
+        update_keys: Some(Arc::new(public_keys(&spec.update_keys, keys)?)),
+        next_key_hashes: non_empty(key_hashes(&spec.next_keys, keys)?),
+        portable: spec.portable.then_some(true),
+        witness: (!witnesses.is_empty()).then(|| Arc::new(witnesses)),
+        watchers: spec
+            .watchers
+            .clone()
+            .filter(|w| !w.is_empty())
+            .map(Arc::new),
+        ttl: spec.ttl,
+        ..Default::default()
+    };
+
+    CreateDIDConfig::builder()
+        .address(&spec.address)
+        .authorization_keys(authorization_keys)
+        .did_document(spec.document("{DID}", keys)?)
+        .parameters(parameters)
+        .witness_secrets(witness_secrets)
+        .build()
+}
+
+/// `updateKeys` and `nextKeyHashes` in force after a planned entry.
+struct KeyState {
+    update_keys: Vec<Multibase>,
+    next_key_hashes: Vec<Multibase>,
+}
+
+fn plan_update<S: BuildHasher>(
+    spec: &DidSpec,
+    state: &DIDWebVHState,
+    keys: &StdHashMap<String, Secret, S>,
+) -> Result<Vec<PlannedUpdate>, DIDWebVHError> {
+    let last = state
+        .log_entries()
+        .last()
+        .ok_or_else(|| DIDWebVHError::LogEntryError("No log entries exist".to_string()))?;
+    let params = &last.validated_parameters;
+    let current_doc = last.get_state();
+    let current_did = current_doc
+        .get("id")
+        .and_then(Value::as_str)
+        .ok_or_else(|| DIDWebVHError::DIDError("DID not found in log entry state".to_string()))?;
+
+    if state.deactivated() || params.deactivated == Some(true) {
+        return if spec.deactivated {
+            Ok(Vec::new())
+        } else {
+            Err(DIDWebVHError::DeactivatedError(format!(
+                "{current_did} is deactivated and can't be changed"
+            )))
+        };
+    }
+
+    let portable = params.portable == Some(true);
+    if spec.portable && !portable {
+        return Err(DIDWebVHError::SpecError(
+            "Portability can only be turned on when the DID is created".to_string(),
+        ));
+    }
+    let target_did =
+        migration_target(&WebVHURL::parse_did_url(current_did)?, &spec.address)?.to_did_base();
+    let migrate = target_did != current_did;
+    if migrate && !portable {
+        return Err(DIDWebVHError::SpecError(format!(
+            "The spec moves {current_did} to {target_did}, but the DID isn't portable"
+        )));
+    }
+
+    // The document each step starts from, and the one the spec wants
+    let base_doc = if migrate {
+        preview_migration(state, &spec.address)?.document
+    } else {
+        current_doc.clone()
+    };
+    let mut desired_doc = spec.document(&target_did, keys)?;
+    keep_own_aliases(&mut desired_doc, &base_doc, state.scid(), &target_did);
+
+    // Parameter changes
+    let mut update = PlannedUpdate::default();
+    let desired_update_keys = public_keys(&spec.update_keys, keys)?;
+    if !same_set(&desired_update_keys, &params.active_update_keys) {
+        update.changes.push("rotate updateKeys".to_string());
+        update.update_keys = Some(desired_update_keys.clone());
+    }
+    let current_hashes = params
+        .next_key_hashes
+        .as_deref()
+        .cloned()
+        .unwrap_or_default();
+    // Pre-rotation has to end before deactivation
+    let desired_hashes = if spec.deactivated {
+        Vec::new()
+    } else {
+        key_hashes(&spec.next_keys, keys)?
+    };
+    if !same_set(&desired_hashes, &current_hashes) {
+        update.changes.push(if desired_hashes.is_empty() {
+            "end pre-rotation".to_string()
+        } else {
+            "commit nextKeyHashes".to_string()
+        });
+        update.next_key_hashes = Some(desired_hashes.clone());
+    }
+    let desired_witnesses = match &spec.witnesses {
+        Some(witnesses) => Some(witness_config(witnesses, keys)?),
+        None => None,
+    };
+    let current_witnesses = params.witness.as_deref().cloned();
+    if let Some(witnesses) = &desired_witnesses
+        && !same_witnesses(witnesses, current_witnesses.as_ref())
+    {
+        update.changes.push(match witnesses.threshold() {
+            Some(threshold) => format!(
+                "witnesses: {} with threshold {threshold}",
+                witnesses.witnesses().map_or(0, <[Witness]>::len)
+            ),
+            None => "witnesses: none".to_string(),
+        });
+        update.witness = Some(witnesses.clone());
+    }
+    if let Some(watchers) = &spec.watchers
+        && !same_set(
+            watchers,
+            params.watchers.as_deref().map_or(&[], Vec::as_slice),
+        )
+    {
+        update.changes.push(format!("watchers: {}", watchers.len()));
+        update.watchers = Some(watchers.clone());
+    }
+    if let Some(ttl) = spec.ttl
+        && params.ttl != Some(ttl)
+    {
+        update.changes.push(format!("ttl: {ttl}"));
+        update.ttl = Some(ttl);
+    }
+
+    let witness_secrets = witness_secrets(
+        keys,
+        desired_witnesses.as_ref().unwrap_or(&Witnesses::Empty {}),
+        current_witnesses.as_ref(),
+    );
+    let mut steps = Vec::new();
+
+    // 1. Migration, carrying the parameter changes
+    if migrate {
+        let mut step = std::mem::take(&mut update);
+        step.changes.insert(0, format!("migrate to {target_did}"));
+        step.migrate_to = Some(spec.address.clone());
+        steps.push(step);
+    }
+
+    // 2. Document changes, turning portability off, and anything a migration
+    //    couldn't carry
+    if desired_doc != base_doc {
+        update.changes.push("update DID Document".to_string());
+        update.document = Some(desired_doc);
+    }
+    if portable && !spec.portable {
+        update.changes.push("portable: false".to_string());
+        update.disable_portability = true;
+    }
+    if !update.changes.is_empty() {
+        steps.push(update);
+    }
+
+    // 3. Deactivation
+    if spec.deactivated {
+        steps.push(PlannedUpdate {
+            changes: vec!["deactivate".to_string()],
+            deactivate: true,
+            ..Default::default()
+        });
+    }
+
+    let mut key_state = KeyState {
+        update_keys: params.active_update_keys.to_vec(),
+        next_key_hashes: current_hashes,
+    };
+    for step in &mut steps {
+        step.signing_key = Some(signing_key(
+            step,
+            &mut key_state,
+            &desired_update_keys,
+            keys,
+        )?);
+        step.witness_secrets.clone_from(&witness_secrets);
+    }
+    Ok(steps)
+}
+
+/// Pick the key that signs `step` and advance `key_state` past it.
+fn signing_key<S: BuildHasher>(
+    step: &mut PlannedUpdate,
+    key_state: &mut KeyState,
+    desired_update_keys: &[Multibase],
+    keys: &StdHashMap<String, Secret, S>,
+) -> Result<Secret, DIDWebVHError> {
+    let signer = if key_state.next_key_hashes.is_empty() {
+        find_key(keys, |pk, _| key_state.update_keys.contains(pk)).ok_or_else(|| {
+            DIDWebVHError::SecretsError(
+                "None of the spec's keys is in the DID's current updateKeys".to_string(),
+            )
+        })?
+    } else {
+        // Pre-rotation: every entry reveals keys committed by the previous one
+        let revealed = step
+            .update_keys
+            .get_or_insert_with(|| desired_update_keys.to_vec());
+        if let Some(key) = revealed
+            .iter()
+            .find(|pk| !key_state.next_key_hashes.contains(&hash_public_key(pk)))
+        {
+            return Err(DIDWebVHError::SpecError(format!(
+                "Pre-rotation is active, so every log entry must rotate to keys committed in \
+                 nextKeyHashes, but {key} wasn't committed. Apply the spec in stages: {}",
+                step.changes.join(", ")
+            )));
+        }
+        find_key(keys, |pk, _| revealed.contains(pk)).ok_or_else(|| {
+            DIDWebVHError::SecretsError(
+                "None of the revealed updateKeys is in the key map".to_string(),
+            )
+        })?
+    };
+
+    if let Some(update_keys) = &step.update_keys {
+        key_state.update_keys.clone_from(update_keys);
+    }
+    if let Some(hashes) = &step.next_key_hashes {
+        key_state.next_key_hashes.clone_from(hashes);
+    }
+    Ok(signer)
+}
+
+/// First key (by reference, for a stable choice) matching `filter`, as a signer.
+fn find_key<S: BuildHasher>(
+    keys: &StdHashMap<String, Secret, S>,
+    filter: impl Fn(&Multibase, &str) -> bool,
+) -> Option<Secret> {
+    let mut names: Vec<&String> = keys.keys().collect();
+    names.sort();
+    names.into_iter().find_map(|name| {
+        let pk = public_key(name, keys).ok()?;
+        filter(&pk, name).then(|| signer(name, keys).ok()).flatten()
+    })
+}
+
+/// Carry over `alsoKnownAs` entries that are earlier locations of this DID.
+fn keep_own_aliases(desired: &mut Value, base: &Value, scid: &str, did: &str) {
+    let prefix = format!("did:webvh:{scid}:");
+    let own: Vec<Value> = base
+        .get("alsoKnownAs")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter(|alias| {
+            alias
+                .as_str()
+                .is_some_and(|a| a.starts_with(&prefix) && a != did)
+        })
+        .cloned()
+        .collect();
+    if own.is_empty() {
+        return;
+    }
+    let Some(desired) = desired.as_object_mut() else {
+        return;
+    };
+    let aliases = desired
+        .entry("alsoKnownAs")
+        .or_insert_with(|| Value::Array(Vec::new()));
+    if let Some(aliases) = aliases.as_array_mut() {
+        for alias in own {
+            if !aliases.contains(&alias) {
+                aliases.push(alias);
+            }
+        }
+    }
+}
+
+/// `{did}#{id}` for a relative id; absolute ids are kept.
+fn fragment_id(did: &str, id: &str) -> String {
+    if id.contains(':') {
+        id.to_string()
+    } else {
+        [did, "#", id.trim_start_matches('#')].concat()
+    }
+}
+
+fn lookup<'a, S: BuildHasher>(
+    reference: &str,
+    keys: &'a StdHashMap<String, Secret, S>,
+) -> Result<&'a Secret, DIDWebVHError> {
+    keys.get(reference)
+        .ok_or_else(|| DIDWebVHError::SecretsError(format!("No key for reference '{reference}'")))
+}
+
+fn public_key<S: BuildHasher>(
+    reference: &str,
+    keys: &StdHashMap<String, Secret, S>,
+) -> Result<Multibase, DIDWebVHError> {
+    lookup(reference, keys)?
+        .get_public_keymultibase()
+        .map(Multibase::new)
+        .map_err(|e| {
+            DIDWebVHError::SecretsError(format!("Couldn't get public key of '{reference}': {e}"))
+        })
+}
+
+fn public_keys<S: BuildHasher>(
+    references: &[String],
+    keys: &StdHashMap<String, Secret, S>,
+) -> Result<Vec<Multibase>, DIDWebVHError> {
+    references.iter().map(|r| public_key(r, keys)).collect()
+}
+
+fn key_hashes<S: BuildHasher>(
+    references: &[String],
+    keys: &StdHashMap<String, Secret, S>,
+) -> Result<Vec<Multibase>, DIDWebVHError> {
+    references
+        .iter()
+        .map(|reference| {
+            lookup(reference, keys)?
+                .get_public_keymultibase_hash()
+                .map(Multibase::new)
+                .map_err(|e| {
+                    DIDWebVHError::SecretsError(format!("Couldn't hash key '{reference}': {e}"))
+                })
+        })
+        .collect()
+}
+
+fn hash_public_key(public_key: &Multibase) -> Multibase {
+    Multibase::new(Secret::base58_hash_string(public_key.as_str()).unwrap_or_default())
+}
+
+/// The referenced key with the `did:key` verification method log entries are
+/// signed with.
+fn signer<S: BuildHasher>(
+    reference: &str,
+    keys: &StdHashMap<String, Secret, S>,
+) -> Result<Secret, DIDWebVHError> {
+    let pk = public_key(reference, keys)?;
+    let mut secret = lookup(reference, keys)?.clone();
+    secret.id = format!("did:key:{pk}#{pk}");
+    Ok(secret)
+}
+
+fn witness_config<S: BuildHasher>(
+    spec: &SpecWitnesses,
+    keys: &StdHashMap<String, Secret, S>,
+) -> Result<Witnesses, DIDWebVHError> {
+    if spec.witnesses.is_empty() {
+        return Ok(Witnesses::Empty {});
+    }
+    let witnesses = spec
+        .witnesses
+        .iter()
+        .map(|witness| {
+            if witness.starts_with("did:key:") {
+                Ok(Witness::new(witness.as_str()))
+            } else {
+                public_key(witness, keys).map(|pk| Witness::new(pk.to_string()))
+            }
+        })
+        .collect::<Result<Vec<_>, DIDWebVHError>>()?;
+    let witnesses = Witnesses::Value {
+        threshold: spec.threshold,
+        witnesses,
+    };
+    witnesses.validate()?;
+    Ok(witnesses)
+}
+
+/// Keys in `keys` belonging to a desired or current witness, keyed by witness DID.
+fn witness_secrets<S: BuildHasher>(
+    keys: &StdHashMap<String, Secret, S>,
+    desired: &Witnesses,
+    current: Option<&Witnesses>,
+) -> HashMap<String, Secret> {
+    let witness_dids: HashSet<String> = [Some(desired), current]
+        .into_iter()
+        .flatten()
+        .filter_map(Witnesses::witnesses)
+        .flatten()
+        .map(Witness::as_did)
+        .collect();
+    keys.iter()
+        .filter_map(|(name, secret)| {
+            let did = format!("did:key:{}", public_key(name, keys).ok()?);
+            witness_dids.contains(&did).then(|| (did, secret.clone()))
+        })
+        .collect()
+}
+
+fn same_witnesses(desired: &Witnesses, current: Option<&Witnesses>) -> bool {
+    let current = current.unwrap_or(&Witnesses::Empty {});
+    if desired.is_empty() || current.is_empty() {
+        return desired.is_empty() && current.is_empty();
+    }
+    let dids = |w: &Witnesses| -> Vec<String> {
+        w.witnesses()
+            .unwrap_or_default()
+            .iter()
+            .map(Witness::as_did)
+            .collect()
+    };
+    desired.threshold() == current.threshold() && same_set(&dids(desired), &dids(current))
+}
+
+fn same_set<T: Eq + std::hash::Hash>(a: &[T], b: &[T]) -> bool {
+    a.iter().collect::<HashSet<_>>() == b.iter().collect::<HashSet<_>>()
+}
+
+fn non_empty(values: Vec<Multibase>) -> Option<Arc<Vec<Multibase>>> {
+    (!values.is_empty()).then(|| Arc::new(values))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{create::create_did, test_utils::generate_signing_key};
+
+    fn keys(names: &[&str]) -> HashMap<String, Secret> {
+        names
+            .iter()
+            .map(|name| ((*name).to_string(), generate_signing_key()))
+            .collect()
+    }
+
+    fn spec() -> DidSpec {
+        DidSpec {
+            address: "https://example.com/dids/alice".to_string(),
+            update_keys: vec!["auth-1".to_string()],
+            verification_methods: vec![SpecVerificationMethod {
+                id: "key-0".to_string(),
+                key: Some("signing".to_string()),
+                relationships: vec![
+                    VerificationRelationship::Authentication,
+                    VerificationRelationship::AssertionMethod,
+                ],
+                ..Default::default()
+            }],
+            portable: true,
+            ..Default::default()
+        }
+    }
+
+    /// Create the DID an hour ago and return its validated state.
+    async fn create(spec: &DidSpec, keys: &HashMap<String, Secret>) -> DIDWebVHState {
+        let Plan::Create(mut config) = reconcile(spec, None, keys).unwrap() else {
+            panic!("expected a create plan");
+        };
+        config.version_time = Some(Utc::now().fixed_offset() - Duration::hours(1));
+        let result = create_did(*config).await.unwrap();
+        let mut state = DIDWebVHState::from_log_entries(vec![result.log_entry().clone()]);
+        state.set_witness_proofs(result.witness_proofs().clone());
+        state.validate().unwrap().assert_complete().unwrap();
+        state
+    }
+
+    /// Re-validate `state` from scratch, as a resolver would.
+    fn revalidate(state: &DIDWebVHState) -> DIDWebVHState {
+        let mut fresh = DIDWebVHState::from_log_entries(
+            state
+                .log_entries()
+                .iter()
+                .map(|e| e.log_entry.clone())
+                .collect(),
+        );
+        fresh.set_witness_proofs(state.witness_proofs().clone());
+        fresh.validate().unwrap().assert_complete().unwrap();
+        fresh
+    }
+
+    #[test]
+    fn document_from_spec() {
+        let keys = keys(&["auth-1", "signing"]);
+        let mut spec = spec();
+        spec.services = vec![json!({
+            "id": "#website",
+            "type": "LinkedDomains",
+            "serviceEndpoint": "https://example.com",
+        })];
+        let doc = spec.document("did:example:1", &keys).unwrap();
+        assert_eq!(doc["verificationMethod"][0]["id"], "did:example:1#key-0");
+        assert_eq!(
+            doc["verificationMethod"][0]["publicKeyMultibase"],
+            public_key("signing", &keys).unwrap().as_str()
+        );
+        assert_eq!(doc["authentication"], json!(["did:example:1#key-0"]));
+        assert!(doc.get("keyAgreement").is_none());
+        assert_eq!(doc["service"][0]["id"], "did:example:1#website");
+
+        spec.verification_methods[0].key = Some("missing".to_string());
+        assert!(spec.document("did:example:1", &keys).is_err());
+    }
+
+    #[test]
+    fn unknown_fields_rejected() {
+        let err = DidSpec::from_json_str(
+            r#"{"address": "https://example.com", "updateKeys": [], "tll": 1}"#,
+        )
+        .unwrap_err();
+        assert!(matches!(err, DIDWebVHError::SpecError(_)));
+    }
+
+    #[cfg(all(feature = "yaml", feature = "toml"))]
+    #[test]
+    fn formats_agree() {
+        let yaml = DidSpec::from_yaml_str(
+            "address: https://example.com\nupdateKeys: [auth-1]\nttl: 600\n\
+             verificationMethods:\n  - id: key-0\n    key: signing\n    relationships: [authentication]\n\
+             witnesses:\n  threshold: 1\n  witnesses: [w1]\n",
+        )
+        .unwrap();
+        let toml = DidSpec::from_toml_str(
+            "address = \"https://example.com\"\nupdateKeys = [\"auth-1\"]\nttl = 600\n\
+             [[verificationMethods]]\nid = \"key-0\"\nkey = \"signing\"\nrelationships = [\"authentication\"]\n\
+             [witnesses]\nthreshold = 1\nwitnesses = [\"w1\"]\n",
+        )
+        .unwrap();
+        let json = DidSpec::from_json_str(&serde_json::to_string(&yaml).unwrap()).unwrap();
+        assert_eq!(yaml, toml);
+        assert_eq!(yaml, json);
+    }
+
+    #[tokio::test]
+    async fn created_did_matches_its_spec() {
+        let keys = keys(&["auth-1", "signing", "witness-1"]);
+        let mut spec = spec();
+        spec.witnesses = Some(SpecWitnesses {
+            threshold: 1,
+            witnesses: vec!["witness-1".to_string()],
+        });
+        let state = create(&spec, &keys).await;
+        assert_eq!(state.witness_proofs().get_total_count(), 1);
+        assert!(reconcile(&spec, Some(&state), &keys).unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn parameter_and_document_changes_in_one_entry() {
+        let keys = keys(&["auth-1", "auth-2", "signing"]);
+        let mut spec = spec();
+        let state = create(&spec, &keys).await;
+
+        spec.ttl = Some(600);
+        spec.update_keys = vec!["auth-2".to_string()];
+        spec.also_known_as = vec!["did:web:example.com:dids:alice".to_string()];
+        let Plan::Update(steps) = reconcile(&spec, Some(&state), &keys).unwrap() else {
+            panic!("expected an update plan");
+        };
+        assert_eq!(steps.len(), 1);
+        assert_eq!(
+            steps[0].changes(),
+            ["rotate updateKeys", "ttl: 600", "update DID Document"]
+        );
+
+        let state = revalidate(&apply_updates(state, &steps).await.unwrap());
+        let last = state.log_entries().last().unwrap();
+        assert_eq!(last.validated_parameters.ttl, Some(600));
+        assert!(reconcile(&spec, Some(&state), &keys).unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn migration_then_portability_off() {
+        let keys = keys(&["auth-1", "signing"]);
+        let mut spec = spec();
+        let state = create(&spec, &keys).await;
+        let old_did = state.log_entries()[0].get_state()["id"]
+            .as_str()
+            .unwrap()
+            .to_string();
+
+        spec.address = "https://new.example.com/alice".to_string();
+        spec.portable = false;
+        let Plan::Update(steps) = reconcile(&spec, Some(&state), &keys).unwrap() else {
+            panic!("expected an update plan");
+        };
+        assert_eq!(steps.len(), 2);
+        assert!(steps[0].is_migration());
+        assert_eq!(steps[1].changes(), ["portable: false"]);
+
+        let state = revalidate(&apply_updates(state, &steps).await.unwrap());
+        let doc = state.log_entries().last().unwrap().get_state();
+        assert!(
+            doc["id"]
+                .as_str()
+                .unwrap()
+                .ends_with(":new.example.com:alice")
+        );
+        assert_eq!(doc["alsoKnownAs"], json!([old_did]));
+        assert!(reconcile(&spec, Some(&state), &keys).unwrap().is_empty());
+
+        spec.portable = true;
+        assert!(reconcile(&spec, Some(&state), &keys).is_err());
+    }
+
+    #[tokio::test]
+    async fn pre_rotation() {
+        let keys = keys(&["auth-1", "auth-2", "auth-3", "signing"]);
+        let mut spec = spec();
+        spec.next_keys = vec!["auth-2".to_string()];
+        let state = create(&spec, &keys).await;
+
+        // Changing the document alone would need the committed key revealed
+        spec.ttl = Some(60);
+        assert!(matches!(
+            reconcile(&spec, Some(&state), &keys),
+            Err(DIDWebVHError::SpecError(_))
+        ));
+
+        spec.update_keys = vec!["auth-2".to_string()];
+        spec.next_keys = vec!["auth-3".to_string()];
+        let Plan::Update(steps) = reconcile(&spec, Some(&state), &keys).unwrap() else {
+            panic!("expected an update plan");
+        };
+        assert_eq!(steps.len(), 1);
+        let state = revalidate(&apply_updates(state, &steps).await.unwrap());
+        assert!(reconcile(&spec, Some(&state), &keys).unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn deactivation_ends_pre_rotation_first() {
+        let keys = keys(&["auth-1", "auth-2", "signing"]);
+        let mut spec = spec();
+        spec.next_keys = vec!["auth-2".to_string()];
+        let state = create(&spec, &keys).await;
+
+        spec.update_keys = vec!["auth-2".to_string()];
+        spec.deactivated = true;
+        let Plan::Update(steps) = reconcile(&spec, Some(&state), &keys).unwrap() else {
+            panic!("expected an update plan");
+        };
+        assert_eq!(steps.len(), 2);
+        assert_eq!(
+            steps[0].changes(),
+            ["rotate updateKeys", "end pre-rotation"]
+        );
+        assert!(steps[1].is_deactivation());
+
+        let state = revalidate(&apply_updates(state, &steps).await.unwrap());
+        assert!(state.deactivated());
+        assert!(reconcile(&spec, Some(&state), &keys).unwrap().is_empty());
+        spec.deactivated = false;
+        assert!(reconcile(&spec, Some(&state), &keys).is_err());
+    }
+
+    #[tokio::test]
+    async fn recent_entry_blocks_apply() {
+        let keys = keys(&["auth-1", "signing"]);
+        let mut spec = spec();
+        let Plan::Create(config) = reconcile(&spec, None, &keys).unwrap() else {
+            panic!("expected a create plan");
+        };
+        let result = create_did(*config).await.unwrap();
+        let mut state = DIDWebVHState::from_log_entries(vec![result.log_entry().clone()]);
+        state.validate().unwrap().assert_complete().unwrap();
+
+        spec.ttl = Some(60);
+        let Plan::Update(steps) = reconcile(&spec, Some(&state), &keys).unwrap() else {
+            panic!("expected an update plan");
+        };
+        assert!(matches!(
+            apply_updates(state, &steps).await,
+            Err(DIDWebVHError::LogEntryError(_))
+        ));
+    }
+}
@@ -92,7 +92,7 @@ fn replace_webvh_prefix(input: &str) -> String {
     result
 }
 
-fn to_web_did(old_state: &Value) -> Result<Value, DIDWebVHError> {
+pub(crate) fn to_web_did(old_state: &Value) -> Result<Value, DIDWebVHError> {
     // What is the new DID?
     let (webvh_did, web_did) = if let Some(id) = old_state.get("id")
         && let Some(id_str) = id.as_str()
@@ -0,0 +1,491 @@
+/*!
+*   Universal Resolver driver, behind the `driver` feature.
+*
+*   [`ResolverDriver`] answers `GET /1.0/identifiers/{did}` the way the
+*   [Universal Resolver](https://github.com/decentralized-identity/universal-resolver)
+*   expects its drivers to, resolving with [`DIDWebVHState::resolve`]:
+*
+*   - `versionId`, `versionTime` and `versionNumber` query parameters select
+*     a version, as they would on the DID itself
+*   - `Accept: application/did+ld+json` (or `application/did+json`) returns
+*     the DID document alone
+*   - `Accept: application/ld+json;profile="https://w3id.org/did-resolution"`,
+*     `application/json`, any wildcard or no `Accept` returns the full resolution
+*     result (`didDocument`, `didDocumentMetadata`, `didResolutionMetadata`)
+*
+*   The DID may be given as-is or percent-encoded (`did%3Awebvh%3A...`).
+*   Accepted media types are tried in the order listed; `q` weights other
+*   than `q=0` are ignored.
+*
+*   Failures are returned as a resolution result whose
+*   `didResolutionMetadata.error` is one of `invalidDid` (400), `notFound`
+*   (404), `representationNotSupported` (406), `methodNotSupported` (501) or
+*   `internalError` (500). A deactivated DID resolves with status 410.
+*
+*   ```ignore
+*   let driver = ResolverDriver::new().options(ResolveOptions {
+*       timeout: Some(Duration::from_secs(5)),
+*       ..Default::default()
+*   });
+*   let listener = tokio::net::TcpListener::bind("0.0.0.0:8080").await?;
+*   axum::serve(listener, driver.router()).await?;
+*   ```
+*
+*   The `didwebvh-driver` binary (`driver-bin` feature) runs this on its own.
+*/
+
+use crate::{
+    DIDWebVHError, DIDWebVHState,
+    log_entry::{LogEntryMethods, MetaData},
+    resolve::ResolveOptions,
+};
+use axum::{
+    Router,
+    extract::State,
+    http::{HeaderMap, Method, StatusCode, Uri, header},
+    response::{IntoResponse, Response},
+};
+use percent_encoding::percent_decode_str;
+use serde_json::{Value, json};
+use std::sync::Arc;
+
+/// Path prefix of the resolve endpoint.
+pub const IDENTIFIERS_PATH: &str = "/1.0/identifiers/";
+
+/// Content type of a DID document in JSON-LD form.
+pub const DID_LD_JSON_CONTENT_TYPE: &str = "application/did+ld+json";
+
+/// Content type of a DID document in plain JSON form.
+pub const DID_JSON_CONTENT_TYPE: &str = "application/did+json";
+
+/// Content type of a full DID resolution result.
+pub const RESOLUTION_CONTENT_TYPE: &str =
+    "application/ld+json;profile=\"https://w3id.org/did-resolution\"";
+
+/// Query parameters passed through to the DID.
+const VERSION_QUERY_KEYS: [&str; 3] = ["versionId", "versionTime", "versionNumber"];
+
+/// What the client asked for in its `Accept` header.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Representation {
+    /// The DID document alone, with this content type.
+    Document(&'static str),
+    /// The full resolution result.
+    Result,
+}
+
+/// Universal Resolver driver for did:webvh. See the [module docs](self).
+#[derive(Clone, Debug, Default)]
+pub struct ResolverDriver {
+    options: ResolveOptions,
+}
+
+impl ResolverDriver {
+    /// A driver resolving with [`ResolveOptions::default`].
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Options used for every resolution (timeout, response size limit,
+    /// following `canonicalId` ...).
+    pub fn options(mut self, options: ResolveOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// An axum [`Router`] serving the resolve endpoint.
+    pub fn router(self) -> Router {
+        Router::new().fallback(handle).with_state(Arc::new(self))
+    }
+
+    /// Resolve `did` and build the response for `representation`.
+    async fn resolve(&self, did: &str, representation: Representation) -> Response {
+        let mut state = DIDWebVHState::default();
+        let (entry, metadata) = match state.resolve(did, self.options.clone()).await {
+            Ok(resolved) => resolved,
+            Err(e) => return error_response(&e),
+        };
+        let document = match entry.get_did_document() {
+            Ok(document) => document,
+            Err(e) => return error_response(&e),
+        };
+        let status = if metadata.deactivated {
+            StatusCode::GONE
+        } else {
+            StatusCode::OK
+        };
+        match representation {
+            Representation::Document(content_type) => {
+                json_response(status, content_type, &document)
+            }
+            Representation::Result => json_response(
+                status,
+                RESOLUTION_CONTENT_TYPE,
+                &json!({
+                    "didDocument": document,
+                    "didDocumentMetadata": document_metadata(&metadata),
+                    "didResolutionMetadata": { "contentType": DID_LD_JSON_CONTENT_TYPE },
+                }),
+            ),
+        }
+    }
+}
+
+/// `didDocumentMetadata` of a resolution result.
+fn document_metadata(metadata: &MetaData) -> Value {
+    let mut document_metadata = json!({
+        "created": metadata.created,
+        "updated": metadata.updated,
+        "versionId": metadata.version_id,
+        "versionNumber": metadata.version_number,
+        "versionTime": metadata.version_time,
+    });
+    if metadata.deactivated {
+        document_metadata["deactivated"] = json!(true);
+    }
+    if let Some(canonical_id) = &metadata.canonical_id {
+        document_metadata["canonicalId"] = json!(canonical_id);
+    }
+    document_metadata
+}
+
+/// Pick the representation from the `Accept` header, `None` if nothing
+/// offered is acceptable.
+fn representation(headers: &HeaderMap) -> Option<Representation> {
+    let Some(accept) = headers
+        .get(header::ACCEPT)
+        .and_then(|v| v.to_str().ok())
+        .filter(|v| !v.trim().is_empty())
+    else {
+        return Some(Representation::Result);
+    };
+
+    for range in accept.split(',') {
+        let mut parts = range.split(';').map(str::trim);
+        let media_type = parts.next().unwrap_or_default().to_ascii_lowercase();
+        let params: Vec<&str> = parts.collect();
+        if params.iter().any(|p| p.replace(' ', "") == "q=0") {
+            continue;
+        }
+        let found = match media_type.as_str() {
+            DID_LD_JSON_CONTENT_TYPE => Some(Representation::Document(DID_LD_JSON_CONTENT_TYPE)),
+            DID_JSON_CONTENT_TYPE | "application/did" => {
+                Some(Representation::Document(DID_JSON_CONTENT_TYPE))
+            }
+            "application/ld+json"
+                if params
+                    .iter()
+                    .any(|p| p.trim_matches('"').contains("w3id.org/did-resolution")) =>
+            {
+                Some(Representation::Result)
+            }
+            "application/did-resolution" | "application/json" | "application/*" | "*/*" => {
+                Some(Representation::Result)
+            }
+            _ => None,
+        };
+        if found.is_some() {
+            return found;
+        }
+    }
+    None
+}
+
+/// The DID to resolve: the identifier from the path (percent-decoded if it
+/// was encoded whole) plus any version query parameters.
+fn requested_did(identifier: &str, query: Option<&str>) -> String {
+    let mut did = if identifier
+        .get(..6)
+        .is_some_and(|p| p.eq_ignore_ascii_case("did%3A"))
+    {
+        percent_decode_str(identifier)
+            .decode_utf8_lossy()
+            .into_owned()
+    } else {
+        identifier.to_string()
+    };
+
+    for pair in query.unwrap_or_default().split('&') {
+        if let Some((key, value)) = pair.split_once('=')
+            && VERSION_QUERY_KEYS.contains(&key)
+        {
+            did.push(if did.contains('?') { '&' } else { '?' });
+            did.push_str(key);
+            did.push('=');
+            did.push_str(&percent_decode_str(value).decode_utf8_lossy());
+        }
+    }
+    did
+}
+
+fn error_response(e: &DIDWebVHError) -> Response {
+    let (status, error) = match e {
+        DIDWebVHError::UnsupportedMethod(_) => (StatusCode::NOT_IMPLEMENTED, "methodNotSupported"),
+        DIDWebVHError::InvalidMethodIdentifier(_) | DIDWebVHError::DIDError(_) => {
+            (StatusCode::BAD_REQUEST, "invalidDid")
+        }
+        DIDWebVHError::NotFound(_)
+        | DIDWebVHError::NetworkError {
+            status_code: Some(404 | 410),
+            ..
+        } => (StatusCode::NOT_FOUND, "notFound"),
+        _ => (StatusCode::INTERNAL_SERVER_ERROR, "internalError"),
+    };
+    failure(status, error, &e.to_string())
+}
+
+/// A resolution result carrying only an error.
+fn failure(status: StatusCode, error: &str, message: &str) -> Response {
+    json_response(
+        status,
+        RESOLUTION_CONTENT_TYPE,
+        &json!({
+            "didDocument": null,
+            "didDocumentMetadata": {},
+            "didResolutionMetadata": { "error": error, "errorMessage": message },
+        }),
+    )
+}
+
+fn json_response(status: StatusCode, content_type: &'static str, body: &Value) -> Response {
+    (
+        status,
+        [(header::CONTENT_TYPE, content_type)],
+        body.to_string(),
+    )
+        .into_response()
+}
+
+async fn handle(
+    State(driver): State<Arc<ResolverDriver>>,
+    method: Method,
+    uri: Uri,
+    headers: HeaderMap,
+) -> Response {
+    let Some(identifier) = uri
+        .path()
+        .strip_prefix(IDENTIFIERS_PATH)
+        .filter(|i| !i.is_empty())
+    else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    if method != Method::GET {
+        return StatusCode::METHOD_NOT_ALLOWED.into_response();
+    }
+    let Some(representation) = representation(&headers) else {
+        return failure(
+            StatusCode::NOT_ACCEPTABLE,
+            "representationNotSupported",
+            "None of the accepted media types can be produced",
+        );
+    };
+
+    let did = requested_did(identifier, uri.query());
+    driver.resolve(&did, representation).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        DID_LD_JSON_CONTENT_TYPE, RESOLUTION_CONTENT_TYPE, Representation, ResolverDriver,
+        representation, requested_did,
+    };
+    use crate::{
+        DIDWebVHState,
+        test_utils::{did_doc_with_key, key_and_params},
+        update::{UpdateDIDConfig, update_did},
+    };
+    use axum::{
+        Router,
+        body::{Body, to_bytes},
+        http::{HeaderMap, HeaderValue, Request, StatusCode, header},
+    };
+    use chrono::{Duration, Utc};
+    use serde_json::Value;
+    use tower::ServiceExt;
+    use wiremock::{Mock, MockServer, ResponseTemplate, matchers::path};
+
+    /// Host a two-entry log on a mock server, returning the server (keep it
+    /// alive) and the DID. The second entry deactivates the DID if asked.
+    async fn hosted_did(deactivate: bool) -> (MockServer, String) {
+        let server = MockServer::start().await;
+        let port = server.address().port();
+        let t0 = Utc::now().fixed_offset() - Duration::hours(1);
+
+        let (key, params) = key_and_params();
+        let doc = did_doc_with_key(&format!("did:webvh:{{SCID}}:localhost%3A{port}"), &key);
+        let mut state = DIDWebVHState::default();
+        state
+            .create_log_entry(Some(t0), &doc, &params, &key)
+            .await
+            .unwrap();
+        let did = format!("did:webvh:{}:localhost%3A{port}", state.scid());
+
+        let config = UpdateDIDConfig::builder()
+            .state(state)
+            .signing_key(key)
+            .version_time(t0 + Duration::minutes(1));
+        let config = if deactivate {
+            config.deactivate(true)
+        } else {
+            config.ttl(300)
+        };
+        let state = update_did(config.build().unwrap())
+            .await
+            .unwrap()
+            .into_state();
+        let jsonl: String = state
+            .log_entries()
+            .iter()
+            .map(|e| serde_json::to_string(&e.log_entry).unwrap() + "\n")
+            .collect();
+
+        Mock::given(path("/.well-known/did.jsonl"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(jsonl))
+            .mount(&server)
+            .await;
+        (server, did)
+    }
+
+    async fn get(
+        router: &Router,
+        target: &str,
+        accept: Option<&str>,
+    ) -> (StatusCode, String, Value) {
+        let mut request = Request::get(target);
+        if let Some(accept) = accept {
+            request = request.header(header::ACCEPT, accept);
+        }
+        let response = router
+            .clone()
+            .oneshot(request.body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        let status = response.status();
+        let content_type = response
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .map(|v| v.to_str().unwrap().to_string())
+            .unwrap_or_default();
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let body = serde_json::from_slice(&body).unwrap_or(Value::Null);
+        (status, content_type, body)
+    }
+
+    #[test]
+    fn accept_negotiation() {
+        let pick = |accept: &str| {
+            let mut headers = HeaderMap::new();
+            headers.insert(header::ACCEPT, HeaderValue::from_str(accept).unwrap());
+            representation(&headers)
+        };
+        assert_eq!(
+            representation(&HeaderMap::new()),
+            Some(Representation::Result)
+        );
+        assert_eq!(pick("*/*"), Some(Representation::Result));
+        assert_eq!(
+            pick("application/ld+json; profile=\"https://w3id.org/did-resolution\""),
+            Some(Representation::Result)
+        );
+        assert_eq!(
+            pick("text/html, application/did+ld+json"),
+            Some(Representation::Document(DID_LD_JSON_CONTENT_TYPE))
+        );
+        assert_eq!(
+            pick("application/did+ld+json;q=0, application/json"),
+            Some(Representation::Result)
+        );
+        assert_eq!(pick("application/ld+json"), None);
+        assert_eq!(pick("text/html"), None);
+    }
+
+    #[test]
+    fn version_query_is_passed_through() {
+        assert_eq!(
+            requested_did(
+                "did%3Awebvh%3Ascid%3Aexample.com%253A8080",
+                Some("versionTime=2025-01-01T00%3A00%3A00%2B02%3A00&foo=bar")
+            ),
+            "did:webvh:scid:example.com%3A8080?versionTime=2025-01-01T00:00:00+02:00"
+        );
+        assert_eq!(
+            requested_did("did:webvh:scid:example.com%3A8080", None),
+            "did:webvh:scid:example.com%3A8080"
+        );
+    }
+
+    #[tokio::test]
+    async fn resolves_document_or_result() {
+        let (_server, did) = hosted_did(false).await;
+        let router = ResolverDriver::new().router();
+        let target = format!("/1.0/identifiers/{did}");
+
+        let (status, content_type, body) = get(&router, &target, None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(content_type, RESOLUTION_CONTENT_TYPE);
+        assert_eq!(body["didDocument"]["id"], did.as_str());
+        assert_eq!(body["didDocumentMetadata"]["versionNumber"], 2);
+        assert!(body["didDocumentMetadata"].get("deactivated").is_none());
+        assert_eq!(
+            body["didResolutionMetadata"]["contentType"],
+            DID_LD_JSON_CONTENT_TYPE
+        );
+
+        let (status, content_type, body) =
+            get(&router, &target, Some("application/did+ld+json")).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(content_type, DID_LD_JSON_CONTENT_TYPE);
+        assert_eq!(body["id"], did.as_str());
+        assert!(body.get("didDocumentMetadata").is_none());
+
+        let (status, _, body) = get(&router, &format!("{target}?versionNumber=1"), None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["didDocumentMetadata"]["versionNumber"], 1);
+
+        let (status, _, body) = get(&router, &format!("{target}?versionNumber=9"), None).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(body["didResolutionMetadata"]["error"], "notFound");
+
+        let (status, _, body) = get(&router, &target, Some("text/html")).await;
+        assert_eq!(status, StatusCode::NOT_ACCEPTABLE);
+        assert_eq!(
+            body["didResolutionMetadata"]["error"],
+            "representationNotSupported"
+        );
+    }
+
+    #[tokio::test]
+    async fn deactivated_did_is_gone() {
+        let (_server, did) = hosted_did(true).await;
+        let router = ResolverDriver::new().router();
+        let (status, _, body) = get(&router, &format!("/1.0/identifiers/{did}"), None).await;
+        assert_eq!(status, StatusCode::GONE);
+        assert_eq!(body["didDocumentMetadata"]["deactivated"], true);
+    }
+
+    #[tokio::test]
+    async fn errors_follow_the_driver_contract() {
+        let server = MockServer::start().await;
+        let port = server.address().port();
+        let router = ResolverDriver::new().router();
+
+        let missing = format!("/1.0/identifiers/did:webvh:QmMissing:localhost%3A{port}");
+        let (status, content_type, body) = get(&router, &missing, None).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(content_type, RESOLUTION_CONTENT_TYPE);
+        assert_eq!(body["didResolutionMetadata"]["error"], "notFound");
+        assert!(body["didDocument"].is_null());
+
+        let (status, _, body) = get(&router, "/1.0/identifiers/did:web:example.com", None).await;
+        assert_eq!(status, StatusCode::NOT_IMPLEMENTED);
+        assert_eq!(body["didResolutionMetadata"]["error"], "methodNotSupported");
+
+        let (status, _, body) = get(&router, "/1.0/identifiers/did:webvh:scid", None).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["didResolutionMetadata"]["error"], "invalidDid");
+
+        let (status, _, _) = get(&router, "/1.0/other", None).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+}
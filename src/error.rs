@@ -0,0 +1,327 @@
+/*!
+*   Machine-readable context for [`DIDWebVHError`].
+*
+*   Every error has a stable [`ErrorCode`]. Failures of a verification check
+*   while validating a log are [`DIDWebVHError::CheckFailed`] and carry a
+*   [`CheckError`]: which [`CheckKind`] failed, for which log entry, and the
+*   underlying error where there is one. Callers can branch on these instead
+*   of matching on messages.
+*
+*   ```
+*   use didwebvh_rs::{CheckKind, DIDWebVHError, ErrorCode};
+*
+*   fn http_status(e: &DIDWebVHError) -> u16 {
+*       match (e.code(), e.check_kind()) {
+*           (ErrorCode::NotFound, _) => 404,
+*           (_, Some(CheckKind::SignatureInvalid | CheckKind::UnauthorizedKey)) => 403,
+*           (ErrorCode::CheckFailed, _) => 422,
+*           _ => 500,
+*       }
+*   }
+*   ```
+*/
+
+use crate::DIDWebVHError;
+use std::{error::Error, fmt};
+
+/// Stable code of a [`DIDWebVHError`], one per variant.
+#[non_exhaustive]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ErrorCode {
+    /// [`DIDWebVHError::CheckFailed`]
+    CheckFailed,
+    /// [`DIDWebVHError::DeactivatedError`]
+    Deactivated,
+    /// [`DIDWebVHError::DIDError`]
+    Did,
+    /// [`DIDWebVHError::InvalidMethodIdentifier`]
+    InvalidMethodIdentifier,
+    /// [`DIDWebVHError::LogEntryError`]
+    LogEntry,
+    /// [`DIDWebVHError::NetworkError`]
+    Network,
+    /// [`DIDWebVHError::NotFound`]
+    NotFound,
+    /// [`DIDWebVHError::ResponseTooLarge`]
+    ResponseTooLarge,
+    /// [`DIDWebVHError::NotImplemented`]
+    NotImplemented,
+    /// [`DIDWebVHError::ParametersError`]
+    Parameters,
+    /// [`DIDWebVHError::SCIDError`]
+    Scid,
+    /// [`DIDWebVHError::SecretsError`]
+    Secrets,
+    /// [`DIDWebVHError::ServerError`]
+    Server,
+    /// [`DIDWebVHError::SpecError`]
+    Spec,
+    /// [`DIDWebVHError::ScenarioError`]
+    Scenario,
+    /// [`DIDWebVHError::StoreError`]
+    Store,
+    /// [`DIDWebVHError::UnsupportedMethod`]
+    UnsupportedMethod,
+    /// [`DIDWebVHError::ValidationError`]
+    Validation,
+    /// [`DIDWebVHError::WitnessProofError`]
+    WitnessProof,
+}
+
+impl ErrorCode {
+    /// The code as used in logs and APIs, e.g. `check_failed`.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::CheckFailed => "check_failed",
+            Self::Deactivated => "deactivated",
+            Self::Did => "did",
+            Self::InvalidMethodIdentifier => "invalid_method_identifier",
+            Self::LogEntry => "log_entry",
+            Self::Network => "network",
+            Self::NotFound => "not_found",
+            Self::ResponseTooLarge => "response_too_large",
+            Self::NotImplemented => "not_implemented",
+            Self::Parameters => "parameters",
+            Self::Scid => "scid",
+            Self::Secrets => "secrets",
+            Self::Server => "server",
+            Self::Spec => "spec",
+            Self::Scenario => "scenario",
+            Self::Store => "store",
+            Self::UnsupportedMethod => "unsupported_method",
+            Self::Validation => "validation",
+            Self::WitnessProof => "witness_proof",
+        }
+    }
+}
+
+impl fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// A verification check performed on a log entry.
+#[non_exhaustive]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum CheckKind {
+    /// The entry has no proof.
+    ProofMissing,
+    /// The proof's `proofPurpose` isn't `assertionMethod`.
+    ProofPurpose,
+    /// The proof uses a cryptosuite the spec doesn't allow.
+    Cryptosuite,
+    /// The parameters are invalid or an invalid change of the previous ones.
+    Parameters,
+    /// The proof is signed by a key that isn't an authorized update key.
+    UnauthorizedKey,
+    /// The proof's signature doesn't verify.
+    SignatureInvalid,
+    /// The versionId number doesn't follow the previous entry's.
+    VersionNumber,
+    /// The versionId entry hash doesn't match the entry.
+    HashMismatch,
+    /// The versionTime is in the future or not after the previous entry's.
+    VersionTime,
+    /// The SCID doesn't match the first entry or the DID document `id`.
+    ScidMismatch,
+    /// The DID moved without being portable or without its previous DID in
+    /// `alsoKnownAs`.
+    Portability,
+    /// A witness proof doesn't verify.
+    WitnessProofInvalid,
+    /// Too few witness proofs verified to meet the threshold.
+    WitnessThreshold,
+}
+
+impl CheckKind {
+    /// The kind as used in logs and APIs, e.g. `signature_invalid`.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::ProofMissing => "proof_missing",
+            Self::ProofPurpose => "proof_purpose",
+            Self::Cryptosuite => "cryptosuite",
+            Self::Parameters => "parameters",
+            Self::UnauthorizedKey => "unauthorized_key",
+            Self::SignatureInvalid => "signature_invalid",
+            Self::VersionNumber => "version_number",
+            Self::HashMismatch => "hash_mismatch",
+            Self::VersionTime => "version_time",
+            Self::ScidMismatch => "scid_mismatch",
+            Self::Portability => "portability",
+            Self::WitnessProofInvalid => "witness_proof_invalid",
+            Self::WitnessThreshold => "witness_threshold",
+        }
+    }
+}
+
+impl fmt::Display for CheckKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// A failed verification check, carried by [`DIDWebVHError::CheckFailed`].
+#[derive(Debug)]
+pub struct CheckError {
+    /// Which check failed.
+    pub kind: CheckKind,
+    /// Version number of the failing log entry, when known.
+    pub version_number: Option<u32>,
+    /// versionId of the failing log entry, when known.
+    pub version_id: Option<String>,
+    /// Human-readable description.
+    pub message: String,
+    source: Option<Box<dyn Error + Send + Sync>>,
+}
+
+impl CheckError {
+    /// A failure of `kind` without log entry context.
+    pub fn new(kind: CheckKind, message: impl fmt::Display) -> Self {
+        Self {
+            kind,
+            version_number: None,
+            version_id: None,
+            message: message.to_string(),
+            source: None,
+        }
+    }
+
+    /// Sets the failing log entry, taking the version number from the
+    /// `N-hash` versionId.
+    pub fn with_version_id(mut self, version_id: &str) -> Self {
+        self.version_number = version_id
+            .split_once('-')
+            .and_then(|(number, _)| number.parse().ok());
+        self.version_id = Some(version_id.to_string());
+        self
+    }
+
+    /// Sets the underlying error.
+    pub fn with_source(mut self, source: impl Into<Box<dyn Error + Send + Sync>>) -> Self {
+        self.source = Some(source.into());
+        self
+    }
+}
+
+impl fmt::Display for CheckError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.version_id {
+            Some(version_id) => write!(f, "[{version_id}] {}: {}", self.kind, self.message),
+            None => write!(f, "{}: {}", self.kind, self.message),
+        }
+    }
+}
+
+impl Error for CheckError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        self.source.as_deref().map(|e| e as &(dyn Error + 'static))
+    }
+}
+
+impl From<CheckError> for DIDWebVHError {
+    fn from(e: CheckError) -> Self {
+        Self::CheckFailed(Box::new(e))
+    }
+}
+
+impl DIDWebVHError {
+    /// The stable code of this error.
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            Self::CheckFailed(_) => ErrorCode::CheckFailed,
+            Self::DeactivatedError(_) => ErrorCode::Deactivated,
+            Self::DIDError(_) => ErrorCode::Did,
+            Self::InvalidMethodIdentifier(_) => ErrorCode::InvalidMethodIdentifier,
+            Self::LogEntryError(_) => ErrorCode::LogEntry,
+            Self::NetworkError { .. } => ErrorCode::Network,
+            Self::NotFound(_) => ErrorCode::NotFound,
+            Self::ResponseTooLarge { .. } => ErrorCode::ResponseTooLarge,
+            Self::NotImplemented(_) => ErrorCode::NotImplemented,
+            Self::ParametersError(_) => ErrorCode::Parameters,
+            Self::SCIDError(_) => ErrorCode::Scid,
+            Self::SecretsError(_) => ErrorCode::Secrets,
+            Self::ServerError(_) => ErrorCode::Server,
+            Self::SpecError(_) => ErrorCode::Spec,
+            Self::ScenarioError(_) => ErrorCode::Scenario,
+            Self::StoreError(_) => ErrorCode::Store,
+            Self::UnsupportedMethod(_) => ErrorCode::UnsupportedMethod,
+            Self::ValidationError(_) => ErrorCode::Validation,
+            Self::WitnessProofError(_) => ErrorCode::WitnessProof,
+        }
+    }
+
+    /// The failed check, if this error or one of its sources is one.
+    pub fn check(&self) -> Option<&CheckError> {
+        let mut next: Option<&(dyn Error + 'static)> = Some(self);
+        while let Some(e) = next {
+            // `CheckFailed` exposes its boxed `CheckError` as the source
+            if let Some(check) = e
+                .downcast_ref::<CheckError>()
+                .or_else(|| e.downcast_ref::<Box<CheckError>>().map(AsRef::as_ref))
+            {
+                return Some(check);
+            }
+            next = e.source();
+        }
+        None
+    }
+
+    /// Which check failed, see [`check`](Self::check).
+    pub fn check_kind(&self) -> Option<CheckKind> {
+        self.check().map(|c| c.kind)
+    }
+
+    /// Version number of the log entry that failed a check.
+    pub fn version_number(&self) -> Option<u32> {
+        self.check().and_then(|c| c.version_number)
+    }
+
+    /// versionId of the log entry that failed a check.
+    pub fn version_id(&self) -> Option<&str> {
+        self.check().and_then(|c| c.version_id.as_deref())
+    }
+
+    /// Adds the failing log entry to a failed check that doesn't name one.
+    pub(crate) fn in_version(self, version_id: &str) -> Self {
+        match self {
+            Self::CheckFailed(check) if check.version_id.is_none() => {
+                (*check).with_version_id(version_id).into()
+            }
+            e => e,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CheckError, CheckKind, ErrorCode};
+    use crate::DIDWebVHError;
+    use std::{error::Error, sync::Arc};
+
+    #[test]
+    fn check_context_survives_wrapping() {
+        let inner: DIDWebVHError = CheckError::new(CheckKind::HashMismatch, "bad hash").into();
+        let inner = inner.in_version("2-QmHash");
+        assert_eq!(inner.code(), ErrorCode::CheckFailed);
+        assert_eq!(inner.version_number(), Some(2));
+        assert_eq!(
+            inner.to_string(),
+            "CheckFailed: [2-QmHash] hash_mismatch: bad hash"
+        );
+
+        let outer: DIDWebVHError = CheckError::new(CheckKind::HashMismatch, "truncated")
+            .with_version_id("2-QmHash")
+            .with_source(Arc::new(inner))
+            .into();
+        assert_eq!(outer.check_kind(), Some(CheckKind::HashMismatch));
+        assert_eq!(outer.version_id(), Some("2-QmHash"));
+        let source = outer.check().unwrap().source().unwrap();
+        assert!(source.to_string().ends_with("bad hash"));
+
+        let plain = DIDWebVHError::NotFound("x".to_string());
+        assert_eq!(plain.code().as_str(), "not_found");
+        assert_eq!(plain.check_kind(), None);
+        assert_eq!(plain.version_number(), None);
+    }
+}
@@ -0,0 +1,624 @@
+/*!
+*   Pre-rotation key chain planning.
+*
+*   With pre-rotation active, every log entry commits the hashes of the keys
+*   allowed to sign the *next* entry (`nextKeyHashes`). Losing track of which
+*   key was committed for which version permanently locks the controller out
+*   of the DID, so [`KeyChainPlanner`] generates the future keys up front,
+*   assigns one to each upcoming versionNumber, and tells the caller exactly
+*   which secret, `updateKeys` and `nextKeyHashes` to use for the next
+*   [`create_did`](crate::create::create_did) /
+*   [`update_did`](crate::update::update_did).
+*
+*   The planner can be exported as an encrypted escrow file (`encryption`
+*   feature) so the committed keys can be stored offline and recovered.
+*
+*   # Example
+*
+*   ```ignore
+*   use didwebvh_rs::key_chain::KeyChainPlanner;
+*
+*   let mut planner = KeyChainPlanner::generate(KeyType::Ed25519, 5)?;
+*
+*   // Genesis: sign with key #1, commit the hash of key #2
+*   let step = planner.next_step(None)?;
+*   let config = CreateDIDConfig::builder()
+*       .address("https://example.com/")
+*       .authorization_key(step.signing_key.clone())
+*       .did_document(doc)
+*       .parameters(step.parameters(Parameters::default()))
+*       .build()?;
+*
+*   // Later updates: the planner picks the committed key for the next version
+*   let step = planner.next_step(Some(&state))?;
+*   let config = step.apply(UpdateDIDConfig::builder().state(state)).build()?;
+*   let result = update_did(config).await?;
+*   planner.record_published(result.state())?;
+*   ```
+*/
+
+use crate::{
+    DIDWebVHError, DIDWebVHState, Multibase, Signer, did_key::generate_did_key,
+    log_entry::LogEntryMethods, parameters::Parameters, update::UpdateDIDConfigBuilder,
+};
+use affinidi_secrets_resolver::secrets::{KeyType, Secret};
+#[cfg(feature = "encryption")]
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+/// `contentType` of the sealed escrow envelope.
+#[cfg(feature = "encryption")]
+const ESCROW_CONTENT_TYPE: &str = "didwebvh-key-chain";
+
+/// A pre-generated key, bound to the log entry version it will authorise.
+#[derive(Clone, Debug)]
+pub struct ChainKey {
+    /// versionNumber of the log entry this key signs (and becomes the `updateKeys` of).
+    pub version_number: u32,
+    /// The key itself. `id` is the `did:key:{mb}#{mb}` verification method.
+    pub secret: Secret,
+}
+
+impl ChainKey {
+    /// Public key multibase, as it appears in `updateKeys`.
+    pub fn public_key(&self) -> Result<Multibase, DIDWebVHError> {
+        self.secret
+            .get_public_keymultibase()
+            .map(Multibase::new)
+            .map_err(|e| {
+                DIDWebVHError::SecretsError(format!(
+                    "Couldn't get public key for version {}: {e}",
+                    self.version_number
+                ))
+            })
+    }
+
+    /// Hash of the public key multibase, as it appears in `nextKeyHashes`.
+    pub fn key_hash(&self) -> Result<Multibase, DIDWebVHError> {
+        self.secret
+            .get_public_keymultibase_hash()
+            .map(Multibase::new)
+            .map_err(|e| {
+                DIDWebVHError::SecretsError(format!(
+                    "Couldn't hash public key for version {}: {e}",
+                    self.version_number
+                ))
+            })
+    }
+}
+
+/// Everything needed to create the next log entry under the planned key chain.
+///
+/// Returned by [`KeyChainPlanner::next_step`].
+#[derive(Clone, Debug)]
+pub struct NextKeyStep {
+    /// versionNumber of the log entry about to be created.
+    pub version_number: u32,
+    /// Key to sign the entry with.
+    pub signing_key: Secret,
+    /// `updateKeys` to publish in the entry (the signing key's public key).
+    pub update_keys: Vec<Multibase>,
+    /// `nextKeyHashes` to publish in the entry (the hash of the following version's key).
+    pub next_key_hashes: Vec<Multibase>,
+}
+
+impl NextKeyStep {
+    /// Overlay this step's `update_keys` and `next_key_hashes` onto `base`.
+    ///
+    /// Use for the genesis entry, where the parameters go into
+    /// [`CreateDIDConfig`](crate::create::CreateDIDConfig).
+    pub fn parameters(&self, mut base: Parameters) -> Parameters {
+        base.update_keys = Some(Arc::new(self.update_keys.clone()));
+        base.next_key_hashes = Some(Arc::new(self.next_key_hashes.clone()));
+        base
+    }
+
+    /// Set the signing key, `update_keys` and `next_key_hashes` on an update builder.
+    pub fn apply<W: Signer>(
+        self,
+        builder: UpdateDIDConfigBuilder<Secret, W>,
+    ) -> UpdateDIDConfigBuilder<Secret, W> {
+        builder
+            .signing_key(self.signing_key)
+            .update_keys(self.update_keys)
+            .next_key_hashes(self.next_key_hashes)
+    }
+}
+
+/// Generates and tracks pre-rotation keys for upcoming log entry versions.
+///
+/// Key `n` signs version `n` and is published as its `updateKeys`; version
+/// `n` commits the hash of key `n + 1` in `nextKeyHashes`. The planner
+/// refuses to hand out a key whose hash doesn't match what the DID's log
+/// actually committed, so an out-of-sync escrow is caught before an update
+/// is signed rather than after it fails to resolve.
+///
+/// Holds private keys, so it deliberately doesn't implement `Serialize`:
+/// the only way out is the sealed escrow.
+#[derive(Clone, Debug)]
+pub struct KeyChainPlanner {
+    /// Key type used when generating further keys.
+    key_type: KeyType,
+    /// SCID of the DID this chain is bound to, once known.
+    scid: Option<String>,
+    /// Highest versionNumber known to be published. `0` before genesis.
+    published_version: u32,
+    /// Planned keys, ordered by strictly increasing `version_number`.
+    keys: Vec<ChainKey>,
+}
+
+impl KeyChainPlanner {
+    /// Plan `depth` keys for a new DID, covering versions `1..=depth`.
+    pub fn generate(key_type: KeyType, depth: u32) -> Result<Self, DIDWebVHError> {
+        Self::generate_from(key_type, 1, depth)
+    }
+
+    /// Plan `depth` keys starting at `first_version`.
+    ///
+    /// Use this to bring an existing DID under pre-rotation: publish an
+    /// update (signed with the current update key) whose `nextKeyHashes` is
+    /// [`commitment_for(first_version - 1)`](Self::commitment_for), then
+    /// drive subsequent versions from the planner.
+    pub fn generate_from(
+        key_type: KeyType,
+        first_version: u32,
+        depth: u32,
+    ) -> Result<Self, DIDWebVHError> {
+        if first_version == 0 {
+            return Err(DIDWebVHError::SecretsError(
+                "Key chain versions start at 1".to_string(),
+            ));
+        }
+        let mut planner = Self {
+            key_type,
+            scid: None,
+            published_version: first_version - 1,
+            keys: Vec::new(),
+        };
+        planner.extend(depth)?;
+        Ok(planner)
+    }
+
+    /// Generate `count` more keys after the last planned version.
+    ///
+    /// Re-export the escrow afterwards: the new keys exist nowhere else.
+    pub fn extend(&mut self, count: u32) -> Result<(), DIDWebVHError> {
+        let last = self
+            .keys
+            .last()
+            .map_or(self.published_version, |k| k.version_number);
+        if last.checked_add(count).is_none() {
+            return Err(DIDWebVHError::SecretsError(format!(
+                "Key chain can't plan {count} more keys after versionNumber {last}"
+            )));
+        }
+        for version_number in (1..=count).map(|i| last + i) {
+            let (_, secret) = generate_did_key(self.key_type)?;
+            self.keys.push(ChainKey {
+                version_number,
+                secret,
+            });
+        }
+        Ok(())
+    }
+
+    /// Key type used for generated keys.
+    pub fn key_type(&self) -> KeyType {
+        self.key_type
+    }
+
+    /// SCID of the DID this chain is bound to, if it has been recorded.
+    pub fn scid(&self) -> Option<&str> {
+        self.scid.as_deref()
+    }
+
+    /// Highest versionNumber recorded as published (`0` before genesis).
+    pub fn published_version(&self) -> u32 {
+        self.published_version
+    }
+
+    /// All planned keys, including ones already used.
+    pub fn keys(&self) -> &[ChainKey] {
+        &self.keys
+    }
+
+    /// The key planned for `version_number`, if any.
+    pub fn key_for_version(&self, version_number: u32) -> Option<&ChainKey> {
+        self.keys
+            .iter()
+            .find(|k| k.version_number == version_number)
+    }
+
+    /// Number of further log entries the planner can authorise before it
+    /// needs [`extend`](Self::extend)ing.
+    ///
+    /// Each entry needs its own key plus the following key's hash, so the
+    /// last planned key can only be committed, never used.
+    pub fn remaining(&self) -> u32 {
+        let planned = self
+            .keys
+            .iter()
+            .filter(|k| k.version_number > self.published_version)
+            .count();
+        u32::try_from(planned.saturating_sub(1)).unwrap_or(u32::MAX)
+    }
+
+    /// The `nextKeyHashes` value that log entry `version_number` must publish.
+    pub fn commitment_for(&self, version_number: u32) -> Result<Vec<Multibase>, DIDWebVHError> {
+        let next = self.key_for_version(version_number + 1).ok_or_else(|| {
+            DIDWebVHError::SecretsError(format!(
+                "Key chain has no key planned for version {}; call extend() and re-export the escrow",
+                version_number + 1
+            ))
+        })?;
+        Ok(vec![next.key_hash()?])
+    }
+
+    /// Work out the key and parameters for the next log entry.
+    ///
+    /// `state` is the current (validated) DID state, or `None` for genesis.
+    /// When the last entry committed `nextKeyHashes`, the planned key's hash
+    /// must be among them — otherwise the planner and the published log have
+    /// diverged and an error is returned instead of a key that would be
+    /// rejected.
+    pub fn next_step(&self, state: Option<&DIDWebVHState>) -> Result<NextKeyStep, DIDWebVHError> {
+        let last_entry = state.and_then(|s| s.log_entries().last());
+
+        if let (Some(scid), Some(state)) = (&self.scid, state)
+            && last_entry.is_some()
+            && state.scid() != scid
+        {
+            return Err(DIDWebVHError::SecretsError(format!(
+                "Key chain is bound to SCID {scid}, but the state is for SCID {}",
+                state.scid()
+            )));
+        }
+
+        let version_number = last_entry.map_or(1, |e| e.version_number + 1);
+        let key = self.key_for_version(version_number).ok_or_else(|| {
+            DIDWebVHError::SecretsError(format!(
+                "Key chain has no key planned for version {version_number}"
+            ))
+        })?;
+
+        if let Some(last_entry) = last_entry {
+            let params = &last_entry.validated_parameters;
+            if !params.pre_rotation_active {
+                return Err(DIDWebVHError::SecretsError(format!(
+                    "Pre-rotation is not active at version {}; publish nextKeyHashes from \
+                     commitment_for({}) before using the key chain",
+                    last_entry.version_number, last_entry.version_number
+                )));
+            }
+            let hash = key.key_hash()?;
+            let committed = params
+                .next_key_hashes
+                .as_ref()
+                .is_some_and(|hashes| hashes.contains(&hash));
+            if !committed {
+                return Err(DIDWebVHError::SecretsError(format!(
+                    "Planned key for version {version_number} was not committed in the \
+                     nextKeyHashes of version {}; the key chain is out of sync with the log",
+                    last_entry.version_number
+                )));
+            }
+        }
+
+        Ok(NextKeyStep {
+            version_number,
+            signing_key: key.secret.clone(),
+            update_keys: vec![key.public_key()?],
+            next_key_hashes: self.commitment_for(version_number)?,
+        })
+    }
+
+    /// Record that `state` has been published, binding the planner to its SCID.
+    ///
+    /// Checks that every entry in the planned range that revealed keys
+    /// revealed the planned one, so a divergence is caught at the version
+    /// where it happened.
+    pub fn record_published(&mut self, state: &DIDWebVHState) -> Result<(), DIDWebVHError> {
+        let Some(last_entry) = state.log_entries().last() else {
+            return Err(DIDWebVHError::SecretsError(
+                "Can't record an empty DID state".to_string(),
+            ));
+        };
+        if let Some(scid) = &self.scid
+            && scid != state.scid()
+        {
+            return Err(DIDWebVHError::SecretsError(format!(
+                "Key chain is bound to SCID {scid}, but the state is for SCID {}",
+                state.scid()
+            )));
+        }
+
+        for entry in state.log_entries() {
+            if entry.version_number <= self.published_version {
+                continue;
+            }
+            let Some(key) = self.key_for_version(entry.version_number) else {
+                continue;
+            };
+            let Some(update_keys) = &entry.log_entry.get_parameters().update_keys else {
+                continue;
+            };
+            let public_key = key.public_key()?;
+            if !update_keys.is_empty() && !update_keys.contains(&public_key) {
+                return Err(DIDWebVHError::SecretsError(format!(
+                    "Version {} published updateKeys that don't include the planned key",
+                    entry.version_number
+                )));
+            }
+        }
+
+        self.scid = Some(state.scid().to_string());
+        self.published_version = last_entry.version_number;
+        Ok(())
+    }
+}
+
+/// Plaintext form of a [`KeyChainPlanner`], only ever written inside the
+/// sealed escrow envelope.
+#[cfg(feature = "encryption")]
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct KeyChainShadow {
+    key_type: KeyType,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    scid: Option<String>,
+    published_version: u32,
+    keys: Vec<ChainKeyShadow>,
+}
+
+#[cfg(feature = "encryption")]
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ChainKeyShadow {
+    version_number: u32,
+    secret: Secret,
+}
+
+#[cfg(feature = "encryption")]
+impl From<&KeyChainPlanner> for KeyChainShadow {
+    fn from(planner: &KeyChainPlanner) -> Self {
+        Self {
+            key_type: planner.key_type,
+            scid: planner.scid.clone(),
+            published_version: planner.published_version,
+            keys: planner
+                .keys
+                .iter()
+                .map(|k| ChainKeyShadow {
+                    version_number: k.version_number,
+                    secret: k.secret.clone(),
+                })
+                .collect(),
+        }
+    }
+}
+
+#[cfg(feature = "encryption")]
+impl From<KeyChainShadow> for KeyChainPlanner {
+    fn from(shadow: KeyChainShadow) -> Self {
+        Self {
+            key_type: shadow.key_type,
+            scid: shadow.scid,
+            published_version: shadow.published_version,
+            keys: shadow
+                .keys
+                .into_iter()
+                .map(|k| ChainKey {
+                    version_number: k.version_number,
+                    secret: k.secret,
+                })
+                .collect(),
+        }
+    }
+}
+
+#[cfg(feature = "encryption")]
+impl KeyChainPlanner {
+    /// Serialise and encrypt the planner as a JSON escrow document.
+    pub fn export_escrow(&self, passphrase: &str) -> Result<String, DIDWebVHError> {
+        self.export_escrow_with(passphrase, crate::sealed::KdfParams::default())
+    }
+
+    pub(crate) fn export_escrow_with(
+        &self,
+        passphrase: &str,
+        kdf: crate::sealed::KdfParams,
+    ) -> Result<String, DIDWebVHError> {
+        let plaintext =
+            zeroize::Zeroizing::new(serde_json::to_vec(&KeyChainShadow::from(self)).map_err(
+                |e| DIDWebVHError::SecretsError(format!("Couldn't serialize key chain: {e}")),
+            )?);
+        let envelope =
+            crate::sealed::SealedEnvelope::seal(ESCROW_CONTENT_TYPE, &plaintext, passphrase, kdf)?;
+        serde_json::to_string_pretty(&envelope).map_err(|e| {
+            DIDWebVHError::SecretsError(format!("Couldn't serialize key chain escrow: {e}"))
+        })
+    }
+
+    /// Decrypt and load a planner previously produced by [`export_escrow`](Self::export_escrow).
+    pub fn import_escrow(escrow: &str, passphrase: &str) -> Result<Self, DIDWebVHError> {
+        let envelope: crate::sealed::SealedEnvelope =
+            serde_json::from_str(escrow).map_err(|e| {
+                DIDWebVHError::SecretsError(format!("Couldn't parse key chain escrow: {e}"))
+            })?;
+        let plaintext = envelope.open(ESCROW_CONTENT_TYPE, passphrase)?;
+        let shadow = serde_json::from_slice::<KeyChainShadow>(&plaintext).map_err(|e| {
+            DIDWebVHError::SecretsError(format!("Couldn't deserialize key chain: {e}"))
+        })?;
+        // Keys are looked up and extended by versionNumber, in order
+        if shadow
+            .keys
+            .windows(2)
+            .any(|pair| pair[0].version_number >= pair[1].version_number)
+        {
+            return Err(DIDWebVHError::SecretsError(
+                "Key chain escrow versionNumbers aren't strictly increasing".to_string(),
+            ));
+        }
+        Ok(Self::from(shadow))
+    }
+
+    /// Write the encrypted escrow to `path`.
+    ///
+    /// Like [`KeyStore::save`](crate::keystore::KeyStore::save), the file is
+    /// replaced atomically and left with mode `0600` on Unix.
+    pub fn save_escrow(&self, path: &str, passphrase: &str) -> Result<(), DIDWebVHError> {
+        let escrow = self.export_escrow(passphrase)?;
+        crate::write_private(std::path::Path::new(path), escrow.as_bytes()).map_err(|e| {
+            DIDWebVHError::SecretsError(format!("Couldn't write key chain escrow ({path}): {e}"))
+        })
+    }
+
+    /// Read and decrypt an escrow file written by [`save_escrow`](Self::save_escrow).
+    pub fn load_escrow(path: &str, passphrase: &str) -> Result<Self, DIDWebVHError> {
+        let escrow = std::fs::read_to_string(path).map_err(|e| {
+            DIDWebVHError::SecretsError(format!("Couldn't read key chain escrow ({path}): {e}"))
+        })?;
+        Self::import_escrow(&escrow, passphrase)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::KeyChainPlanner;
+    use crate::{
+        DIDWebVHError, DIDWebVHState,
+        create::{CreateDIDConfig, create_did},
+        log_entry_state::LogEntryValidationStatus,
+        parameters::Parameters,
+        test_utils::did_doc_with_key,
+        update::{UpdateDIDConfig, update_did},
+    };
+    use affinidi_secrets_resolver::secrets::KeyType;
+    use chrono::{Duration, Utc};
+
+    /// Create a DID from the planner's genesis step and return a validated state.
+    async fn genesis(planner: &mut KeyChainPlanner) -> DIDWebVHState {
+        let step = planner.next_step(None).unwrap();
+        assert_eq!(step.version_number, 1);
+        let doc = did_doc_with_key("did:webvh:{SCID}:example.com", &step.signing_key);
+        let result = create_did(
+            CreateDIDConfig::builder()
+                .address("https://example.com/")
+                .authorization_key(step.signing_key.clone())
+                .did_document(doc)
+                .parameters(step.parameters(Parameters::default()))
+                .version_time(Utc::now().fixed_offset() - Duration::hours(1))
+                .build()
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+        let mut state = DIDWebVHState::from_log_entries(vec![result.log_entry().clone()]);
+        state.validate().unwrap().assert_complete().unwrap();
+        planner.record_published(&state).unwrap();
+        state
+    }
+
+    #[tokio::test]
+    async fn planned_chain_rotates_and_validates() {
+        let mut planner = KeyChainPlanner::generate(KeyType::Ed25519, 4).unwrap();
+        assert_eq!(planner.remaining(), 3);
+
+        let mut state = genesis(&mut planner).await;
+        assert_eq!(planner.published_version(), 1);
+        assert_eq!(planner.scid(), Some(state.scid()));
+
+        for minutes in 1..=2 {
+            let step = planner.next_step(Some(&state)).unwrap();
+            let config = step
+                .apply(UpdateDIDConfig::builder().state(state))
+                .version_time(
+                    Utc::now().fixed_offset() - Duration::hours(1) + Duration::minutes(minutes),
+                )
+                .build()
+                .unwrap();
+            state = update_did(config).await.unwrap().into_state();
+            planner.record_published(&state).unwrap();
+        }
+
+        assert_eq!(planner.published_version(), 3);
+        assert_eq!(planner.remaining(), 0);
+
+        // The full chain must survive a fresh validation pass.
+        for entry in state.log_entries_mut() {
+            entry.validation_status = LogEntryValidationStatus::NotValidated;
+        }
+        state.validate().unwrap().assert_complete().unwrap();
+
+        // Version 4 is committed but there is no key 5 to commit next.
+        assert!(matches!(
+            planner.next_step(Some(&state)),
+            Err(DIDWebVHError::SecretsError(_))
+        ));
+        planner.extend(1).unwrap();
+        assert_eq!(planner.next_step(Some(&state)).unwrap().version_number, 4);
+    }
+
+    #[tokio::test]
+    async fn out_of_sync_planner_is_rejected() {
+        let mut planner = KeyChainPlanner::generate(KeyType::Ed25519, 3).unwrap();
+        let state = genesis(&mut planner).await;
+
+        // A different planner never committed its key for version 2.
+        let other = KeyChainPlanner::generate(KeyType::Ed25519, 3).unwrap();
+        let err = other.next_step(Some(&state)).unwrap_err();
+        assert!(err.to_string().contains("out of sync"), "{err}");
+    }
+
+    #[test]
+    fn generate_from_zero_rejected() {
+        assert!(KeyChainPlanner::generate_from(KeyType::Ed25519, 0, 2).is_err());
+    }
+
+    #[test]
+    fn commitment_for_last_planned_version_fails() {
+        let planner = KeyChainPlanner::generate_from(KeyType::Ed25519, 5, 2).unwrap();
+        assert_eq!(planner.keys()[0].version_number, 5);
+        assert!(planner.commitment_for(5).is_ok());
+        assert!(planner.commitment_for(6).is_err());
+    }
+
+    #[cfg(feature = "encryption")]
+    #[test]
+    fn escrow_round_trip() {
+        let planner = KeyChainPlanner::generate(KeyType::Ed25519, 3).unwrap();
+        let escrow = planner
+            .export_escrow_with("correct horse", crate::sealed::TEST_KDF)
+            .unwrap();
+        assert!(!escrow.contains(&planner.keys()[0].secret.get_private_keymultibase().unwrap()));
+
+        let restored = KeyChainPlanner::import_escrow(&escrow, "correct horse").unwrap();
+        assert_eq!(restored.keys().len(), 3);
+        for (a, b) in planner.keys().iter().zip(restored.keys()) {
+            assert_eq!(a.version_number, b.version_number);
+            assert_eq!(a.key_hash().unwrap(), b.key_hash().unwrap());
+        }
+
+        assert!(KeyChainPlanner::import_escrow(&escrow, "wrong horse").is_err());
+
+        let mut shuffled = planner;
+        shuffled.keys.swap(0, 2);
+        let escrow = shuffled
+            .export_escrow_with("correct horse", crate::sealed::TEST_KDF)
+            .unwrap();
+        assert!(KeyChainPlanner::import_escrow(&escrow, "correct horse").is_err());
+    }
+
+    #[test]
+    fn extend_stops_at_the_last_version_number() {
+        let mut planner =
+            KeyChainPlanner::generate_from(KeyType::Ed25519, u32::MAX - 1, 1).unwrap();
+        assert_eq!(planner.keys().last().unwrap().version_number, u32::MAX - 1);
+        assert!(planner.extend(2).is_err());
+        assert!(planner.extend(1).is_ok());
+        assert!(planner.extend(1).is_err());
+        assert!(planner.extend(0).is_ok());
+        assert_eq!(planner.keys().last().unwrap().version_number, u32::MAX);
+    }
+}
@@ -0,0 +1,605 @@
+/*!
+*   Keystore for the secrets that control a DID.
+*
+*   A [`KeyStore`] holds every private key a controller needs: authorization
+*   keys (`updateKeys`), pre-rotation keys committed in `nextKeyHashes`,
+*   verification method keys from the DID Document, and witness signing keys.
+*   Each [`KeyStoreEntry`] is labelled with the DID it belongs to, its
+*   [`KeyRole`] and the versionNumber it was introduced at.
+*
+*   Keys are looked up by verification method ([`KeyStore::signer`]) or, for
+*   the next log entry, by what the published log currently authorises
+*   ([`KeyStore::authorization_signer`]). Both return a [`Secret`], which
+*   implements [`Signer`](crate::Signer) and can be handed straight to
+*   [`UpdateDIDConfig`](crate::update::UpdateDIDConfig).
+*
+*   With the `encryption` feature the keystore is persisted as a
+*   passphrase-encrypted file (Argon2id + XChaCha20-Poly1305). There is
+*   deliberately no plaintext export.
+*
+*   # Example
+*
+*   ```ignore
+*   use didwebvh_rs::keystore::KeyStore;
+*
+*   let store = KeyStore::load("example-keystore.json", &passphrase)?;
+*   let config = UpdateDIDConfig::builder()
+*       .state(state.clone())
+*       .signing_key(store.authorization_signer(&state)?)
+*       .witness_secrets(store.witness_secrets(state.did()))
+*       .document(new_doc)
+*       .build()?;
+*   ```
+*/
+
+use crate::{DIDWebVHError, DIDWebVHState, Multibase};
+use affinidi_secrets_resolver::secrets::Secret;
+use ahash::HashMap;
+use serde::{Deserialize, Serialize};
+
+/// `contentType` of the sealed keystore envelope.
+#[cfg(feature = "encryption")]
+const KEYSTORE_CONTENT_TYPE: &str = "didwebvh-keystore";
+
+/// What a stored key is used for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum KeyRole {
+    /// Published in `updateKeys`; signs log entries.
+    Authorization,
+    /// Committed in `nextKeyHashes`; becomes an authorization key once revealed.
+    NextKey,
+    /// Backs a verification method in the DID Document.
+    VerificationMethod,
+    /// Signs witness proofs for the DID.
+    Witness,
+}
+
+/// A secret together with the labels used to find it again.
+#[derive(Clone, Debug)]
+pub struct KeyStoreEntry {
+    /// DID the key belongs to (for witness keys, the DID being witnessed).
+    pub did: String,
+    /// What the key is used for.
+    pub role: KeyRole,
+    /// versionNumber of the log entry that introduced the key, if known.
+    pub version_number: Option<u32>,
+    /// Lookup id: the verification method id for [`KeyRole::VerificationMethod`],
+    /// the witness DID for [`KeyRole::Witness`], and the `did:key` verification
+    /// method for authorization and next keys.
+    pub id: String,
+    /// The key itself.
+    pub secret: Secret,
+}
+
+impl KeyStoreEntry {
+    /// Create an entry. `id` is the lookup id described on [`KeyStoreEntry::id`].
+    pub fn new(
+        did: impl Into<String>,
+        role: KeyRole,
+        version_number: Option<u32>,
+        id: impl Into<String>,
+        secret: Secret,
+    ) -> Self {
+        Self {
+            did: did.into(),
+            role,
+            version_number,
+            id: id.into(),
+            secret,
+        }
+    }
+
+    /// Public key multibase, as it appears in `updateKeys`.
+    pub fn public_key(&self) -> Result<Multibase, DIDWebVHError> {
+        self.secret
+            .get_public_keymultibase()
+            .map(Multibase::new)
+            .map_err(|e| {
+                DIDWebVHError::SecretsError(format!("Couldn't get public key for {}: {e}", self.id))
+            })
+    }
+
+    /// Hash of the public key multibase, as it appears in `nextKeyHashes`.
+    pub fn key_hash(&self) -> Result<Multibase, DIDWebVHError> {
+        self.secret
+            .get_public_keymultibase_hash()
+            .map(Multibase::new)
+            .map_err(|e| {
+                DIDWebVHError::SecretsError(format!(
+                    "Couldn't hash public key for {}: {e}",
+                    self.id
+                ))
+            })
+    }
+}
+
+/// Collection of labelled DID secrets.
+///
+/// Deliberately doesn't implement `Serialize`: the only way out is the
+/// encrypted export.
+#[derive(Clone, Debug, Default)]
+pub struct KeyStore {
+    entries: Vec<KeyStoreEntry>,
+}
+
+impl KeyStore {
+    /// Create an empty keystore.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add an entry, replacing any existing entry with the same DID, role and id.
+    pub fn insert(&mut self, entry: KeyStoreEntry) {
+        self.entries
+            .retain(|e| !(e.did == entry.did && e.role == entry.role && e.id == entry.id));
+        self.entries.push(entry);
+    }
+
+    /// Remove and return the entry with the given DID, role and id.
+    pub fn remove(&mut self, did: &str, role: KeyRole, id: &str) -> Option<KeyStoreEntry> {
+        let index = self
+            .entries
+            .iter()
+            .position(|e| e.did == did && e.role == role && e.id == id)?;
+        Some(self.entries.remove(index))
+    }
+
+    /// All entries, in insertion order.
+    pub fn entries(&self) -> &[KeyStoreEntry] {
+        &self.entries
+    }
+
+    /// Entries labelled with `did`.
+    pub fn entries_for<'a>(&'a self, did: &'a str) -> impl Iterator<Item = &'a KeyStoreEntry> {
+        self.entries.iter().filter(move |e| e.did == did)
+    }
+
+    /// Number of stored entries.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Whether the keystore is empty.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Find the entry for a verification method id (e.g. `did:webvh:...#key-0`
+    /// or `did:key:z6Mk...#z6Mk...`).
+    pub fn by_verification_method(&self, vm: &str) -> Option<&KeyStoreEntry> {
+        self.entries
+            .iter()
+            .find(|e| e.id == vm || e.secret.id == vm)
+    }
+
+    /// Signer for a verification method id.
+    pub fn signer(&self, vm: &str) -> Result<Secret, DIDWebVHError> {
+        self.by_verification_method(vm)
+            .map(|e| e.secret.clone())
+            .ok_or_else(|| {
+                DIDWebVHError::SecretsError(format!(
+                    "No key in keystore for verification method {vm}"
+                ))
+            })
+    }
+
+    /// Find a secret by its public key multibase.
+    pub fn find_by_public_key(&self, key: &str) -> Option<&Secret> {
+        self.entries
+            .iter()
+            .find(|e| e.public_key().is_ok_and(|pk| pk.as_str() == key))
+            .map(|e| &e.secret)
+    }
+
+    /// Find a secret by its public key multibase hash.
+    pub fn find_by_hash(&self, hash: &str) -> Option<&Secret> {
+        self.entries
+            .iter()
+            .find(|e| e.key_hash().is_ok_and(|h| h.as_str() == hash))
+            .map(|e| &e.secret)
+    }
+
+    /// Key allowed to sign the next log entry of `state`.
+    ///
+    /// With pre-rotation active this is a stored key whose hash was committed
+    /// in the last entry's `nextKeyHashes`; otherwise it is a stored key in
+    /// the active `updateKeys`. Only authorization and next keys are considered.
+    pub fn authorization_signer(&self, state: &DIDWebVHState) -> Result<Secret, DIDWebVHError> {
+        let Some(last_entry) = state.log_entries().last() else {
+            return Err(DIDWebVHError::SecretsError(
+                "Can't pick an authorization key for an empty DID state".to_string(),
+            ));
+        };
+        let params = &last_entry.validated_parameters;
+
+        let candidates = self
+            .entries
+            .iter()
+            .filter(|e| matches!(e.role, KeyRole::Authorization | KeyRole::NextKey));
+        for entry in candidates {
+            let authorised = if params.pre_rotation_active {
+                params
+                    .next_key_hashes
+                    .as_ref()
+                    .is_some_and(|hashes| entry.key_hash().is_ok_and(|h| hashes.contains(&h)))
+            } else {
+                entry
+                    .public_key()
+                    .is_ok_and(|pk| params.active_update_keys.contains(&pk))
+            };
+            if authorised {
+                return Ok(entry.secret.clone());
+            }
+        }
+
+        Err(DIDWebVHError::SecretsError(format!(
+            "Keystore has no key authorised to update {} after version {}",
+            state.scid(),
+            last_entry.version_number
+        )))
+    }
+
+    /// Witness signing secrets for `did`, keyed by witness DID.
+    ///
+    /// The map can be passed directly to `witness_secrets()` on the create
+    /// and update config builders.
+    pub fn witness_secrets(&self, did: &str) -> HashMap<String, Secret> {
+        self.entries_for(did)
+            .filter(|e| e.role == KeyRole::Witness)
+            .map(|e| (e.id.clone(), e.secret.clone()))
+            .collect()
+    }
+
+    /// Build a keystore from the secrets produced by the interactive create flow.
+    ///
+    /// All keys are labelled with the new DID and version 1.
+    #[cfg(feature = "cli")]
+    pub fn from_create_result(result: &crate::cli_create::InteractiveCreateResult) -> Self {
+        let did = result.did();
+        let mut store = Self::new();
+        for secret in result.authorization_secrets() {
+            store.insert(KeyStoreEntry::new(
+                did,
+                KeyRole::Authorization,
+                Some(1),
+                secret.id.clone(),
+                secret.clone(),
+            ));
+        }
+        for secret in result.next_key_secrets() {
+            store.insert(KeyStoreEntry::new(
+                did,
+                KeyRole::NextKey,
+                Some(1),
+                secret.id.clone(),
+                secret.clone(),
+            ));
+        }
+        for (vm, secret) in result.verification_method_secrets() {
+            store.insert(KeyStoreEntry::new(
+                did,
+                KeyRole::VerificationMethod,
+                Some(1),
+                vm.clone(),
+                secret.clone(),
+            ));
+        }
+        for (witness, secret) in result.witness_secrets() {
+            store.insert(KeyStoreEntry::new(
+                did,
+                KeyRole::Witness,
+                Some(1),
+                witness.clone(),
+                secret.clone(),
+            ));
+        }
+        store
+    }
+}
+
+/// Plaintext form of a [`KeyStore`], only ever written inside the sealed
+/// envelope.
+#[cfg(feature = "encryption")]
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct KeyStoreShadow {
+    entries: Vec<KeyStoreEntryShadow>,
+}
+
+#[cfg(feature = "encryption")]
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct KeyStoreEntryShadow {
+    did: String,
+    role: KeyRole,
+    version_number: Option<u32>,
+    id: String,
+    secret: Secret,
+}
+
+#[cfg(feature = "encryption")]
+impl From<&KeyStore> for KeyStoreShadow {
+    fn from(store: &KeyStore) -> Self {
+        Self {
+            entries: store
+                .entries
+                .iter()
+                .map(|e| KeyStoreEntryShadow {
+                    did: e.did.clone(),
+                    role: e.role,
+                    version_number: e.version_number,
+                    id: e.id.clone(),
+                    secret: e.secret.clone(),
+                })
+                .collect(),
+        }
+    }
+}
+
+#[cfg(feature = "encryption")]
+impl From<KeyStoreShadow> for KeyStore {
+    fn from(shadow: KeyStoreShadow) -> Self {
+        Self {
+            entries: shadow
+                .entries
+                .into_iter()
+                .map(|e| KeyStoreEntry::new(e.did, e.role, e.version_number, e.id, e.secret))
+                .collect(),
+        }
+    }
+}
+
+#[cfg(feature = "encryption")]
+impl KeyStore {
+    /// Serialise and encrypt the keystore as a JSON document.
+    pub fn export_encrypted(&self, passphrase: &str) -> Result<String, DIDWebVHError> {
+        self.export_encrypted_with(passphrase, crate::sealed::KdfParams::default())
+    }
+
+    pub(crate) fn export_encrypted_with(
+        &self,
+        passphrase: &str,
+        kdf: crate::sealed::KdfParams,
+    ) -> Result<String, DIDWebVHError> {
+        let plaintext =
+            zeroize::Zeroizing::new(serde_json::to_vec(&KeyStoreShadow::from(self)).map_err(
+                |e| DIDWebVHError::SecretsError(format!("Couldn't serialize keystore: {e}")),
+            )?);
+        let envelope = crate::sealed::SealedEnvelope::seal(
+            KEYSTORE_CONTENT_TYPE,
+            &plaintext,
+            passphrase,
+            kdf,
+        )?;
+        serde_json::to_string_pretty(&envelope).map_err(|e| {
+            DIDWebVHError::SecretsError(format!("Couldn't serialize keystore envelope: {e}"))
+        })
+    }
+
+    /// Decrypt and load a keystore produced by [`export_encrypted`](Self::export_encrypted).
+    pub fn import_encrypted(data: &str, passphrase: &str) -> Result<Self, DIDWebVHError> {
+        let envelope: crate::sealed::SealedEnvelope = serde_json::from_str(data).map_err(|e| {
+            DIDWebVHError::SecretsError(format!("Couldn't parse keystore envelope: {e}"))
+        })?;
+        let plaintext = envelope.open(KEYSTORE_CONTENT_TYPE, passphrase)?;
+        serde_json::from_slice::<KeyStoreShadow>(&plaintext)
+            .map(Self::from)
+            .map_err(|e| DIDWebVHError::SecretsError(format!("Couldn't deserialize keystore: {e}")))
+    }
+
+    /// Write the encrypted keystore to `path`.
+    ///
+    /// The file is replaced atomically (temporary file, fsync, rename). On
+    /// Unix it is left with mode `0600`, also when overwriting an existing
+    /// file.
+    pub fn save(&self, path: &str, passphrase: &str) -> Result<(), DIDWebVHError> {
+        let data = self.export_encrypted(passphrase)?;
+        crate::write_private(std::path::Path::new(path), data.as_bytes()).map_err(|e| {
+            DIDWebVHError::SecretsError(format!("Couldn't write keystore ({path}): {e}"))
+        })
+    }
+
+    /// Read and decrypt a keystore file written by [`save`](Self::save).
+    pub fn load(path: &str, passphrase: &str) -> Result<Self, DIDWebVHError> {
+        let data = std::fs::read_to_string(path).map_err(|e| {
+            DIDWebVHError::SecretsError(format!("Couldn't read keystore ({path}): {e}"))
+        })?;
+        Self::import_encrypted(&data, passphrase)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{KeyRole, KeyStore, KeyStoreEntry};
+    use crate::{
+        DIDWebVHState, Multibase,
+        create::{CreateDIDConfig, create_did},
+        parameters::Parameters,
+        test_utils::{did_doc_with_key, generate_signing_key},
+        update::{UpdateDIDConfig, update_did},
+    };
+    use chrono::{Duration, Utc};
+    use std::sync::Arc;
+
+    #[tokio::test]
+    async fn authorization_signer_follows_pre_rotation() {
+        let current = generate_signing_key();
+        let next = generate_signing_key();
+        let unrelated = generate_signing_key();
+        let doc = did_doc_with_key("did:webvh:{SCID}:example.com", &current);
+        let params = Parameters {
+            update_keys: Some(Arc::new(vec![Multibase::new(
+                current.get_public_keymultibase().unwrap(),
+            )])),
+            next_key_hashes: Some(Arc::new(vec![Multibase::new(
+                next.get_public_keymultibase_hash().unwrap(),
+            )])),
+            ..Default::default()
+        };
+        let result = create_did(
+            CreateDIDConfig::builder()
+                .address("https://example.com/")
+                .authorization_key(current.clone())
+                .did_document(doc)
+                .parameters(params)
+                .version_time(Utc::now().fixed_offset() - Duration::hours(1))
+                .build()
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+        let mut state = DIDWebVHState::from_log_entries(vec![result.log_entry().clone()]);
+        state.validate().unwrap().assert_complete().unwrap();
+        let did = result.did().to_string();
+
+        let mut store = KeyStore::new();
+        for (role, secret) in [
+            (KeyRole::Authorization, &current),
+            (KeyRole::NextKey, &next),
+            (KeyRole::Authorization, &unrelated),
+        ] {
+            store.insert(KeyStoreEntry::new(
+                &did,
+                role,
+                Some(1),
+                secret.id.clone(),
+                secret.clone(),
+            ));
+        }
+
+        // Only the committed next key may sign version 2.
+        let signer = store.authorization_signer(&state).unwrap();
+        assert_eq!(signer.id, next.id);
+
+        let public = Multibase::new(signer.get_public_keymultibase().unwrap());
+        let config = UpdateDIDConfig::builder()
+            .state(state)
+            .signing_key(signer)
+            .update_keys(vec![public])
+            .next_key_hashes(Vec::new())
+            .build()
+            .unwrap();
+        let state = update_did(config).await.unwrap().into_state();
+        assert_eq!(state.log_entries().len(), 2);
+
+        // Pre-rotation is now off, so the revealed key is the active update key.
+        assert_eq!(store.authorization_signer(&state).unwrap().id, next.id);
+    }
+
+    #[test]
+    fn lookup_by_verification_method_and_key() {
+        let vm_key = generate_signing_key();
+        let witness = generate_signing_key();
+        let witness_did = vm_key.id.split('#').next().unwrap().to_string();
+        let mut store = KeyStore::new();
+        store.insert(KeyStoreEntry::new(
+            "did:webvh:abc:example.com",
+            KeyRole::VerificationMethod,
+            Some(1),
+            "did:webvh:abc:example.com#key-0",
+            vm_key.clone(),
+        ));
+        store.insert(KeyStoreEntry::new(
+            "did:webvh:abc:example.com",
+            KeyRole::Witness,
+            None,
+            witness_did.clone(),
+            witness.clone(),
+        ));
+
+        let signer = store.signer("did:webvh:abc:example.com#key-0").unwrap();
+        assert_eq!(signer.id, vm_key.id);
+        assert!(store.signer(&vm_key.id).is_ok());
+        assert!(store.signer("did:webvh:abc:example.com#key-1").is_err());
+
+        let pk = vm_key.get_public_keymultibase().unwrap();
+        assert_eq!(store.find_by_public_key(&pk).unwrap().id, vm_key.id);
+        let hash = witness.get_public_keymultibase_hash().unwrap();
+        assert_eq!(store.find_by_hash(&hash).unwrap().id, witness.id);
+
+        let witnesses = store.witness_secrets("did:webvh:abc:example.com");
+        assert_eq!(witnesses.len(), 1);
+        assert!(witnesses.contains_key(&witness_did));
+        assert!(
+            store
+                .witness_secrets("did:webvh:other:example.com")
+                .is_empty()
+        );
+
+        // Re-inserting the same label replaces rather than duplicates.
+        store.insert(KeyStoreEntry::new(
+            "did:webvh:abc:example.com",
+            KeyRole::Witness,
+            Some(2),
+            witness_did.clone(),
+            witness,
+        ));
+        assert_eq!(store.len(), 2);
+        assert!(
+            store
+                .remove("did:webvh:abc:example.com", KeyRole::Witness, &witness_did)
+                .is_some()
+        );
+        assert_eq!(store.len(), 1);
+    }
+
+    #[cfg(feature = "encryption")]
+    #[test]
+    fn encrypted_round_trip() {
+        let key = generate_signing_key();
+        let mut store = KeyStore::new();
+        store.insert(KeyStoreEntry::new(
+            "did:webvh:abc:example.com",
+            KeyRole::Authorization,
+            Some(3),
+            key.id.clone(),
+            key.clone(),
+        ));
+
+        let data = store
+            .export_encrypted_with("correct horse", crate::sealed::TEST_KDF)
+            .unwrap();
+        assert!(!data.contains(&key.get_private_keymultibase().unwrap()));
+
+        let restored = KeyStore::import_encrypted(&data, "correct horse").unwrap();
+        let entry = &restored.entries()[0];
+        assert_eq!(entry.did, "did:webvh:abc:example.com");
+        assert_eq!(entry.role, KeyRole::Authorization);
+        assert_eq!(entry.version_number, Some(3));
+        assert_eq!(
+            entry.secret.get_private_keymultibase().unwrap(),
+            key.get_private_keymultibase().unwrap()
+        );
+
+        assert!(KeyStore::import_encrypted(&data, "wrong horse").is_err());
+    }
+
+    #[cfg(all(feature = "encryption", unix))]
+    #[test]
+    fn saved_file_is_owner_only() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let path = std::env::temp_dir().join(format!(
+            "didwebvh_test_keystore_{}.json",
+            std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap()
+                .as_nanos()
+        ));
+        let path_str = path.to_str().unwrap();
+        KeyStore::new().save(path_str, "pass").unwrap();
+
+        let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+        assert!(KeyStore::load(path_str, "pass").unwrap().is_empty());
+
+        // Re-saving over a world-readable file narrows it again
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o644)).unwrap();
+        KeyStore::new().save(path_str, "pass").unwrap();
+        let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+        assert!(KeyStore::load(path_str, "pass").unwrap().is_empty());
+        let _ = std::fs::remove_file(path);
+    }
+}
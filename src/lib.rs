@@ -36,20 +36,60 @@ pub mod cli_update;
 pub mod create;
 /// `did:key` helpers used by tests, examples and the interactive CLI.
 pub mod did_key;
+/// Declarative DID specs reconciled against a DID's log.
+pub mod did_spec;
 pub mod did_web;
+/// Universal Resolver driver endpoint, gated behind the `driver` feature.
+#[cfg(feature = "driver")]
+pub mod driver;
+/// Stable error codes and the context of failed verification checks.
+pub mod error;
+/// Pre-rotation key chains: future keys planned per versionNumber.
+pub mod key_chain;
+/// Labelled secret keys for the DIDs a controller manages.
+pub mod keystore;
+/// Lints for SHOULD-level recommendations of the specification.
+pub mod lint;
 pub mod log_entry;
 /// Manages per-entry validation state during DID log processing.
 pub mod log_entry_state;
+/// Previews and publishing bundles for moving a DID to a new location.
+pub mod migrate;
 pub mod multibase_type;
 pub mod parameters;
+/// Log entries held back from publishing until witnessed.
+pub mod pending;
 pub mod prelude;
+/// Static site layout and HTTP headers for hosting a DID.
+pub mod publish;
 pub mod resolve;
+/// Deterministic test-suite scenario scripts and the vectors they produce.
+pub mod scenario;
+/// JSON Schemas for the did:webvh files and structural validation, gated
+/// behind the `schema` feature.
+#[cfg(feature = "schema")]
+pub mod schema;
+/// Passphrase-based encryption for secret key material at rest, gated behind
+/// the `encryption` feature.
+#[cfg(feature = "encryption")]
+pub(crate) mod sealed;
+/// HTTP server hosting DIDs from a [`DidStore`](store::DidStore), gated
+/// behind the `server` feature.
+#[cfg(feature = "server")]
+pub mod server;
+/// Persistent storage for DID logs and witness proofs.
+pub mod store;
 pub mod update;
 /// Parsing and conversion of `did:webvh` URLs and HTTP URLs.
 pub mod url;
 pub mod validate;
+/// JavaScript bindings for resolution and verification, gated behind the
+/// `wasm-bindgen` feature.
+#[cfg(feature = "wasm-bindgen")]
+pub mod wasm;
 pub mod witness;
 
+pub use error::{CheckError, CheckKind, ErrorCode};
 pub use multibase_type::Multibase;
 pub use validate::{TruncationReason, ValidationReport};
 
@@ -147,9 +187,137 @@ pub(crate) fn ensure_object_mut(
         .ok_or_else(|| DIDWebVHError::DIDError("Expected a JSON object".to_string()))
 }
 
+/// How [`DIDWebVHState::create_log_entry_with`] writes a new entry.
+#[derive(Clone, Copy, Debug, Default)]
+pub(crate) struct EntryOptions {
+    /// Stamp the proof with the entry's versionTime, see [`proof_options`].
+    pub(crate) deterministic: bool,
+    /// Write `updateKeys`, `nextKeyHashes`, `witness` and `watchers` even when
+    /// unchanged, see [`Parameters::restate`](parameters::Parameters::restate).
+    pub(crate) restate_parameters: bool,
+}
+
+/// Options for signing a proof over a log entry with the given versionTime.
+/// Deterministic proofs are `created` at the versionTime, so the same inputs
+/// always produce the same proof; otherwise the signing clock is used.
+pub(crate) fn proof_options(
+    version_time: DateTime<FixedOffset>,
+    deterministic: bool,
+) -> SignOptions {
+    if deterministic {
+        SignOptions::new().with_created(version_time.to_utc())
+    } else {
+        SignOptions::new()
+    }
+}
+
+/// Crash-safe file replacement: write `contents` to a temporary file next to
+/// `path`, fsync it, rename it over `path` and fsync the directory. A crash
+/// leaves either the old file or the new one, never a mix.
+pub(crate) fn write_atomic(path: &std::path::Path, contents: &[u8]) -> std::io::Result<()> {
+    StagedFile::new(path, contents)?.persist()
+}
+
+/// The first half of [`write_atomic`]: `contents` written and synced to a
+/// temporary file next to `path`, not yet renamed over it. Dropping it
+/// without [`persist`](Self::persist) removes the temporary file.
+pub(crate) struct StagedFile {
+    tmp: std::path::PathBuf,
+    path: std::path::PathBuf,
+}
+
+/// [`write_atomic`] for secrets: the file is staged readable by its owner
+/// only and, on Unix, left with mode `0600` even when it replaces a file with
+/// wider permissions.
+#[cfg(feature = "encryption")]
+pub(crate) fn write_private(path: &std::path::Path, contents: &[u8]) -> std::io::Result<()> {
+    StagedFile::create(path, contents, true)?.persist()?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600))?;
+    }
+    Ok(())
+}
+
+impl StagedFile {
+    pub(crate) fn new(path: &std::path::Path, contents: &[u8]) -> std::io::Result<Self> {
+        Self::create(path, contents, false)
+    }
+
+    fn create(path: &std::path::Path, contents: &[u8], private: bool) -> std::io::Result<Self> {
+        use std::io::Write;
+
+        let file_name = path.file_name().ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("{} is not a file path", path.display()),
+            )
+        })?;
+        // Unique per call, so concurrent writers of the same path in this
+        // process never share (and truncate) a temporary file
+        static STAGED: std::sync::atomic::AtomicU64 = std::sync::atomic::AtomicU64::new(0);
+        let mut tmp_name = file_name.to_os_string();
+        tmp_name.push(format!(
+            ".{}.{}.tmp",
+            std::process::id(),
+            STAGED.fetch_add(1, std::sync::atomic::Ordering::Relaxed)
+        ));
+        let tmp = path.with_file_name(tmp_name);
+
+        let mut options = std::fs::OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        if private {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+        #[cfg(not(unix))]
+        let _ = private;
+        let mut file = options.open(&tmp)?;
+        let staged = Self {
+            tmp,
+            path: path.to_path_buf(),
+        };
+        file.write_all(contents)?;
+        file.sync_all()?;
+        Ok(staged)
+    }
+
+    /// Rename the temporary file over the target and fsync the directory.
+    pub(crate) fn persist(self) -> std::io::Result<()> {
+        std::fs::rename(&self.tmp, &self.path)?;
+        // Persist the rename itself. Directories can't be opened for syncing
+        // on every platform, so this is best effort.
+        #[cfg(unix)]
+        if let Some(dir) = self.path.parent() {
+            let dir = if dir.as_os_str().is_empty() {
+                std::path::Path::new(".")
+            } else {
+                dir
+            };
+            if let Ok(dir) = std::fs::File::open(dir) {
+                let _ = dir.sync_all();
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Drop for StagedFile {
+    fn drop(&mut self) {
+        // Gone already once persisted
+        let _ = std::fs::remove_file(&self.tmp);
+    }
+}
+
 /// Error types for WebVH method
 #[derive(Error, Debug)]
 pub enum DIDWebVHError {
+    /// A verification check failed while validating a log entry. See
+    /// [`check`](Self::check) for which one.
+    #[error("CheckFailed: {0}")]
+    CheckFailed(#[source] Box<CheckError>),
     /// The DID has been deactivated and can no longer be resolved.
     #[error("DeactivatedError: {0}")]
     DeactivatedError(String),
@@ -197,9 +365,23 @@ pub enum DIDWebVHError {
     /// An error related to the Self-Certifying Identifier (SCID).
     #[error("SCIDError: {0}")]
     SCIDError(String),
+    /// An error occurred while handling secret key material (key chain
+    /// planning, escrow encryption/decryption).
+    #[error("SecretsError: {0}")]
+    SecretsError(String),
     /// A server-side error occurred while processing the DID.
     #[error("ServerError: {0}")]
     ServerError(String),
+    /// A [`DidSpec`](did_spec::DidSpec) couldn't be read or can't be reached
+    /// from the DID's current state.
+    #[error("SpecError: {0}")]
+    SpecError(String),
+    /// A [`Scenario`](scenario::Scenario) couldn't be read, run or checked.
+    #[error("ScenarioError: {0}")]
+    ScenarioError(String),
+    /// A [`DidStore`](store::DidStore) backend failed or rejected a write.
+    #[error("StoreError: {0}")]
+    StoreError(String),
     /// The DID method is not `did:webvh`.
     #[error("UnsupportedMethod: {0}")]
     UnsupportedMethod(String),
@@ -249,6 +431,10 @@ pub struct DIDWebVHState {
     /// Validated?
     pub(crate) validated: bool,
 
+    /// Log entry staged for publishing, awaiting witness proofs.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) pending: Option<LogEntryState>,
+
     /// Deactivated?
     pub(crate) deactivated: bool,
 }
@@ -279,6 +465,44 @@ impl DIDWebVHState {
         &mut self.witness_proofs
     }
 
+    /// The log as `did.jsonl`: one JSON line per entry.
+    pub fn log_jsonl(&self) -> Result<String, DIDWebVHError> {
+        Self::entries_jsonl(self.log_entries.iter().map(|e| &e.log_entry))
+    }
+
+    /// The witness proofs as `did-witness.json`, or `None` if there are none.
+    pub fn witness_proofs_json(&self) -> Result<Option<String>, DIDWebVHError> {
+        if self.witness_proofs.get_total_count() == 0 {
+            return Ok(None);
+        }
+        Self::proofs_json(&self.witness_proofs).map(Some)
+    }
+
+    /// A single log entry as one line of `did.jsonl`, without the newline.
+    pub(crate) fn entry_json(entry: &LogEntry) -> Result<String, DIDWebVHError> {
+        serde_json::to_string(entry)
+            .map_err(|e| DIDWebVHError::LogEntryError(format!("Couldn't serialize LogEntry: {e}")))
+    }
+
+    /// `entries` as `did.jsonl`, see [`log_jsonl`](Self::log_jsonl).
+    pub(crate) fn entries_jsonl<'a>(
+        entries: impl IntoIterator<Item = &'a LogEntry>,
+    ) -> Result<String, DIDWebVHError> {
+        let mut log = String::new();
+        for entry in entries {
+            log.push_str(&Self::entry_json(entry)?);
+            log.push('\n');
+        }
+        Ok(log)
+    }
+
+    /// `proofs` as `did-witness.json`.
+    pub(crate) fn proofs_json(proofs: &WitnessProofCollection) -> Result<String, DIDWebVHError> {
+        serde_json::to_string(&proofs.proofs).map_err(|e| {
+            DIDWebVHError::WitnessProofError(format!("Couldn't serialize Witness Proofs Data: {e}"))
+        })
+    }
+
     /// Returns references to both the log entries and the mutable witness proof collection.
     /// This allows simultaneous read access to log entries and write access to witness proofs,
     /// which would otherwise conflict when using separate accessor methods.
@@ -401,6 +625,28 @@ impl DIDWebVHState {
         parameters: &Parameters,
         signing_key: &dyn Signer,
     ) -> Result<&LogEntryState, DIDWebVHError> {
+        self.create_log_entry_with(
+            version_time,
+            document,
+            parameters,
+            signing_key,
+            EntryOptions::default(),
+        )
+        .await
+    }
+
+    /// [`create_log_entry`](Self::create_log_entry), written as `options`
+    /// asks: with a proof `created` at the entry's versionTime instead of the
+    /// signing clock, and/or with unchanged parameters restated.
+    pub(crate) async fn create_log_entry_with(
+        &mut self,
+        version_time: Option<DateTime<FixedOffset>>,
+        document: &Value,
+        parameters: &Parameters,
+        signing_key: &dyn Signer,
+        options: EntryOptions,
+    ) -> Result<&LogEntryState, DIDWebVHError> {
+        self.ensure_no_pending()?;
         let now = Utc::now();
         let last_log_entry = self.log_entries.last();
 
@@ -437,11 +683,15 @@ impl DIDWebVHState {
                 Version::default()
             };
 
+            // Only use the difference of the parameters
+            let mut written = parameters.diff(&last_log_entry.validated_parameters)?;
+            if options.restate_parameters {
+                written.restate(parameters);
+            }
             LogEntry::create(
                 last_log_entry.get_version_id().to_string(),
                 version_time.unwrap_or_else(|| now.fixed_offset()),
-                // Only use the difference of the parameters
-                parameters.diff(&last_log_entry.validated_parameters)?,
+                written,
                 document.clone(),
                 webvh_version,
             )?
@@ -526,13 +776,17 @@ impl DIDWebVHState {
         };
 
         // Generate the proof for the log entry
-        let proof = DataIntegrityProof::sign(&new_entry, signing_key, SignOptions::new())
-            .await
-            .map_err(|e| {
-                DIDWebVHError::SCIDError(format!(
-                    "Couldn't generate Data Integrity Proof for LogEntry. Reason: {e}"
-                ))
-            })?;
+        let proof = DataIntegrityProof::sign(
+            &new_entry,
+            signing_key,
+            proof_options(new_entry.get_version_time(), options.deterministic),
+        )
+        .await
+        .map_err(|e| {
+            DIDWebVHError::SCIDError(format!(
+                "Couldn't generate Data Integrity Proof for LogEntry. Reason: {e}"
+            ))
+        })?;
 
         new_entry.add_proof(proof);
 
@@ -621,6 +875,8 @@ impl DIDWebVHState {
                 .as_deref()
                 .cloned(),
             watchers: log_entry.validated_parameters.watchers.as_deref().cloned(),
+            canonical_id: None,
+            equivalent_id: None,
         }
     }
 
@@ -704,7 +960,7 @@ impl DIDWebVHState {
     pub fn save_state(&self, path: &str) -> Result<(), DIDWebVHError> {
         let json = serde_json::to_string_pretty(self)
             .map_err(|e| DIDWebVHError::DIDError(format!("Failed to serialize state: {e}")))?;
-        std::fs::write(path, json)
+        write_atomic(std::path::Path::new(path), json.as_bytes())
             .map_err(|e| DIDWebVHError::DIDError(format!("Failed to write state to {path}: {e}")))
     }
 
@@ -717,8 +973,10 @@ impl DIDWebVHState {
         let json = std::fs::read_to_string(path).map_err(|e| {
             DIDWebVHError::DIDError(format!("Failed to read state from {path}: {e}"))
         })?;
-        serde_json::from_str(&json)
-            .map_err(|e| DIDWebVHError::DIDError(format!("Failed to deserialize state: {e}")))
+        let mut state: Self = serde_json::from_str(&json)
+            .map_err(|e| DIDWebVHError::DIDError(format!("Failed to deserialize state: {e}")))?;
+        state.restore_pending_proofs();
+        Ok(state)
     }
 
     /// Extract the multibase key fragment from a verification method URI.
@@ -1380,6 +1638,30 @@ mod tests {
         let actual = didwebvh.log_entries[0].log_entry.get_version_time_string();
         let expected = custom_time.to_rfc3339_opts(chrono::SecondsFormat::Secs, true);
         assert_eq!(actual, expected);
+        // The proof keeps the signing clock unless deterministic output is asked for
+        let created = |state: &DIDWebVHState| {
+            state.log_entries[0].log_entry.get_proofs()[0]
+                .created
+                .clone()
+                .unwrap()
+        };
+        assert_ne!(created(&didwebvh), expected);
+
+        let mut deterministic = DIDWebVHState::default();
+        deterministic
+            .create_log_entry_with(
+                Some(custom_time),
+                &state,
+                &params,
+                &key_with_id,
+                super::EntryOptions {
+                    deterministic: true,
+                    ..Default::default()
+                },
+            )
+            .await
+            .unwrap();
+        assert_eq!(created(&deterministic), expected);
     }
 
     // ===== get_specific_log_entry() tests =====
@@ -1667,6 +1949,21 @@ mod tests {
         assert!(result.is_err());
     }
 
+    #[test]
+    fn staged_files_for_one_path_dont_collide() {
+        let path = std::env::temp_dir().join(format!(
+            "didwebvh_test_staged_{}.json",
+            Utc::now().timestamp_nanos_opt().unwrap()
+        ));
+        let first = super::StagedFile::new(&path, b"first").unwrap();
+        let second = super::StagedFile::new(&path, b"second").unwrap();
+        first.persist().unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), b"first");
+        second.persist().unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), b"second");
+        std::fs::remove_file(path).ok();
+    }
+
     // ===== Convenience API tests =====
 
     #[tokio::test]
@@ -0,0 +1,580 @@
+/*!
+*   Lints for SHOULD-level recommendations of the did:webvh specification
+*
+*   Validation accepts everything the specification allows. Some of that is
+*   still discouraged, e.g. a deactivated DID keeping its `updateKeys`, or a
+*   witness configuration that can't tolerate a single witness being offline.
+*   [`lint`] reports these for a [`DIDWebVHState`], each under a
+*   stable [`LintCode`] so a publishing pipeline can allow or deny them
+*   individually:
+*
+*   ```no_run
+*   # use didwebvh_rs::{DIDWebVHState, lint::{LintCode, LintOptions, lint_with}};
+*   # fn check(state: &DIDWebVHState) -> Result<(), didwebvh_rs::DIDWebVHError> {
+*   let options = LintOptions::new()
+*       .deny_all()
+*       .allow(LintCode::WatchersUnset);
+*   let report = lint_with(state, &options)?;
+*   for finding in &report.findings {
+*       println!("{finding}");
+*   }
+*   if report.has_errors() {
+*       std::process::exit(1);
+*   }
+*   # Ok(())
+*   # }
+*   ```
+*/
+
+use crate::{
+    DIDWebVHError, DIDWebVHState, log_entry_state::LogEntryValidationStatus, witness::Witnesses,
+};
+use serde_json::Value;
+use std::{collections::BTreeMap, fmt, str::FromStr};
+
+/// Stable identifier of each lint.
+///
+/// The string form ([`LintCode::as_str`]) is what configuration and reports
+/// use and won't change between releases.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[non_exhaustive]
+pub enum LintCode {
+    /// `deactivated-update-keys`: the DID is deactivated but `updateKeys`
+    /// isn't empty.
+    DeactivatedUpdateKeys,
+    /// `ttl-zero`: `ttl` is 0, so resolvers can't cache the DID.
+    TtlZero,
+    /// `ttl-unset`: `ttl` was never set, so resolvers fall back to their own
+    /// default.
+    TtlUnset,
+    /// `witness-no-fault-tolerance`: the witness threshold equals the number
+    /// of witnesses, so one unavailable witness blocks every update.
+    WitnessNoFaultTolerance,
+    /// `pre-rotation-disabled`: no `nextKeyHashes`, so a leaked update key
+    /// can rotate the DID to keys of its choosing.
+    PreRotationDisabled,
+    /// `moved-without-also-known-as`: the DID has moved but the DID
+    /// Document's `alsoKnownAs` doesn't list its earlier DIDs.
+    MovedWithoutAlsoKnownAs,
+    /// `unused-verification-method`: a verification method isn't referenced
+    /// by any verification relationship.
+    UnusedVerificationMethod,
+    /// `watchers-unset`: no watchers are configured.
+    WatchersUnset,
+}
+
+impl LintCode {
+    /// Every lint, in code order.
+    pub const ALL: [LintCode; 8] = [
+        LintCode::DeactivatedUpdateKeys,
+        LintCode::TtlZero,
+        LintCode::TtlUnset,
+        LintCode::WitnessNoFaultTolerance,
+        LintCode::PreRotationDisabled,
+        LintCode::MovedWithoutAlsoKnownAs,
+        LintCode::UnusedVerificationMethod,
+        LintCode::WatchersUnset,
+    ];
+
+    /// The stable string form of the code.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LintCode::DeactivatedUpdateKeys => "deactivated-update-keys",
+            LintCode::TtlZero => "ttl-zero",
+            LintCode::TtlUnset => "ttl-unset",
+            LintCode::WitnessNoFaultTolerance => "witness-no-fault-tolerance",
+            LintCode::PreRotationDisabled => "pre-rotation-disabled",
+            LintCode::MovedWithoutAlsoKnownAs => "moved-without-also-known-as",
+            LintCode::UnusedVerificationMethod => "unused-verification-method",
+            LintCode::WatchersUnset => "watchers-unset",
+        }
+    }
+
+    /// The area of the DID the lint is about.
+    pub fn category(&self) -> LintCategory {
+        match self {
+            LintCode::DeactivatedUpdateKeys => LintCategory::Deactivation,
+            LintCode::TtlZero | LintCode::TtlUnset => LintCategory::Caching,
+            LintCode::WitnessNoFaultTolerance => LintCategory::Witnesses,
+            LintCode::PreRotationDisabled => LintCategory::KeyManagement,
+            LintCode::MovedWithoutAlsoKnownAs => LintCategory::Migration,
+            LintCode::UnusedVerificationMethod => LintCategory::Document,
+            LintCode::WatchersUnset => LintCategory::Watchers,
+        }
+    }
+}
+
+impl fmt::Display for LintCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for LintCode {
+    type Err = DIDWebVHError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        LintCode::ALL
+            .into_iter()
+            .find(|code| code.as_str() == s)
+            .ok_or_else(|| DIDWebVHError::ParametersError(format!("Unknown lint code: {s}")))
+    }
+}
+
+/// The area of a DID a lint is about.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum LintCategory {
+    /// Deactivating the DID
+    Deactivation,
+    /// How long resolvers may cache the DID
+    Caching,
+    /// Witness configuration
+    Witnesses,
+    /// Update and pre-rotation keys
+    KeyManagement,
+    /// Moving the DID to a new location
+    Migration,
+    /// The DID Document itself
+    Document,
+    /// Watcher configuration
+    Watchers,
+}
+
+/// What to do with a lint's findings.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LintLevel {
+    /// Don't report it
+    Allow,
+    /// Report it as a warning
+    #[default]
+    Warn,
+    /// Report it as an error ([`LintReport::has_errors`])
+    Deny,
+}
+
+/// The level of each lint. Every lint warns unless configured otherwise.
+#[derive(Clone, Debug, Default)]
+pub struct LintOptions {
+    levels: BTreeMap<LintCode, LintLevel>,
+}
+
+impl LintOptions {
+    /// Every lint at [`LintLevel::Warn`].
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the level of one lint.
+    pub fn level(mut self, code: LintCode, level: LintLevel) -> Self {
+        self.levels.insert(code, level);
+        self
+    }
+
+    /// Don't report `code`.
+    pub fn allow(self, code: LintCode) -> Self {
+        self.level(code, LintLevel::Allow)
+    }
+
+    /// Report `code` as an error.
+    pub fn deny(self, code: LintCode) -> Self {
+        self.level(code, LintLevel::Deny)
+    }
+
+    /// Report every lint as an error. Later calls can relax single lints.
+    pub fn deny_all(self) -> Self {
+        LintCode::ALL
+            .into_iter()
+            .fold(self, |options, code| options.deny(code))
+    }
+
+    /// The configured level of `code`.
+    pub fn get(&self, code: LintCode) -> LintLevel {
+        self.levels.get(&code).copied().unwrap_or_default()
+    }
+}
+
+/// A reported lint.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LintFinding {
+    /// Which lint
+    pub code: LintCode,
+    /// [`LintLevel::Warn`] or [`LintLevel::Deny`]
+    pub level: LintLevel,
+    /// `versionId` of the log entry it was found in
+    pub version_id: String,
+    /// What was found
+    pub message: String,
+}
+
+impl fmt::Display for LintFinding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let level = if self.level == LintLevel::Deny {
+            "error"
+        } else {
+            "warning"
+        };
+        write!(
+            f,
+            "{level}[{}]: {} ({})",
+            self.code, self.message, self.version_id
+        )
+    }
+}
+
+/// Findings of [`lint`] / [`lint_with`].
+#[derive(Clone, Debug, Default)]
+pub struct LintReport {
+    /// Findings in [`LintCode`] order
+    pub findings: Vec<LintFinding>,
+}
+
+impl LintReport {
+    /// Whether any denied lint was found.
+    pub fn has_errors(&self) -> bool {
+        self.errors().next().is_some()
+    }
+
+    /// Findings of denied lints.
+    pub fn errors(&self) -> impl Iterator<Item = &LintFinding> {
+        self.findings.iter().filter(|f| f.level == LintLevel::Deny)
+    }
+
+    /// Findings of lints at [`LintLevel::Warn`].
+    pub fn warnings(&self) -> impl Iterator<Item = &LintFinding> {
+        self.findings.iter().filter(|f| f.level == LintLevel::Warn)
+    }
+}
+
+/// Lint a validated DID with every lint at [`LintLevel::Warn`].
+pub fn lint(state: &DIDWebVHState) -> Result<LintReport, DIDWebVHError> {
+    lint_with(state, &LintOptions::default())
+}
+
+/// Lint a DID, checking the parameters and DID Document of its latest log
+/// entry.
+///
+/// The state must be validated (see [`DIDWebVHState::validate`]) or built by
+/// this crate, e.g. by [`DIDWebVHState::create_log_entry`], so that the
+/// active parameters are known; otherwise an error is returned.
+pub fn lint_with(
+    state: &DIDWebVHState,
+    options: &LintOptions,
+) -> Result<LintReport, DIDWebVHError> {
+    let Some(latest) = state.log_entries().last() else {
+        return Err(DIDWebVHError::NotFound(
+            "No log entries to lint".to_string(),
+        ));
+    };
+    if matches!(
+        latest.validation_status,
+        LogEntryValidationStatus::NotValidated | LogEntryValidationStatus::Invalid(_)
+    ) {
+        return Err(DIDWebVHError::ValidationError(
+            "The DID must be validated before it can be linted".to_string(),
+        ));
+    }
+
+    let parameters = &latest.validated_parameters;
+    let document = latest.get_state();
+    let mut found: Vec<(LintCode, String)> = Vec::new();
+
+    if parameters.deactivated == Some(true) {
+        // Entries that leave `updateKeys` out keep the previous keys active
+        if parameters
+            .update_keys
+            .as_ref()
+            .is_none_or(|keys| !keys.is_empty())
+        {
+            found.push((
+                LintCode::DeactivatedUpdateKeys,
+                format!(
+                    "updateKeys should be [] once deactivated, but {} key(s) remain",
+                    parameters.active_update_keys.len()
+                ),
+            ));
+        }
+    } else {
+        if parameters
+            .next_key_hashes
+            .as_ref()
+            .is_none_or(|hashes| hashes.is_empty())
+        {
+            found.push((
+                LintCode::PreRotationDisabled,
+                "nextKeyHashes isn't set, so key pre-rotation is disabled".to_string(),
+            ));
+        }
+        if let Some(witnesses) = &parameters.active_witness
+            && let Witnesses::Value {
+                threshold,
+                witnesses,
+            } = witnesses.as_ref()
+            && *threshold as usize >= witnesses.len()
+        {
+            found.push((
+                LintCode::WitnessNoFaultTolerance,
+                format!(
+                    "witness threshold {threshold} needs every one of the {} witnesses",
+                    witnesses.len()
+                ),
+            ));
+        }
+        if parameters
+            .watchers
+            .as_ref()
+            .is_none_or(|watchers| watchers.is_empty())
+        {
+            found.push((
+                LintCode::WatchersUnset,
+                "no watchers are configured".to_string(),
+            ));
+        }
+    }
+
+    match parameters.ttl {
+        Some(0) => found.push((
+            LintCode::TtlZero,
+            "ttl is 0, so resolvers can't cache the DID".to_string(),
+        )),
+        None => found.push((
+            LintCode::TtlUnset,
+            "ttl isn't set, so resolvers use their own cache lifetime".to_string(),
+        )),
+        Some(_) => {}
+    }
+
+    let also_known_as = string_list(document.get("alsoKnownAs"));
+    let current_id = document.get("id").and_then(Value::as_str).unwrap_or("");
+    let mut earlier_ids: Vec<&str> = Vec::new();
+    for entry in state.log_entries() {
+        if let Some(id) = entry.get_state().get("id").and_then(Value::as_str)
+            && id != current_id
+            && !earlier_ids.contains(&id)
+        {
+            earlier_ids.push(id);
+        }
+    }
+    let missing: Vec<&str> = earlier_ids
+        .into_iter()
+        .filter(|id| !also_known_as.contains(id))
+        .collect();
+    if !missing.is_empty() {
+        found.push((
+            LintCode::MovedWithoutAlsoKnownAs,
+            format!(
+                "the DID moved but alsoKnownAs doesn't list {}",
+                missing.join(", ")
+            ),
+        ));
+    }
+
+    for id in unused_verification_methods(document, current_id) {
+        found.push((
+            LintCode::UnusedVerificationMethod,
+            format!("verification method {id} isn't used by any verification relationship"),
+        ));
+    }
+
+    found.sort_by_key(|(code, _)| *code);
+    Ok(LintReport {
+        findings: found
+            .into_iter()
+            .filter_map(|(code, message)| {
+                let level = options.get(code);
+                (level != LintLevel::Allow).then(|| LintFinding {
+                    code,
+                    level,
+                    version_id: latest.get_version_id().to_string(),
+                    message,
+                })
+            })
+            .collect(),
+    })
+}
+
+/// The strings of a JSON string or array of strings.
+fn string_list(value: Option<&Value>) -> Vec<&str> {
+    match value {
+        Some(Value::String(s)) => vec![s.as_str()],
+        Some(Value::Array(values)) => values.iter().filter_map(Value::as_str).collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// `id` of each verification method no verification relationship refers to.
+fn unused_verification_methods<'a>(document: &'a Value, did: &str) -> Vec<&'a str> {
+    const RELATIONSHIPS: [&str; 5] = [
+        "authentication",
+        "assertionMethod",
+        "keyAgreement",
+        "capabilityInvocation",
+        "capabilityDelegation",
+    ];
+    let absolute = |id: &str| {
+        if id.starts_with('#') {
+            format!("{did}{id}")
+        } else {
+            id.to_string()
+        }
+    };
+    let used: Vec<String> = RELATIONSHIPS
+        .iter()
+        .flat_map(|relationship| string_list(document.get(*relationship)))
+        .map(absolute)
+        .collect();
+
+    document
+        .get("verificationMethod")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter_map(|method| method.get("id").and_then(Value::as_str))
+        .filter(|id| !used.contains(&absolute(id)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{LintCode, LintLevel, LintOptions, lint, lint_with};
+    use crate::{
+        DIDWebVHState, Multibase,
+        test_utils::{did_doc_with_key, key_and_params},
+        witness::{Witness, Witnesses},
+    };
+    use serde_json::json;
+    use std::sync::Arc;
+
+    fn codes(report: &super::LintReport) -> Vec<&'static str> {
+        report.findings.iter().map(|f| f.code.as_str()).collect()
+    }
+
+    #[test]
+    fn codes_round_trip() {
+        for code in LintCode::ALL {
+            assert_eq!(code.as_str().parse::<LintCode>().unwrap(), code);
+        }
+        assert!("no-such-lint".parse::<LintCode>().is_err());
+    }
+
+    #[tokio::test]
+    async fn flags_defaults_and_document_issues() {
+        let (key, params) = key_and_params();
+        let mut doc = did_doc_with_key("did:webvh:{SCID}:example.com", &key);
+        doc["verificationMethod"]
+            .as_array_mut()
+            .unwrap()
+            .push(json!({"id": "#spare", "type": "Multikey", "publicKeyMultibase": "z6Mk"}));
+        let params = crate::parameters::Parameters {
+            ttl: Some(0),
+            witness: Some(Arc::new(Witnesses::Value {
+                threshold: 1,
+                witnesses: vec![Witness::new("z6MkWitness")],
+            })),
+            ..params
+        };
+        let mut state = DIDWebVHState::default();
+        state
+            .create_log_entry(None, &doc, &params, &key)
+            .await
+            .unwrap();
+        let raw: String = state
+            .log_entries()
+            .iter()
+            .map(|e| serde_json::to_string(&e.log_entry).unwrap() + "\n")
+            .collect();
+        let unvalidated = DIDWebVHState::parse_log_entries(&raw).unwrap();
+        let unvalidated =
+            DIDWebVHState::from_log_entries(unvalidated.into_iter().map(|e| e.log_entry).collect());
+        assert!(lint(&unvalidated).is_err(), "not validated yet");
+
+        let report = lint(&state).unwrap();
+        assert_eq!(
+            codes(&report),
+            [
+                "ttl-zero",
+                "witness-no-fault-tolerance",
+                "pre-rotation-disabled",
+                "unused-verification-method",
+                "watchers-unset"
+            ]
+        );
+        assert!(report.findings[3].message.contains("#spare"));
+        assert!(!report.has_errors());
+        assert_eq!(report.warnings().count(), 5);
+
+        let options = LintOptions::new()
+            .deny_all()
+            .allow(LintCode::WatchersUnset)
+            .level(LintCode::TtlZero, LintLevel::Warn);
+        let report = lint_with(&state, &options).unwrap();
+        assert_eq!(report.findings.len(), 4);
+        assert_eq!(report.errors().count(), 3);
+        assert!(
+            report.findings[0]
+                .to_string()
+                .starts_with("warning[ttl-zero]: ")
+        );
+    }
+
+    #[tokio::test]
+    async fn flags_moves_and_deactivation() {
+        let (key, params) = key_and_params();
+        let params = crate::parameters::Parameters {
+            portable: Some(true),
+            ttl: Some(300),
+            watchers: Some(Arc::new(vec!["https://watcher.example.com".to_string()])),
+            ..params
+        };
+        let mut state = DIDWebVHState::default();
+        state
+            .create_log_entry(
+                None,
+                &did_doc_with_key("did:webvh:{SCID}:example.com", &key),
+                &params,
+                &key,
+            )
+            .await
+            .unwrap();
+        let old_did = format!("did:webvh:{}:example.com", state.scid());
+        let new_did = format!("did:webvh:{}:example.org", state.scid());
+        let moved = did_doc_with_key(&new_did, &key);
+        state
+            .create_log_entry(
+                None,
+                &moved,
+                &crate::parameters::Parameters::default(),
+                &key,
+            )
+            .await
+            .unwrap();
+        let report = lint(&state).unwrap();
+        assert!(codes(&report).contains(&"moved-without-also-known-as"));
+        assert!(report.findings.iter().any(|f| f.message.contains(&old_did)));
+
+        // Deactivating without emptying updateKeys
+        let mut state = DIDWebVHState::default();
+        state
+            .create_log_entry(
+                None,
+                &did_doc_with_key("did:webvh:{SCID}:example.com", &key),
+                &params,
+                &key,
+            )
+            .await
+            .unwrap();
+        let doc = state.log_entries()[0].get_state().clone();
+        let deactivate = crate::parameters::Parameters {
+            deactivated: Some(true),
+            update_keys: Some(Arc::new(vec![Multibase::new(
+                key.get_public_keymultibase().unwrap(),
+            )])),
+            ..Default::default()
+        };
+        state
+            .create_log_entry(None, &doc, &deactivate, &key)
+            .await
+            .unwrap();
+        assert_eq!(codes(&lint(&state).unwrap()), ["deactivated-update-keys"]);
+    }
+}
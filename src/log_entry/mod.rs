@@ -14,12 +14,29 @@ use serde::{Deserialize, Serialize};
 use serde_json::{Value, json};
 use serde_json_canonicalizer::to_string;
 use sha2::{Digest, Sha256};
-use std::{fs::OpenOptions, io::Write};
 use tracing::debug;
 
 pub mod read;
 pub mod spec_1_0;
 pub mod spec_1_0_pre;
+pub mod write;
+
+/// The error for a log entry that didn't deserialize. With the `schema`
+/// feature it lists every schema violation, with JSON Pointer paths.
+fn parse_error(values: &Value, e: &serde_json::Error) -> DIDWebVHError {
+    #[cfg(feature = "schema")]
+    {
+        let violations = crate::schema::describe_log_entry(values);
+        if !violations.is_empty() {
+            return DIDWebVHError::LogEntryError(format!(
+                "Failed to parse LogEntry: {e}. Schema violations: {violations}"
+            ));
+        }
+    }
+    #[cfg(not(feature = "schema"))]
+    let _ = values;
+    DIDWebVHError::LogEntryError(format!("Failed to parse LogEntry: {e}"))
+}
 
 /// Encodes a SHA-256 digest as a multihash byte array.
 /// Multihash format: [hash_function_code, digest_length, ...digest_bytes]
@@ -35,6 +52,7 @@ pub(crate) fn encode_sha256_multihash(digest: &[u8]) -> Vec<u8> {
 /// Resolved Document MetaData
 /// Returned as resolved Document MetaData on a successful resolve
 #[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 #[serde(rename_all = "camelCase")]
 pub struct MetaData {
     /// The `<version_number>-<hash>` identifier for this log entry.
@@ -59,6 +77,14 @@ pub struct MetaData {
     pub witness: Option<Witnesses>,
     /// Watcher endpoints configured for this DID.
     pub watchers: Option<Vec<String>>,
+    /// Set when the DID has migrated: the `id` of the latest DID Document,
+    /// which differs from the DID that was resolved.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub canonical_id: Option<String>,
+    /// Set when a `did:scid:vh` DID was resolved: the `did:webvh` DID it was
+    /// found as.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub equivalent_id: Option<Vec<String>>,
 }
 
 /// Extracts raw public key bytes from a data integrity proof.
@@ -418,9 +444,7 @@ impl LogEntry {
                     ))
                 } else {
                     Ok(LogEntry::Spec1_0(
-                        serde_json::from_value::<LogEntry1_0>(values).map_err(|e| {
-                            DIDWebVHError::LogEntryError(format!("Failed to parse LogEntry: {e}"))
-                        })?,
+                        LogEntry1_0::deserialize(&values).map_err(|e| parse_error(&values, &e))?,
                     ))
                 }
             }
@@ -457,47 +481,6 @@ impl LogEntry {
         }
     }
 
-    /// Append a valid LogEntry to a file
-    pub fn save_to_file(&self, file_path: &str) -> Result<(), DIDWebVHError> {
-        let append = if self.get_version_id_fields()?.0 == 1 {
-            false // Don't append to the file if this is the first version
-        } else {
-            true // Append to the file for all subsequent versions
-        };
-
-        let mut file = OpenOptions::new()
-            .create(true)
-            .write(true)
-            .truncate(!append)
-            .append(append)
-            .open(file_path)
-            .map_err(|e| {
-                DIDWebVHError::LogEntryError(format!("Couldn't open file {file_path}: {e}"))
-            })?;
-
-        file.write_all(
-            serde_json::to_string(self)
-                .map_err(|e| {
-                    DIDWebVHError::LogEntryError(format!(
-                        "Couldn't serialize LogEntry to JSON. Reason: {e}",
-                    ))
-                })?
-                .as_bytes(),
-        )
-        .map_err(|e| {
-            DIDWebVHError::LogEntryError(format!(
-                "Couldn't append LogEntry to file({file_path}). Reason: {e}",
-            ))
-        })?;
-        file.write_all("\n".as_bytes()).map_err(|e| {
-            DIDWebVHError::LogEntryError(format!(
-                "Couldn't append LogEntry to file({file_path}). Reason: {e}",
-            ))
-        })?;
-
-        Ok(())
-    }
-
     /// Generates a SCID from a preliminary LogEntry
     /// This only needs to be called once when the DID is first created.
     pub(crate) fn generate_first_scid(&self) -> Result<String, DIDWebVHError> {
@@ -682,6 +665,8 @@ mod tests {
             deactivated: false,
             witness: None,
             watchers: None,
+            canonical_id: None,
+            equivalent_id: None,
         };
         let v = serde_json::to_value(&meta).unwrap();
         assert_eq!(v.get("versionNumber"), Some(&serde_json::json!(42)));
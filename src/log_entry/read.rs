@@ -4,7 +4,7 @@
 
 use super::LogEntry;
 use crate::{
-    DIDWebVHError, Multibase, SCID_HOLDER,
+    CheckError, CheckKind, DIDWebVHError, Multibase, SCID_HOLDER,
     log_entry::{LogEntryMethods, PublicKey, spec_1_0::LogEntry1_0, spec_1_0_pre::LogEntry1_0Pre},
     parameters::Parameters,
 };
@@ -56,20 +56,36 @@ impl LogEntry {
         previous_parameters: Option<&Parameters>,
     ) -> Result<Parameters, DIDWebVHError> {
         debug!("Verifiying LogEntry: {}", self.get_version_id());
+        self.check_log_entry(previous_log_entry, previous_parameters)
+            .map_err(|e| e.in_version(self.get_version_id()))
+    }
 
+    /// The checks of [`verify_log_entry`](Self::verify_log_entry), whose
+    /// failures don't name the entry yet.
+    fn check_log_entry(
+        &self,
+        previous_log_entry: Option<&LogEntry>,
+        previous_parameters: Option<&Parameters>,
+    ) -> Result<Parameters, DIDWebVHError> {
         // Ensure we are dealing with a signed LogEntry
         let Some(proof) = &self.get_proofs().first() else {
-            return Err(DIDWebVHError::ValidationError(
-                "Missing proof in the signed LogEntry!".to_string(),
-            ));
+            return Err(CheckError::new(
+                CheckKind::ProofMissing,
+                "Missing proof in the signed LogEntry!",
+            )
+            .into());
         };
 
         // Ensure proofPurpose is assertionMethod as required by the spec
         if proof.proof_purpose != "assertionMethod" {
-            return Err(DIDWebVHError::ValidationError(format!(
-                "Invalid proofPurpose '{}': must be 'assertionMethod'",
-                proof.proof_purpose
-            )));
+            return Err(CheckError::new(
+                CheckKind::ProofPurpose,
+                format!(
+                    "Invalid proofPurpose '{}': must be 'assertionMethod'",
+                    proof.proof_purpose
+                ),
+            )
+            .into());
         }
 
         // didwebvh 1.0 mandates eddsa-jcs-2022 for the controller's log-entry
@@ -93,21 +109,28 @@ impl LogEntry {
             _ => false,
         };
         if !cryptosuite_ok {
-            return Err(DIDWebVHError::ValidationError(format!(
-                "Invalid cryptosuite {:?}: log entry proofs must use eddsa-jcs-2022 \
-                 (or, with the `experimental-pqc` build feature, a JCS-canonicalized \
-                 PQC suite from W3C di-quantum-safe v0.3)",
-                proof.cryptosuite
-            )));
+            return Err(CheckError::new(
+                CheckKind::Cryptosuite,
+                format!(
+                    "Invalid cryptosuite {:?}: log entry proofs must use eddsa-jcs-2022 \
+                     (or, with the `experimental-pqc` build feature, a JCS-canonicalized \
+                     PQC suite from W3C di-quantum-safe v0.3)",
+                    proof.cryptosuite
+                ),
+            )
+            .into());
         }
 
         // Ensure the Parameters are correctly setup
         let parameters = match self.get_parameters().validate(previous_parameters) {
             Ok(params) => params,
             Err(e) => {
-                return Err(DIDWebVHError::LogEntryError(format!(
-                    "Failed to validate parameters: {e}",
-                )));
+                return Err(CheckError::new(
+                    CheckKind::Parameters,
+                    format!("Failed to validate parameters: {e}"),
+                )
+                .with_source(e)
+                .into());
             }
         };
         debug!("Validated parameters: {parameters:#?}");
@@ -140,11 +163,12 @@ impl LogEntry {
                      Parameters::validate invariant broken",
                 );
                 if previous_params.next_key_hashes.is_none() {
-                    return Err(DIDWebVHError::ValidationError(
+                    return Err(CheckError::new(
+                        CheckKind::UnauthorizedKey,
                         "previous entry claims pre-rotation but has no nextKeyHashes: \
-                         refusing to self-authorise"
-                            .to_string(),
-                    ));
+                         refusing to self-authorise",
+                    )
+                    .into());
                 }
                 &parameters.active_update_keys
             }
@@ -155,10 +179,14 @@ impl LogEntry {
                 "Signing key {} is not authorized",
                 &proof.verification_method
             );
-            return Err(DIDWebVHError::ValidationError(format!(
-                "Signing key ({}) is not authorized",
-                &proof.verification_method
-            )));
+            return Err(CheckError::new(
+                CheckKind::UnauthorizedKey,
+                format!(
+                    "Signing key ({}) is not authorized",
+                    &proof.verification_method
+                ),
+            )
+            .into());
         }
 
         // Verify Signature
@@ -180,7 +208,11 @@ impl LogEntry {
                 VerifyOptions::new(),
             )
             .map_err(|e| {
-                DIDWebVHError::LogEntryError(format!("Signature verification failed: {e}"))
+                CheckError::new(
+                    CheckKind::SignatureInvalid,
+                    format!("Signature verification failed: {e}"),
+                )
+                .with_source(e)
             })?;
 
         // As a version of this LogEntry gets modified to recalculate hashes,
@@ -250,22 +282,29 @@ impl LogEntry {
             let (id, _) = previous.get_version_id_fields()?;
 
             if current_id != id + 1 {
-                return Err(DIDWebVHError::ValidationError(format!(
-                    "Current LogEntry version ID ({current_id}) must be one greater than previous version ID ({id})",
-                )));
+                return Err(CheckError::new(
+                    CheckKind::VersionNumber,
+                    format!(
+                        "Current LogEntry version ID ({current_id}) must be one greater than previous version ID ({id})",
+                    ),
+                )
+                .into());
             }
             // Set the versionId to the previous versionId to calculate the hash
             self.set_version_id(previous.get_version_id());
         } else if current_id != 1 {
-            return Err(DIDWebVHError::ValidationError(format!(
-                "First LogEntry must have version ID 1, got {current_id}",
-            )));
+            return Err(CheckError::new(
+                CheckKind::VersionNumber,
+                format!("First LogEntry must have version ID 1, got {current_id}"),
+            )
+            .into());
         } else {
             let scid = self
                 .get_scid()
                 .ok_or_else(|| {
-                    DIDWebVHError::ValidationError(
-                        "First LogEntry must have a valid SCID".to_string(),
+                    CheckError::new(
+                        CheckKind::ScidMismatch,
+                        "First LogEntry must have a valid SCID",
                     )
                 })?
                 .to_string();
@@ -275,9 +314,13 @@ impl LogEntry {
         // Validate the entryHash
         let entry_hash = self.generate_log_entry_hash()?;
         if entry_hash != current_hash {
-            return Err(DIDWebVHError::ValidationError(format!(
-                "Current LogEntry version ID ({current_id}) hash ({current_hash}) does not match calculated hash ({entry_hash})",
-            )));
+            return Err(CheckError::new(
+                CheckKind::HashMismatch,
+                format!(
+                    "Current LogEntry version ID ({current_id}) hash ({current_hash}) does not match calculated hash ({entry_hash})",
+                ),
+            )
+            .into());
         }
 
         Ok(())
@@ -308,9 +351,13 @@ impl LogEntry {
                 .and_then(|rest| rest.split_once(':'))
                 .map(|(s, _)| s);
             if doc_scid.is_none() || doc_scid != scid {
-                return Err(DIDWebVHError::ValidationError(format!(
-                    "DID document id SCID ({doc_scid:?}) does not match parameters.scid ({scid:?})",
-                )));
+                return Err(CheckError::new(
+                    CheckKind::ScidMismatch,
+                    format!(
+                        "DID document id SCID ({doc_scid:?}) does not match parameters.scid ({scid:?})",
+                    ),
+                )
+                .into());
             }
         }
 
@@ -319,9 +366,11 @@ impl LogEntry {
         {
             // DID identifier changed — this is a move/rename
             if parameters.portable != Some(true) {
-                return Err(DIDWebVHError::ValidationError(
-                    "DID document id has changed but portable is not enabled".to_string(),
-                ));
+                return Err(CheckError::new(
+                    CheckKind::Portability,
+                    "DID document id has changed but portable is not enabled",
+                )
+                .into());
             }
 
             // Per spec: the previous DID string MUST appear in alsoKnownAs
@@ -335,9 +384,13 @@ impl LogEntry {
                 });
 
             if !has_previous_in_also_known_as {
-                return Err(DIDWebVHError::ValidationError(format!(
-                    "DID has been moved but previous DID ({previous_did}) is not in alsoKnownAs",
-                )));
+                return Err(CheckError::new(
+                    CheckKind::Portability,
+                    format!(
+                        "DID has been moved but previous DID ({previous_did}) is not in alsoKnownAs",
+                    ),
+                )
+                .into());
             }
         }
 
@@ -347,20 +400,28 @@ impl LogEntry {
     /// Verifies everything is ok with the versionTime LogEntry field
     fn verify_version_time(&self, previous: Option<&LogEntry>) -> Result<(), DIDWebVHError> {
         if self.get_version_time() > Utc::now() {
-            return Err(DIDWebVHError::ValidationError(format!(
-                "versionTime ({}) cannot be in the future",
-                self.get_version_time_string()
-            )));
+            return Err(CheckError::new(
+                CheckKind::VersionTime,
+                format!(
+                    "versionTime ({}) cannot be in the future",
+                    self.get_version_time_string()
+                ),
+            )
+            .into());
         }
 
         if let Some(previous) = previous {
             // Current time must be strictly greater than the previous time (per spec)
             if self.get_version_time() <= previous.get_version_time() {
-                return Err(DIDWebVHError::ValidationError(format!(
-                    "Current versionTime ({}) must be greater than previous versionTime ({})",
-                    self.get_version_time_string(),
-                    previous.get_version_time_string()
-                )));
+                return Err(CheckError::new(
+                    CheckKind::VersionTime,
+                    format!(
+                        "Current versionTime ({}) must be greater than previous versionTime ({})",
+                        self.get_version_time_string(),
+                        previous.get_version_time_string()
+                    ),
+                )
+                .into());
             }
         }
 
@@ -374,7 +435,10 @@ impl LogEntry {
         let scid = self
             .get_scid()
             .ok_or_else(|| {
-                DIDWebVHError::ValidationError("First LogEntry must have a valid SCID".to_string())
+                CheckError::new(
+                    CheckKind::ScidMismatch,
+                    "First LogEntry must have a valid SCID",
+                )
             })?
             .to_string();
 
@@ -398,9 +462,11 @@ impl LogEntry {
 
         let verify_scid = scid_entry.generate_first_scid()?;
         if scid != verify_scid {
-            return Err(DIDWebVHError::ValidationError(format!(
-                "SCID ({scid}) does not match calculated SCID ({verify_scid})",
-            )));
+            return Err(CheckError::new(
+                CheckKind::ScidMismatch,
+                format!("SCID ({scid}) does not match calculated SCID ({verify_scid})"),
+            )
+            .into());
         }
 
         // The check above proves `parameters.scid` is the genesis self-hash.
@@ -420,9 +486,13 @@ impl LogEntry {
             .and_then(|rest| rest.split_once(':'))
             .map(|(s, _)| s);
         if doc_scid != Some(scid.as_str()) {
-            return Err(DIDWebVHError::ValidationError(format!(
-                "DID document id SCID ({doc_scid:?}) does not match parameters.scid ({scid})",
-            )));
+            return Err(CheckError::new(
+                CheckKind::ScidMismatch,
+                format!(
+                    "DID document id SCID ({doc_scid:?}) does not match parameters.scid ({scid})",
+                ),
+            )
+            .into());
         }
 
         Ok(())
@@ -433,7 +503,7 @@ impl LogEntry {
 mod tests {
     use std::sync::Arc;
 
-    use crate::DIDWebVHError;
+    use crate::CheckKind;
     use crate::Multibase;
     use crate::log_entry::LogEntry;
     use crate::log_entry::spec_1_0::LogEntry1_0;
@@ -486,7 +556,8 @@ mod tests {
     /// Tests that verify_log_entry rejects proofs with any proofPurpose other
     /// than "assertionMethod", including "authentication", "keyAgreement",
     /// "capabilityInvocation", and an empty string.
-    /// Expected: Each invalid purpose returns a ValidationError mentioning "assertionMethod".
+    /// Expected: Each invalid purpose fails the `ProofPurpose` check for
+    /// version 1, mentioning "assertionMethod".
     /// This matters because the WebVH spec mandates that log entry proofs use
     /// "assertionMethod"; accepting other purposes would weaken the trust model.
     #[test]
@@ -509,9 +580,12 @@ mod tests {
 
             let result = entry.verify_log_entry(None, None);
             assert!(
-                matches!(result, Err(DIDWebVHError::ValidationError(ref msg)) if msg.contains("assertionMethod")),
+                matches!(&result, Err(e) if e.check_kind() == Some(CheckKind::ProofPurpose) && e.to_string().contains("assertionMethod")),
                 "Expected assertionMethod error for proofPurpose '{bad_purpose}', got: {result:?}",
             );
+            let err = result.unwrap_err();
+            assert_eq!(err.version_id(), Some("1-abcdef"));
+            assert_eq!(err.version_number(), Some(1));
         }
     }
 
@@ -538,7 +612,7 @@ mod tests {
 
         let result = entry.verify_log_entry(None, None);
         assert!(
-            matches!(result, Err(DIDWebVHError::ValidationError(ref msg)) if msg.contains("eddsa-jcs-2022")),
+            matches!(&result, Err(e) if e.check_kind() == Some(CheckKind::Cryptosuite) && e.to_string().contains("eddsa-jcs-2022")),
             "Expected eddsa-jcs-2022 error, got: {result:?}",
         );
     }
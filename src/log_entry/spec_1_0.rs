@@ -13,6 +13,7 @@ use crate::{
 /// Each version of the DID gets a new log entry
 /// [Log Entries](https://identity.foundation/didwebvh/v1.0/#the-did-log-file)
 #[derive(Clone, Debug, Deserialize, Serialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 #[serde(rename_all = "camelCase")]
 pub struct LogEntry1_0 {
     /// format integer-prev_hash
@@ -30,6 +31,10 @@ pub struct LogEntry1_0 {
 
     /// Data Integrity Proof
     #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    #[cfg_attr(
+        feature = "schema",
+        schemars(with = "Vec<crate::schema::DataIntegrityProofSchema>")
+    )]
     pub proof: Vec<affinidi_data_integrity::DataIntegrityProof>,
 }
 
@@ -0,0 +1,335 @@
+/*!
+*  Writes a JSON Log file. Appends are crash-safe:
+*
+*  - the file is held under an exclusive advisory lock while it is checked
+*    and appended to
+*  - a torn trailing line left by an earlier crash is repaired first (see
+*    [`repair_log_file`])
+*  - the entries already on disk are checked against what is being appended,
+*    so the log never gets a gap, a duplicate or a fork
+*  - each append is a single write followed by an fsync
+*
+*  The first entry goes through the same lock and check: it is only written
+*  to a missing or empty file, never over another log.
+*/
+
+use super::{LogEntry, LogEntryMethods, parse_version_id_fields};
+use crate::{DIDWebVHError, DIDWebVHState};
+use serde_json::Value;
+use std::{
+    fs::{File, OpenOptions},
+    io::{Read, Seek, SeekFrom, Write},
+};
+use tracing::warn;
+
+/// What [`repair_log_file`] found at the end of a log file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LogRepair {
+    /// The file ends with a complete line; nothing was changed.
+    Clean,
+    /// The last entry was complete but its newline was missing; it was added.
+    NewlineAdded,
+    /// A partial last line was removed.
+    TornLineRemoved {
+        /// Bytes truncated from the end of the file.
+        bytes: u64,
+    },
+}
+
+impl LogEntry {
+    /// Append a valid LogEntry to a file.
+    ///
+    /// The first version starts the file, which must be missing, empty or
+    /// hold only this entry. Later versions are appended only if the last
+    /// entry on disk is the previous version. Saving the entry that is
+    /// already last is a no-op, so retrying after a crash is safe.
+    pub fn save_to_file(&self, file_path: &str) -> Result<(), DIDWebVHError> {
+        let line = serialize_line(self)?;
+        let (version_number, _) = self.get_version_id_fields()?;
+
+        with_locked_log(file_path, |file, on_disk| {
+            if version_number == 1 {
+                return match on_disk {
+                    [] => append(file, file_path, &line),
+                    [only] if only == self.get_version_id() => Ok(()),
+                    _ => Err(DIDWebVHError::LogEntryError(format!(
+                        "Log file ({file_path}) already holds another log; won't start a new one over it"
+                    ))),
+                };
+            }
+            let Some(last) = on_disk.last() else {
+                return Err(DIDWebVHError::LogEntryError(format!(
+                    "Can't append versionNumber {version_number} to empty log file ({file_path})"
+                )));
+            };
+            if last == self.get_version_id() {
+                return Ok(());
+            }
+            let (last_number, _) = parse_version_id_fields(last)?;
+            if last_number + 1 != version_number {
+                return Err(DIDWebVHError::LogEntryError(format!(
+                    "Can't append versionNumber {version_number} to log file ({file_path}) ending at versionNumber {last_number}"
+                )));
+            }
+            append(file, file_path, &line)
+        })
+    }
+}
+
+impl DIDWebVHState {
+    /// Append the log entries of this state that `file_path` doesn't hold
+    /// yet, creating the file if needed. Returns how many were appended.
+    ///
+    /// The entries on disk must be a prefix of this state's log (compared by
+    /// versionId); otherwise nothing is written. A pending entry is not
+    /// written.
+    pub fn append_log_file(&self, file_path: &str) -> Result<usize, DIDWebVHError> {
+        with_locked_log(file_path, |file, on_disk| {
+            let published = self.log_entries();
+            if on_disk.len() > published.len()
+                || on_disk
+                    .iter()
+                    .zip(published)
+                    .any(|(d, p)| d != p.get_version_id())
+            {
+                return Err(DIDWebVHError::LogEntryError(format!(
+                    "Log file ({file_path}) has diverged from this DID's log"
+                )));
+            }
+            let mut lines = String::new();
+            for entry in &published[on_disk.len()..] {
+                lines.push_str(&serialize_line(&entry.log_entry)?);
+            }
+            if !lines.is_empty() {
+                append(file, file_path, &lines)?;
+            }
+            Ok(published.len() - on_disk.len())
+        })
+    }
+}
+
+/// Detect and repair a torn trailing line in a log file, as left by a crash
+/// part way through an append.
+///
+/// A trailing fragment that is a complete JSON entry only lacks its newline,
+/// which is added. Anything else after the last newline is truncated. The
+/// file is locked while it is repaired. Appends through
+/// [`LogEntry::save_to_file`] and [`DIDWebVHState::append_log_file`] run
+/// this first.
+pub fn repair_log_file(file_path: &str) -> Result<LogRepair, DIDWebVHError> {
+    let mut file = open_locked(file_path, false)?;
+    repair(&mut file, file_path)
+}
+
+/// Open `file_path` for reading and writing and take an exclusive lock,
+/// released when the file is dropped.
+fn open_locked(file_path: &str, create: bool) -> Result<File, DIDWebVHError> {
+    let file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(create)
+        .truncate(false)
+        .open(file_path)
+        .map_err(|e| {
+            DIDWebVHError::LogEntryError(format!("Couldn't open file {file_path}: {e}"))
+        })?;
+    file.lock().map_err(|e| {
+        DIDWebVHError::LogEntryError(format!("Couldn't lock file {file_path}: {e}"))
+    })?;
+    Ok(file)
+}
+
+/// Lock and repair `file_path`, then call `f` with the file and the
+/// versionIds already in it.
+fn with_locked_log<T>(
+    file_path: &str,
+    f: impl FnOnce(&mut File, &[String]) -> Result<T, DIDWebVHError>,
+) -> Result<T, DIDWebVHError> {
+    let mut file = open_locked(file_path, true)?;
+    let repaired = repair(&mut file, file_path)?;
+    if repaired != LogRepair::Clean {
+        warn!("Repaired log file ({file_path}) before appending: {repaired:?}");
+    }
+
+    let mut contents = String::new();
+    file.seek(SeekFrom::Start(0))
+        .and_then(|_| file.read_to_string(&mut contents))
+        .map_err(|e| io_error(file_path, &e))?;
+    let version_ids = contents
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| {
+            serde_json::from_str::<Value>(line)
+                .ok()
+                .and_then(|v| v.get("versionId")?.as_str().map(ToString::to_string))
+                .ok_or_else(|| {
+                    DIDWebVHError::LogEntryError(format!(
+                        "Log file ({file_path}) has an entry without a versionId"
+                    ))
+                })
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    f(&mut file, &version_ids)
+}
+
+fn repair(file: &mut File, file_path: &str) -> Result<LogRepair, DIDWebVHError> {
+    let mut contents = Vec::new();
+    file.seek(SeekFrom::Start(0))
+        .and_then(|_| file.read_to_end(&mut contents))
+        .map_err(|e| io_error(file_path, &e))?;
+
+    let tail_start = contents
+        .iter()
+        .rposition(|b| *b == b'\n')
+        .map_or(0, |i| i + 1);
+    let tail = &contents[tail_start..];
+    if tail.is_empty() {
+        return Ok(LogRepair::Clean);
+    }
+
+    let complete = serde_json::from_slice::<Value>(tail)
+        .is_ok_and(|v| v.get("versionId").is_some_and(Value::is_string));
+    let result = if complete {
+        file.seek(SeekFrom::End(0))
+            .and_then(|_| file.write_all(b"\n"))
+            .map(|()| LogRepair::NewlineAdded)
+    } else {
+        file.set_len(tail_start as u64)
+            .map(|()| LogRepair::TornLineRemoved {
+                bytes: tail.len() as u64,
+            })
+    };
+    let repaired = result
+        .and_then(|r| file.sync_all().map(|()| r))
+        .map_err(|e| io_error(file_path, &e))?;
+    Ok(repaired)
+}
+
+fn append(file: &mut File, file_path: &str, text: &str) -> Result<(), DIDWebVHError> {
+    file.seek(SeekFrom::End(0))
+        .and_then(|_| file.write_all(text.as_bytes()))
+        .and_then(|()| file.sync_all())
+        .map_err(|e| {
+            DIDWebVHError::LogEntryError(format!(
+                "Couldn't append LogEntry to file({file_path}). Reason: {e}",
+            ))
+        })
+}
+
+fn serialize_line(entry: &LogEntry) -> Result<String, DIDWebVHError> {
+    let mut line = serde_json::to_string(entry).map_err(|e| {
+        DIDWebVHError::LogEntryError(format!("Couldn't serialize LogEntry to JSON. Reason: {e}",))
+    })?;
+    line.push('\n');
+    Ok(line)
+}
+
+fn io_error(file_path: &str, e: &std::io::Error) -> DIDWebVHError {
+    DIDWebVHError::LogEntryError(format!("Couldn't write log file ({file_path}): {e}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{LogRepair, repair_log_file};
+    use crate::{
+        DIDWebVHState,
+        log_entry::{LogEntry, spec_1_0::LogEntry1_0},
+        parameters::spec_1_0::Parameters1_0,
+    };
+    use chrono::Utc;
+    use serde_json::json;
+    use std::io::Write;
+
+    fn entry(version_id: &str) -> LogEntry {
+        LogEntry::Spec1_0(LogEntry1_0 {
+            version_id: version_id.to_string(),
+            version_time: Utc::now().fixed_offset(),
+            parameters: Parameters1_0::default(),
+            state: json!({"id": "did:webvh:scid:example.com"}),
+            proof: vec![],
+        })
+    }
+
+    fn temp_log(name: &str) -> String {
+        std::env::temp_dir()
+            .join(format!(
+                "didwebvh_test_{name}_{}.jsonl",
+                std::time::SystemTime::now()
+                    .duration_since(std::time::UNIX_EPOCH)
+                    .unwrap()
+                    .as_nanos()
+            ))
+            .to_str()
+            .unwrap()
+            .to_string()
+    }
+
+    fn append_raw(path: &str, bytes: &[u8]) {
+        std::fs::OpenOptions::new()
+            .append(true)
+            .open(path)
+            .unwrap()
+            .write_all(bytes)
+            .unwrap();
+    }
+
+    #[test]
+    fn torn_line_repaired_before_append() {
+        let path = temp_log("torn");
+        entry("1-a").save_to_file(&path).unwrap();
+        append_raw(&path, br#"{"versionId":"2-b","versionTi"#);
+
+        // The torn write of 2-b is dropped and 2-b appended cleanly
+        entry("2-b").save_to_file(&path).unwrap();
+        let loaded = LogEntry::load_from_file(&path).unwrap();
+        assert_eq!(loaded.len(), 2);
+        assert_eq!(repair_log_file(&path).unwrap(), LogRepair::Clean);
+
+        append_raw(&path, b"{\"versi");
+        assert_eq!(
+            repair_log_file(&path).unwrap(),
+            LogRepair::TornLineRemoved { bytes: 7 }
+        );
+        assert_eq!(LogEntry::load_from_file(&path).unwrap().len(), 2);
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[test]
+    fn missing_newline_added() {
+        let path = temp_log("newline");
+        let line = serde_json::to_string(&entry("1-a")).unwrap();
+        std::fs::write(&path, line).unwrap();
+        assert_eq!(repair_log_file(&path).unwrap(), LogRepair::NewlineAdded);
+        entry("2-b").save_to_file(&path).unwrap();
+        assert_eq!(LogEntry::load_from_file(&path).unwrap().len(), 2);
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[test]
+    fn append_checked_against_log() {
+        let path = temp_log("checked");
+        entry("1-a").save_to_file(&path).unwrap();
+        entry("1-a").save_to_file(&path).unwrap();
+        // Gap
+        assert!(entry("3-c").save_to_file(&path).is_err());
+        // Retrying the last write is a no-op
+        entry("2-b").save_to_file(&path).unwrap();
+        entry("2-b").save_to_file(&path).unwrap();
+        assert_eq!(LogEntry::load_from_file(&path).unwrap().len(), 2);
+
+        let state = DIDWebVHState::from_log_entries(vec![entry("1-a"), entry("2-b"), entry("3-c")]);
+        assert_eq!(state.append_log_file(&path).unwrap(), 1);
+        assert_eq!(state.append_log_file(&path).unwrap(), 0);
+
+        let forked = DIDWebVHState::from_log_entries(vec![entry("1-a"), entry("2-x")]);
+        assert!(forked.append_log_file(&path).is_err());
+        assert_eq!(LogEntry::load_from_file(&path).unwrap().len(), 3);
+
+        // A first entry never replaces an existing log
+        assert!(entry("1-a").save_to_file(&path).is_err());
+        assert!(entry("1-x").save_to_file(&path).is_err());
+        assert_eq!(LogEntry::load_from_file(&path).unwrap().len(), 3);
+        let _ = std::fs::remove_file(path);
+    }
+}
@@ -0,0 +1,679 @@
+/*!
+*   DID migration: typed rewriting of DID references when a portable DID moves.
+*
+*   Moving a DID to a new domain (or to a new path on the same domain) changes
+*   its identifier, so every place in the DID Document that *identifies* the
+*   DID or one of its resources has to follow. Only identifier positions are
+*   rewritten:
+*
+*   - the document `id` and `controller`
+*   - `id` / `controller` of each `verificationMethod`
+*   - references (and embedded methods) in the verification relationships
+*     (`authentication`, `assertionMethod`, `keyAgreement`,
+*     `capabilityInvocation`, `capabilityDelegation`)
+*   - `id` of each `service`
+*
+*   Everything else, in particular `serviceEndpoint` values, is left alone.
+*   The [`MigrationReport`] lists every rewritten location and every
+*   `did:webvh` reference that was deliberately left untouched, so the
+*   controller can review both before signing.
+*
+*   [`preview_migration`] computes the new document without signing anything;
+*   [`update_did`](crate::update::update_did) with
+*   [`migrate_to`](crate::update::UpdateDIDConfigBuilder::migrate_to) uses the
+*   same logic and returns the report on the result.
+*/
+
+use crate::{DIDWebVHError, DIDWebVHState, ensure_object_mut, url::WebVHURL};
+use serde::Serialize;
+use serde_json::Value;
+use url::Url;
+
+/// Verification relationships whose entries reference (or embed) verification methods.
+const VERIFICATION_RELATIONSHIPS: [&str; 5] = [
+    "authentication",
+    "assertionMethod",
+    "keyAgreement",
+    "capabilityInvocation",
+    "capabilityDelegation",
+];
+
+/// A DID reference that was rewritten to the new DID.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RewrittenReference {
+    /// JSON Pointer to the rewritten value (e.g. `/verificationMethod/0/id`).
+    pub path: String,
+    /// Value before migration.
+    pub old: String,
+    /// Value after migration.
+    pub new: String,
+}
+
+/// Why a `did:webvh` reference was left as-is.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum UntouchedReason {
+    /// The value refers to a different DID.
+    OtherDid,
+    /// The value refers to the migrating DID, but sits outside an identifier
+    /// position (e.g. inside a `serviceEndpoint`). Review it by hand.
+    NotAnIdentifier,
+}
+
+/// A `did:webvh` reference that migration did not rewrite.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UntouchedReference {
+    /// JSON Pointer to the value.
+    pub path: String,
+    /// The value, unchanged.
+    pub value: String,
+    /// Why it was not rewritten.
+    pub reason: UntouchedReason,
+}
+
+/// Everything a migration changed, and everything it chose not to change.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MigrationReport {
+    /// The DID before migration.
+    pub old_did: String,
+    /// The DID after migration.
+    pub new_did: String,
+    /// Every identifier position that was rewritten.
+    pub rewritten: Vec<RewrittenReference>,
+    /// Every other `did:webvh` reference in the document, left as-is.
+    pub untouched: Vec<UntouchedReference>,
+}
+
+/// Result of [`preview_migration`].
+#[derive(Clone, Debug)]
+pub struct MigrationPreview {
+    /// The migrated DID Document, with the old DID appended to `alsoKnownAs`.
+    pub document: Value,
+    /// What changed.
+    pub report: MigrationReport,
+}
+
+/// Compute the DID Document a migration of `state` to `new_address` would publish.
+///
+/// `new_address` may be an HTTP(S) URL (`https://new.example.com/dids/alice`),
+/// a `did:webvh` DID (the SCID is replaced with the current one), or an
+/// absolute path (`/dids/alice`) to move the DID within its current domain.
+///
+/// Nothing is signed. Fails if the DID is not portable or the target is the
+/// current location.
+pub fn preview_migration(
+    state: &DIDWebVHState,
+    new_address: &str,
+) -> Result<MigrationPreview, DIDWebVHError> {
+    let last_entry = state
+        .log_entries()
+        .last()
+        .ok_or_else(|| DIDWebVHError::LogEntryError("No log entries exist".to_string()))?;
+
+    if last_entry.validated_parameters.portable != Some(true) {
+        return Err(DIDWebVHError::ParametersError(
+            "DID must have portable=true to migrate".to_string(),
+        ));
+    }
+
+    let old_did = last_entry
+        .get_state()
+        .get("id")
+        .and_then(|v| v.as_str())
+        .ok_or_else(|| DIDWebVHError::DIDError("DID not found in log entry state".to_string()))?
+        .to_string();
+
+    let new_did = migration_target(&WebVHURL::parse_did_url(&old_did)?, new_address)?.to_did_base();
+    if new_did == old_did {
+        return Err(DIDWebVHError::DIDError(format!(
+            "Migration target ({new_address}) resolves to the current DID ({old_did})"
+        )));
+    }
+
+    let (mut document, report) = rewrite_did_references(last_entry.get_state(), &old_did, &new_did);
+
+    // Add previous DID to alsoKnownAs
+    if let Some(alias) = document.get_mut("alsoKnownAs") {
+        if let Some(arr) = alias.as_array_mut() {
+            arr.push(Value::String(old_did));
+        }
+    } else {
+        ensure_object_mut(&mut document)?.insert(
+            "alsoKnownAs".to_string(),
+            Value::Array(vec![Value::String(old_did)]),
+        );
+    }
+
+    Ok(MigrationPreview { document, report })
+}
+
+/// Resolve `new_address` against the current DID location. The SCID is always kept.
+pub(crate) fn migration_target(
+    current: &WebVHURL,
+    new_address: &str,
+) -> Result<WebVHURL, DIDWebVHError> {
+    let mut target = if new_address.starts_with("did:") {
+        WebVHURL::parse_did_url(new_address)?
+    } else if new_address.starts_with('/') {
+        // Same domain, new path. Route through `parse_url` so the path gets
+        // the same normalisation as a full URL would.
+        let mut url = current.get_http_url(None)?;
+        url.set_query(None);
+        url.set_fragment(None);
+        url.set_path(new_address);
+        WebVHURL::parse_url(&url)?
+    } else {
+        let url = Url::parse(new_address)
+            .map_err(|e| DIDWebVHError::DIDError(format!("Invalid URL: {e}")))?;
+        WebVHURL::parse_url(&url)?
+    };
+    target.scid.clone_from(&current.scid);
+    Ok(target)
+}
+
+/// Rewrite references to `old_did` in the identifier positions of `document`.
+///
+/// Returns the rewritten document and a report of what was (and wasn't)
+/// changed. `alsoKnownAs` is not modified.
+pub fn rewrite_did_references(
+    document: &Value,
+    old_did: &str,
+    new_did: &str,
+) -> (Value, MigrationReport) {
+    let mut document = document.clone();
+    let mut report = MigrationReport {
+        old_did: old_did.to_string(),
+        new_did: new_did.to_string(),
+        ..Default::default()
+    };
+
+    if let Some(doc) = document.as_object_mut() {
+        let mut rewriter = Rewriter {
+            old_did,
+            new_did,
+            report: &mut report,
+        };
+
+        if let Some(id) = doc.get_mut("id") {
+            rewriter.reference(id, "/id".to_string());
+        }
+        if let Some(controller) = doc.get_mut("controller") {
+            rewriter.controller(controller, "/controller");
+        }
+        if let Some(Value::Array(methods)) = doc.get_mut("verificationMethod") {
+            for (i, method) in methods.iter_mut().enumerate() {
+                rewriter.method(method, &format!("/verificationMethod/{i}"));
+            }
+        }
+        for relationship in VERIFICATION_RELATIONSHIPS {
+            if let Some(Value::Array(entries)) = doc.get_mut(relationship) {
+                for (i, entry) in entries.iter_mut().enumerate() {
+                    let path = format!("/{relationship}/{i}");
+                    if entry.is_string() {
+                        rewriter.reference(entry, path);
+                    } else {
+                        rewriter.method(entry, &path);
+                    }
+                }
+            }
+        }
+        if let Some(Value::Array(services)) = doc.get_mut("service") {
+            for (i, service) in services.iter_mut().enumerate() {
+                if let Some(id) = service.get_mut("id") {
+                    rewriter.reference(id, format!("/service/{i}/id"));
+                }
+            }
+        }
+    }
+
+    let mut untouched = Vec::new();
+    collect_untouched(&document, String::new(), old_did, &report, &mut untouched);
+    report.untouched = untouched;
+
+    (document, report)
+}
+
+struct Rewriter<'a> {
+    old_did: &'a str,
+    new_did: &'a str,
+    report: &'a mut MigrationReport,
+}
+
+impl Rewriter<'_> {
+    /// Rewrite a single string value if it is the old DID or a DID URL under it.
+    fn reference(&mut self, value: &mut Value, path: String) {
+        let Some(old) = value.as_str() else {
+            return;
+        };
+        let Some(new) = rewrite_reference(old, self.old_did, self.new_did) else {
+            return;
+        };
+        self.report.rewritten.push(RewrittenReference {
+            path,
+            old: old.to_string(),
+            new: new.clone(),
+        });
+        *value = Value::String(new);
+    }
+
+    /// `controller` may be a single DID or a list of DIDs.
+    fn controller(&mut self, value: &mut Value, path: &str) {
+        if let Value::Array(controllers) = value {
+            for (i, controller) in controllers.iter_mut().enumerate() {
+                self.reference(controller, format!("{path}/{i}"));
+            }
+        } else {
+            self.reference(value, path.to_string());
+        }
+    }
+
+    /// An embedded verification method: rewrite its `id` and `controller`.
+    fn method(&mut self, method: &mut Value, path: &str) {
+        if let Some(id) = method.get_mut("id") {
+            self.reference(id, format!("{path}/id"));
+        }
+        if let Some(controller) = method.get_mut("controller") {
+            self.controller(controller, &format!("{path}/controller"));
+        }
+    }
+}
+
+/// `reference` rewritten onto `new_did`, if it is `old_did` itself or a DID URL
+/// under it (followed by `#`, `?` or `/`). A longer DID that merely shares the
+/// prefix (`old_did:sub`) is a different DID and is not matched.
+fn rewrite_reference(reference: &str, old_did: &str, new_did: &str) -> Option<String> {
+    let rest = reference.strip_prefix(old_did)?;
+    if rest.is_empty() || rest.starts_with(['#', '?', '/']) {
+        Some(format!("{new_did}{rest}"))
+    } else {
+        None
+    }
+}
+
+/// Record every string containing a `did:webvh` reference that wasn't rewritten.
+fn collect_untouched(
+    value: &Value,
+    path: String,
+    old_did: &str,
+    report: &MigrationReport,
+    untouched: &mut Vec<UntouchedReference>,
+) {
+    match value {
+        Value::String(s) => {
+            if !s.contains("did:webvh:") || report.rewritten.iter().any(|r| r.path == path) {
+                return;
+            }
+            let reason = if s.contains(old_did) {
+                UntouchedReason::NotAnIdentifier
+            } else {
+                UntouchedReason::OtherDid
+            };
+            untouched.push(UntouchedReference {
+                path,
+                value: s.clone(),
+                reason,
+            });
+        }
+        Value::Array(items) => {
+            for (i, item) in items.iter().enumerate() {
+                collect_untouched(item, format!("{path}/{i}"), old_did, report, untouched);
+            }
+        }
+        Value::Object(map) => {
+            for (key, item) in map {
+                // JSON Pointer escaping (RFC 6901)
+                let key = key.replace('~', "~0").replace('/', "~1");
+                collect_untouched(item, format!("{path}/{key}"), old_did, report, untouched);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// The files to serve at one location of a migrated DID.
+#[derive(Clone, Debug)]
+pub struct HostedArtifacts {
+    /// The `did:webvh` DID for this location.
+    pub did: String,
+    /// The equivalent `did:web` DID for this location.
+    pub web_did: String,
+    /// Directory URL the files are served from (e.g. `https://example.com/.well-known/`).
+    pub base_url: Url,
+    /// Full log, one entry per line.
+    pub did_jsonl: String,
+    /// Witness proofs, if the DID has any.
+    pub did_witness_json: Option<String>,
+    /// `did:web` DID Document for this location.
+    pub did_json: Value,
+}
+
+impl HostedArtifacts {
+    /// `(url, contents)` for every file to publish at this location.
+    pub fn files(&self) -> Result<Vec<(Url, String)>, DIDWebVHError> {
+        let join = |name: &str| {
+            self.base_url
+                .join(name)
+                .map_err(|e| DIDWebVHError::DIDError(format!("Invalid URL for {name}: {e}")))
+        };
+        let did_json = serde_json::to_string_pretty(&self.did_json)
+            .map_err(|e| DIDWebVHError::DIDError(format!("Couldn't serialize did.json: {e}")))?;
+
+        let mut files = vec![(join("did.jsonl")?, self.did_jsonl.clone())];
+        if let Some(witness) = &self.did_witness_json {
+            files.push((join("did-witness.json")?, witness.clone()));
+        }
+        files.push((join("did.json")?, did_json));
+        Ok(files)
+    }
+
+    /// Write the files into `dir` (the local directory served at [`base_url`](Self::base_url)).
+    pub fn write_to_dir(&self, dir: &std::path::Path) -> Result<(), DIDWebVHError> {
+        std::fs::create_dir_all(dir).map_err(|e| {
+            DIDWebVHError::DIDError(format!("Couldn't create {}: {e}", dir.display()))
+        })?;
+        for (url, contents) in self.files()? {
+            let name = url
+                .path_segments()
+                .and_then(|mut s| s.next_back())
+                .unwrap_or_default();
+            let path = dir.join(name);
+            std::fs::write(&path, contents).map_err(|e| {
+                DIDWebVHError::DIDError(format!("Couldn't write {}: {e}", path.display()))
+            })?;
+        }
+        Ok(())
+    }
+}
+
+/// Everything to publish at the old and new locations after a migration.
+///
+/// Both locations serve the full log and witness proofs, so resolvers that
+/// still hold the old DID see the migration entry and report the new DID as
+/// `canonicalId`. Each location also gets a `did:web` document: the new one
+/// is the migrated document, the old one keeps the old identifiers and lists
+/// the new DIDs in `alsoKnownAs`.
+#[derive(Clone, Debug)]
+pub struct MigrationBundle {
+    /// Artifacts for the location the DID moved away from.
+    pub old_location: HostedArtifacts,
+    /// Artifacts for the location the DID lives at now.
+    pub new_location: HostedArtifacts,
+}
+
+impl MigrationBundle {
+    /// Build the bundle for the most recent migration in `state`.
+    ///
+    /// Fails if the DID has never changed location.
+    pub fn from_state(state: &DIDWebVHState) -> Result<Self, DIDWebVHError> {
+        let did_of = |doc: &Value| {
+            doc.get("id")
+                .and_then(|v| v.as_str())
+                .map(str::to_string)
+                .ok_or_else(|| {
+                    DIDWebVHError::DIDError("DID not found in log entry state".to_string())
+                })
+        };
+
+        let last_entry = state
+            .log_entries()
+            .last()
+            .ok_or_else(|| DIDWebVHError::LogEntryError("No log entries exist".to_string()))?;
+        let new_doc = last_entry.get_state();
+        let new_did = did_of(new_doc)?;
+        let mut old_did = None;
+        for entry in state.log_entries().iter().rev() {
+            let did = did_of(entry.get_state())?;
+            if did != new_did {
+                old_did = Some(did);
+                break;
+            }
+        }
+        let old_did = old_did
+            .ok_or_else(|| DIDWebVHError::DIDError(format!("{new_did} has never been migrated")))?;
+
+        let did_jsonl = state.log_jsonl()?;
+        let did_witness_json = state.witness_proofs_json()?;
+
+        // New location: the migrated document as-is
+        let new_location = HostedArtifacts {
+            web_did: DIDWebVHState::convert_webvh_id_to_web_id(&new_did),
+            base_url: WebVHURL::parse_did_url(&new_did)?.get_http_url(Some(""))?,
+            did_jsonl: did_jsonl.clone(),
+            did_witness_json: did_witness_json.clone(),
+            did_json: crate::did_web::to_web_did(new_doc)?,
+            did: new_did.clone(),
+        };
+
+        // Old location: same keys and services under the old identifiers,
+        // pointing at the new DIDs through alsoKnownAs
+        let (old_doc, _) = rewrite_did_references(new_doc, &new_did, &old_did);
+        let mut old_did_json = crate::did_web::to_web_did(&old_doc)?;
+        let also_known_as = ensure_object_mut(&mut old_did_json)?
+            .entry("alsoKnownAs")
+            .or_insert_with(|| Value::Array(Vec::new()));
+        if let Some(aliases) = also_known_as.as_array_mut() {
+            for alias in [&new_did, &new_location.web_did] {
+                if !aliases.iter().any(|a| a.as_str() == Some(alias.as_str())) {
+                    aliases.push(Value::String(alias.clone()));
+                }
+            }
+        }
+        let old_location = HostedArtifacts {
+            web_did: DIDWebVHState::convert_webvh_id_to_web_id(&old_did),
+            base_url: WebVHURL::parse_did_url(&old_did)?.get_http_url(Some(""))?,
+            did_jsonl,
+            did_witness_json,
+            did_json: old_did_json,
+            did: old_did,
+        };
+
+        Ok(Self {
+            old_location,
+            new_location,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{MigrationBundle, UntouchedReason, migration_target, rewrite_did_references};
+    use crate::{
+        DIDWebVHState,
+        parameters::Parameters,
+        test_utils::{did_doc_with_key, key_and_params},
+        update::{UpdateDIDConfig, update_did},
+        url::WebVHURL,
+    };
+    use chrono::{Duration, Utc};
+    use serde_json::json;
+
+    const OLD: &str = "did:webvh:QmSCID:old.example.com";
+    const NEW: &str = "did:webvh:QmSCID:new.example.com";
+
+    #[test]
+    fn rewrites_identifier_positions_only() {
+        let doc = json!({
+            "id": OLD,
+            "controller": [OLD, "did:webvh:QmOther:old.example.com"],
+            "verificationMethod": [{
+                "id": format!("{OLD}#key-0"),
+                "controller": OLD,
+                "type": "Multikey",
+            }],
+            "authentication": [format!("{OLD}#key-0"), {
+                "id": format!("{OLD}#key-1"),
+                "controller": OLD,
+            }],
+            "assertionMethod": ["#key-0"],
+            "service": [{
+                "id": format!("{OLD}#linked"),
+                "type": "LinkedDomains",
+                "serviceEndpoint": {
+                    "origins": ["https://old.example.com/"],
+                    "did": format!("{OLD}/resources/1"),
+                },
+            }],
+        });
+
+        let (new_doc, report) = rewrite_did_references(&doc, OLD, NEW);
+
+        assert_eq!(new_doc["id"], NEW);
+        assert_eq!(new_doc["controller"][0], NEW);
+        assert_eq!(
+            new_doc["verificationMethod"][0]["id"],
+            format!("{NEW}#key-0")
+        );
+        assert_eq!(new_doc["authentication"][0], format!("{NEW}#key-0"));
+        assert_eq!(new_doc["authentication"][1]["controller"], NEW);
+        assert_eq!(new_doc["assertionMethod"][0], "#key-0");
+        assert_eq!(new_doc["service"][0]["id"], format!("{NEW}#linked"));
+        // Service endpoints are never rewritten
+        assert_eq!(
+            new_doc["service"][0]["serviceEndpoint"],
+            doc["service"][0]["serviceEndpoint"]
+        );
+
+        let paths: Vec<&str> = report.rewritten.iter().map(|r| r.path.as_str()).collect();
+        assert_eq!(
+            paths,
+            [
+                "/id",
+                "/controller/0",
+                "/verificationMethod/0/id",
+                "/verificationMethod/0/controller",
+                "/authentication/0",
+                "/authentication/1/id",
+                "/authentication/1/controller",
+                "/service/0/id",
+            ]
+        );
+
+        assert_eq!(report.untouched.len(), 2);
+        let other = report
+            .untouched
+            .iter()
+            .find(|u| u.path == "/controller/1")
+            .unwrap();
+        assert_eq!(other.reason, UntouchedReason::OtherDid);
+        let endpoint = report
+            .untouched
+            .iter()
+            .find(|u| u.path == "/service/0/serviceEndpoint/did")
+            .unwrap();
+        assert_eq!(endpoint.reason, UntouchedReason::NotAnIdentifier);
+    }
+
+    #[test]
+    fn prefix_sharing_did_is_not_rewritten() {
+        let child = format!("{OLD}:users:alice");
+        let doc = json!({ "id": OLD, "controller": child });
+        let (new_doc, report) = rewrite_did_references(&doc, OLD, NEW);
+        assert_eq!(new_doc["controller"], child);
+        assert_eq!(report.rewritten.len(), 1);
+        assert_eq!(report.untouched.len(), 1);
+    }
+
+    #[test]
+    fn same_domain_path_target() {
+        let current = WebVHURL::parse_did_url("did:webvh:QmSCID:example.com%3A8080").unwrap();
+        let target = migration_target(&current, "/dids/alice").unwrap();
+        assert_eq!(
+            target.to_did_base(),
+            "did:webvh:QmSCID:example.com%3A8080:dids:alice"
+        );
+
+        let current = WebVHURL::parse_did_url("did:webvh:QmSCID:example.com:dids:alice").unwrap();
+        let target = migration_target(&current, "/").unwrap();
+        assert_eq!(target.to_did_base(), "did:webvh:QmSCID:example.com");
+    }
+
+    #[test]
+    fn did_target_keeps_scid() {
+        let current = WebVHURL::parse_did_url(OLD).unwrap();
+        let target = migration_target(&current, "did:webvh:{SCID}:new.example.com").unwrap();
+        assert_eq!(target.to_did_base(), NEW);
+    }
+
+    #[tokio::test]
+    async fn bundle_covers_both_locations() {
+        let (key, params) = key_and_params();
+        let params = Parameters {
+            portable: Some(true),
+            ..params
+        };
+        let doc = did_doc_with_key("did:webvh:{SCID}:example.com", &key);
+        let mut state = DIDWebVHState::default();
+        state
+            .create_log_entry(
+                Some(Utc::now().fixed_offset() - Duration::hours(1)),
+                &doc,
+                &params,
+                &key,
+            )
+            .await
+            .unwrap();
+        assert!(MigrationBundle::from_state(&state).is_err());
+        let old_did = state.log_entries()[0].get_state()["id"]
+            .as_str()
+            .unwrap()
+            .to_string();
+
+        let config = UpdateDIDConfig::builder()
+            .state(state)
+            .signing_key(key)
+            .migrate_to("https://new.example.com/")
+            .build()
+            .unwrap();
+        let result = update_did(config).await.unwrap();
+        let new_did = result.did().to_string();
+
+        let bundle = MigrationBundle::from_state(result.state()).unwrap();
+        let old = &bundle.old_location;
+        let new = &bundle.new_location;
+        assert_eq!(old.did, old_did);
+        assert_eq!(new.did, new_did);
+        assert_eq!(old.base_url.as_str(), "https://example.com/.well-known/");
+        assert_eq!(
+            new.base_url.as_str(),
+            "https://new.example.com/.well-known/"
+        );
+        assert_eq!(old.did_jsonl, new.did_jsonl);
+        assert_eq!(old.did_jsonl.lines().count(), 2);
+        assert!(old.did_witness_json.is_none());
+
+        assert_eq!(new.did_json["id"], "did:web:new.example.com");
+        assert_eq!(old.did_json["id"], "did:web:example.com");
+        assert_eq!(
+            old.did_json["verificationMethod"][0]["id"],
+            "did:web:example.com#key-0"
+        );
+        let aliases = old.did_json["alsoKnownAs"].as_array().unwrap();
+        assert!(aliases.contains(&json!(new_did)));
+        assert!(aliases.contains(&json!("did:web:new.example.com")));
+
+        let files: Vec<String> = old
+            .files()
+            .unwrap()
+            .into_iter()
+            .map(|(url, _)| url.to_string())
+            .collect();
+        assert_eq!(
+            files,
+            [
+                "https://example.com/.well-known/did.jsonl",
+                "https://example.com/.well-known/did.json",
+            ]
+        );
+
+        // The old location's log resolves under the old DID and points to the new one
+        let mut resolver = DIDWebVHState::default();
+        let (_, metadata) = resolver
+            .resolve_log(&old_did, &old.did_jsonl, None)
+            .await
+            .unwrap();
+        assert_eq!(metadata.canonical_id.as_deref(), Some(new_did.as_str()));
+    }
+}
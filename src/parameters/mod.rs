@@ -221,6 +221,33 @@ impl Parameters {
         Ok(diff)
     }
 
+    /// Fill in `updateKeys`, `nextKeyHashes`, `witness` and `watchers` that a
+    /// [`diff`](Self::diff) left out as unchanged, from the full set `full`
+    /// (empty where `full` has none). This is how the didwebvh test suite's
+    /// generator writes entries. A deactivating entry restates only
+    /// `updateKeys`.
+    pub(crate) fn restate(&mut self, full: &Parameters) {
+        if self.update_keys.is_none() {
+            self.update_keys = Some(full.update_keys.clone().unwrap_or_default());
+        }
+        if self.deactivated == Some(true) {
+            return;
+        }
+        if self.next_key_hashes.is_none() {
+            self.next_key_hashes = Some(full.next_key_hashes.clone().unwrap_or_default());
+        }
+        if self.witness.is_none() {
+            self.witness = Some(
+                full.witness
+                    .clone()
+                    .unwrap_or_else(|| Arc::new(Witnesses::Empty {})),
+            );
+        }
+        if self.watchers.is_none() {
+            self.watchers = Some(full.watchers.clone().unwrap_or_default());
+        }
+    }
+
     /// Returns the differences in Parameter attributes
     /// that use tri-state logic
     /// None = Absent, use previous value
@@ -0,0 +1,520 @@
+/*!
+*   Staged publishing of witnessed log entries.
+*
+*   When witnesses sign asynchronously, a new entry can't be published the
+*   moment it is created: a resolver that fetches `did.jsonl` before enough
+*   witness proofs are in `did-witness.json` would fail. Instead the entry is
+*   staged as *pending* on the [`DIDWebVHState`]:
+*
+*   1. [`DIDWebVHState::stage_log_entry`] (or `update_did` with
+*      [`stage`](crate::update::UpdateDIDConfigBuilder::stage)) creates and
+*      signs the entry but holds it outside the published log
+*   2. witness proofs arrive, via
+*      [`request_witness_proofs`](crate::witness::remote::request_witness_proofs)
+*      or [`DIDWebVHState::add_pending_witness_proof`]; they are held as
+*      future-entry proofs
+*   3. [`DIDWebVHState::pending_status`] reports when the threshold is met
+*   4. [`DIDWebVHState::publish_pending`] moves the entry into the log and
+*      writes both files
+*
+*   Files are written so a resolver sees a valid DID at every step: first
+*   `did-witness.json` with both the existing proofs and the new ones, then
+*   `did.jsonl` with the new entry, then `did-witness.json` again with
+*   superseded proofs pruned. Each write goes to a temporary file in the same
+*   directory which is then renamed over the target.
+*
+*   The pending entry is kept by [`save_state`](DIDWebVHState::save_state) /
+*   [`load_state`](DIDWebVHState::load_state), so a controller can wait for
+*   witnesses across restarts.
+*/
+
+use crate::{
+    DIDWebVHError, DIDWebVHState, EntryOptions, Signer,
+    log_entry::LogEntryMethods,
+    log_entry_state::LogEntryState,
+    parameters::Parameters,
+    witness::{WitnessVerifyOptions, Witnesses},
+    write_atomic,
+};
+use affinidi_data_integrity::DataIntegrityProof;
+use chrono::{DateTime, FixedOffset};
+use serde_json::Value;
+use std::path::Path;
+
+/// Witness progress of a pending entry.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PendingStatus {
+    /// versionId of the pending entry.
+    pub version_id: String,
+    /// Witness threshold, `None` if the entry doesn't need witnessing.
+    pub threshold: Option<u32>,
+    /// Active witnesses with a valid proof for the pending entry.
+    pub witnessed_by: Vec<String>,
+    /// Whether the entry can be published.
+    pub ready: bool,
+}
+
+impl DIDWebVHState {
+    /// The staged entry awaiting witness proofs, if any.
+    pub fn pending_entry(&self) -> Option<&LogEntryState> {
+        self.pending.as_ref()
+    }
+
+    /// The entry witness proofs are collected for: the pending entry if there
+    /// is one, otherwise the latest published entry.
+    pub(crate) fn witness_target(&self) -> Option<&LogEntryState> {
+        self.pending.as_ref().or(self.log_entries.last())
+    }
+
+    /// Create and sign a log entry, but hold it as pending instead of
+    /// appending it to the published log. Arguments are as for
+    /// [`create_log_entry`](Self::create_log_entry).
+    ///
+    /// Errors if an entry is already pending.
+    pub async fn stage_log_entry(
+        &mut self,
+        version_time: Option<DateTime<FixedOffset>>,
+        document: &Value,
+        parameters: &Parameters,
+        signing_key: &dyn Signer,
+    ) -> Result<&LogEntryState, DIDWebVHError> {
+        self.stage_log_entry_with(
+            version_time,
+            document,
+            parameters,
+            signing_key,
+            EntryOptions::default(),
+        )
+        .await
+    }
+
+    /// [`stage_log_entry`](Self::stage_log_entry), written as `options` asks
+    /// (see [`create_log_entry_with`](Self::create_log_entry_with)).
+    pub(crate) async fn stage_log_entry_with(
+        &mut self,
+        version_time: Option<DateTime<FixedOffset>>,
+        document: &Value,
+        parameters: &Parameters,
+        signing_key: &dyn Signer,
+        options: EntryOptions,
+    ) -> Result<&LogEntryState, DIDWebVHError> {
+        self.ensure_no_pending()?;
+        let meta = (
+            self.scid.clone(),
+            self.meta_first_ts.clone(),
+            self.meta_last_ts.clone(),
+        );
+        self.create_log_entry_with(version_time, document, parameters, signing_key, options)
+            .await?;
+        self.stage_last_entry(meta);
+        Ok(self.pending.as_ref().expect("just staged"))
+    }
+
+    /// Move the entry `create_log_entry` just appended into the pending slot,
+    /// restoring the metadata it updated.
+    pub(crate) fn stage_last_entry(&mut self, meta: (String, String, String)) {
+        self.pending = self.log_entries.pop();
+        (self.scid, self.meta_first_ts, self.meta_last_ts) = meta;
+    }
+
+    pub(crate) fn ensure_no_pending(&self) -> Result<(), DIDWebVHError> {
+        match &self.pending {
+            Some(pending) => Err(DIDWebVHError::LogEntryError(format!(
+                "Log entry {} is pending; publish or discard it first",
+                pending.get_version_id()
+            ))),
+            None => Ok(()),
+        }
+    }
+
+    /// Add a witness proof for the pending entry.
+    ///
+    /// The proof must come from one of the entry's active witnesses and
+    /// verify against its versionId.
+    pub fn add_pending_witness_proof(
+        &mut self,
+        proof: &DataIntegrityProof,
+        options: &WitnessVerifyOptions,
+    ) -> Result<PendingStatus, DIDWebVHError> {
+        let Some(pending) = &self.pending else {
+            return Err(DIDWebVHError::WitnessProofError(
+                "No log entry is pending".to_string(),
+            ));
+        };
+        let is_witness = pending
+            .get_active_witnesses()
+            .and_then(|w| {
+                w.witnesses().map(|w| {
+                    w.iter()
+                        .any(|w| w.as_did_key() == proof.verification_method)
+                })
+            })
+            .unwrap_or(false);
+        if !is_witness {
+            return Err(DIDWebVHError::WitnessProofError(format!(
+                "{} is not an active witness for {}",
+                proof.verification_method,
+                pending.get_version_id()
+            )));
+        }
+        pending.log_entry.validate_witness_proof(proof, options)?;
+
+        let version_id = pending.get_version_id().to_string();
+        if !self
+            .witness_proofs
+            .get_proofs(&version_id)
+            .is_some_and(|v| {
+                v.proof
+                    .iter()
+                    .any(|p| p.verification_method == proof.verification_method)
+            })
+        {
+            self.witness_proofs.add_proof(&version_id, proof, true)?;
+        }
+        self.pending_status(options)
+            .ok_or_else(|| DIDWebVHError::WitnessProofError("No log entry is pending".to_string()))
+    }
+
+    /// Witness progress of the pending entry, or `None` if nothing is pending.
+    pub fn pending_status(&self, options: &WitnessVerifyOptions) -> Option<PendingStatus> {
+        let pending = self.pending.as_ref()?;
+        let version_id = pending.get_version_id().to_string();
+        let witnesses = pending.get_active_witnesses();
+        let (Some(threshold), Some(nodes)) = (
+            witnesses.as_deref().and_then(Witnesses::threshold),
+            witnesses.as_deref().and_then(Witnesses::witnesses),
+        ) else {
+            return Some(PendingStatus {
+                version_id,
+                threshold: None,
+                witnessed_by: Vec::new(),
+                ready: true,
+            });
+        };
+
+        let witnessed_by: Vec<String> = nodes
+            .iter()
+            .filter(|w| {
+                self.witness_proofs
+                    .get_proofs(&version_id)
+                    .is_some_and(|v| {
+                        v.proof.iter().any(|p| {
+                            p.verification_method == w.as_did_key()
+                                && pending.log_entry.validate_witness_proof(p, options).is_ok()
+                        })
+                    })
+            })
+            .map(|w| w.as_did())
+            .collect();
+        Some(PendingStatus {
+            version_id,
+            threshold: Some(threshold),
+            ready: witnessed_by.len() >= threshold as usize,
+            witnessed_by,
+        })
+    }
+
+    /// Drop the pending entry and any proofs collected for it.
+    pub fn discard_pending(&mut self) -> Option<LogEntryState> {
+        let pending = self.pending.take()?;
+        self.witness_proofs
+            .remove_version_id(pending.get_version_id());
+        Some(pending)
+    }
+
+    /// Publish the pending entry once its witness threshold is met, writing
+    /// the full log to `log_path` and the witness proofs to `witness_path`.
+    ///
+    /// See the [module docs](self) for the write order. `witness_path` is
+    /// only written if the DID has witness proofs. If `did.jsonl` can't be
+    /// written the entry stays pending.
+    pub fn publish_pending(
+        &mut self,
+        log_path: impl AsRef<Path>,
+        witness_path: impl AsRef<Path>,
+        options: &WitnessVerifyOptions,
+    ) -> Result<&LogEntryState, DIDWebVHError> {
+        let Some(status) = self.pending_status(options) else {
+            return Err(DIDWebVHError::LogEntryError(
+                "No log entry is pending".to_string(),
+            ));
+        };
+        if !status.ready {
+            return Err(DIDWebVHError::WitnessProofError(format!(
+                "Pending entry {} has {} of {} witness proofs",
+                status.version_id,
+                status.witnessed_by.len(),
+                status.threshold.unwrap_or_default()
+            )));
+        }
+        let (log_path, witness_path) = (log_path.as_ref(), witness_path.as_ref());
+        let has_proofs = self.witness_proofs.get_total_count() > 0;
+
+        // 1. Proofs for both the published log and the new entry
+        if has_proofs {
+            write_file(witness_path, &Self::proofs_json(&self.witness_proofs)?)?;
+        }
+
+        // 2. The log, now including the new entry. `self` is only updated
+        //    once it's written
+        let pending = self.pending.as_ref().expect("pending_status returned Some");
+        let log = Self::entries_jsonl(
+            self.log_entries
+                .iter()
+                .chain([pending])
+                .map(|e| &e.log_entry),
+        )?;
+        write_file(log_path, &log)?;
+
+        let pending = self.pending.take().expect("pending_status returned Some");
+        self.witness_proofs
+            .set_future_entry(pending.get_version_id(), false);
+        if self.log_entries.is_empty() {
+            self.scid = pending
+                .validated_parameters
+                .scid
+                .as_ref()
+                .map(ToString::to_string)
+                .unwrap_or_default();
+            self.meta_first_ts = pending.log_entry.get_version_time_string();
+            self.meta_last_ts = self.meta_first_ts.clone();
+        } else {
+            self.meta_last_ts = pending.log_entry.get_version_time().to_string();
+        }
+        self.log_entries.push(pending);
+
+        // 3. Prune proofs the new ones supersede
+        if has_proofs {
+            self.witness_proofs.write_optimise_records()?;
+            write_file(witness_path, &Self::proofs_json(&self.witness_proofs)?)?;
+        }
+
+        Ok(self.log_entries.last().expect("just pushed"))
+    }
+
+    /// Re-flag proofs for the pending entry as future-entry proofs after
+    /// deserialization (the flag itself isn't serialized).
+    pub(crate) fn restore_pending_proofs(&mut self) {
+        if let Some(pending) = &self.pending {
+            self.witness_proofs
+                .set_future_entry(pending.get_version_id(), true);
+        }
+    }
+}
+
+fn write_file(path: &Path, contents: &str) -> Result<(), DIDWebVHError> {
+    write_atomic(path, contents.as_bytes())
+        .map_err(|e| DIDWebVHError::DIDError(format!("Couldn't write {}: {e}", path.display())))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        DIDWebVHState,
+        log_entry::LogEntryMethods,
+        parameters::Parameters,
+        test_utils::{did_doc_with_key, generate_signing_key, key_and_params},
+        witness::{
+            Witness, WitnessVerifyOptions, Witnesses,
+            remote::{LocalWitnessClient, request_witness_proofs},
+        },
+    };
+    use affinidi_data_integrity::{DataIntegrityProof, SignOptions};
+    use affinidi_secrets_resolver::secrets::Secret;
+    use ahash::HashMap;
+    use chrono::{Duration, Utc};
+    use serde_json::json;
+    use std::sync::Arc;
+
+    fn by_did(secrets: &[&Secret]) -> HashMap<String, Secret> {
+        secrets
+            .iter()
+            .map(|s| (s.id.split('#').next().unwrap().to_string(), (*s).clone()))
+            .collect()
+    }
+
+    /// A published, fully witnessed DID (threshold 2 of `a`, `b`) with an
+    /// update staged on top of it.
+    async fn staged(a: &Secret, b: &Secret) -> DIDWebVHState {
+        let (key, params) = key_and_params();
+        let params = Parameters {
+            witness: Some(Arc::new(Witnesses::Value {
+                threshold: 2,
+                witnesses: vec![
+                    Witness::new(a.get_public_keymultibase().unwrap()),
+                    Witness::new(b.get_public_keymultibase().unwrap()),
+                ],
+            })),
+            ..params
+        };
+        let t0 = Utc::now().fixed_offset() - Duration::hours(1);
+        let doc = did_doc_with_key("did:webvh:{SCID}:example.com", &key);
+        let mut state = DIDWebVHState::default();
+        state
+            .create_log_entry(Some(t0), &doc, &params, &key)
+            .await
+            .unwrap();
+        request_witness_proofs(
+            &LocalWitnessClient::new(by_did(&[a, b])),
+            &mut state,
+            &WitnessVerifyOptions::new(),
+        )
+        .await
+        .unwrap();
+
+        let mut doc = state.log_entries()[0].get_state().clone();
+        doc["alsoKnownAs"] = json!(["https://example.com/alice"]);
+        state
+            .stage_log_entry(
+                Some(t0 + Duration::minutes(1)),
+                &doc,
+                &Parameters::default(),
+                &key,
+            )
+            .await
+            .unwrap();
+        state
+    }
+
+    #[tokio::test]
+    async fn publish_waits_for_threshold() {
+        let (a, b) = (generate_signing_key(), generate_signing_key());
+        let mut state = staged(&a, &b).await;
+        let options = WitnessVerifyOptions::new();
+        assert_eq!(state.log_entries().len(), 1);
+
+        // Only witness A is reachable; its proof is kept
+        let err = request_witness_proofs(
+            &LocalWitnessClient::new(by_did(&[&a])),
+            &mut state,
+            &options,
+        )
+        .await
+        .unwrap_err();
+        assert!(err.to_string().contains("threshold"), "{err}");
+        let status = state.pending_status(&options).unwrap();
+        assert_eq!(status.witnessed_by.len(), 1);
+        assert!(!status.ready);
+
+        let dir = std::env::temp_dir().join(format!(
+            "didwebvh_test_pending_{}",
+            Utc::now().timestamp_nanos_opt().unwrap()
+        ));
+        std::fs::create_dir_all(&dir).unwrap();
+        let (log_path, witness_path) = (dir.join("did.jsonl"), dir.join("did-witness.json"));
+        assert!(
+            state
+                .publish_pending(&log_path, &witness_path, &options)
+                .is_err()
+        );
+        assert!(!log_path.exists());
+
+        // B answers later
+        let version_id = status.version_id.clone();
+        let proof =
+            DataIntegrityProof::sign(&json!({ "versionId": version_id }), &b, SignOptions::new())
+                .await
+                .unwrap();
+        assert!(
+            state
+                .add_pending_witness_proof(&proof, &options)
+                .unwrap()
+                .ready
+        );
+
+        // A failed log write leaves the entry pending
+        assert!(
+            state
+                .publish_pending(&dir, &witness_path, &options)
+                .is_err()
+        );
+        assert_eq!(state.pending_entry().unwrap().get_version_id(), version_id);
+        assert_eq!(state.log_entries().len(), 1);
+
+        state
+            .publish_pending(&log_path, &witness_path, &options)
+            .unwrap();
+        assert!(state.pending_entry().is_none());
+        assert_eq!(state.log_entries().len(), 2);
+        // Proofs for the first entry were pruned in favour of the new ones
+        assert_eq!(state.witness_proofs().get_total_count(), 2);
+
+        let did = state.log_entries()[1].get_state()["id"]
+            .as_str()
+            .unwrap()
+            .to_string();
+        let log = std::fs::read_to_string(&log_path).unwrap();
+        let proofs = std::fs::read_to_string(&witness_path).unwrap();
+        let mut resolved = DIDWebVHState::default();
+        let (entry, _) = resolved
+            .resolve_log(&did, &log, Some(&proofs))
+            .await
+            .unwrap();
+        assert_eq!(entry.get_version_id(), version_id);
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn pending_survives_save_and_load() {
+        let (a, b) = (generate_signing_key(), generate_signing_key());
+        let mut state = staged(&a, &b).await;
+        let options = WitnessVerifyOptions::new();
+        let _ = request_witness_proofs(
+            &LocalWitnessClient::new(by_did(&[&a])),
+            &mut state,
+            &options,
+        )
+        .await;
+
+        let path = std::env::temp_dir().join(format!(
+            "didwebvh_test_pending_state_{}.json",
+            Utc::now().timestamp_nanos_opt().unwrap()
+        ));
+        let path = path.to_str().unwrap();
+        state.save_state(path).unwrap();
+        let mut loaded = DIDWebVHState::load_state(path).unwrap();
+        let _ = std::fs::remove_file(path);
+
+        let pending = loaded.pending_entry().unwrap().get_version_id().to_string();
+        assert_eq!(
+            loaded.witness_proofs().get_proof_count(&pending),
+            state.witness_proofs().get_proof_count(&pending)
+        );
+        // Still flagged as future proofs, so the published entry keeps its proofs
+        loaded
+            .witness_proofs_mut()
+            .write_optimise_records()
+            .unwrap();
+        let published = loaded.log_entries()[0].get_version_id().to_string();
+        assert_eq!(loaded.witness_proofs().get_proof_count(&published), 2);
+    }
+
+    #[tokio::test]
+    async fn one_pending_entry_at_a_time() {
+        let (a, b) = (generate_signing_key(), generate_signing_key());
+        let mut state = staged(&a, &b).await;
+        let (key, _) = key_and_params();
+        let doc = state.log_entries()[0].get_state().clone();
+        let err = state
+            .create_log_entry(None, &doc, &Parameters::default(), &key)
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("is pending"), "{err}");
+
+        let _ = request_witness_proofs(
+            &LocalWitnessClient::new(by_did(&[&a])),
+            &mut state,
+            &WitnessVerifyOptions::new(),
+        )
+        .await;
+        let discarded = state.discard_pending().unwrap();
+        assert_eq!(
+            state
+                .witness_proofs()
+                .get_proof_count(discarded.get_version_id()),
+            0
+        );
+        assert!(state.pending_entry().is_none());
+    }
+}
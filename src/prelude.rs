@@ -38,3 +38,4 @@ pub use crate::resolve::ResolveOptions;
 pub use crate::update::{UpdateDIDConfig, update_did};
 pub use crate::witness::Witnesses;
 pub use crate::witness::proofs::WitnessProofCollection;
+pub use crate::{CheckKind, ErrorCode};
@@ -0,0 +1,339 @@
+/*!
+*   Static site publishing for did:webvh hosting.
+*
+*   [`SiteBundle::from_state`] lays out every file a web server needs to
+*   serve a DID, at the paths its URL resolves to:
+*
+*   | DID                                   | Files                                                     |
+*   |---------------------------------------|-----------------------------------------------------------|
+*   | `did:webvh:{SCID}:example.com`        | `.well-known/did.jsonl`, `.well-known/did-witness.json`, `.well-known/did.json`, `whois.vp` |
+*   | `did:webvh:{SCID}:example.com:alice`  | `alice/did.jsonl`, `alice/did-witness.json`, `alice/did.json`, `alice/whois.vp` |
+*
+*   `whois.vp` is served next to the log except for domain-only DIDs, where
+*   it lives at the site root rather than under `.well-known/`, as the spec
+*   requires. `did-witness.json` is only written when the log has witnessed
+*   entries; `did.json` (the did:web equivalent) and `whois.vp` are optional.
+*
+*   Each file carries the HTTP headers it should be served with: content
+*   type, `Cache-Control` matching the DID's `ttl`, and CORS so browser-based
+*   resolvers can fetch it. [`SiteBundle::headers_file`] renders these in the
+*   `_headers` format understood by Netlify and Cloudflare Pages; for S3-like
+*   hosting set them as object metadata on upload.
+*/
+
+use crate::{
+    DIDWebVHError, DIDWebVHState, log_entry_state::LogEntryValidationStatus, url::WebVHURL,
+    write_atomic,
+};
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+use url::Url;
+
+/// `Cache-Control` max-age when the DID sets no `ttl` (the spec default).
+pub const DEFAULT_TTL: u32 = 3600;
+
+pub(crate) const LOG_CONTENT_TYPE: &str = "application/jsonl";
+pub(crate) const WITNESS_CONTENT_TYPE: &str = "application/json";
+pub(crate) const DID_JSON_CONTENT_TYPE: &str = "application/did+json";
+pub(crate) const WHOIS_CONTENT_TYPE: &str = "application/vp";
+
+/// What to include in a [`SiteBundle`].
+#[derive(Clone, Debug)]
+pub struct PublishOptions {
+    /// Signed `whois.vp` Verifiable Presentation to publish. Default: none.
+    pub whois: Option<Value>,
+    /// Publish the did:web equivalent as `did.json`. Default: `true`.
+    pub did_json: bool,
+    /// Add `Access-Control-Allow-Origin: *`. Default: `true`.
+    pub cors: bool,
+}
+
+impl Default for PublishOptions {
+    fn default() -> Self {
+        Self {
+            whois: None,
+            did_json: true,
+            cors: true,
+        }
+    }
+}
+
+impl PublishOptions {
+    /// Defaults: `did.json` and CORS on, no `whois.vp`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Publish `vp` as `whois.vp`.
+    pub fn whois(mut self, vp: Value) -> Self {
+        self.whois = Some(vp);
+        self
+    }
+
+    /// Whether to publish `did.json`.
+    pub fn did_json(mut self, did_json: bool) -> Self {
+        self.did_json = did_json;
+        self
+    }
+
+    /// Whether to add CORS headers.
+    pub fn cors(mut self, cors: bool) -> Self {
+        self.cors = cors;
+        self
+    }
+}
+
+/// One file of a [`SiteBundle`].
+#[derive(Clone, Debug)]
+pub struct PublishedFile {
+    /// Path relative to the site root, e.g. `.well-known/did.jsonl`.
+    pub path: PathBuf,
+    /// URL the file is resolved from.
+    pub url: Url,
+    /// File contents.
+    pub contents: String,
+    /// HTTP headers to serve the file with.
+    pub headers: Vec<(String, String)>,
+}
+
+/// Every file needed to host a DID on a static site.
+#[derive(Clone, Debug)]
+pub struct SiteBundle {
+    /// The DID being published.
+    pub did: String,
+    /// Files in write order: witness proofs before the log that needs them.
+    pub files: Vec<PublishedFile>,
+}
+
+impl SiteBundle {
+    /// Lay out the files for the latest state of a DID. Every entry must be
+    /// validated (resolved, or created with this library).
+    pub fn from_state(
+        state: &DIDWebVHState,
+        options: &PublishOptions,
+    ) -> Result<Self, DIDWebVHError> {
+        let last = state
+            .log_entries()
+            .last()
+            .ok_or_else(|| DIDWebVHError::LogEntryError("No log entries exist".to_string()))?;
+        // Entries are `Ok` once validated, or when created by this library
+        if let Some(entry) = state
+            .log_entries()
+            .iter()
+            .find(|e| e.validation_status != LogEntryValidationStatus::Ok)
+        {
+            return Err(DIDWebVHError::ValidationError(format!(
+                "LogEntry {} must be validated before the DID is published",
+                entry.get_version_id()
+            )));
+        }
+        let did = last
+            .get_state()
+            .get("id")
+            .and_then(Value::as_str)
+            .ok_or_else(|| DIDWebVHError::DIDError("DID not found in log entry state".to_string()))?
+            .to_string();
+        let url = WebVHURL::parse_did_url(&did)?;
+        let ttl = last.validated_parameters.ttl.unwrap_or(DEFAULT_TTL);
+
+        let mut common = vec![("Cache-Control".to_string(), format!("max-age={ttl}"))];
+        if options.cors {
+            common.push(("Access-Control-Allow-Origin".to_string(), "*".to_string()));
+        }
+        let file = |url: Url, content_type: &str, contents: String| {
+            let mut headers = vec![("Content-Type".to_string(), content_type.to_string())];
+            headers.extend(common.iter().cloned());
+            PublishedFile {
+                path: PathBuf::from(url.path().trim_start_matches('/')),
+                url,
+                contents,
+                headers,
+            }
+        };
+        let to_json = |value: &Value, name: &str| {
+            serde_json::to_string_pretty(value)
+                .map_err(|e| DIDWebVHError::DIDError(format!("Couldn't serialize {name}: {e}")))
+        };
+
+        let mut files = Vec::new();
+        let witnessed = state
+            .log_entries()
+            .iter()
+            .any(|e| e.get_active_witnesses().is_some_and(|w| !w.is_empty()));
+        if witnessed {
+            files.push(file(
+                url.get_http_url(Some("did-witness.json"))?,
+                WITNESS_CONTENT_TYPE,
+                DIDWebVHState::proofs_json(state.witness_proofs())?,
+            ));
+        }
+
+        files.push(file(
+            url.get_http_url(Some("did.jsonl"))?,
+            LOG_CONTENT_TYPE,
+            state.log_jsonl()?,
+        ));
+
+        if options.did_json {
+            files.push(file(
+                url.get_http_url(Some("did.json"))?,
+                DID_JSON_CONTENT_TYPE,
+                to_json(&state.to_web_did()?, "did.json")?,
+            ));
+        }
+        if let Some(whois) = &options.whois {
+            files.push(file(
+                url.get_http_whois_url()?,
+                WHOIS_CONTENT_TYPE,
+                to_json(whois, "whois.vp")?,
+            ));
+        }
+
+        Ok(Self { did, files })
+    }
+
+    /// Write the files under `root` (the site's document root). Each file is
+    /// replaced atomically, witness proofs before the log.
+    pub fn write_to_dir(&self, root: &Path) -> Result<(), DIDWebVHError> {
+        for file in &self.files {
+            let path = root.join(&file.path);
+            if let Some(dir) = path.parent() {
+                std::fs::create_dir_all(dir).map_err(|e| {
+                    DIDWebVHError::DIDError(format!("Couldn't create {}: {e}", dir.display()))
+                })?;
+            }
+            write_atomic(&path, file.contents.as_bytes()).map_err(|e| {
+                DIDWebVHError::DIDError(format!("Couldn't write {}: {e}", path.display()))
+            })?;
+        }
+        Ok(())
+    }
+
+    /// The headers of every file in `_headers` format (Netlify, Cloudflare Pages).
+    pub fn headers_file(&self) -> String {
+        let mut out = String::new();
+        for file in &self.files {
+            out.push('/');
+            out.push_str(&file.path.to_string_lossy());
+            out.push('\n');
+            for (name, value) in &file.headers {
+                out.push_str(&format!("  {name}: {value}\n"));
+            }
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{PublishOptions, SiteBundle};
+    use crate::{
+        DIDWebVHState, Multibase,
+        test_utils::{did_doc_with_key, generate_signing_key, key_and_params},
+        witness::{Witness, Witnesses},
+    };
+    use chrono::{Duration, Utc};
+    use serde_json::json;
+    use std::{path::PathBuf, sync::Arc};
+
+    async fn state_for(did: &str, ttl: Option<u32>) -> DIDWebVHState {
+        state_with(did, ttl, None).await
+    }
+
+    async fn state_with(did: &str, ttl: Option<u32>, witness: Option<Witnesses>) -> DIDWebVHState {
+        let (key, mut params) = key_and_params();
+        params.ttl = ttl;
+        params.witness = witness.map(Arc::new);
+        let doc = did_doc_with_key(did, &key);
+        let mut state = DIDWebVHState::default();
+        state
+            .create_log_entry(
+                Some(Utc::now().fixed_offset() - Duration::minutes(1)),
+                &doc,
+                &params,
+                &key,
+            )
+            .await
+            .unwrap();
+        state
+    }
+
+    fn paths(bundle: &SiteBundle) -> Vec<PathBuf> {
+        bundle.files.iter().map(|f| f.path.clone()).collect()
+    }
+
+    #[tokio::test]
+    async fn domain_only_uses_well_known() {
+        let state = state_for("did:webvh:{SCID}:example.com", None).await;
+        let bundle = SiteBundle::from_state(
+            &state,
+            &PublishOptions::new().whois(json!({"type": "VerifiablePresentation"})),
+        )
+        .unwrap();
+        assert_eq!(
+            paths(&bundle),
+            [".well-known/did.jsonl", ".well-known/did.json", "whois.vp"].map(PathBuf::from)
+        );
+        let headers = bundle.headers_file();
+        assert!(headers.contains("/.well-known/did.jsonl\n  Content-Type: application/jsonl\n  Cache-Control: max-age=3600\n  Access-Control-Allow-Origin: *\n"), "{headers}");
+    }
+
+    #[tokio::test]
+    async fn witnessed_state_writes_proofs_before_log() {
+        let witness = generate_signing_key();
+        let witnesses = Witnesses::Value {
+            threshold: 1,
+            witnesses: vec![Witness {
+                id: Multibase::new(format!(
+                    "did:key:{}",
+                    witness.get_public_keymultibase().unwrap()
+                )),
+            }],
+        };
+        let state = state_with("did:webvh:{SCID}:example.com", None, Some(witnesses)).await;
+        let bundle = SiteBundle::from_state(&state, &PublishOptions::new()).unwrap();
+        assert_eq!(
+            paths(&bundle),
+            [
+                ".well-known/did-witness.json",
+                ".well-known/did.jsonl",
+                ".well-known/did.json"
+            ]
+            .map(PathBuf::from)
+        );
+    }
+
+    #[tokio::test]
+    async fn path_did_and_ttl() {
+        let state = state_for("did:webvh:{SCID}:example.com:dids:alice", Some(300)).await;
+        let bundle =
+            SiteBundle::from_state(&state, &PublishOptions::new().did_json(false).cors(false))
+                .unwrap();
+        assert_eq!(paths(&bundle), [PathBuf::from("dids/alice/did.jsonl")]);
+        assert_eq!(
+            bundle.files[0].headers,
+            vec![
+                ("Content-Type".to_string(), "application/jsonl".to_string()),
+                ("Cache-Control".to_string(), "max-age=300".to_string()),
+            ]
+        );
+
+        let root = std::env::temp_dir().join(format!(
+            "didwebvh-publish-{}",
+            Utc::now().timestamp_nanos_opt().unwrap()
+        ));
+        bundle.write_to_dir(&root).unwrap();
+        let log = std::fs::read_to_string(root.join("dids/alice/did.jsonl")).unwrap();
+        assert_eq!(log, bundle.files[0].contents);
+        let _ = std::fs::remove_dir_all(root);
+    }
+
+    #[tokio::test]
+    async fn unvalidated_state_refused() {
+        let created = state_for("did:webvh:{SCID}:example.com", None).await;
+        let loaded =
+            DIDWebVHState::from_log_entries(vec![created.log_entries()[0].log_entry.clone()]);
+        assert!(SiteBundle::from_state(&loaded, &PublishOptions::new()).is_err());
+        assert!(SiteBundle::from_state(&DIDWebVHState::default(), &PublishOptions::new()).is_err());
+    }
+}
@@ -4,6 +4,8 @@
 //! [`crate::DIDWebVHState::resolve`] Will load a WebVH DID using HTTP(S)
 //! [`crate::DIDWebVHState::resolve_file`] Will load a WebVH DID using a local file path
 //! [`crate::DIDWebVHState::resolve_log`] Will load a WebVH DID from raw JSONL string data
+//! [`crate::DIDWebVHState::resolve_scid`] Will load a `did:scid:vh` DID from the WebVH log it points at
+//! [`crate::DIDWebVHState::resolve_web`] Will load a `did:web` DID and check it against its WebVH log
 //! `resolve_state` is an internal function that will validate the DID and return
 //! the resolved result
 
@@ -36,6 +38,12 @@ use url::Url;
 #[cfg(feature = "ssi")]
 pub mod ssi_resolve;
 
+#[cfg(feature = "network")]
+mod scid;
+/// Resolving `did:web` DIDs checked against their WebVH log
+#[cfg(feature = "network")]
+pub mod web;
+
 pub mod implicit; // WebVH specification implies specific Services for a DID Document
 
 /// Default maximum HTTP response size: 200 KB.
@@ -53,6 +61,15 @@ pub struct ResolveOptions {
     /// Maximum allowed HTTP response body size in bytes (default: 200 KB).
     /// Applies independently to each downloaded file (did.jsonl, did-witness.json).
     pub max_response_bytes: u64,
+    /// When the latest DID Document `id` differs from the DID being resolved
+    /// (the DID has migrated), resolve the new DID from its own location and
+    /// return that result instead (default: false). Only one hop is followed,
+    /// and the new log must extend the old one.
+    pub follow_canonical_id: bool,
+    /// Locations (`host/path`, the form of the `src` hint) to look for a
+    /// `did:scid:vh` DID's log after its own `src` hint (default: none). See
+    /// [`DIDWebVHState::resolve_scid`].
+    pub scid_sources: Vec<String>,
 }
 
 #[cfg(feature = "network")]
@@ -62,6 +79,8 @@ impl Default for ResolveOptions {
             timeout: None,
             eager_witness_download: false,
             max_response_bytes: DEFAULT_MAX_RESPONSE_BYTES,
+            follow_canonical_id: false,
+            scid_sources: Vec::new(),
         }
     }
 }
@@ -72,6 +91,29 @@ pub struct DIDWebVH;
 
 #[cfg(feature = "network")]
 impl DIDWebVH {
+    /// A client for one-off fetches outside [`DIDWebVHState::resolve`], with
+    /// the same timeout and redirect policy.
+    fn http_client(options: &ResolveOptions) -> Result<Client, DIDWebVHError> {
+        #[cfg(all(target_arch = "wasm32", target_os = "unknown"))]
+        {
+            trace!(
+                "timeout is not available in WASM builds! {:#?}",
+                options.timeout
+            );
+            Ok(Client::new())
+        }
+        #[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+        reqwest::ClientBuilder::new()
+            .timeout(options.timeout.unwrap_or(Duration::from_secs(10)))
+            .redirect(reqwest::redirect::Policy::none())
+            .build()
+            .map_err(|e| DIDWebVHError::NetworkError {
+                url: String::new(),
+                status_code: None,
+                message: format!("Failed to build HTTP client: {e}"),
+            })
+    }
+
     /// Fetches a file from the given URL, enforcing a maximum response body size.
     ///
     /// The size limit is checked in two ways:
@@ -540,12 +582,29 @@ impl DIDWebVHState {
                 self.expires = DateTime::default();
             }
 
-            self.resolve_state(&parsed_did_url)
+            self.resolve_loaded(&parsed_did_url, options).await
         }
         .instrument(_span)
         .await
     }
 
+    /// Resolve `parsed_did_url` against the log already loaded into this
+    /// state, following `canonicalId` when `options` ask for it.
+    pub(crate) async fn resolve_loaded(
+        &mut self,
+        parsed_did_url: &WebVHURL,
+        options: ResolveOptions,
+    ) -> Result<(&LogEntry, MetaData), DIDWebVHError> {
+        let (index, metadata) = self.resolve_state_index(parsed_did_url)?;
+        if options.follow_canonical_id
+            && !parsed_did_url.has_version_query()
+            && let Some(canonical_id) = metadata.canonical_id
+        {
+            return self.follow_canonical_id(canonical_id, options).await;
+        }
+        Ok((&self.log_entries[index].log_entry, metadata))
+    }
+
     /// Like [`resolve()`](Self::resolve), but returns owned (cloned) values
     /// so the caller does not borrow `self`.
     pub async fn resolve_owned(
@@ -556,6 +615,47 @@ impl DIDWebVHState {
         let (entry, metadata) = self.resolve(did, options).await?;
         Ok((entry.clone(), metadata))
     }
+
+    /// Resolve `canonical_id` from its own location and replace this state
+    /// with the result, provided it is the same DID (same SCID) and its log
+    /// extends the one already resolved.
+    async fn follow_canonical_id(
+        &mut self,
+        canonical_id: String,
+        options: ResolveOptions,
+    ) -> Result<(&LogEntry, MetaData), DIDWebVHError> {
+        let mut followed = DIDWebVHState::default();
+        let options = ResolveOptions {
+            follow_canonical_id: false,
+            ..options
+        };
+        let (_, mut metadata) = Box::pin(followed.resolve_owned(&canonical_id, options)).await?;
+
+        if followed.scid != self.scid {
+            return Err(DIDWebVHError::ValidationError(format!(
+                "canonicalId ({canonical_id}) has SCID {}, expected {}",
+                followed.scid, self.scid
+            )));
+        }
+        let extends = followed.log_entries.len() >= self.log_entries.len()
+            && self
+                .log_entries
+                .iter()
+                .zip(&followed.log_entries)
+                .all(|(a, b)| a.get_version_id() == b.get_version_id());
+        if !extends {
+            return Err(DIDWebVHError::ValidationError(format!(
+                "Log at canonicalId ({canonical_id}) does not extend the log at the original location"
+            )));
+        }
+
+        *self = followed;
+        metadata.canonical_id = Some(canonical_id);
+        let last = self.log_entries.last().ok_or_else(|| {
+            DIDWebVHError::NotFound("No LogEntries found after validation".to_string())
+        })?;
+        Ok((&last.log_entry, metadata))
+    }
 }
 
 impl DIDWebVHState {
@@ -563,6 +663,16 @@ impl DIDWebVHState {
         &mut self,
         parsed_did_url: &WebVHURL,
     ) -> Result<(&LogEntry, MetaData), DIDWebVHError> {
+        let (index, metadata) = self.resolve_state_index(parsed_did_url)?;
+        Ok((&self.log_entries[index].log_entry, metadata))
+    }
+
+    /// Validates the state and returns the index of the log entry that
+    /// `parsed_did_url` resolves to, with its metadata.
+    fn resolve_state_index(
+        &mut self,
+        parsed_did_url: &WebVHURL,
+    ) -> Result<(usize, MetaData), DIDWebVHError> {
         let _span = span!(Level::DEBUG, "resolve_state").entered();
         // A resolver MUST reject a truncated log — a partial resolution is
         // worse than no resolution because the caller cannot tell the
@@ -601,31 +711,44 @@ impl DIDWebVHState {
         }
 
         // DID is fully validated
-        if parsed_did_url.query_version_id.is_some()
-            || parsed_did_url.query_version_time.is_some()
-            || parsed_did_url.query_version_number.is_some()
-        {
+        let (index, mut metadata) = if parsed_did_url.has_version_query() {
             match self.get_specific_log_entry(
                 parsed_did_url.query_version_id.as_deref(),
                 parsed_did_url.query_version_time,
                 parsed_did_url.query_version_number,
             ) {
                 Ok(entry) => {
-                    let metadata = self.generate_meta_data(entry);
-                    Ok((&entry.log_entry, metadata))
+                    let index = self
+                        .log_entries
+                        .iter()
+                        .position(|e| e.version_number == entry.version_number)
+                        .unwrap_or_default();
+                    (index, self.generate_meta_data(entry))
+                }
+                Err(e) => {
+                    return Err(DIDWebVHError::NotFound(format!(
+                        "Query matched no log entry: {e}"
+                    )));
                 }
-                Err(e) => Err(DIDWebVHError::NotFound(format!(
-                    "Query matched no log entry: {e}"
-                ))),
             }
         } else if let Some(last) = self.log_entries.last() {
-            let metadata = self.generate_meta_data(last);
-            Ok((&last.log_entry, metadata))
+            (self.log_entries.len() - 1, self.generate_meta_data(last))
         } else {
-            Err(DIDWebVHError::NotFound(
+            return Err(DIDWebVHError::NotFound(
                 "No LogEntries found after validation".to_string(),
-            ))
-        }
+            ));
+        };
+
+        // If the DID has since moved, the latest document's `id` is where it lives now
+        metadata.canonical_id = self
+            .log_entries
+            .last()
+            .and_then(|e| e.get_state().get("id"))
+            .and_then(|v| v.as_str())
+            .filter(|id| *id != resolved_did)
+            .map(str::to_string);
+
+        Ok((index, metadata))
     }
 }
 
@@ -709,6 +832,112 @@ mod tests {
         assert!(result.is_ok(), "eager resolve failed: {result:?}");
     }
 
+    /// A DID that moved to a new path on the same host: the old location
+    /// serves the log up to the migration, the new location has moved on.
+    /// Without `follow_canonical_id` the old log is returned with the new DID
+    /// as `canonicalId`; with it, the new location's longer log is returned.
+    #[tokio::test]
+    async fn resolve_follows_canonical_id() {
+        use crate::{
+            parameters::Parameters,
+            test_utils::{did_doc_with_key, key_and_params},
+            update::{UpdateDIDConfig, update_did},
+        };
+        use chrono::{Duration, Utc};
+
+        let server = MockServer::start().await;
+        let port = server.address().port();
+        let t0 = Utc::now().fixed_offset() - Duration::hours(1);
+
+        let (key, params) = key_and_params();
+        let params = Parameters {
+            portable: Some(true),
+            ..params
+        };
+        let doc = did_doc_with_key(&format!("did:webvh:{{SCID}}:localhost%3A{port}"), &key);
+        let mut state = DIDWebVHState::default();
+        state
+            .create_log_entry(Some(t0), &doc, &params, &key)
+            .await
+            .unwrap();
+        let old_did = format!("did:webvh:{}:localhost%3A{port}", state.scid());
+
+        let state = update_did(
+            UpdateDIDConfig::builder()
+                .state(state)
+                .signing_key(key.clone())
+                .migrate_to("/moved")
+                .version_time(t0 + Duration::minutes(1))
+                .build()
+                .unwrap(),
+        )
+        .await
+        .unwrap()
+        .into_state();
+        let new_did = format!("{}:moved", old_did);
+        let jsonl = |state: &DIDWebVHState| {
+            state
+                .log_entries()
+                .iter()
+                .map(|e| serde_json::to_string(&e.log_entry).unwrap() + "\n")
+                .collect::<String>()
+        };
+        let old_log = jsonl(&state);
+
+        let state = update_did(
+            UpdateDIDConfig::builder()
+                .state(state)
+                .signing_key(key)
+                .ttl(300)
+                .version_time(t0 + Duration::minutes(2))
+                .build()
+                .unwrap(),
+        )
+        .await
+        .unwrap()
+        .into_state();
+
+        Mock::given(path("/.well-known/did.jsonl"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(old_log))
+            .mount(&server)
+            .await;
+        Mock::given(path("/moved/did.jsonl"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(jsonl(&state)))
+            .mount(&server)
+            .await;
+
+        let mut webvh = DIDWebVHState::default();
+        let (entry, metadata) = webvh
+            .resolve(&old_did, ResolveOptions::default())
+            .await
+            .unwrap();
+        assert_eq!(entry.get_version_id_fields().unwrap().0, 2);
+        assert_eq!(metadata.canonical_id.as_deref(), Some(new_did.as_str()));
+
+        let mut webvh = DIDWebVHState::default();
+        let (entry, metadata) = webvh
+            .resolve(
+                &old_did,
+                ResolveOptions {
+                    follow_canonical_id: true,
+                    ..ResolveOptions::default()
+                },
+            )
+            .await
+            .unwrap();
+        assert_eq!(entry.get_version_id_fields().unwrap().0, 3);
+        assert_eq!(metadata.canonical_id.as_deref(), Some(new_did.as_str()));
+        assert_eq!(webvh.log_entries().len(), 3);
+
+        // Resolving the new DID directly reports no canonicalId
+        let mut webvh = DIDWebVHState::default();
+        let (_, metadata) = webvh
+            .resolve(&new_did, ResolveOptions::default())
+            .await
+            .unwrap();
+        assert!(metadata.canonical_id.is_none());
+    }
+
     /// Resolve a specific versionId served from a local mock server.
     #[tokio::test]
     async fn resolve_mock_specific_version() {
@@ -0,0 +1,323 @@
+//! Resolving `did:scid:vh` DIDs through the WebVH log they point at.
+//!
+//! `did:scid:vh:1:<SCID>?src=<host/path>` names a WebVH DID by its SCID, with
+//! a hint of where its log is published (see
+//! [`DIDWebVHState::convert_webvh_id_to_scid_id`]).
+
+use crate::{
+    DIDWebVHError, DIDWebVHState,
+    log_entry::{LogEntry, MetaData},
+    resolve::ResolveOptions,
+};
+use tracing::{Instrument, Level, span};
+
+/// Query parameters passed through to the WebVH DID.
+const VERSION_QUERY_KEYS: [&str; 3] = ["versionId", "versionTime", "versionNumber"];
+
+/// The parts of a `did:scid:vh` DID needed to find its log.
+#[derive(Debug, PartialEq, Eq)]
+struct ScidDid {
+    scid: String,
+    src: Option<String>,
+    /// `key=value` version selectors, in the order given.
+    version_query: Vec<String>,
+}
+
+impl ScidDid {
+    fn parse(did: &str) -> Result<Self, DIDWebVHError> {
+        let Some(rest) = did.strip_prefix("did:scid:vh:") else {
+            return Err(if did.starts_with("did:") {
+                DIDWebVHError::UnsupportedMethod(format!("Expected did:scid:vh, got: {did}"))
+            } else {
+                DIDWebVHError::InvalidMethodIdentifier(format!("Not a DID: {did}"))
+            });
+        };
+        let (version, rest) = rest.split_once(':').ok_or_else(|| {
+            DIDWebVHError::InvalidMethodIdentifier(format!("{did} is missing the SCID"))
+        })?;
+        if version != "1" {
+            return Err(DIDWebVHError::InvalidMethodIdentifier(format!(
+                "Unsupported did:scid:vh version ({version}), expected 1"
+            )));
+        }
+
+        let rest = rest.split_once('#').map_or(rest, |(rest, _)| rest);
+        let (scid, query) = rest.split_once('?').unwrap_or((rest, ""));
+        if scid.is_empty() || scid.contains(':') {
+            return Err(DIDWebVHError::InvalidMethodIdentifier(format!(
+                "{did} has an invalid SCID"
+            )));
+        }
+
+        let mut src = None;
+        let mut version_query = Vec::new();
+        for pair in query.split('&').filter(|p| !p.is_empty()) {
+            match pair.split_once('=') {
+                Some(("src", value)) if !value.is_empty() => src = Some(value.to_string()),
+                Some((key, _)) if VERSION_QUERY_KEYS.contains(&key) => {
+                    version_query.push(pair.to_string());
+                }
+                Some(_) => {}
+                None => {
+                    return Err(DIDWebVHError::DIDError(format!(
+                        "DID Query parameter ({pair}) is invalid. Must be in the format key=value."
+                    )));
+                }
+            }
+        }
+
+        Ok(Self {
+            scid: scid.to_string(),
+            src,
+            version_query,
+        })
+    }
+
+    /// The WebVH DID (without query) published at `source`.
+    fn webvh_did(&self, source: &str) -> String {
+        format!(
+            "did:webvh:{}:{}",
+            self.scid,
+            source.trim_matches('/').replace('/', ":")
+        )
+    }
+}
+
+impl DIDWebVHState {
+    /// Resolves a `did:scid:vh` DID (`did:scid:vh:1:<SCID>?src=<host/path>`).
+    ///
+    /// The `src` hint, then each of [`ResolveOptions::scid_sources`], is
+    /// tried as the location of `did:webvh:<SCID>:<location>` until one
+    /// resolves and its SCID matches. `versionId`, `versionTime` and
+    /// `versionNumber` queries carry over. The result is that of
+    /// [`resolve`](Self::resolve), with `equivalent_id` set to the WebVH DID
+    /// it was found as.
+    ///
+    /// If no location resolves, a verification failure is returned ahead of
+    /// "not found" or network errors.
+    pub async fn resolve_scid(
+        &mut self,
+        did: &str,
+        options: ResolveOptions,
+    ) -> Result<(&LogEntry, MetaData), DIDWebVHError> {
+        let _span = span!(Level::DEBUG, "resolve_scid", DID = did);
+        async move {
+            let parsed = ScidDid::parse(did)?;
+            let mut sources: Vec<&str> = parsed.src.iter().map(String::as_str).collect();
+            for source in &options.scid_sources {
+                if !sources.contains(&source.as_str()) {
+                    sources.push(source);
+                }
+            }
+            if sources.is_empty() {
+                return Err(DIDWebVHError::DIDError(format!(
+                    "{did} has no src hint and no scid_sources were given"
+                )));
+            }
+
+            let mut failures = Vec::new();
+            for source in sources {
+                let webvh_did = parsed.webvh_did(source);
+                let mut query = webvh_did.clone();
+                for (i, pair) in parsed.version_query.iter().enumerate() {
+                    query.push(if i == 0 { '?' } else { '&' });
+                    query.push_str(pair);
+                }
+
+                let mut state = DIDWebVHState::default();
+                let (_, mut metadata) = match state.resolve_owned(&query, options.clone()).await {
+                    Ok(resolved) => resolved,
+                    Err(e) => {
+                        failures.push((source, e));
+                        continue;
+                    }
+                };
+                if metadata.scid != parsed.scid {
+                    failures.push((
+                        source,
+                        DIDWebVHError::SCIDError(format!(
+                            "Log at {source} has SCID {}, expected {}",
+                            metadata.scid, parsed.scid
+                        )),
+                    ));
+                    continue;
+                }
+
+                metadata.equivalent_id = Some(vec![webvh_did]);
+                *self = state;
+                let entry = self
+                    .log_entries
+                    .iter()
+                    .find(|e| e.version_number == metadata.version_number)
+                    .ok_or_else(|| {
+                        DIDWebVHError::NotFound("Resolved log entry is missing".to_string())
+                    })?;
+                return Ok((&entry.log_entry, metadata));
+            }
+
+            if failures.len() == 1 {
+                return Err(failures.remove(0).1);
+            }
+            if let Some(i) = failures.iter().position(|(_, e)| {
+                !matches!(
+                    e,
+                    DIDWebVHError::NotFound(_) | DIDWebVHError::NetworkError { .. }
+                )
+            }) {
+                return Err(failures.remove(i).1);
+            }
+            Err(DIDWebVHError::NotFound(format!(
+                "{did} not found at any source: {}",
+                failures
+                    .iter()
+                    .map(|(source, e)| format!("{source}: {e}"))
+                    .collect::<Vec<_>>()
+                    .join("; ")
+            )))
+        }
+        .instrument(_span)
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ScidDid;
+    use crate::{
+        DIDWebVHError, DIDWebVHState,
+        log_entry::LogEntryMethods,
+        resolve::ResolveOptions,
+        test_utils::{did_doc_with_key, key_and_params},
+    };
+    use chrono::{Duration, Utc};
+    use wiremock::{Mock, MockServer, ResponseTemplate, matchers::path};
+
+    /// Host a two-entry log at `/dids/alice/` on a mock server, returning the
+    /// server (keep it alive) and the WebVH DID.
+    async fn hosted_did() -> (MockServer, String) {
+        let server = MockServer::start().await;
+        let port = server.address().port();
+        let t0 = Utc::now().fixed_offset() - Duration::hours(1);
+
+        let (key, params) = key_and_params();
+        let doc = did_doc_with_key(
+            &format!("did:webvh:{{SCID}}:localhost%3A{port}:dids:alice"),
+            &key,
+        );
+        let mut state = DIDWebVHState::default();
+        state
+            .create_log_entry(Some(t0), &doc, &params, &key)
+            .await
+            .unwrap();
+        let did = format!("did:webvh:{}:localhost%3A{port}:dids:alice", state.scid());
+        let mut doc = state.log_entries()[0].get_state().clone();
+        doc["alsoKnownAs"] = serde_json::json!([DIDWebVHState::convert_webvh_id_to_scid_id(&did)]);
+        state
+            .create_log_entry(Some(t0 + Duration::minutes(1)), &doc, &params, &key)
+            .await
+            .unwrap();
+        let jsonl: String = state
+            .log_entries()
+            .iter()
+            .map(|e| serde_json::to_string(&e.log_entry).unwrap() + "\n")
+            .collect();
+
+        Mock::given(path("/dids/alice/did.jsonl"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(jsonl))
+            .mount(&server)
+            .await;
+        (server, did)
+    }
+
+    #[test]
+    fn parse_scid_did() {
+        assert_eq!(
+            ScidDid::parse("did:scid:vh:1:QmAbc?src=example.com/dids/alice&versionNumber=2#key-0")
+                .unwrap(),
+            ScidDid {
+                scid: "QmAbc".to_string(),
+                src: Some("example.com/dids/alice".to_string()),
+                version_query: vec!["versionNumber=2".to_string()],
+            }
+        );
+        assert_eq!(
+            ScidDid::parse("did:scid:vh:1:QmAbc")
+                .unwrap()
+                .webvh_did("example.com/dids/alice/"),
+            "did:webvh:QmAbc:example.com:dids:alice"
+        );
+        assert!(matches!(
+            ScidDid::parse("did:webvh:QmAbc:example.com"),
+            Err(DIDWebVHError::UnsupportedMethod(_))
+        ));
+        assert!(ScidDid::parse("did:scid:vh:2:QmAbc").is_err());
+        assert!(ScidDid::parse("did:scid:vh:1:?src=example.com").is_err());
+    }
+
+    #[tokio::test]
+    async fn resolves_through_src_hint() {
+        let (_server, did) = hosted_did().await;
+        let scid_did = DIDWebVHState::convert_webvh_id_to_scid_id(&did);
+
+        let mut state = DIDWebVHState::default();
+        let (entry, metadata) = state
+            .resolve_scid(&scid_did, ResolveOptions::default())
+            .await
+            .unwrap();
+        assert_eq!(entry.get_state()["id"], did.as_str());
+        assert_eq!(metadata.version_number, 2);
+        assert_eq!(metadata.equivalent_id, Some(vec![did.clone()]));
+
+        let (_, metadata) = DIDWebVHState::default()
+            .resolve_scid(
+                &format!("{scid_did}&versionNumber=1"),
+                ResolveOptions::default(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(metadata.version_number, 1);
+    }
+
+    #[tokio::test]
+    async fn falls_back_to_alternate_sources() {
+        let (server, did) = hosted_did().await;
+        let dead = MockServer::start().await;
+        let scid = did.split(':').nth(2).unwrap();
+        let scid_did = format!(
+            "did:scid:vh:1:{scid}?src=localhost%3A{}/dids/alice",
+            dead.address().port()
+        );
+        let options = ResolveOptions {
+            scid_sources: vec![format!(
+                "localhost%3A{}/dids/alice",
+                server.address().port()
+            )],
+            ..Default::default()
+        };
+
+        let (_, metadata) = DIDWebVHState::default()
+            .resolve_scid(&scid_did, options.clone())
+            .await
+            .unwrap();
+        assert_eq!(metadata.equivalent_id, Some(vec![did]));
+
+        // A different SCID: the log found doesn't verify as that DID, which
+        // is reported ahead of the unreachable src
+        let err = DIDWebVHState::default()
+            .resolve_scid(
+                &format!(
+                    "did:scid:vh:1:QmOther?src=localhost%3A{}",
+                    dead.address().port()
+                ),
+                options,
+            )
+            .await
+            .unwrap_err();
+        assert!(matches!(err, DIDWebVHError::ValidationError(_)), "{err}");
+        let err = DIDWebVHState::default()
+            .resolve_scid("did:scid:vh:1:QmOther", ResolveOptions::default())
+            .await
+            .unwrap_err();
+        assert!(matches!(err, DIDWebVHError::DIDError(_)));
+    }
+}
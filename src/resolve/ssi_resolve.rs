@@ -1,6 +1,20 @@
 /*!
 *   Resolver trait methods for webvh derived from the SpruiceID SSI Library
 *
+*   - [`WebVHResolver`] resolves `did:webvh` with configurable [`ResolveOptions`]
+*   - [`DIDWebFallback`] resolves `did:web` identifiers through the did:webvh
+*     log published at the same location
+*   - [`DIDWebVH`] resolves `did:webvh` with the default options
+*
+*   Resolvers compose the usual `ssi` way, e.g.
+*   `(WebVHResolver::new(), DIDWebFallback::new()).resolve(did)`.
+*
+*   The returned document includes the implicit `#files` and `#whois`
+*   services. `versionId` and `versionTime` resolution options (and
+*   `versionNumber`, or a query on the DID itself) select a version. The
+*   `ssi` document metadata only carries `deactivated`; use
+*   [`WebVHResolver::resolve_with_metadata`] for the full [`MetaData`].
+*
 *   NOTE: This is a naïve implementation that will download the DID information on every resolve
 *
 *   If you want greater control and caching then please use the DIDWebVHState.resolve() method directly
@@ -8,74 +22,509 @@
 
 use crate::{
     DIDWebVHError, DIDWebVHState,
-    log_entry::LogEntryMethods,
-    resolve::{DIDWebVH, ResolveOptions},
+    did_web::to_web_did,
+    log_entry::{LogEntryMethods, MetaData},
+    resolve::{DIDWebVH, ResolveOptions, web::WebDIDVerification},
 };
+use serde_json::Value;
 use ssi::dids::{
     DIDMethod, DIDMethodResolver, Document,
     document::{
         self,
         representation::{self, MediaType},
     },
-    resolution::{self, Error, Options},
+    resolution::{self, Error, Options, Parameter, Parameters},
 };
+use std::time::Duration;
 use tracing::{Instrument, Level, span};
 
-impl DIDMethodResolver for DIDWebVH {
+/// Resolves `did:webvh` DIDs for the `ssi` crate with configurable
+/// [`ResolveOptions`].
+///
+/// A `network_timeout` resolution parameter (in seconds) overrides the
+/// configured timeout for a single resolve.
+#[derive(Clone, Debug, Default)]
+pub struct WebVHResolver {
+    options: ResolveOptions,
+}
+
+impl WebVHResolver {
+    /// A resolver using [`ResolveOptions::default`].
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Options used for every resolution.
+    pub fn options(mut self, options: ResolveOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Resolve `did` (which may carry a version query) to its DID document,
+    /// with implicit services, and the full resolution metadata.
+    pub async fn resolve_with_metadata(
+        &self,
+        did: &str,
+    ) -> Result<(Value, MetaData), DIDWebVHError> {
+        resolve_document(did, self.options.clone()).await
+    }
+}
+
+impl DIDMethod for WebVHResolver {
+    const DID_METHOD_NAME: &'static str = "webvh";
+}
+
+impl DIDMethodResolver for WebVHResolver {
     /// Resolves a webvh DID using the SSI Crate Traits
     /// This is a naïve implementation and will fully load the DID from source each resolve
-    ///
-    /// Does make use of Optional parameters
-    /// parameters("network_timeout") (defaults to 10 seconds): Time in seconds before timing out
     async fn resolve_method_representation<'a>(
         &'a self,
         method_specific_id: &'a str,
         options: Options,
-    ) -> Result<ssi::dids::resolution::Output<Vec<u8>>, Error> {
+    ) -> Result<resolution::Output<Vec<u8>>, Error> {
         let _span = span!(
             Level::DEBUG,
-            "DIDWebVH::resolve_method_representation",
+            "WebVHResolver::resolve_method_representation",
             method_specific_id = method_specific_id
         );
         async move {
-            let mut state = DIDWebVHState::default();
-            match state
-                .resolve(method_specific_id, ResolveOptions::default())
-                .await
-            {
-                Ok((log_entry, _)) => {
-                    let document: Document = serde_json::from_value(log_entry.get_state().clone())
-                        .map_err(|e| {
-                            Error::internal(format!("Failed to parse DID Document: {e}"))
-                        })?;
-
-                    let content_type = options.accept.unwrap_or(MediaType::Json);
-                    let represented = document.into_representation(representation::Options::Json);
-
-                    Ok(resolution::Output::new(
-                        represented.to_bytes(),
-                        document::Metadata::default(),
-                        resolution::Metadata::from_content_type(Some(content_type.to_string())),
-                    ))
-                }
-                Err(DIDWebVHError::NotFound(_)) => Err(Error::NotFound),
-                Err(e) => Err(Error::Internal(e.to_string())),
-            }
+            let did = with_version_query(
+                &format!("did:webvh:{method_specific_id}"),
+                &options.parameters,
+            );
+            let (document, metadata) =
+                resolve_document(&did, resolve_options(&self.options, &options.parameters)?)
+                    .await
+                    .map_err(ssi_error)?;
+            output(document, &metadata, options.accept)
         }
         .instrument(_span)
         .await
     }
 }
 
+/// Resolves `did:web` identifiers for the `ssi` crate through the did:webvh
+/// log published at the same location.
+///
+/// The served `did.json` is fetched and checked against the `did:webvh` log
+/// behind it ([`DIDWebVHState::resolve_web`]); a document that doesn't match
+/// the log is an error. A version query selects an entry of that log, in
+/// did:web form (as [`DIDWebVHState::to_web_did`] would publish it). A
+/// `did:web` with no log at its location is `NotFound`, so legacy did:web
+/// DIDs need a plain did:web resolver as well.
+#[derive(Clone, Debug, Default)]
+pub struct DIDWebFallback {
+    options: ResolveOptions,
+}
+
+impl DIDWebFallback {
+    /// A resolver using [`ResolveOptions::default`].
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Options used for every resolution.
+    pub fn options(mut self, options: ResolveOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Resolve a `did:web` DID (which may carry a version query) to its
+    /// did:web document and the resolution metadata of the did:webvh log
+    /// behind it.
+    pub async fn resolve_with_metadata(
+        &self,
+        did: &str,
+    ) -> Result<(Value, MetaData), DIDWebVHError> {
+        let Some(method_specific_id) = did.strip_prefix("did:web:") else {
+            return Err(DIDWebVHError::UnsupportedMethod(format!(
+                "Expected did:web, got: {did}"
+            )));
+        };
+        resolve_web_document(method_specific_id, self.options.clone()).await
+    }
+}
+
+impl DIDMethod for DIDWebFallback {
+    const DID_METHOD_NAME: &'static str = "web";
+}
+
+impl DIDMethodResolver for DIDWebFallback {
+    async fn resolve_method_representation<'a>(
+        &'a self,
+        method_specific_id: &'a str,
+        options: Options,
+    ) -> Result<resolution::Output<Vec<u8>>, Error> {
+        let _span = span!(
+            Level::DEBUG,
+            "DIDWebFallback::resolve_method_representation",
+            method_specific_id = method_specific_id
+        );
+        async move {
+            let id = with_version_query(method_specific_id, &options.parameters);
+            let (document, metadata) =
+                resolve_web_document(&id, resolve_options(&self.options, &options.parameters)?)
+                    .await
+                    .map_err(ssi_error)?;
+            output(document, &metadata, options.accept)
+        }
+        .instrument(_span)
+        .await
+    }
+}
+
+impl DIDMethodResolver for DIDWebVH {
+    /// Resolves a webvh DID using the SSI Crate Traits, with the default
+    /// [`ResolveOptions`]. See [`WebVHResolver`].
+    async fn resolve_method_representation<'a>(
+        &'a self,
+        method_specific_id: &'a str,
+        options: Options,
+    ) -> Result<resolution::Output<Vec<u8>>, Error> {
+        WebVHResolver::default()
+            .resolve_method_representation(method_specific_id, options)
+            .await
+    }
+}
+
 impl DIDMethod for DIDWebVH {
     const DID_METHOD_NAME: &'static str = "webvh";
 }
 
+/// Resolve a did:webvh DID to its document (with implicit services).
+async fn resolve_document(
+    did: &str,
+    options: ResolveOptions,
+) -> Result<(Value, MetaData), DIDWebVHError> {
+    let mut state = DIDWebVHState::default();
+    let (log_entry, metadata) = state.resolve(did, options).await?;
+    Ok((log_entry.get_did_document()?, metadata))
+}
+
+/// Resolve a did:web method-specific id through the did:webvh log at the
+/// same location.
+///
+/// The served `did.json` is checked against the log by
+/// [`DIDWebVHState::resolve_web`] and returned as is; a version query is
+/// answered from the log that call already downloaded.
+async fn resolve_web_document(
+    method_specific_id: &str,
+    options: ResolveOptions,
+) -> Result<(Value, MetaData), DIDWebVHError> {
+    let (location, query) = match method_specific_id.split_once('?') {
+        Some((location, query)) => (location, Some(query)),
+        None => (method_specific_id, None),
+    };
+    let mut state = DIDWebVHState::default();
+    let (served, verification) = state
+        .resolve_web(&format!("did:web:{location}"), options.clone())
+        .await?;
+    let WebDIDVerification::Verified {
+        webvh_did,
+        metadata,
+    } = verification
+    else {
+        return Err(DIDWebVHError::NotFound(format!(
+            "No did:webvh log at {location}"
+        )));
+    };
+    let Some(query) = query else {
+        return Ok((served, *metadata));
+    };
+    let witness_proofs = state.witness_proofs_json()?;
+    let (entry, metadata) = DIDWebVHState::default()
+        .resolve_log_owned(
+            &format!("{webvh_did}?{query}"),
+            &state.log_jsonl()?,
+            witness_proofs.as_deref(),
+        )
+        .await?;
+    Ok((to_web_did(entry.get_state())?, metadata))
+}
+
+/// Append the version selected by the resolution parameters to `did`.
+fn with_version_query(did: &str, parameters: &Parameters) -> String {
+    let version_number = parameters
+        .additional
+        .get("versionNumber")
+        .and_then(Parameter::as_string);
+    let mut did = did.to_string();
+    for (key, value) in [
+        ("versionId", parameters.version_id.as_deref()),
+        ("versionTime", parameters.version_time.as_deref()),
+        ("versionNumber", version_number),
+    ] {
+        if let Some(value) = value {
+            did.push(if did.contains('?') { '&' } else { '?' });
+            did.push_str(key);
+            did.push('=');
+            did.push_str(value);
+        }
+    }
+    did
+}
+
+/// `options` with the `network_timeout` resolution parameter applied.
+fn resolve_options(
+    options: &ResolveOptions,
+    parameters: &Parameters,
+) -> Result<ResolveOptions, Error> {
+    let mut options = options.clone();
+    if let Some(timeout) = parameters.additional.get("network_timeout") {
+        let seconds = timeout
+            .as_string()
+            .and_then(|s| s.parse().ok())
+            .ok_or(Error::InvalidOptions)?;
+        options.timeout = Some(Duration::from_secs(seconds));
+    }
+    Ok(options)
+}
+
+/// Build the `ssi` resolution output in the accepted representation.
+fn output(
+    document: Value,
+    metadata: &MetaData,
+    accept: Option<MediaType>,
+) -> Result<resolution::Output<Vec<u8>>, Error> {
+    let document_metadata = document::Metadata {
+        deactivated: metadata.deactivated.then_some(true),
+    };
+    let content_type = accept.unwrap_or(MediaType::Json);
+    let bytes = match content_type {
+        // The document is already JSON-LD, `@context` included
+        MediaType::JsonLd => serde_json::to_vec(&document)
+            .map_err(|e| Error::internal(format!("Failed to serialize DID Document: {e}")))?,
+        MediaType::Json => {
+            let document: Document = serde_json::from_value(document)
+                .map_err(|e| Error::internal(format!("Failed to parse DID Document: {e}")))?;
+            document
+                .into_representation(representation::Options::Json)
+                .to_bytes()
+        }
+    };
+    Ok(resolution::Output::new(
+        bytes,
+        document_metadata,
+        resolution::Metadata::from_content_type(Some(content_type.to_string())),
+    ))
+}
+
+/// Map a resolution failure to the matching `ssi` error kind.
+fn ssi_error(e: DIDWebVHError) -> Error {
+    match e {
+        DIDWebVHError::NotFound(_)
+        | DIDWebVHError::NetworkError {
+            status_code: Some(404 | 410),
+            ..
+        } => Error::NotFound,
+        DIDWebVHError::UnsupportedMethod(method) => Error::MethodNotSupported(method),
+        DIDWebVHError::InvalidMethodIdentifier(_) | DIDWebVHError::DIDError(_) => {
+            Error::InvalidMethodSpecificId(e.to_string())
+        }
+        e => Error::Internal(e.to_string()),
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use ssi::dids::{DID, DIDResolver};
+    use super::{DIDWebFallback, WebVHResolver};
+    use crate::{
+        DIDWebVHState,
+        resolve::{DIDWebVH, ResolveOptions},
+        test_utils::{did_doc_with_key, key_and_params},
+        update::{UpdateDIDConfig, update_did},
+    };
+    use chrono::{Duration, Utc};
+    use ssi::dids::{
+        DID, DIDResolver,
+        document::representation::MediaType,
+        resolution::{ErrorKind, Options, Parameter},
+    };
+    use wiremock::{Mock, MockServer, ResponseTemplate, matchers::path};
 
-    use crate::resolve::DIDWebVH;
+    /// Host a two-entry log on a mock server, returning the server (keep it
+    /// alive) and the DID. The second entry deactivates the DID if asked.
+    async fn hosted_did(deactivate: bool) -> (MockServer, String) {
+        let server = MockServer::start().await;
+        let port = server.address().port();
+        let t0 = Utc::now().fixed_offset() - Duration::hours(1);
+
+        let (key, params) = key_and_params();
+        let doc = did_doc_with_key(&format!("did:webvh:{{SCID}}:localhost%3A{port}"), &key);
+        let mut state = DIDWebVHState::default();
+        state
+            .create_log_entry(Some(t0), &doc, &params, &key)
+            .await
+            .unwrap();
+        let did = format!("did:webvh:{}:localhost%3A{port}", state.scid());
+
+        let config = UpdateDIDConfig::builder()
+            .state(state)
+            .signing_key(key)
+            .version_time(t0 + Duration::minutes(1));
+        let config = if deactivate {
+            config.deactivate(true)
+        } else {
+            config.ttl(300)
+        };
+        let state = update_did(config.build().unwrap())
+            .await
+            .unwrap()
+            .into_state();
+        let jsonl = state.log_jsonl().unwrap();
+
+        Mock::given(path("/.well-known/did.jsonl"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(jsonl))
+            .mount(&server)
+            .await;
+        Mock::given(path("/.well-known/did.json"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(state.to_web_did().unwrap()))
+            .mount(&server)
+            .await;
+        (server, did)
+    }
+
+    #[tokio::test]
+    async fn metadata_and_implicit_services() {
+        let (_server, did) = hosted_did(true).await;
+        let output = WebVHResolver::new()
+            .resolve(DID::new(&did).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(output.document_metadata.deactivated, Some(true));
+        assert!(
+            output
+                .document
+                .service
+                .iter()
+                .any(|s| s.id.as_str() == format!("{did}#files"))
+        );
+
+        let (document, metadata) = WebVHResolver::new()
+            .resolve_with_metadata(&did)
+            .await
+            .unwrap();
+        assert_eq!(document["id"], did.as_str());
+        assert!(metadata.deactivated);
+        assert_eq!(metadata.version_number, 2);
+
+        // DIDWebVH keeps working with the defaults
+        let output = DIDWebVH.resolve(DID::new(&did).unwrap()).await.unwrap();
+        assert_eq!(output.document_metadata.deactivated, Some(true));
+    }
+
+    #[tokio::test]
+    async fn version_parameters_select_a_version() {
+        let (_server, did) = hosted_did(false).await;
+        let resolver = WebVHResolver::new().options(ResolveOptions {
+            timeout: Some(std::time::Duration::from_secs(5)),
+            ..Default::default()
+        });
+
+        let (_, metadata) = resolver
+            .resolve_with_metadata(&format!("{did}?versionNumber=1"))
+            .await
+            .unwrap();
+        assert_eq!(metadata.version_number, 1);
+
+        let mut options = Options::default();
+        options.parameters.additional.insert(
+            "versionNumber".to_string(),
+            Parameter::String("9".to_string()),
+        );
+        let err = resolver
+            .resolve_with(DID::new(&did).unwrap(), options)
+            .await
+            .unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::NotFound);
+
+        let mut options = Options {
+            accept: Some(MediaType::JsonLd),
+            ..Default::default()
+        };
+        options.parameters.version_id = Some(metadata.version_id.clone());
+        let output = resolver
+            .resolve_representation(DID::new(&did).unwrap(), options)
+            .await
+            .unwrap();
+        assert_eq!(
+            output.metadata.content_type.as_deref(),
+            Some("application/did+ld+json")
+        );
+        let document: serde_json::Value = serde_json::from_slice(&output.document).unwrap();
+        assert!(document.get("@context").is_some());
+    }
+
+    #[tokio::test]
+    async fn errors_map_to_ssi_kinds() {
+        let server = MockServer::start().await;
+        let port = server.address().port();
+        let resolver = WebVHResolver::new();
+
+        let missing = format!("did:webvh:QmMissing:localhost%3A{port}");
+        let err = resolver
+            .resolve(DID::new(&missing).unwrap())
+            .await
+            .unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::NotFound);
+
+        let err = resolver
+            .resolve(DID::new("did:webvh:scid").unwrap())
+            .await
+            .unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidMethodSpecificId);
+
+        let err = resolver
+            .resolve(DID::new("did:key:z6Mk").unwrap())
+            .await
+            .unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::MethodNotSupported);
+    }
+
+    #[tokio::test]
+    async fn did_web_resolves_through_the_log() {
+        let (server, did) = hosted_did(false).await;
+        let port = server.address().port();
+        let web_did = format!("did:web:localhost%3A{port}");
+        let resolvers = (WebVHResolver::new(), DIDWebFallback::new());
+
+        let output = resolvers
+            .resolve(DID::new(&web_did).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(output.document.id.as_str(), web_did);
+        // did.json and did.jsonl, each downloaded once
+        let requests = server.received_requests().await.unwrap();
+        let downloads = |file: &str| {
+            requests
+                .iter()
+                .filter(|r| r.url.path() == format!("/.well-known/{file}"))
+                .count()
+        };
+        assert_eq!((downloads("did.json"), downloads("did.jsonl")), (1, 1));
+        let output = resolvers.resolve(DID::new(&did).unwrap()).await.unwrap();
+        assert_eq!(output.document.id.as_str(), did);
+
+        let (document, metadata) = DIDWebFallback::new()
+            .resolve_with_metadata(&format!("{web_did}?versionNumber=1"))
+            .await
+            .unwrap();
+        assert_eq!(document["controller"], did.as_str());
+        assert_eq!(metadata.version_number, 1);
+
+        let elsewhere = MockServer::start().await;
+        let err = resolvers
+            .resolve(
+                DID::new(&format!(
+                    "did:web:localhost%3A{}",
+                    elsewhere.address().port()
+                ))
+                .unwrap(),
+            )
+            .await
+            .unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::NotFound);
+    }
 
     #[tokio::test]
     #[ignore = "requires network access to identity.foundation"]
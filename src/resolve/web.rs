@@ -0,0 +1,391 @@
+//! Resolving `did:web` DIDs and checking them against the WebVH log they
+//! were exported from.
+//!
+//! [`DIDWebVHState::to_web_did`] produces the `did.json` a WebVH DID can be
+//! published alongside. [`DIDWebVHState::resolve_web`] goes the other way: it
+//! fetches a `did:web` document and, where a WebVH log stands behind it,
+//! checks that the served document is exactly what `to_web_did` gives for
+//! the latest log entry, so a tampered `did.json` is detected.
+
+use crate::{
+    DIDWebVHError, DIDWebVHState,
+    did_web::to_web_did,
+    log_entry::{LogEntryMethods, MetaData},
+    resolve::{DIDWebVH, ResolveOptions},
+    url::WebVHURL,
+};
+use serde_json::Value;
+use tracing::{Instrument, Level, span};
+
+/// How a `did:web` document resolved by [`DIDWebVHState::resolve_web`]
+/// relates to a WebVH log.
+#[derive(Clone, Debug)]
+pub enum WebDIDVerification {
+    /// The document matches the latest entry of this WebVH DID's log.
+    Verified {
+        /// The WebVH DID the document was checked against.
+        webvh_did: String,
+        /// Resolution metadata of that WebVH DID.
+        metadata: Box<MetaData>,
+    },
+    /// No `did:webvh` alias and no `did.jsonl` next to `did.json`: a plain
+    /// did:web DID that can't be checked.
+    Unverified,
+}
+
+impl WebDIDVerification {
+    /// Whether the document was checked against a WebVH log.
+    pub fn is_verified(&self) -> bool {
+        matches!(self, Self::Verified { .. })
+    }
+}
+
+/// The `host/path` location of a `did:web` DID, without query or fragment.
+fn web_location(did: &str) -> Result<&str, DIDWebVHError> {
+    let Some(rest) = did.strip_prefix("did:web:") else {
+        return Err(if did.starts_with("did:") {
+            DIDWebVHError::UnsupportedMethod(format!("Expected did:web, got: {did}"))
+        } else {
+            DIDWebVHError::InvalidMethodIdentifier(format!("Not a DID: {did}"))
+        });
+    };
+    let location = rest.split(['?', '#']).next().unwrap_or_default();
+    if location.is_empty() {
+        return Err(DIDWebVHError::InvalidMethodIdentifier(format!(
+            "{did} is missing the domain"
+        )));
+    }
+    Ok(location)
+}
+
+/// The files of a DID published at `location`. Any SCID will do to find
+/// them.
+fn location_url(location: &str) -> Result<WebVHURL, DIDWebVHError> {
+    WebVHURL::parse_did_url(&format!("did:webvh:scid:{location}"))
+}
+
+fn is_missing(e: &DIDWebVHError) -> bool {
+    matches!(
+        e,
+        DIDWebVHError::NotFound(_)
+            | DIDWebVHError::NetworkError {
+                status_code: Some(404 | 410),
+                ..
+            }
+    )
+}
+
+/// The `host/path` location of a `did:webvh` DID, without its SCID, query
+/// or fragment.
+fn webvh_location(did: &str) -> Option<&str> {
+    let (_, location) = did.strip_prefix("did:webvh:")?.split_once(':')?;
+    location.split(['?', '#']).next().filter(|l| !l.is_empty())
+}
+
+/// Top-level members that differ between `served` and `expected`.
+fn differences(served: &Value, expected: &Value) -> Vec<String> {
+    match (served.as_object(), expected.as_object()) {
+        (Some(served), Some(expected)) => {
+            let mut keys: Vec<&String> = served
+                .keys()
+                .chain(expected.keys().filter(|k| !served.contains_key(*k)))
+                .filter(|k| served.get(*k) != expected.get(*k))
+                .collect();
+            keys.sort();
+            keys.into_iter().cloned().collect()
+        }
+        _ => vec!["(document)".to_string()],
+    }
+}
+
+impl DIDWebVHState {
+    /// Resolves a `did:web` DID, checking its `did.json` against the WebVH
+    /// log behind it.
+    ///
+    /// The served document's `id` must be `did` (without query or fragment).
+    /// The WebVH DID is the first `did:webvh` entry of the document's
+    /// `alsoKnownAs`, which must be published at the same `host/path`, or
+    /// else the one whose `did.jsonl` is published next to `did.json`. It is
+    /// resolved as by [`resolve`](Self::resolve), downloading its log once
+    /// (this state then holds it), and the served document must equal
+    /// [`to_web_did`](Self::to_web_did) of its latest entry. A mismatch is a
+    /// [`DIDWebVHError::ValidationError`] naming the differing members.
+    ///
+    /// With neither an alias nor a log, the served document is returned as
+    /// [`WebDIDVerification::Unverified`].
+    pub async fn resolve_web(
+        &mut self,
+        did: &str,
+        options: ResolveOptions,
+    ) -> Result<(Value, WebDIDVerification), DIDWebVHError> {
+        let _span = span!(Level::DEBUG, "resolve_web", DID = did);
+        async move {
+            let location = web_location(did)?;
+            let client = DIDWebVH::http_client(&options)?;
+            let raw = DIDWebVH::download_file(
+                client.clone(),
+                location_url(location)?.get_http_url(Some("did.json"))?,
+                options.max_response_bytes,
+            )
+            .await?;
+            let served: Value = serde_json::from_str(&raw).map_err(|e| {
+                DIDWebVHError::DIDError(format!("did.json for {did} isn't valid JSON: {e}"))
+            })?;
+            // The document must be about the DID that was requested
+            let web_did = format!("did:web:{location}");
+            if served.get("id").and_then(Value::as_str) != Some(web_did.as_str()) {
+                return Err(DIDWebVHError::ValidationError(format!(
+                    "did.json for {did} has a different id: {}",
+                    served.get("id").unwrap_or(&Value::Null)
+                )));
+            }
+
+            let alias = served
+                .get("alsoKnownAs")
+                .and_then(Value::as_array)
+                .into_iter()
+                .flatten()
+                .filter_map(Value::as_str)
+                .find(|aka| aka.starts_with("did:webvh:"))
+                .map(str::to_string);
+            let mut state = DIDWebVHState::default();
+            let (webvh_did, entry, metadata) = match alias {
+                // Only a WebVH DID published at the same location stands
+                // behind this did:web DID
+                Some(alias) if webvh_location(&alias) != Some(location) => {
+                    return Err(DIDWebVHError::ValidationError(format!(
+                        "did.json for {did} names {alias}, which isn't published at {location}"
+                    )));
+                }
+                Some(alias) => {
+                    let (entry, metadata) = state.resolve_owned(&alias, options).await?;
+                    (alias, entry, metadata)
+                }
+                None => {
+                    let url = location_url(location)?;
+                    let log = match DIDWebVH::get_log_entries(
+                        url.clone(),
+                        client.clone(),
+                        options.max_response_bytes,
+                    )
+                    .await
+                    {
+                        Ok(log) => log,
+                        Err(e) if is_missing(&e) => {
+                            return Ok((served, WebDIDVerification::Unverified));
+                        }
+                        Err(e) => return Err(e),
+                    };
+                    let entries = DIDWebVHState::parse_log_entries(&log)?;
+                    // The SCID of the first entry names the DID
+                    let scid = entries
+                        .first()
+                        .and_then(|entry| entry.log_entry.get_parameters().scid)
+                        .ok_or_else(|| {
+                            DIDWebVHError::NotFound(format!("No did:webvh log at {location}"))
+                        })?;
+                    let webvh_did = format!("did:webvh:{scid}:{location}");
+                    DIDWebVHState::validate_log_entries(&entries, &webvh_did)?;
+
+                    let needs_witnesses = DIDWebVHState::needs_witness_proofs(&entries);
+                    if options.eager_witness_download || needs_witnesses {
+                        state.witness_proofs = DIDWebVHState::resolve_witness_proofs(
+                            DIDWebVH::get_witness_proofs(url, client, options.max_response_bytes)
+                                .await,
+                            needs_witnesses,
+                        )?;
+                    }
+                    state.log_entries = entries;
+
+                    let parsed_did_url = WebVHURL::parse_did_url(&webvh_did)?;
+                    let (entry, metadata) = state.resolve_loaded(&parsed_did_url, options).await?;
+                    let entry = entry.clone();
+                    (webvh_did, entry, metadata)
+                }
+            };
+
+            let expected = to_web_did(entry.get_state())?;
+            if served != expected {
+                return Err(DIDWebVHError::ValidationError(format!(
+                    "did.json for {did} doesn't match {webvh_did}; differing members: {}",
+                    differences(&served, &expected).join(", ")
+                )));
+            }
+
+            *self = state;
+            Ok((
+                served,
+                WebDIDVerification::Verified {
+                    webvh_did,
+                    metadata: Box::new(metadata),
+                },
+            ))
+        }
+        .instrument(_span)
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{WebDIDVerification, differences, web_location, webvh_location};
+    use crate::{
+        DIDWebVHError, DIDWebVHState,
+        resolve::ResolveOptions,
+        test_utils::{did_doc_with_key, key_and_params},
+    };
+    use serde_json::{Value, json};
+    use wiremock::{Mock, MockServer, ResponseTemplate, matchers::path};
+
+    /// Host a log at `/dids/alice/did.jsonl`, returning the server (keep it
+    /// alive), the WebVH DID and its did:web export.
+    async fn hosted_did() -> (MockServer, String, Value) {
+        let server = MockServer::start().await;
+        let port = server.address().port();
+        let (key, params) = key_and_params();
+        let doc = did_doc_with_key(
+            &format!("did:webvh:{{SCID}}:localhost%3A{port}:dids:alice"),
+            &key,
+        );
+        let mut state = DIDWebVHState::default();
+        state
+            .create_log_entry(None, &doc, &params, &key)
+            .await
+            .unwrap();
+        let did = format!("did:webvh:{}:localhost%3A{port}:dids:alice", state.scid());
+        let jsonl = serde_json::to_string(&state.log_entries()[0].log_entry).unwrap() + "\n";
+
+        Mock::given(path("/dids/alice/did.jsonl"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(jsonl))
+            .mount(&server)
+            .await;
+        (server, did, state.to_web_did().unwrap())
+    }
+
+    async fn serve_did_json(server: &MockServer, document: &Value) {
+        Mock::given(path("/dids/alice/did.json"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(document))
+            .mount(server)
+            .await;
+    }
+
+    #[test]
+    fn parse_web_location() {
+        assert_eq!(
+            web_location("did:web:example.com:dids:alice#key-0").unwrap(),
+            "example.com:dids:alice"
+        );
+        assert!(matches!(
+            web_location("did:webvh:QmAbc:example.com"),
+            Err(DIDWebVHError::UnsupportedMethod(_))
+        ));
+        assert!(web_location("did:web:").is_err());
+        assert_eq!(
+            webvh_location("did:webvh:QmAbc:example.com%3A8080:dids?versionId=1-x"),
+            Some("example.com%3A8080:dids")
+        );
+        assert_eq!(webvh_location("did:webvh:QmAbc"), None);
+        assert_eq!(
+            differences(&json!({"id": 1, "a": 2}), &json!({"id": 1, "b": 2})),
+            ["a", "b"]
+        );
+    }
+
+    #[tokio::test]
+    async fn verifies_the_exported_document() {
+        let (server, did, web_doc) = hosted_did().await;
+        serve_did_json(&server, &web_doc).await;
+
+        let mut state = DIDWebVHState::default();
+        let (document, verification) = state
+            .resolve_web(web_doc["id"].as_str().unwrap(), ResolveOptions::default())
+            .await
+            .unwrap();
+        assert_eq!(document, web_doc);
+        let WebDIDVerification::Verified {
+            webvh_did,
+            metadata,
+        } = verification
+        else {
+            panic!("expected a verified document");
+        };
+        assert_eq!(webvh_did, did);
+        assert_eq!(metadata.version_number, 1);
+        assert_eq!(state.log_entries().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn detects_a_tampered_document() {
+        let (server, _, web_doc) = hosted_did().await;
+        let web_did = web_doc["id"].as_str().unwrap().to_string();
+        // Dropping the alias still finds the log next to did.json
+        let mut tampered = web_doc.clone();
+        tampered["alsoKnownAs"] = json!([]);
+        tampered["verificationMethod"][0]["publicKeyMultibase"] = json!("zAttacker");
+        serve_did_json(&server, &tampered).await;
+
+        let err = DIDWebVHState::default()
+            .resolve_web(&web_did, ResolveOptions::default())
+            .await
+            .unwrap_err();
+        let DIDWebVHError::ValidationError(message) = err else {
+            panic!("expected a validation error, got {err}");
+        };
+        assert!(
+            message.ends_with("alsoKnownAs, verificationMethod"),
+            "{message}"
+        );
+        // Finding the log and resolving it took one download
+        let requests = server.received_requests().await.unwrap();
+        let log_downloads = requests
+            .iter()
+            .filter(|r| r.url.path() == "/dids/alice/did.jsonl")
+            .count();
+        assert_eq!(log_downloads, 1);
+    }
+
+    /// An attacker replacing did.json with the export of their own WebVH DID
+    /// hosted elsewhere.
+    #[tokio::test]
+    async fn rejects_an_alias_at_another_location() {
+        let (_attacker, attacker_did, attacker_doc) = hosted_did().await;
+        let victim = MockServer::start().await;
+        let victim_did = format!("did:web:localhost%3A{}:dids:alice", victim.address().port());
+
+        // As exported: the id gives it away
+        serve_did_json(&victim, &attacker_doc).await;
+        let err = DIDWebVHState::default()
+            .resolve_web(&victim_did, ResolveOptions::default())
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("different id"), "{err}");
+
+        // With the id rewritten, the alias still points elsewhere
+        let victim = MockServer::start().await;
+        let victim_did = format!("did:web:localhost%3A{}:dids:alice", victim.address().port());
+        let mut forged = attacker_doc.clone();
+        forged["id"] = json!(victim_did);
+        serve_did_json(&victim, &forged).await;
+        let err = DIDWebVHState::default()
+            .resolve_web(&victim_did, ResolveOptions::default())
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains(&attacker_did), "{err}");
+        assert!(err.to_string().contains("isn't published at"), "{err}");
+    }
+
+    #[tokio::test]
+    async fn plain_did_web_is_unverified() {
+        let server = MockServer::start().await;
+        let web_did = format!("did:web:localhost%3A{}:dids:alice", server.address().port());
+        let document = json!({"id": web_did});
+        serve_did_json(&server, &document).await;
+
+        let (served, verification) = DIDWebVHState::default()
+            .resolve_web(&web_did, ResolveOptions::default())
+            .await
+            .unwrap();
+        assert_eq!(served, document);
+        assert!(!verification.is_verified());
+    }
+}
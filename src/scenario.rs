@@ -0,0 +1,1221 @@
+/*!
+*   Scenario scripts in the style of the didwebvh test suite: a list of
+*   create / update / migrate / deactivate / resolve steps that is run through
+*   [`create_did`] and [`update_did`] and produces the `did.jsonl`,
+*   `did-witness.json` and `resolutionResult*.json` files of a test-suite
+*   vector.
+*
+*   Everything that is normally random or taken from the clock is fixed, so a
+*   scenario gives byte-identical files on every run:
+*
+*   - Keys are numbers. Key `n` is the Ed25519 key whose 32-byte seed is `n`
+*     (big-endian), the convention of the test suite's own generator: key `1`
+*     is `z6MkjchhfUsD6mmvni8mCdXHw216Xrm9bQe2mBH1P5RDjVJG`.
+*   - The first entry is stamped 2000-01-01T00:00:00Z and every following one
+*     a day later. A step can set its own `versionTime`.
+*   - Proofs, including witness proofs, are `created` at their entry's
+*     versionTime.
+*
+*   ```yaml
+*   description: Rotate to a pre-committed key, then deactivate
+*   steps:
+*     - op: create
+*       updateKeys: [1]
+*       nextKeys: [2]
+*       witness: { threshold: 1, witnesses: [16] }
+*     - op: update
+*       updateKeys: [2]
+*       nextKeys: [3]
+*       addServices:
+*         - { id: "#linked-domain", type: LinkedDomains, serviceEndpoint: "https://example.com" }
+*     - op: resolve
+*       versionNumber: 1
+*     - op: deactivate
+*   ```
+*
+*   ```ignore
+*   use didwebvh_rs::scenario::Scenario;
+*
+*   let output = Scenario::load("scenarios/pre-rotation.yaml")?.run().await?;
+*   output.write_to("pre-rotation")?; // generate the vector
+*   output.check("pre-rotation")?; // or check a committed one
+*   ```
+*
+*   DID Documents are laid out like the test suite's: a `Multikey`
+*   verification method per key, with the last eight characters of the key as
+*   its fragment, referenced from `authentication`. The first entry spells out
+*   every parameter.
+*
+*   The step format is this crate's own, modelled on the test suite but not
+*   its `script.yaml` (which isn't imported with the vectors in
+*   `tests/test_vectors/test_suite`), so upstream scripts aren't expected to
+*   load as they are. Against those vectors, single-entry scenarios match
+*   byte for byte. The test suite's generator restates `updateKeys`,
+*   `nextKeyHashes`, `witness` and `watchers` in every entry; a scenario with
+*   `restateParameters: true` does the same (see
+*   [`UpdateDIDConfig::restate_parameters`](crate::update::UpdateDIDConfig::restate_parameters))
+*   and then reproduces the longer vectors byte for byte too. Without it,
+*   later entries carry only the parameters that changed: they resolve the
+*   same, but their bytes and versionIds differ.
+*
+*   A `resolve` step resolves the log as it stands at that point into
+*   `resolutionResult.json`, or `resolutionResult.<versionNumber>.json` for a
+*   given version. The final log is resolved into `resolutionResult.json`
+*   unless a step already did.
+*/
+
+use crate::{
+    DIDWebVHError, DIDWebVHState, Multibase,
+    create::{CreateDIDConfig, create_did},
+    log_entry::LogEntryMethods,
+    parameters::Parameters,
+    update::{UpdateDIDConfig, update_did},
+    witness::{Witness, Witnesses},
+};
+use affinidi_secrets_resolver::secrets::Secret;
+use ahash::HashMap;
+use chrono::{DateTime, Duration, FixedOffset};
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::Path,
+    sync::Arc,
+};
+
+/// Name of the file the final (or an unversioned `resolve`) resolution is
+/// written to.
+const RESOLUTION_RESULT: &str = "resolutionResult.json";
+
+/// A test-suite scenario script.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct Scenario {
+    /// What the scenario exercises.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+
+    /// Where the DID is created. Default: `https://example.com/`.
+    #[serde(default = "default_address")]
+    pub address: String,
+
+    /// versionTime of the first log entry. Default: 2000-01-01T00:00:00Z.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub start_time: Option<DateTime<FixedOffset>>,
+
+    /// Restate `updateKeys`, `nextKeyHashes`, `witness` and `watchers` in
+    /// every entry, as the test suite's generator does, instead of writing
+    /// only the parameters that changed. See
+    /// [`UpdateDIDConfig::restate_parameters`].
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub restate_parameters: bool,
+
+    /// The steps, run in order. The first one creates the DID.
+    pub steps: Vec<Step>,
+}
+
+fn default_address() -> String {
+    "https://example.com/".to_string()
+}
+
+/// One step of a [`Scenario`], tagged by `op`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "camelCase")]
+pub enum Step {
+    /// Create the DID.
+    Create(CreateStep),
+    /// Update the DID Document and/or parameters.
+    Update(UpdateStep),
+    /// Move the DID to another address.
+    Migrate(MigrateStep),
+    /// Deactivate the DID.
+    Deactivate(DeactivateStep),
+    /// Resolve the log written so far.
+    Resolve(ResolveStep),
+}
+
+/// The `create` step.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct CreateStep {
+    /// Keys allowed to update the DID (`updateKeys`). The first one signs.
+    pub update_keys: Vec<u32>,
+
+    /// Pre-rotation keys whose hashes go into `nextKeyHashes`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub next_keys: Vec<u32>,
+
+    /// Can the DID move to another address?
+    #[serde(default)]
+    pub portable: bool,
+
+    /// Witness configuration.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub witness: Option<ScenarioWitness>,
+
+    /// Watcher URLs.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub watchers: Vec<String>,
+
+    /// Cache TTL in seconds.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ttl: Option<u32>,
+
+    /// Keys listed as verification methods. Default: `updateKeys`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub verification_methods: Option<Vec<u32>>,
+
+    /// Services of the DID Document, as given.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub services: Vec<Value>,
+
+    /// `alsoKnownAs` of the DID Document.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub also_known_as: Vec<String>,
+
+    /// versionTime of the entry, instead of the scenario clock.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version_time: Option<DateTime<FixedOffset>>,
+}
+
+/// The `update` step. Fields left out are unchanged.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct UpdateStep {
+    /// Key that signs the entry. Default: the first new `updateKeys` key
+    /// while pre-rotation is active, otherwise the first current one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signer: Option<u32>,
+
+    /// New `updateKeys`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub update_keys: Option<Vec<u32>>,
+
+    /// New pre-rotation keys. Empty turns pre-rotation off.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub next_keys: Option<Vec<u32>>,
+
+    /// New witness configuration. No witnesses turns witnessing off.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub witness: Option<ScenarioWitness>,
+
+    /// New watcher URLs.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub watchers: Option<Vec<String>>,
+
+    /// New cache TTL in seconds.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ttl: Option<u32>,
+
+    /// `false` turns portability off.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub portable: Option<bool>,
+
+    /// Keys listed as verification methods, replacing the current ones.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub verification_methods: Option<Vec<u32>>,
+
+    /// Services, replacing the current ones.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub services: Option<Vec<Value>>,
+
+    /// Services appended to the current ones.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub add_services: Vec<Value>,
+
+    /// `alsoKnownAs`, replacing the current one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub also_known_as: Option<Vec<String>>,
+
+    /// Entries appended to `alsoKnownAs`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub add_also_known_as: Vec<String>,
+
+    /// versionTime of the entry, instead of the scenario clock.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version_time: Option<DateTime<FixedOffset>>,
+}
+
+/// The `migrate` step.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct MigrateStep {
+    /// The new address: an `https://` URL or a `did:webvh` DID.
+    pub to: String,
+
+    /// Key that signs the entry. Default: as for [`UpdateStep::signer`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signer: Option<u32>,
+
+    /// New `updateKeys`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub update_keys: Option<Vec<u32>>,
+
+    /// New pre-rotation keys. Empty turns pre-rotation off.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub next_keys: Option<Vec<u32>>,
+
+    /// versionTime of the entry, instead of the scenario clock.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version_time: Option<DateTime<FixedOffset>>,
+}
+
+/// The `deactivate` step. While pre-rotation is active it writes two
+/// entries, the first ending pre-rotation one second before the second.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct DeactivateStep {
+    /// Key that signs the entry. Default: the first pre-rotation key while
+    /// pre-rotation is active, otherwise the first current `updateKeys` key.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signer: Option<u32>,
+
+    /// versionTime of the (last) entry, instead of the scenario clock.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version_time: Option<DateTime<FixedOffset>>,
+}
+
+/// The `resolve` step.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct ResolveStep {
+    /// Resolve this version instead of the latest one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version_number: Option<u32>,
+}
+
+/// Witnesses of a scenario DID, by key number.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct ScenarioWitness {
+    /// Number of witness proofs needed.
+    pub threshold: u32,
+
+    /// Witness keys.
+    pub witnesses: Vec<u32>,
+}
+
+/// The files a [`Scenario`] produced, by file name.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ScenarioOutput {
+    /// The DID as of the last entry.
+    pub did: String,
+
+    /// File name to contents: `did.jsonl`, `did-witness.json` (only with
+    /// witnesses) and the `resolutionResult*.json` files.
+    pub files: BTreeMap<String, String>,
+}
+
+impl Scenario {
+    /// Parse a JSON scenario.
+    pub fn from_json_str(scenario: &str) -> Result<Self, DIDWebVHError> {
+        serde_json::from_str(scenario)
+            .map_err(|e| DIDWebVHError::ScenarioError(format!("Invalid JSON scenario: {e}")))
+    }
+
+    /// Parse a YAML scenario.
+    #[cfg(feature = "yaml")]
+    pub fn from_yaml_str(scenario: &str) -> Result<Self, DIDWebVHError> {
+        serde_yaml_ng::from_str(scenario)
+            .map_err(|e| DIDWebVHError::ScenarioError(format!("Invalid YAML scenario: {e}")))
+    }
+
+    /// Read a scenario file, picking the format from its extension (`.json`,
+    /// or `.yaml` / `.yml` with the `yaml` feature).
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, DIDWebVHError> {
+        let path = path.as_ref();
+        let scenario = std::fs::read_to_string(path).map_err(|e| {
+            DIDWebVHError::ScenarioError(format!("Couldn't read {}: {e}", path.display()))
+        })?;
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("json") => Self::from_json_str(&scenario),
+            #[cfg(feature = "yaml")]
+            Some("yaml" | "yml") => Self::from_yaml_str(&scenario),
+            _ => Err(DIDWebVHError::ScenarioError(format!(
+                "Unsupported scenario format: {} (JSON, or YAML with the `yaml` feature)",
+                path.display()
+            ))),
+        }
+    }
+
+    /// Run the steps and return the files of the resulting vector.
+    pub async fn run(&self) -> Result<ScenarioOutput, DIDWebVHError> {
+        let start = match self.start_time {
+            Some(start) => start,
+            None => DateTime::parse_from_rfc3339("2000-01-01T00:00:00Z").map_err(|e| {
+                DIDWebVHError::ScenarioError(format!("Invalid scenario start time: {e}"))
+            })?,
+        };
+        let mut runner = Runner {
+            state: None,
+            restate_parameters: self.restate_parameters,
+            clock: start,
+            update_keys: Vec::new(),
+            next_keys: Vec::new(),
+            witness_keys: BTreeSet::new(),
+            files: BTreeMap::new(),
+        };
+
+        for (i, step) in self.steps.iter().enumerate() {
+            let result = match step {
+                Step::Create(step) => runner.create(&self.address, step).await,
+                Step::Update(step) => runner.update(step).await,
+                Step::Migrate(step) => runner.migrate(step).await,
+                Step::Deactivate(step) => runner.deactivate(step).await,
+                Step::Resolve(step) => runner.resolve(step.version_number).await,
+            };
+            result
+                .map_err(|e| DIDWebVHError::ScenarioError(format!("Step {} failed: {e}", i + 1)))?;
+        }
+        if !runner.files.contains_key(RESOLUTION_RESULT) {
+            runner.resolve(None).await?;
+        }
+        runner.finish()
+    }
+}
+
+impl ScenarioOutput {
+    /// Write the files into `dir`, creating it if needed.
+    pub fn write_to(&self, dir: impl AsRef<Path>) -> Result<(), DIDWebVHError> {
+        let dir = dir.as_ref();
+        std::fs::create_dir_all(dir).map_err(|e| {
+            DIDWebVHError::ScenarioError(format!("Couldn't create {}: {e}", dir.display()))
+        })?;
+        for (name, contents) in &self.files {
+            let path = dir.join(name);
+            crate::write_atomic(&path, contents.as_bytes()).map_err(|e| {
+                DIDWebVHError::ScenarioError(format!("Couldn't write {}: {e}", path.display()))
+            })?;
+        }
+        Ok(())
+    }
+
+    /// Check the vector in `dir` against these files. Files are compared as
+    /// JSON (line by line for `did.jsonl`), so formatting doesn't matter; the
+    /// error lists every file that is missing or differs.
+    pub fn check(&self, dir: impl AsRef<Path>) -> Result<(), DIDWebVHError> {
+        let dir = dir.as_ref();
+        let mut differences = Vec::new();
+        for (name, expected) in &self.files {
+            let path = dir.join(name);
+            match std::fs::read_to_string(&path) {
+                Ok(actual) if same_json(name, expected, &actual) => {}
+                Ok(_) => differences.push(format!("{name} differs")),
+                Err(e) => differences.push(format!("{name}: {e}")),
+            }
+        }
+        if differences.is_empty() {
+            Ok(())
+        } else {
+            Err(DIDWebVHError::ScenarioError(format!(
+                "{} doesn't match the scenario: {}",
+                dir.display(),
+                differences.join(", ")
+            )))
+        }
+    }
+}
+
+/// Key `n` of a scenario: the Ed25519 key whose 32-byte seed is `n`
+/// (big-endian), with the `did:key` verification method entries and witness
+/// proofs are signed with.
+pub fn scenario_key(n: u32) -> Result<Secret, DIDWebVHError> {
+    let mut seed = [0u8; 32];
+    seed[28..].copy_from_slice(&n.to_be_bytes());
+    let mut key = Secret::generate_ed25519(None, Some(&seed));
+    let pk = key.get_public_keymultibase().map_err(|e| {
+        DIDWebVHError::ScenarioError(format!("Couldn't get the public key of key {n}: {e}"))
+    })?;
+    key.id = format!("did:key:{pk}#{pk}");
+    Ok(key)
+}
+
+/// State carried from one step to the next.
+struct Runner {
+    state: Option<DIDWebVHState>,
+    restate_parameters: bool,
+    /// versionTime of the next entry.
+    clock: DateTime<FixedOffset>,
+    update_keys: Vec<u32>,
+    next_keys: Vec<u32>,
+    /// Every key that has been a witness, so proofs can be signed for
+    /// entries witnessed under an earlier configuration.
+    witness_keys: BTreeSet<u32>,
+    files: BTreeMap<String, String>,
+}
+
+impl Runner {
+    async fn create(&mut self, address: &str, step: &CreateStep) -> Result<(), DIDWebVHError> {
+        if self.state.is_some() {
+            return Err(DIDWebVHError::ScenarioError(
+                "The DID has already been created".to_string(),
+            ));
+        }
+        let signer = step.update_keys.first().ok_or_else(|| {
+            DIDWebVHError::ScenarioError("create needs at least one update key".to_string())
+        })?;
+        let witness = self.witness_config(step.witness.as_ref())?;
+        let parameters = Parameters {
+            update_keys: Some(Arc::new(public_keys(&step.update_keys)?)),
+            next_key_hashes: Some(Arc::new(key_hashes(&step.next_keys)?)),
+            portable: Some(step.portable),
+            witness: Some(Arc::new(witness)),
+            watchers: Some(Arc::new(step.watchers.clone())),
+            deactivated: Some(false),
+            ttl: step.ttl,
+            ..Default::default()
+        };
+        let verification_methods = step
+            .verification_methods
+            .as_deref()
+            .unwrap_or(&step.update_keys);
+        let mut document = json!({
+            "@context": [
+                "https://www.w3.org/ns/did/v1",
+                "https://w3id.org/security/multikey/v1"
+            ],
+            "id": "{DID}",
+            "controller": "{DID}",
+            "assertionMethod": [],
+            "keyAgreement": [],
+            "capabilityDelegation": [],
+            "capabilityInvocation": [],
+        });
+        set_verification_methods(&mut document, "{DID}", verification_methods)?;
+        if !step.services.is_empty() {
+            document["service"] = json!(step.services);
+        }
+        if !step.also_known_as.is_empty() {
+            document["alsoKnownAs"] = json!(step.also_known_as);
+        }
+
+        let config = CreateDIDConfig::builder()
+            .address(address)
+            .authorization_key(scenario_key(*signer)?)
+            .did_document(document)
+            .parameters(parameters)
+            .witness_secrets(self.witness_secrets()?)
+            .version_time(step.version_time.unwrap_or(self.clock))
+            .deterministic(true)
+            .build()?;
+        let result = create_did(config).await?;
+        let mut state = DIDWebVHState::from_log_entries(vec![result.log_entry().clone()]);
+        state.set_witness_proofs(result.witness_proofs().clone());
+        state.validate()?.assert_complete()?;
+
+        self.update_keys.clone_from(&step.update_keys);
+        self.next_keys.clone_from(&step.next_keys);
+        self.set_state(state)
+    }
+
+    async fn update(&mut self, step: &UpdateStep) -> Result<(), DIDWebVHError> {
+        let signer = self.signer(step.signer, step.update_keys.as_deref())?;
+        let witness = step
+            .witness
+            .as_ref()
+            .map(|witness| self.witness_config(Some(witness)))
+            .transpose()?;
+
+        let state = self.take_state()?;
+        let document = document_update(&state, step)?;
+        let mut builder = UpdateDIDConfig::builder()
+            .state(state)
+            .signing_key(scenario_key(signer)?)
+            .witness_secrets(self.witness_secrets()?)
+            .version_time(step.version_time.unwrap_or(self.clock))
+            .deterministic(true)
+            .restate_parameters(self.restate_parameters);
+        if let Some(document) = document {
+            builder = builder.document(document);
+        }
+        if let Some(keys) = &step.update_keys {
+            builder = builder.update_keys(public_keys(keys)?);
+        }
+        if let Some(keys) = &step.next_keys {
+            builder = builder.next_key_hashes(key_hashes(keys)?);
+        }
+        if let Some(witness) = witness {
+            builder = builder.witness(witness);
+        }
+        if let Some(watchers) = &step.watchers {
+            builder = builder.watchers(watchers.clone());
+        }
+        if let Some(ttl) = step.ttl {
+            builder = builder.ttl(ttl);
+        }
+        let mut config = builder.build()?;
+        config.portable = step.portable;
+        let state = update_did(config).await?.into_state();
+        self.rotate_keys(step.update_keys.as_ref(), step.next_keys.as_ref());
+        self.set_state(state)
+    }
+
+    async fn migrate(&mut self, step: &MigrateStep) -> Result<(), DIDWebVHError> {
+        let signer = self.signer(step.signer, step.update_keys.as_deref())?;
+        let mut builder = UpdateDIDConfig::builder()
+            .state(self.take_state()?)
+            .signing_key(scenario_key(signer)?)
+            .witness_secrets(self.witness_secrets()?)
+            .version_time(step.version_time.unwrap_or(self.clock))
+            .deterministic(true)
+            .restate_parameters(self.restate_parameters)
+            .migrate_to(&step.to);
+        if let Some(keys) = &step.update_keys {
+            builder = builder.update_keys(public_keys(keys)?);
+        }
+        if let Some(keys) = &step.next_keys {
+            builder = builder.next_key_hashes(key_hashes(keys)?);
+        }
+        let state = update_did(builder.build()?).await?.into_state();
+        self.rotate_keys(step.update_keys.as_ref(), step.next_keys.as_ref());
+        self.set_state(state)
+    }
+
+    async fn deactivate(&mut self, step: &DeactivateStep) -> Result<(), DIDWebVHError> {
+        let signer = match (step.signer, self.next_keys.first()) {
+            (Some(signer), _) => signer,
+            (None, Some(next)) => *next,
+            (None, None) => self.first_update_key()?,
+        };
+        let mut version_time = step.version_time.unwrap_or(self.clock);
+        if !self.next_keys.is_empty() && step.version_time.is_none() {
+            // Leave room for the entry that ends pre-rotation.
+            version_time += Duration::seconds(1);
+        }
+        let config = UpdateDIDConfig::builder()
+            .state(self.take_state()?)
+            .signing_key(scenario_key(signer)?)
+            .witness_secrets(self.witness_secrets()?)
+            .version_time(version_time)
+            .deterministic(true)
+            .restate_parameters(self.restate_parameters)
+            .deactivate(true)
+            .build()?;
+        let state = update_did(config).await?.into_state();
+        self.update_keys.clear();
+        self.next_keys.clear();
+        self.set_state(state)
+    }
+
+    async fn resolve(&mut self, version_number: Option<u32>) -> Result<(), DIDWebVHError> {
+        let name = version_number.map_or_else(
+            || RESOLUTION_RESULT.to_string(),
+            |n| format!("resolutionResult.{n}.json"),
+        );
+        if self.files.contains_key(&name) {
+            return Err(DIDWebVHError::ScenarioError(format!(
+                "{name} has already been written by an earlier step"
+            )));
+        }
+
+        let state = self.state()?;
+        let did = current_did(state)?;
+        let log = state.log_jsonl()?;
+        let witness_proofs = witness_proofs_file(state)?;
+        let query =
+            version_number.map_or_else(|| did.clone(), |n| format!("{did}?versionNumber={n}"));
+        let (entry, metadata) = DIDWebVHState::default()
+            .resolve_log_owned(&query, &log, witness_proofs.as_deref())
+            .await?;
+
+        let mut document_metadata = json!({
+            "created": metadata.created,
+            "updated": metadata.updated,
+            "versionId": metadata.version_id,
+            "versionNumber": metadata.version_number,
+            "versionTime": metadata.version_time,
+        });
+        if metadata.deactivated {
+            document_metadata["deactivated"] = json!(true);
+        }
+        if let Some(canonical_id) = metadata.canonical_id {
+            document_metadata["canonicalId"] = json!(canonical_id);
+        }
+        let mut document = entry.get_did_document()?;
+        relative_implicit_service_ids(&mut document, entry.get_state());
+        let result = json!({
+            "didDocument": document,
+            "didDocumentMetadata": document_metadata,
+            "didResolutionMetadata": { "contentType": "application/did+ld+json" },
+        });
+        self.files.insert(name, pretty_json(&result)?);
+        Ok(())
+    }
+
+    /// Collect the files once every step has run.
+    fn finish(mut self) -> Result<ScenarioOutput, DIDWebVHError> {
+        let state = self.state()?;
+        let did = current_did(state)?;
+        let log = state.log_jsonl()?;
+        if let Some(witness_proofs) = witness_proofs_file(state)? {
+            self.files
+                .insert("did-witness.json".to_string(), witness_proofs);
+        }
+        self.files.insert("did.jsonl".to_string(), log);
+        Ok(ScenarioOutput {
+            did,
+            files: self.files,
+        })
+    }
+
+    fn state(&self) -> Result<&DIDWebVHState, DIDWebVHError> {
+        self.state.as_ref().ok_or_else(|| {
+            DIDWebVHError::ScenarioError("The DID hasn't been created yet".to_string())
+        })
+    }
+
+    fn take_state(&mut self) -> Result<DIDWebVHState, DIDWebVHError> {
+        self.state.take().ok_or_else(|| {
+            DIDWebVHError::ScenarioError("The DID hasn't been created yet".to_string())
+        })
+    }
+
+    /// Store the state after a step and move the clock a day past its last
+    /// entry.
+    fn set_state(&mut self, state: DIDWebVHState) -> Result<(), DIDWebVHError> {
+        let last = state.log_entries().last().ok_or_else(|| {
+            DIDWebVHError::ScenarioError("The step wrote no log entry".to_string())
+        })?;
+        self.clock = last.log_entry.get_version_time() + Duration::days(1);
+        self.state = Some(state);
+        Ok(())
+    }
+
+    /// The key signing an update: `explicit`, else the first revealed
+    /// `update_keys` key while pre-rotation is active, else the first current
+    /// update key.
+    fn signer(
+        &self,
+        explicit: Option<u32>,
+        update_keys: Option<&[u32]>,
+    ) -> Result<u32, DIDWebVHError> {
+        if let Some(signer) = explicit {
+            return Ok(signer);
+        }
+        if self.next_keys.is_empty() {
+            return self.first_update_key();
+        }
+        update_keys
+            .and_then(|keys| keys.first().copied())
+            .ok_or_else(|| {
+                DIDWebVHError::ScenarioError(
+                    "Pre-rotation is active: the step must reveal a new update key".to_string(),
+                )
+            })
+    }
+
+    fn rotate_keys(&mut self, update_keys: Option<&Vec<u32>>, next_keys: Option<&Vec<u32>>) {
+        if let Some(keys) = update_keys {
+            self.update_keys.clone_from(keys);
+        }
+        if let Some(keys) = next_keys {
+            self.next_keys.clone_from(keys);
+        }
+    }
+
+    fn first_update_key(&self) -> Result<u32, DIDWebVHError> {
+        self.update_keys
+            .first()
+            .copied()
+            .ok_or_else(|| DIDWebVHError::ScenarioError("The DID has no update keys".to_string()))
+    }
+
+    fn witness_config(
+        &mut self,
+        witness: Option<&ScenarioWitness>,
+    ) -> Result<Witnesses, DIDWebVHError> {
+        let Some(witness) = witness.filter(|w| !w.witnesses.is_empty()) else {
+            return Ok(Witnesses::Empty {});
+        };
+        self.witness_keys.extend(&witness.witnesses);
+        let witnesses = Witnesses::Value {
+            threshold: witness.threshold,
+            witnesses: public_keys(&witness.witnesses)?
+                .into_iter()
+                .map(|pk| Witness::new(pk.into_inner()))
+                .collect(),
+        };
+        witnesses.validate()?;
+        Ok(witnesses)
+    }
+
+    /// Witness keys by witness DID.
+    fn witness_secrets(&self) -> Result<HashMap<String, Secret>, DIDWebVHError> {
+        self.witness_keys
+            .iter()
+            .map(|n| {
+                let key = scenario_key(*n)?;
+                let did = key.id.split('#').next().unwrap_or_default().to_string();
+                Ok((did, key))
+            })
+            .collect()
+    }
+}
+
+/// The document of an update step, or `None` if it doesn't change it.
+fn document_update(
+    state: &DIDWebVHState,
+    step: &UpdateStep,
+) -> Result<Option<Value>, DIDWebVHError> {
+    if step.verification_methods.is_none()
+        && step.services.is_none()
+        && step.add_services.is_empty()
+        && step.also_known_as.is_none()
+        && step.add_also_known_as.is_empty()
+    {
+        return Ok(None);
+    }
+    let mut document = state
+        .log_entries()
+        .last()
+        .map(|e| e.get_state().clone())
+        .ok_or_else(|| DIDWebVHError::ScenarioError("The DID has no log entries".to_string()))?;
+    let did = current_did(state)?;
+
+    if let Some(keys) = &step.verification_methods {
+        set_verification_methods(&mut document, &did, keys)?;
+    }
+    extend_array(
+        &mut document,
+        "service",
+        step.services.as_ref().map(|s| s.iter().cloned()),
+        step.add_services.iter().cloned(),
+    );
+    extend_array(
+        &mut document,
+        "alsoKnownAs",
+        step.also_known_as
+            .as_ref()
+            .map(|a| a.iter().map(|alias| json!(alias))),
+        step.add_also_known_as.iter().map(|alias| json!(alias)),
+    );
+    Ok(Some(document))
+}
+
+/// Replace (if `replace` is set) and then append to the array `key` of
+/// `document`, dropping it when it ends up empty.
+fn extend_array(
+    document: &mut Value,
+    key: &str,
+    replace: Option<impl Iterator<Item = Value>>,
+    add: impl Iterator<Item = Value>,
+) {
+    let mut values = match replace {
+        Some(values) => values.collect(),
+        None => document
+            .get(key)
+            .and_then(Value::as_array)
+            .cloned()
+            .unwrap_or_default(),
+    };
+    values.extend(add);
+    if let Some(document) = document.as_object_mut() {
+        if values.is_empty() {
+            document.remove(key);
+        } else {
+            document.insert(key.to_string(), Value::Array(values));
+        }
+    }
+}
+
+/// One `Multikey` verification method per key, each referenced from
+/// `authentication`.
+fn set_verification_methods(
+    document: &mut Value,
+    did: &str,
+    keys: &[u32],
+) -> Result<(), DIDWebVHError> {
+    let mut methods = Vec::new();
+    let mut ids = Vec::new();
+    for pk in public_keys(keys)? {
+        let pk = pk.as_str();
+        let id = format!("{did}#{}", &pk[pk.len().saturating_sub(8)..]);
+        methods.push(json!({
+            "id": id,
+            "type": "Multikey",
+            "publicKeyMultibase": pk,
+            "purpose": "authentication",
+        }));
+        ids.push(json!(id));
+    }
+    document["verificationMethod"] = Value::Array(methods);
+    document["authentication"] = Value::Array(ids);
+    Ok(())
+}
+
+fn public_keys(keys: &[u32]) -> Result<Vec<Multibase>, DIDWebVHError> {
+    keys.iter()
+        .map(|n| {
+            let key = scenario_key(*n)?;
+            let pk = key.id.split('#').next().unwrap_or_default();
+            Ok(Multibase::new(pk.strip_prefix("did:key:").unwrap_or(pk)))
+        })
+        .collect()
+}
+
+fn key_hashes(keys: &[u32]) -> Result<Vec<Multibase>, DIDWebVHError> {
+    public_keys(keys)?
+        .iter()
+        .map(|pk| {
+            Secret::base58_hash_string(pk.as_str())
+                .map(Multibase::new)
+                .map_err(|e| DIDWebVHError::ScenarioError(format!("Couldn't hash key {pk}: {e}")))
+        })
+        .collect()
+}
+
+fn current_did(state: &DIDWebVHState) -> Result<String, DIDWebVHError> {
+    state
+        .log_entries()
+        .last()
+        .and_then(|e| e.get_state().get("id"))
+        .and_then(Value::as_str)
+        .map(ToString::to_string)
+        .ok_or_else(|| DIDWebVHError::ScenarioError("The DID Document has no id".to_string()))
+}
+
+/// Give the services the resolver added to `document` (the implicit `#files`
+/// and `#whois`, absent from the logged `state`) relative ids, as the test
+/// suite writes them.
+fn relative_implicit_service_ids(document: &mut Value, state: &Value) {
+    let Some(did) = document["id"].as_str().map(ToString::to_string) else {
+        return;
+    };
+    let logged = |id: &str| {
+        state["service"]
+            .as_array()
+            .is_some_and(|services| services.iter().any(|s| s["id"] == id))
+    };
+    for service in document["service"].as_array_mut().into_iter().flatten() {
+        if let Some(id) = service["id"].as_str()
+            && let Some(fragment) = id.strip_prefix(&did)
+            && fragment.starts_with('#')
+            && !logged(id)
+            && !logged(fragment)
+        {
+            service["id"] = json!(fragment);
+        }
+    }
+}
+
+/// `did-witness.json` for the log in `state`, or `None` without witnesses.
+fn witness_proofs_file(state: &DIDWebVHState) -> Result<Option<String>, DIDWebVHError> {
+    if state.witness_proofs().get_total_count() == 0 {
+        return Ok(None);
+    }
+    pretty_json(state.witness_proofs()).map(Some)
+}
+
+fn pretty_json(value: &impl Serialize) -> Result<String, DIDWebVHError> {
+    serde_json::to_string_pretty(value)
+        .map(|json| json + "\n")
+        .map_err(|e| DIDWebVHError::ScenarioError(format!("Couldn't serialize JSON: {e}")))
+}
+
+/// Do `expected` and `actual` hold the same JSON (or JSON lines, for `.jsonl`
+/// files)?
+fn same_json(name: &str, expected: &str, actual: &str) -> bool {
+    fn parse(json: &str) -> Option<Value> {
+        serde_json::from_str(json).ok()
+    }
+    if name.ends_with(".jsonl") {
+        let lines = |s: &str| {
+            s.lines()
+                .filter(|l| !l.trim().is_empty())
+                .map(parse)
+                .collect::<Option<Vec<_>>>()
+        };
+        lines(expected).is_some() && lines(expected) == lines(actual)
+    } else {
+        parse(expected).is_some() && parse(expected) == parse(actual)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEST_SUITE: &str = "tests/test_vectors/test_suite";
+
+    fn scenario(steps: Value) -> Scenario {
+        Scenario::from_json_str(&json!({ "steps": steps }).to_string()).unwrap()
+    }
+
+    #[test]
+    fn keys_follow_the_test_suite() {
+        assert_eq!(
+            public_keys(&[1, 2]).unwrap(),
+            vec![
+                Multibase::new("z6MkjchhfUsD6mmvni8mCdXHw216Xrm9bQe2mBH1P5RDjVJG"),
+                Multibase::new("z6MknGc3ocHs3zdPiJbnaaqDi58NGb4pk1Sp9WxWufuXSdxf"),
+            ]
+        );
+    }
+
+    #[test]
+    fn unknown_fields_rejected() {
+        let err = Scenario::from_json_str(
+            r#"{"steps": [{"op": "create", "updateKeys": [1], "portabel": true}]}"#,
+        )
+        .unwrap_err();
+        assert!(matches!(err, DIDWebVHError::ScenarioError(_)));
+        assert!(Scenario::from_json_str(r#"{"steps": [{"op": "rotate"}]}"#).is_err());
+    }
+
+    /// The resolution result in `dir`, or `name` there.
+    fn vector_result(dir: &str, name: &str) -> Value {
+        serde_json::from_str(&std::fs::read_to_string(format!("{dir}/{name}")).unwrap()).unwrap()
+    }
+
+    /// Single-entry scenarios reproduce the test suite's vectors: logs and
+    /// witness proofs byte for byte, resolution results as JSON.
+    #[tokio::test]
+    async fn matches_test_suite_vectors() {
+        for (vector, create) in [
+            ("basic-create", json!({ "op": "create", "updateKeys": [1] })),
+            (
+                "pre-rotation",
+                json!({ "op": "create", "updateKeys": [1], "nextKeys": [2] }),
+            ),
+            (
+                "witness-threshold",
+                json!({
+                    "op": "create",
+                    "updateKeys": [1],
+                    "witness": { "threshold": 1, "witnesses": [16] }
+                }),
+            ),
+        ] {
+            let output = scenario(json!([create])).run().await.unwrap();
+            output
+                .check(format!("{TEST_SUITE}/{vector}"))
+                .unwrap_or_else(|e| panic!("{vector}: {e}"));
+        }
+    }
+
+    /// With `restateParameters`, longer scenarios reproduce the test suite's
+    /// vectors too.
+    #[tokio::test]
+    async fn restated_scenarios_match_test_suite_vectors() {
+        for (vector, steps) in restated_vectors() {
+            let scenario = Scenario::from_json_str(
+                &json!({ "restateParameters": true, "steps": steps }).to_string(),
+            )
+            .unwrap();
+            let output = scenario.run().await.unwrap();
+            output
+                .check(format!("{TEST_SUITE}/{vector}"))
+                .unwrap_or_else(|e| panic!("{vector}: {e}"));
+        }
+    }
+
+    /// The steps of multi-entry test-suite vectors.
+    fn restated_vectors() -> Vec<(&'static str, Value)> {
+        vec![
+            (
+                "basic-update",
+                json!([
+                    { "op": "create", "updateKeys": [1] },
+                    { "op": "update", "addAlsoKnownAs": ["did:web:example.com"] }
+                ]),
+            ),
+            (
+                "key-rotation",
+                json!([
+                    { "op": "create", "updateKeys": [1] },
+                    { "op": "update", "updateKeys": [2], "verificationMethods": [2] }
+                ]),
+            ),
+            (
+                "deactivate",
+                json!([
+                    { "op": "create", "updateKeys": [1] },
+                    { "op": "deactivate" }
+                ]),
+            ),
+            (
+                "multi-update",
+                json!([
+                    { "op": "create", "updateKeys": [1] },
+                    { "op": "resolve", "versionNumber": 1 },
+                    { "op": "update", "addAlsoKnownAs": ["did:web:example.com"] },
+                    { "op": "resolve", "versionNumber": 2 },
+                    { "op": "update", "addAlsoKnownAs": ["did:web:example.org"] }
+                ]),
+            ),
+            (
+                "services",
+                json!([
+                    { "op": "create", "updateKeys": [1] },
+                    { "op": "update", "addServices": [
+                        { "id": "#linked-domain", "type": "LinkedDomains", "serviceEndpoint": "https://example.com" },
+                        { "id": "#messaging", "type": "DIDCommMessaging", "serviceEndpoint": "https://example.com/didcomm" }
+                    ] }
+                ]),
+            ),
+            (
+                "multiple-update-keys",
+                json!([
+                    { "op": "create", "updateKeys": [1, 2] },
+                    { "op": "update", "signer": 2, "addAlsoKnownAs": ["did:web:example.com"] }
+                ]),
+            ),
+            (
+                "pre-rotation-consume",
+                json!([
+                    { "op": "create", "updateKeys": [1], "nextKeys": [2] },
+                    { "op": "update", "updateKeys": [2], "nextKeys": [3], "verificationMethods": [2] }
+                ]),
+            ),
+        ]
+    }
+
+    /// Without `restateParameters`, entries after the first carry only the
+    /// parameters that changed, so their bytes and versionIds differ from the
+    /// test suite's. The genesis entry is identical and the log resolves to
+    /// the same document and metadata otherwise.
+    #[tokio::test]
+    async fn resolves_like_test_suite_vectors() {
+        for (vector, steps) in restated_vectors() {
+            let dir = format!("{TEST_SUITE}/{vector}");
+            let output = scenario(steps).run().await.unwrap();
+            assert!(output.check(&dir).is_err(), "{vector}");
+
+            let expected_log = std::fs::read_to_string(format!("{dir}/did.jsonl")).unwrap();
+            let log = &output.files["did.jsonl"];
+            assert_eq!(
+                log.lines().count(),
+                expected_log.lines().count(),
+                "{vector}"
+            );
+            assert!(
+                same_json(
+                    "did.jsonl",
+                    expected_log.lines().next().unwrap(),
+                    log.lines().next().unwrap()
+                ),
+                "{vector}: genesis entry differs"
+            );
+
+            let result: Value = serde_json::from_str(&output.files[RESOLUTION_RESULT]).unwrap();
+            let expected = vector_result(&dir, RESOLUTION_RESULT);
+            assert_eq!(result["didDocument"], expected["didDocument"], "{vector}");
+            let mut metadata = result["didDocumentMetadata"].clone();
+            let mut expected_metadata = expected["didDocumentMetadata"].clone();
+            for metadata in [&mut metadata, &mut expected_metadata] {
+                metadata.as_object_mut().unwrap().remove("versionId");
+            }
+            assert_eq!(metadata, expected_metadata, "{vector}");
+        }
+    }
+
+    #[tokio::test]
+    async fn runs_are_reproducible() {
+        let scenario = scenario(json!([
+            {
+                "op": "create",
+                "updateKeys": [1],
+                "nextKeys": [2],
+                "portable": true,
+                "witness": { "threshold": 1, "witnesses": [16, 17] }
+            },
+            {
+                "op": "update",
+                "updateKeys": [2],
+                "nextKeys": [3],
+                "witness": { "threshold": 1, "witnesses": [17] },
+                "addServices": [{
+                    "id": "#linked-domain",
+                    "type": "LinkedDomains",
+                    "serviceEndpoint": "https://example.com"
+                }],
+                "addAlsoKnownAs": ["did:web:example.com"]
+            },
+            { "op": "resolve", "versionNumber": 1 },
+            {
+                "op": "migrate",
+                "to": "https://example.org/",
+                "updateKeys": [3],
+                "nextKeys": [4]
+            },
+            { "op": "deactivate" }
+        ]));
+        let output = scenario.run().await.unwrap();
+        assert_eq!(output, scenario.run().await.unwrap());
+        assert!(output.did.ends_with(":example.org"), "{}", output.did);
+        assert_eq!(
+            output.files.keys().collect::<Vec<_>>(),
+            [
+                "did-witness.json",
+                "did.jsonl",
+                "resolutionResult.1.json",
+                "resolutionResult.json"
+            ]
+        );
+
+        // Deactivation ends pre-rotation in an entry of its own first.
+        let log = &output.files["did.jsonl"];
+        let times: Vec<_> = log
+            .lines()
+            .map(|l| serde_json::from_str::<Value>(l).unwrap()["versionTime"].clone())
+            .collect();
+        assert_eq!(
+            times,
+            [
+                "2000-01-01T00:00:00Z",
+                "2000-01-02T00:00:00Z",
+                "2000-01-03T00:00:00Z",
+                "2000-01-04T00:00:00Z",
+                "2000-01-04T00:00:01Z"
+            ]
+        );
+        let result: Value = serde_json::from_str(&output.files[RESOLUTION_RESULT]).unwrap();
+        assert_eq!(result["didDocumentMetadata"]["deactivated"], true);
+        let first: Value = serde_json::from_str(&output.files["resolutionResult.1.json"]).unwrap();
+        assert_eq!(first["didDocumentMetadata"]["versionNumber"], 1);
+        assert!(first["didDocument"].get("service").is_some());
+
+        let dir = std::env::temp_dir().join(format!(
+            "didwebvh_test_scenario_{}",
+            chrono::Utc::now().timestamp_nanos_opt().unwrap()
+        ));
+        output.write_to(&dir).unwrap();
+        output.check(&dir).unwrap();
+        std::fs::write(dir.join("resolutionResult.1.json"), "{}").unwrap();
+        let err = output.check(&dir).unwrap_err().to_string();
+        assert!(err.contains("resolutionResult.1.json differs"), "{err}");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn failing_step_is_reported() {
+        let err = scenario(json!([
+            { "op": "create", "updateKeys": [1] },
+            { "op": "update", "signer": 2, "ttl": 60 }
+        ]))
+        .run()
+        .await
+        .unwrap_err();
+        assert!(err.to_string().contains("Step 2 failed"), "{err}");
+
+        let err = scenario(json!([{ "op": "deactivate" }]))
+            .run()
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("Step 1 failed"), "{err}");
+    }
+
+    #[cfg(feature = "yaml")]
+    #[tokio::test]
+    async fn yaml_scenario() {
+        let scenario = Scenario::from_yaml_str(
+            "description: key rotation\n\
+             steps:\n  \
+               - op: create\n    updateKeys: [1]\n  \
+               - op: update\n    updateKeys: [2]\n",
+        )
+        .unwrap();
+        let output = scenario.run().await.unwrap();
+        assert_eq!(output.files["did.jsonl"].lines().count(), 2);
+    }
+}
@@ -0,0 +1,299 @@
+/*!
+*   JSON Schemas for the did:webvh files, generated from the Rust types, and
+*   structural validation against them.
+*
+*   The schemas describe exactly what this crate (de)serializes: a
+*   [`LogEntry1_0`] is one `did.jsonl` line, [`WitnessProofShadow`] is the
+*   `did-witness.json` file and [`MetaData`] is the resolution metadata.
+*   Copies are kept in the repository's `schemas/` directory for other
+*   implementations.
+*
+*   [`validate_log_entry`], [`validate_log`] and [`validate_witness_proofs`]
+*   report every structural problem with the JSON Pointer of where it is,
+*   without verifying hashes or signatures. They are a pre-check, not a
+*   replacement for [`DIDWebVHState::validate`](crate::DIDWebVHState::validate).
+*/
+
+use crate::{
+    log_entry::{MetaData, spec_1_0::LogEntry1_0},
+    parameters::spec_1_0::Parameters1_0,
+    witness::{Witnesses, proofs::WitnessProofShadow},
+};
+use jsonschema::Validator;
+use schemars::JsonSchema;
+use serde_json::Value;
+use std::{fmt, sync::LazyLock};
+
+/// Schema stand-in for [`affinidi_data_integrity::DataIntegrityProof`], whose
+/// crate doesn't provide one.
+#[derive(JsonSchema)]
+#[serde(rename_all = "camelCase")]
+#[schemars(
+    rename = "DataIntegrityProof",
+    description = "A W3C Data Integrity proof"
+)]
+#[allow(dead_code, reason = "only used for its JsonSchema")]
+pub(crate) struct DataIntegrityProofSchema {
+    /// Always `DataIntegrityProof`
+    #[serde(rename = "type")]
+    type_: String,
+    /// e.g. `eddsa-jcs-2022`
+    cryptosuite: String,
+    /// `did:key` of the signing key
+    verification_method: String,
+    /// `assertionMethod` or `authentication`
+    proof_purpose: String,
+    /// Multibase encoded signature
+    proof_value: String,
+    /// ISO 8601 date of the proof
+    #[serde(skip_serializing_if = "Option::is_none")]
+    created: Option<String>,
+}
+
+fn schema<T: JsonSchema>() -> Value {
+    schemars::schema_for!(T).to_value()
+}
+
+/// Schema of a `did.jsonl` line ([`LogEntry1_0`]).
+pub fn log_entry_schema() -> Value {
+    schema::<LogEntry1_0>()
+}
+
+/// Schema of the `parameters` of a log entry ([`Parameters1_0`]).
+pub fn parameters_schema() -> Value {
+    schema::<Parameters1_0>()
+}
+
+/// Schema of the `witness` parameter ([`Witnesses`]).
+pub fn witnesses_schema() -> Value {
+    schema::<Witnesses>()
+}
+
+/// Schema of the `did-witness.json` file ([`WitnessProofShadow`]).
+pub fn witness_proofs_schema() -> Value {
+    schema::<WitnessProofShadow>()
+}
+
+/// Schema of resolution metadata ([`MetaData`]).
+pub fn metadata_schema() -> Value {
+    schema::<MetaData>()
+}
+
+/// Every schema with the file name it is published under in `schemas/`.
+pub fn schemas() -> Vec<(&'static str, Value)> {
+    vec![
+        ("log-entry.schema.json", log_entry_schema()),
+        ("parameters.schema.json", parameters_schema()),
+        ("witnesses.schema.json", witnesses_schema()),
+        ("witness-proofs.schema.json", witness_proofs_schema()),
+        ("metadata.schema.json", metadata_schema()),
+    ]
+}
+
+/// Formats such as `date-time` are checked, not only annotated.
+fn validator(schema: &Value) -> Validator {
+    jsonschema::options()
+        .should_validate_formats(true)
+        .build(schema)
+        .expect("generated schema is valid")
+}
+
+static LOG_ENTRY_VALIDATOR: LazyLock<Validator> = LazyLock::new(|| validator(&log_entry_schema()));
+
+static WITNESS_PROOFS_VALIDATOR: LazyLock<Validator> =
+    LazyLock::new(|| validator(&witness_proofs_schema()));
+
+/// A structural problem found by schema validation.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SchemaViolation {
+    /// 1-based `did.jsonl` line, when validating a whole log.
+    pub line: Option<usize>,
+    /// JSON Pointer to the offending value (`""` is the whole document).
+    pub pointer: String,
+    /// What is wrong with it.
+    pub message: String,
+}
+
+impl fmt::Display for SchemaViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(line) = self.line {
+            write!(f, "line {line}: ")?;
+        }
+        let pointer = if self.pointer.is_empty() {
+            "(root)"
+        } else {
+            &self.pointer
+        };
+        write!(f, "{pointer}: {}", self.message)
+    }
+}
+
+fn violations(validator: &Validator, value: &Value) -> Vec<SchemaViolation> {
+    validator
+        .iter_errors(value)
+        .map(|e| SchemaViolation {
+            line: None,
+            pointer: e.instance_path().to_string(),
+            message: e.masked().to_string(),
+        })
+        .collect()
+}
+
+fn validate_str(validator: &Validator, raw: &str) -> Result<(), Vec<SchemaViolation>> {
+    let value: Value = serde_json::from_str(raw).map_err(|e| {
+        vec![SchemaViolation {
+            line: None,
+            pointer: String::new(),
+            message: format!("not valid JSON: {e}"),
+        }]
+    })?;
+    let found = violations(validator, &value);
+    if found.is_empty() { Ok(()) } else { Err(found) }
+}
+
+/// Check one `did.jsonl` line against [`log_entry_schema`].
+pub fn validate_log_entry(line: &str) -> Result<(), Vec<SchemaViolation>> {
+    validate_str(&LOG_ENTRY_VALIDATOR, line)
+}
+
+/// Check every line of a `did.jsonl` file against [`log_entry_schema`].
+/// Blank lines are skipped.
+pub fn validate_log(jsonl: &str) -> Result<(), Vec<SchemaViolation>> {
+    let found: Vec<SchemaViolation> = jsonl
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .filter_map(|(i, line)| validate_log_entry(line).err().map(|found| (i + 1, found)))
+        .flat_map(|(line, found)| {
+            found.into_iter().map(move |v| SchemaViolation {
+                line: Some(line),
+                ..v
+            })
+        })
+        .collect();
+    if found.is_empty() { Ok(()) } else { Err(found) }
+}
+
+/// Check a `did-witness.json` file against [`witness_proofs_schema`].
+pub fn validate_witness_proofs(raw: &str) -> Result<(), Vec<SchemaViolation>> {
+    validate_str(&WITNESS_PROOFS_VALIDATOR, raw)
+}
+
+/// The schema violations of a log entry that failed to deserialize, joined
+/// for an error message. Empty if the schema accepts it.
+pub(crate) fn describe_log_entry(value: &Value) -> String {
+    violations(&LOG_ENTRY_VALIDATOR, value)
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{schemas, validate_log, validate_log_entry, validate_witness_proofs};
+    use crate::{DIDWebVHError, log_entry::LogEntry};
+    use serde_json::json;
+    use std::path::Path;
+
+    fn entry() -> serde_json::Value {
+        json!({
+            "versionId": "1-QmHash",
+            "versionTime": "2025-01-01T00:00:00Z",
+            "parameters": {
+                "method": "did:webvh:1.0",
+                "scid": "QmScid",
+                "updateKeys": ["z6MkKey"],
+                "witness": {"threshold": 1, "witnesses": [{"id": "did:key:z6MkWitness"}]},
+                "ttl": 3600
+            },
+            "state": {"id": "did:webvh:QmScid:example.com"},
+            "proof": [{
+                "type": "DataIntegrityProof",
+                "cryptosuite": "eddsa-jcs-2022",
+                "verificationMethod": "did:key:z6MkKey#z6MkKey",
+                "proofPurpose": "assertionMethod",
+                "proofValue": "zSig"
+            }]
+        })
+    }
+
+    /// The files in `schemas/` match the types. Regenerate them with
+    /// `UPDATE_SCHEMAS=1 cargo test --features schema schema::`.
+    #[test]
+    fn published_schemas_are_current() {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("schemas");
+        for (name, schema) in schemas() {
+            let generated = serde_json::to_string_pretty(&schema).unwrap() + "\n";
+            let path = dir.join(name);
+            if std::env::var_os("UPDATE_SCHEMAS").is_some() {
+                std::fs::write(&path, &generated).unwrap();
+            }
+            let published = std::fs::read_to_string(&path).unwrap_or_default();
+            assert!(
+                published == generated,
+                "{name} is out of date; run with UPDATE_SCHEMAS=1"
+            );
+        }
+    }
+
+    #[test]
+    fn valid_entry_passes() {
+        validate_log_entry(&entry().to_string()).unwrap();
+        validate_log(&format!("{}\n\n{}\n", entry(), entry())).unwrap();
+    }
+
+    #[test]
+    fn reports_every_problem_with_pointers() {
+        let mut bad = entry();
+        bad["versionTime"] = json!("yesterday");
+        bad["parameters"]["ttl"] = json!(-1);
+        bad["parameters"]["updateKeys"] = json!("z6MkKey");
+        bad["proof"][0]
+            .as_object_mut()
+            .unwrap()
+            .remove("proofValue");
+        bad.as_object_mut().unwrap().remove("state");
+
+        let found = validate_log(&format!("{}\n{bad}\n", entry())).unwrap_err();
+        let mut pointers: Vec<&str> = found.iter().map(|v| v.pointer.as_str()).collect();
+        pointers.sort_unstable();
+        assert_eq!(
+            pointers,
+            [
+                "",
+                "/parameters/ttl",
+                "/parameters/updateKeys",
+                "/proof/0",
+                "/versionTime"
+            ]
+        );
+        assert!(found.iter().all(|v| v.line == Some(2)));
+        assert!(found[0].to_string().starts_with("line 2: "));
+
+        let found = validate_log_entry("{not json").unwrap_err();
+        assert_eq!(found[0].pointer, "");
+    }
+
+    #[test]
+    fn witness_proof_file() {
+        let proof = entry()["proof"][0].clone();
+        validate_witness_proofs(&json!([{"versionId": "1-QmHash", "proof": [proof]}]).to_string())
+            .unwrap();
+        let found = validate_witness_proofs(&json!([{"proof": {}}]).to_string()).unwrap_err();
+        let pointers: Vec<&str> = found.iter().map(|v| v.pointer.as_str()).collect();
+        assert_eq!(pointers, ["/0", "/0/proof"]);
+    }
+
+    #[test]
+    fn deserialize_errors_name_the_problem() {
+        let mut bad = entry();
+        bad["parameters"]["ttl"] = json!("an hour");
+        let Err(DIDWebVHError::LogEntryError(message)) =
+            LogEntry::deserialize_string(&bad.to_string(), None)
+        else {
+            panic!("expected a LogEntryError");
+        };
+        assert!(message.contains("/parameters/ttl: "), "{message}");
+    }
+}
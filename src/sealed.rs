@@ -0,0 +1,237 @@
+/*!
+*   Passphrase-based encryption for secret key material at rest.
+*
+*   A [`SealedEnvelope`] is the on-disk wrapper used by the key chain escrow
+*   file (and anything else in this crate that persists private keys). The
+*   passphrase is stretched with Argon2id and the payload is encrypted with
+*   XChaCha20-Poly1305. The KDF parameters, salt and nonce travel with the
+*   envelope so they can be tuned later without breaking existing files.
+*
+*   The envelope's `contentType` is bound into the AEAD as associated data,
+*   so an envelope sealed for one purpose can't be opened as another.
+*/
+
+use crate::DIDWebVHError;
+use argon2::{Algorithm, Argon2, Params, Version};
+use base64::{Engine, engine::general_purpose::STANDARD as BASE64};
+use chacha20poly1305::{
+    KeyInit, XChaCha20Poly1305, XNonce,
+    aead::{Aead, Payload},
+};
+use serde::{Deserialize, Serialize};
+use zeroize::Zeroizing;
+
+/// Envelope format identifier. Bumped if the layout or algorithms change.
+const ENVELOPE_FORMAT: &str = "didwebvh-sealed-v1";
+
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 24;
+const KEY_LEN: usize = 32;
+
+/// Argon2id cost parameters recorded alongside the ciphertext.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct KdfParams {
+    /// Memory cost in KiB.
+    pub memory_kib: u32,
+    /// Number of iterations.
+    pub iterations: u32,
+    /// Degree of parallelism.
+    pub parallelism: u32,
+}
+
+impl Default for KdfParams {
+    /// OWASP-recommended Argon2id baseline (19 MiB, 2 iterations, 1 lane).
+    fn default() -> Self {
+        Self {
+            memory_kib: 19 * 1024,
+            iterations: 2,
+            parallelism: 1,
+        }
+    }
+}
+
+/// Encrypted, serialisable wrapper around a secret payload.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct SealedEnvelope {
+    /// Always [`ENVELOPE_FORMAT`].
+    format: String,
+    /// What the payload is (e.g. `didwebvh-key-chain`). Bound as AEAD associated data.
+    content_type: String,
+    /// Argon2id parameters used to derive the encryption key.
+    kdf: KdfParams,
+    /// Base64 Argon2 salt.
+    salt: String,
+    /// Base64 XChaCha20-Poly1305 nonce.
+    nonce: String,
+    /// Base64 ciphertext (including the Poly1305 tag).
+    ciphertext: String,
+}
+
+impl SealedEnvelope {
+    /// Encrypt `plaintext` under `passphrase`, deriving the key with `kdf`.
+    pub fn seal(
+        content_type: &str,
+        plaintext: &[u8],
+        passphrase: &str,
+        kdf: KdfParams,
+    ) -> Result<Self, DIDWebVHError> {
+        let mut salt = [0u8; SALT_LEN];
+        let mut nonce = [0u8; NONCE_LEN];
+        getrandom::fill(&mut salt).map_err(|e| {
+            DIDWebVHError::SecretsError(format!("Couldn't generate random salt: {e}"))
+        })?;
+        getrandom::fill(&mut nonce).map_err(|e| {
+            DIDWebVHError::SecretsError(format!("Couldn't generate random nonce: {e}"))
+        })?;
+
+        let key = derive_key(passphrase, &salt, kdf)?;
+        let cipher = XChaCha20Poly1305::new(key.as_slice().into());
+        let ciphertext = cipher
+            .encrypt(
+                &XNonce::from(nonce),
+                Payload {
+                    msg: plaintext,
+                    aad: content_type.as_bytes(),
+                },
+            )
+            .map_err(|e| DIDWebVHError::SecretsError(format!("Encryption failed: {e}")))?;
+
+        Ok(Self {
+            format: ENVELOPE_FORMAT.to_string(),
+            content_type: content_type.to_string(),
+            kdf,
+            salt: BASE64.encode(salt),
+            nonce: BASE64.encode(nonce),
+            ciphertext: BASE64.encode(ciphertext),
+        })
+    }
+
+    /// Decrypt the payload. `content_type` must match the value the envelope was sealed with.
+    ///
+    /// A wrong passphrase and a tampered envelope are indistinguishable here
+    /// by design; both surface as the same error.
+    pub fn open(
+        &self,
+        content_type: &str,
+        passphrase: &str,
+    ) -> Result<Zeroizing<Vec<u8>>, DIDWebVHError> {
+        if self.format != ENVELOPE_FORMAT {
+            return Err(DIDWebVHError::SecretsError(format!(
+                "Unsupported envelope format ({}), expected {ENVELOPE_FORMAT}",
+                self.format
+            )));
+        }
+        if self.content_type != content_type {
+            return Err(DIDWebVHError::SecretsError(format!(
+                "Envelope holds '{}' data, expected '{content_type}'",
+                self.content_type
+            )));
+        }
+
+        let salt = decode_field("salt", &self.salt)?;
+        let nonce = decode_field("nonce", &self.nonce)?;
+        let ciphertext = decode_field("ciphertext", &self.ciphertext)?;
+        let nonce: [u8; NONCE_LEN] = nonce.as_slice().try_into().map_err(|_| {
+            DIDWebVHError::SecretsError(format!(
+                "Envelope nonce must be {NONCE_LEN} bytes, got {}",
+                nonce.len()
+            ))
+        })?;
+
+        let key = derive_key(passphrase, &salt, self.kdf)?;
+        let cipher = XChaCha20Poly1305::new(key.as_slice().into());
+        cipher
+            .decrypt(
+                &XNonce::from(nonce),
+                Payload {
+                    msg: &ciphertext,
+                    aad: content_type.as_bytes(),
+                },
+            )
+            .map(Zeroizing::new)
+            .map_err(|_| {
+                DIDWebVHError::SecretsError(
+                    "Couldn't decrypt envelope: wrong passphrase or corrupted data".to_string(),
+                )
+            })
+    }
+}
+
+/// Stretch `passphrase` into a 256-bit key with Argon2id.
+fn derive_key(
+    passphrase: &str,
+    salt: &[u8],
+    kdf: KdfParams,
+) -> Result<Zeroizing<[u8; KEY_LEN]>, DIDWebVHError> {
+    let params = Params::new(
+        kdf.memory_kib,
+        kdf.iterations,
+        kdf.parallelism,
+        Some(KEY_LEN),
+    )
+    .map_err(|e| DIDWebVHError::SecretsError(format!("Invalid Argon2 parameters: {e}")))?;
+    let mut key = Zeroizing::new([0u8; KEY_LEN]);
+    Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+        .hash_password_into(passphrase.as_bytes(), salt, key.as_mut())
+        .map_err(|e| DIDWebVHError::SecretsError(format!("Key derivation failed: {e}")))?;
+    Ok(key)
+}
+
+fn decode_field(name: &str, value: &str) -> Result<Vec<u8>, DIDWebVHError> {
+    BASE64.decode(value).map_err(|e| {
+        DIDWebVHError::SecretsError(format!("Envelope field '{name}' is not valid base64: {e}"))
+    })
+}
+
+/// Cheap KDF parameters so unit tests don't spend seconds in Argon2.
+#[cfg(test)]
+pub(crate) const TEST_KDF: KdfParams = KdfParams {
+    memory_kib: 64,
+    iterations: 1,
+    parallelism: 1,
+};
+
+#[cfg(test)]
+mod tests {
+    use super::{SealedEnvelope, TEST_KDF};
+    use crate::DIDWebVHError;
+    use base64::Engine;
+
+    #[test]
+    fn seal_open_round_trip() {
+        let sealed = SealedEnvelope::seal("test", b"top secret", "hunter2", TEST_KDF).unwrap();
+        let opened = sealed.open("test", "hunter2").unwrap();
+        assert_eq!(opened.as_slice(), b"top secret");
+    }
+
+    #[test]
+    fn wrong_passphrase_fails() {
+        let sealed = SealedEnvelope::seal("test", b"top secret", "hunter2", TEST_KDF).unwrap();
+        assert!(matches!(
+            sealed.open("test", "hunter3"),
+            Err(DIDWebVHError::SecretsError(_))
+        ));
+    }
+
+    #[test]
+    fn wrong_content_type_fails() {
+        let sealed = SealedEnvelope::seal("test", b"top secret", "hunter2", TEST_KDF).unwrap();
+        assert!(sealed.open("other", "hunter2").is_err());
+
+        // Relabelling the envelope doesn't help: the label is bound as AAD.
+        let mut relabelled = sealed.clone();
+        relabelled.content_type = "other".to_string();
+        assert!(relabelled.open("other", "hunter2").is_err());
+    }
+
+    #[test]
+    fn tampered_ciphertext_fails() {
+        let mut sealed = SealedEnvelope::seal("test", b"top secret", "hunter2", TEST_KDF).unwrap();
+        let mut ct = super::BASE64.decode(&sealed.ciphertext).unwrap();
+        ct[0] ^= 0xff;
+        sealed.ciphertext = super::BASE64.encode(ct);
+        assert!(sealed.open("test", "hunter2").is_err());
+    }
+}
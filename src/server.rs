@@ -0,0 +1,628 @@
+/*!
+*   Embeddable HTTP server for hosting DIDs, behind the `server` feature.
+*
+*   [`DidServer`] serves the DIDs in a [`DidStore`] at the URLs their DIDs
+*   resolve to ([`WebVHURL`] rules, so `/.well-known/` for domain-only DIDs
+*   and `/<path>/` otherwise):
+*
+*   - `did.jsonl`, `did-witness.json` (if the DID has witness proofs),
+*     `whois.vp` (if stored) and the did:web `did.json`
+*   - a strong `ETag` on every response, honouring `If-None-Match`
+*   - `Cache-Control: max-age=<ttl>` from the DID's `ttl` parameter
+*   - CORS headers, on by default
+*
+*   A DID that migrated is served at every location it has had, so resolvers
+*   holding the old DID still find the log.
+*
+*   With an [`append_token`](DidServer::append_token) set, `POST`ing an
+*   [`AppendRequest`] to a DID's `did.jsonl` URL with
+*   `Authorization: Bearer <token>` appends a new log entry. The entry (and
+*   any witness proofs sent with it) is validated against the stored log
+*   before it is committed.
+*
+*   Requests are matched on the `Host` header and path; anything else is a
+*   `404`. The header's host is case-insensitive and may spell out the DID
+*   URL's default port (`example.com:443`). For single-host or test deployments, [`any_host`](DidServer::any_host)
+*   serves a `GET` whose host matches no DID from the only DID at its path, so
+*   a local server on `127.0.0.1` can stand in for the real domain. Appends
+*   always need the DID's own host.
+*
+*   ```ignore
+*   let server = DidServer::new(Arc::new(DirectoryStore::new("/srv/dids")?))?
+*       .append_token(std::env::var("APPEND_TOKEN")?);
+*   let listener = tokio::net::TcpListener::bind("0.0.0.0:8080").await?;
+*   axum::serve(listener, server.router()).await?;
+*   ```
+*
+*   Store calls are synchronous and run on the request task; a store that
+*   blocks for long should be wrapped accordingly.
+*/
+
+use crate::{
+    DIDWebVHError, DIDWebVHState,
+    log_entry::{LogEntry, LogEntryMethods},
+    publish::{
+        DEFAULT_TTL, DID_JSON_CONTENT_TYPE, LOG_CONTENT_TYPE, WHOIS_CONTENT_TYPE,
+        WITNESS_CONTENT_TYPE,
+    },
+    store::DidStore,
+    url::WebVHURL,
+    witness::{WitnessVerifyOptions, proofs::WitnessProofCollection},
+};
+use ahash::HashMap;
+use axum::{
+    Router,
+    body::{Body, Bytes},
+    extract::State,
+    http::{HeaderMap, HeaderValue, Method, StatusCode, Uri, header},
+    response::{IntoResponse, Response},
+};
+use base58::ToBase58;
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use sha2::{Digest, Sha256};
+use std::sync::{Arc, RwLock};
+use tracing::warn;
+
+/// Body of a `POST` to a DID's `did.jsonl` URL.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AppendRequest {
+    /// The signed log entry to append.
+    pub log_entry: Value,
+    /// Witness proofs to merge in, in `did-witness.json` form. Needed when
+    /// the DID's witnesses must approve the new entry.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub witness_proofs: Option<WitnessProofCollection>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum FileKind {
+    Log,
+    WitnessProofs,
+    DidJson,
+    Whois,
+}
+
+/// Serves the DIDs of a [`DidStore`] over HTTP. See the [module docs](self).
+pub struct DidServer {
+    store: Arc<dyn DidStore>,
+    /// `(authority, path)` -> `(scid, file)`
+    index: RwLock<HashMap<(String, String), (String, FileKind)>>,
+    append_token: Option<String>,
+    cors: bool,
+    any_host: bool,
+    options: WitnessVerifyOptions,
+}
+
+impl DidServer {
+    /// Serve every DID in `store`.
+    pub fn new(store: Arc<dyn DidStore>) -> Result<Self, DIDWebVHError> {
+        let server = Self {
+            store,
+            index: RwLock::new(HashMap::default()),
+            append_token: None,
+            cors: true,
+            any_host: false,
+            options: WitnessVerifyOptions::new(),
+        };
+        for scid in server.store.list()? {
+            server.reindex(&scid)?;
+        }
+        Ok(server)
+    }
+
+    /// Enable the append endpoint, authenticated with this bearer token.
+    pub fn append_token(mut self, token: impl Into<String>) -> Self {
+        self.append_token = Some(token.into());
+        self
+    }
+
+    /// Whether to send CORS headers. Default: `true`.
+    pub fn cors(mut self, cors: bool) -> Self {
+        self.cors = cors;
+        self
+    }
+
+    /// Serve a `GET` or `HEAD` whose `Host` matches no DID from the only DID
+    /// at its path, for single-host or test deployments. Never applies to
+    /// appends. Default: `false`.
+    pub fn any_host(mut self, any_host: bool) -> Self {
+        self.any_host = any_host;
+        self
+    }
+
+    /// Options for verifying witness proofs on append.
+    pub fn witness_options(mut self, options: WitnessVerifyOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Refresh the URLs served for `scid`. Call after writing to the store
+    /// other than through this server.
+    pub fn reindex(&self, scid: &str) -> Result<(), DIDWebVHError> {
+        let log = self.store.read_log(scid)?;
+        let mut dids: Vec<&str> = Vec::new();
+        for entry in &log {
+            if let Some(id) = entry.get_state().get("id").and_then(Value::as_str)
+                && !dids.contains(&id)
+            {
+                dids.push(id);
+            }
+        }
+
+        let mut locations = Vec::new();
+        for (i, did) in dids.iter().enumerate() {
+            let url = match WebVHURL::parse_did_url(did) {
+                Ok(url) => url,
+                Err(e) => {
+                    warn!("Not serving {did}: {e}");
+                    continue;
+                }
+            };
+            let mut files = vec![
+                (url.get_http_url(Some("did.jsonl"))?, FileKind::Log),
+                (
+                    url.get_http_url(Some("did-witness.json"))?,
+                    FileKind::WitnessProofs,
+                ),
+                (url.get_http_whois_url()?, FileKind::Whois),
+            ];
+            // did:web only for the current location
+            if i == dids.len() - 1 {
+                files.push((url.get_http_url(Some("did.json"))?, FileKind::DidJson));
+            }
+            for (url, kind) in files {
+                let host = url.host_str().unwrap_or_default();
+                // A `Host` header may spell out the scheme's default port
+                if url.port().is_none()
+                    && let Some(port) = url.port_or_known_default()
+                {
+                    locations.push(((format!("{host}:{port}"), url.path().to_string()), kind));
+                }
+                let authority = match url.port() {
+                    Some(port) => format!("{host}:{port}"),
+                    None => host.to_string(),
+                };
+                locations.push(((authority, url.path().to_string()), kind));
+            }
+        }
+
+        let mut index = self
+            .index
+            .write()
+            .map_err(|_| DIDWebVHError::ServerError("DidServer index lock poisoned".to_string()))?;
+        index.retain(|_, (s, _)| s != scid);
+        for (key, kind) in locations {
+            index.insert(key, (scid.to_string(), kind));
+        }
+        Ok(())
+    }
+
+    /// An axum [`Router`] serving every request through this server.
+    pub fn router(self) -> Router {
+        Router::new().fallback(handle).with_state(Arc::new(self))
+    }
+
+    /// The file served at `authority` and `path`. With `any_host`, falls back
+    /// to the only DID served at `path`.
+    fn lookup(
+        &self,
+        authority: Option<&str>,
+        path: &str,
+        any_host: bool,
+    ) -> Option<(String, FileKind)> {
+        let index = self.index.read().ok()?;
+        if let Some(authority) = authority
+            && let Some(found) = index.get(&(authority.to_ascii_lowercase(), path.to_string()))
+        {
+            return Some(found.clone());
+        }
+        if !any_host {
+            return None;
+        }
+        // A DID can be indexed under several spellings of its authority
+        let mut at_path = index
+            .iter()
+            .filter(|((_, p), _)| p == path)
+            .map(|(_, found)| found);
+        let found = at_path.next()?;
+        at_path.all(|other| other == found).then(|| found.clone())
+    }
+
+    fn file(&self, scid: &str, kind: FileKind) -> Result<Option<(String, u32)>, DIDWebVHError> {
+        let log = self.store.read_log(scid)?;
+        let ttl = log
+            .iter()
+            .rev()
+            .find_map(|e| e.get_parameters().ttl)
+            .unwrap_or(DEFAULT_TTL);
+        let body = match kind {
+            FileKind::Log => Some(DIDWebVHState::entries_jsonl(&log)?),
+            FileKind::WitnessProofs => {
+                let proofs = self.store.read_witness_proofs(scid)?;
+                if proofs.get_total_count() == 0 {
+                    None
+                } else {
+                    Some(DIDWebVHState::proofs_json(&proofs)?)
+                }
+            }
+            FileKind::DidJson => {
+                let web_did = DIDWebVHState::from_log_entries(log).to_web_did()?;
+                Some(pretty(&web_did)?)
+            }
+            FileKind::Whois => self
+                .store
+                .read_whois(scid)?
+                .as_ref()
+                .map(pretty)
+                .transpose()?,
+        };
+        Ok(body.map(|b| (b, ttl)))
+    }
+
+    fn append(&self, scid: &str, request: &AppendRequest) -> Result<String, DIDWebVHError> {
+        let stored = self.store.read_log(scid)?;
+        let version = stored.last().map(LogEntry::get_webvh_version);
+        let entry = LogEntry::deserialize_string(&request.log_entry.to_string(), version)?;
+        let version_id = entry.get_version_id().to_string();
+
+        let mut proofs = self.store.read_witness_proofs(scid)?;
+        if let Some(extra) = &request.witness_proofs {
+            // The entry is published by this same commit
+            let (version_number, _) = entry.get_version_id_fields()?;
+            let report = proofs.merge(extra, version_number, &self.options)?;
+            if let Some(rejected) = report.rejected.first() {
+                return Err(DIDWebVHError::WitnessProofError(format!(
+                    "Witness proof from {} for {} rejected: {}",
+                    rejected.verification_method, rejected.version_id, rejected.reason
+                )));
+            }
+        }
+
+        let mut entries = stored.clone();
+        entries.push(entry.clone());
+        let mut state = DIDWebVHState::from_log_entries(entries);
+        state.set_witness_proofs(proofs.clone());
+        state.validate_with(&self.options)?.assert_complete()?;
+
+        self.store
+            .commit(scid, Some(stored.len()), &[entry], Some(&proofs))?;
+        self.reindex(scid)?;
+        Ok(version_id)
+    }
+
+    fn authorized(&self, headers: &HeaderMap) -> bool {
+        let Some(token) = &self.append_token else {
+            return false;
+        };
+        let Some(given) = headers
+            .get(header::AUTHORIZATION)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.strip_prefix("Bearer "))
+        else {
+            return false;
+        };
+        // Constant time for equal lengths
+        given.len() == token.len()
+            && given
+                .bytes()
+                .zip(token.bytes())
+                .fold(0u8, |acc, (a, b)| acc | (a ^ b))
+                == 0
+    }
+
+    fn with_cors(&self, mut response: Response) -> Response {
+        if self.cors {
+            let headers = response.headers_mut();
+            headers.insert(
+                header::ACCESS_CONTROL_ALLOW_ORIGIN,
+                HeaderValue::from_static("*"),
+            );
+            headers.insert(
+                header::ACCESS_CONTROL_EXPOSE_HEADERS,
+                HeaderValue::from_static("ETag"),
+            );
+        }
+        response
+    }
+}
+
+fn pretty(value: &Value) -> Result<String, DIDWebVHError> {
+    serde_json::to_string_pretty(value)
+        .map_err(|e| DIDWebVHError::ServerError(format!("Couldn't serialize response: {e}")))
+}
+
+fn error_response(e: &DIDWebVHError) -> Response {
+    let status = match e {
+        DIDWebVHError::StoreError(_) => StatusCode::CONFLICT,
+        DIDWebVHError::NotFound(_) => StatusCode::NOT_FOUND,
+        DIDWebVHError::ServerError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        _ => StatusCode::BAD_REQUEST,
+    };
+    json_response(status, &json!({ "error": e.to_string() }))
+}
+
+fn json_response(status: StatusCode, body: &Value) -> Response {
+    (
+        status,
+        [(header::CONTENT_TYPE, "application/json")],
+        body.to_string(),
+    )
+        .into_response()
+}
+
+async fn handle(
+    State(server): State<Arc<DidServer>>,
+    method: Method,
+    uri: Uri,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    let authority = headers
+        .get(header::HOST)
+        .and_then(|h| h.to_str().ok())
+        .or_else(|| uri.authority().map(|a| a.as_str()));
+    let any_host = server.any_host && method != Method::POST;
+    let found = server.lookup(authority, uri.path(), any_host);
+
+    let response = match (method, found) {
+        (Method::OPTIONS, _) if server.cors => Response::builder()
+            .status(StatusCode::NO_CONTENT)
+            .header(header::ACCESS_CONTROL_ALLOW_METHODS, "GET, HEAD, OPTIONS")
+            .header(header::ACCESS_CONTROL_ALLOW_HEADERS, "If-None-Match")
+            .body(Body::empty())
+            .unwrap_or_default(),
+        (method @ (Method::GET | Method::HEAD), Some((scid, kind))) => {
+            serve(&server, &scid, kind, &headers, method == Method::HEAD)
+        }
+        (Method::POST, Some((scid, FileKind::Log))) if server.authorized(&headers) => {
+            match serde_json::from_slice::<AppendRequest>(&body) {
+                Ok(request) => match server.append(&scid, &request) {
+                    Ok(version_id) => {
+                        json_response(StatusCode::CREATED, &json!({ "versionId": version_id }))
+                    }
+                    Err(e) => error_response(&e),
+                },
+                Err(e) => json_response(
+                    StatusCode::BAD_REQUEST,
+                    &json!({ "error": format!("Invalid append request: {e}") }),
+                ),
+            }
+        }
+        (Method::POST, Some((_, FileKind::Log))) if server.append_token.is_some() => json_response(
+            StatusCode::UNAUTHORIZED,
+            &json!({ "error": "Missing or invalid bearer token" }),
+        ),
+        (Method::GET | Method::HEAD | Method::POST, None) => StatusCode::NOT_FOUND.into_response(),
+        _ => StatusCode::METHOD_NOT_ALLOWED.into_response(),
+    };
+    server.with_cors(response)
+}
+
+fn serve(
+    server: &DidServer,
+    scid: &str,
+    kind: FileKind,
+    headers: &HeaderMap,
+    head_only: bool,
+) -> Response {
+    let (body, ttl) = match server.file(scid, kind) {
+        Ok(Some(file)) => file,
+        Ok(None) => return StatusCode::NOT_FOUND.into_response(),
+        Err(e) => return error_response(&e),
+    };
+    let etag = format!("\"{}\"", Sha256::digest(body.as_bytes()).to_base58());
+    let content_type = match kind {
+        FileKind::Log => LOG_CONTENT_TYPE,
+        FileKind::WitnessProofs => WITNESS_CONTENT_TYPE,
+        FileKind::DidJson => DID_JSON_CONTENT_TYPE,
+        FileKind::Whois => WHOIS_CONTENT_TYPE,
+    };
+    let not_modified = headers
+        .get(header::IF_NONE_MATCH)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.split(',').any(|t| t.trim() == etag || t.trim() == "*"));
+
+    let builder = Response::builder()
+        .header(header::ETAG, &etag)
+        .header(header::CACHE_CONTROL, format!("max-age={ttl}"));
+    let response = if not_modified {
+        builder.status(StatusCode::NOT_MODIFIED).body(Body::empty())
+    } else {
+        builder
+            .status(StatusCode::OK)
+            .header(header::CONTENT_TYPE, content_type)
+            .body(if head_only {
+                Body::empty()
+            } else {
+                Body::from(body)
+            })
+    };
+    response.unwrap_or_else(|e| {
+        error_response(&DIDWebVHError::ServerError(format!(
+            "Couldn't build response: {e}"
+        )))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{AppendRequest, DidServer};
+    use crate::{
+        DIDWebVHState,
+        store::{DidStore, MemoryStore},
+        test_utils::{did_doc_with_key, key_and_params},
+    };
+    use axum::{
+        Router,
+        body::{Body, to_bytes},
+        http::{Request, StatusCode, header},
+        response::Response,
+    };
+    use chrono::{Duration, Utc};
+    use serde_json::json;
+    use std::sync::Arc;
+    use tower::ServiceExt;
+
+    async fn send(router: &Router, request: Request<Body>) -> Response {
+        router.clone().oneshot(request).await.unwrap()
+    }
+
+    fn get(host: &str, path: &str) -> Request<Body> {
+        Request::get(path)
+            .header(header::HOST, host)
+            .body(Body::empty())
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn serves_files_with_etag_and_ttl() {
+        let (key, mut params) = key_and_params();
+        params.ttl = Some(120);
+        let doc = did_doc_with_key("did:webvh:{SCID}:example.com", &key);
+        let mut state = DIDWebVHState::default();
+        state
+            .create_log_entry(
+                Some(Utc::now().fixed_offset() - Duration::minutes(5)),
+                &doc,
+                &params,
+                &key,
+            )
+            .await
+            .unwrap();
+        let store = Arc::new(MemoryStore::new());
+        store.store_state(&state).unwrap();
+        store
+            .write_whois(state.scid(), &json!({"type": "VerifiablePresentation"}))
+            .unwrap();
+        let router = DidServer::new(store.clone()).unwrap().router();
+
+        let response = send(&router, get("example.com", "/.well-known/did.jsonl")).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[header::CACHE_CONTROL], "max-age=120");
+        assert_eq!(response.headers()[header::ACCESS_CONTROL_ALLOW_ORIGIN], "*");
+        let etag = response.headers()[header::ETAG].clone();
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        assert_eq!(String::from_utf8_lossy(&body).lines().count(), 1);
+
+        let cached = Request::get("/.well-known/did.jsonl")
+            .header(header::HOST, "example.com")
+            .header(header::IF_NONE_MATCH, etag)
+            .body(Body::empty())
+            .unwrap();
+        assert_eq!(
+            send(&router, cached).await.status(),
+            StatusCode::NOT_MODIFIED
+        );
+
+        // The default port may be spelled out, but no other
+        let response = send(&router, get("Example.com:443", "/.well-known/did.jsonl")).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            send(&router, get("example.com:80", "/.well-known/did.jsonl"))
+                .await
+                .status(),
+            StatusCode::NOT_FOUND
+        );
+
+        // Other hosts only with `any_host`
+        assert_eq!(
+            send(&router, get("127.0.0.1:8080", "/.well-known/did.json"))
+                .await
+                .status(),
+            StatusCode::NOT_FOUND
+        );
+        let any_host = DidServer::new(store).unwrap().any_host(true).router();
+        let response = send(&any_host, get("127.0.0.1:8080", "/.well-known/did.json")).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response.headers()[header::CONTENT_TYPE],
+            "application/did+json"
+        );
+        assert_eq!(
+            send(&router, get("example.com", "/whois.vp"))
+                .await
+                .status(),
+            StatusCode::OK
+        );
+        // No witnesses, no proofs file
+        assert_eq!(
+            send(&router, get("example.com", "/.well-known/did-witness.json"))
+                .await
+                .status(),
+            StatusCode::NOT_FOUND
+        );
+        assert_eq!(
+            send(&router, get("example.com", "/elsewhere/did.jsonl"))
+                .await
+                .status(),
+            StatusCode::NOT_FOUND
+        );
+    }
+
+    #[tokio::test]
+    async fn append_validates_and_requires_token() {
+        let (key, params) = key_and_params();
+        let doc = did_doc_with_key("did:webvh:{SCID}:example.com:dids:alice", &key);
+        let start = Utc::now().fixed_offset() - Duration::minutes(5);
+        let mut state = DIDWebVHState::default();
+        state
+            .create_log_entry(Some(start), &doc, &params, &key)
+            .await
+            .unwrap();
+        let store = Arc::new(MemoryStore::new());
+        store.store_state(&state).unwrap();
+        let router = DidServer::new(store.clone())
+            .unwrap()
+            .append_token("s3cret")
+            .any_host(true)
+            .router();
+
+        let doc = state.current_document().unwrap();
+        let params = state.current_parameters().unwrap();
+        let entry = state
+            .create_log_entry(Some(start + Duration::minutes(1)), &doc, &params, &key)
+            .await
+            .unwrap()
+            .log_entry
+            .clone();
+        let body = serde_json::to_vec(&AppendRequest {
+            log_entry: serde_json::to_value(&entry).unwrap(),
+            witness_proofs: None,
+        })
+        .unwrap();
+        let post_to = |host: &str, token: &str| {
+            Request::post("/dids/alice/did.jsonl")
+                .header(header::HOST, host)
+                .header(header::AUTHORIZATION, format!("Bearer {token}"))
+                .body(Body::from(body.clone()))
+                .unwrap()
+        };
+        let post = |token: &str| post_to("example.com", token);
+
+        // `any_host` doesn't extend to appends
+        assert_eq!(
+            send(&router, post_to("127.0.0.1:8080", "s3cret"))
+                .await
+                .status(),
+            StatusCode::NOT_FOUND
+        );
+        assert_eq!(store.read_log(state.scid()).unwrap().len(), 1);
+        assert_eq!(
+            send(&router, post("wrong")).await.status(),
+            StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(
+            send(&router, post("s3cret")).await.status(),
+            StatusCode::CREATED
+        );
+        assert_eq!(store.read_log(state.scid()).unwrap().len(), 2);
+        // The same entry again no longer extends the log
+        assert_eq!(
+            send(&router, post("s3cret")).await.status(),
+            StatusCode::BAD_REQUEST
+        );
+        assert_eq!(store.read_log(state.scid()).unwrap().len(), 2);
+    }
+}
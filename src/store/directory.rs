@@ -0,0 +1,330 @@
+//! [`DidStore`] backed by a directory tree.
+//!
+//! Each DID gets a directory named after its SCID:
+//!
+//! ```text
+//! <root>/<scid>/did.jsonl
+//! <root>/<scid>/did-witness.json
+//! <root>/<scid>/whois.vp
+//! ```
+//!
+//! This is a storage layout, not a web root: a DID is served from the path
+//! its identifier encodes, not from its SCID. Serve the store with
+//! `server::DidServer` (`server` feature), or lay out static files with
+//! [`SiteBundle`](crate::publish::SiteBundle).
+
+use super::{DidStore, conflict, deserialize_proofs, parse_entries};
+use crate::{
+    DIDWebVHError, DIDWebVHState, StagedFile, log_entry::LogEntry,
+    witness::proofs::WitnessProofCollection, write_atomic,
+};
+use serde_json::Value;
+use std::{
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+/// Log file name inside each DID directory.
+pub const LOG_FILE: &str = "did.jsonl";
+/// Witness proof file name inside each DID directory.
+pub const WITNESS_FILE: &str = "did-witness.json";
+/// whois Verifiable Presentation file name inside each DID directory.
+pub const WHOIS_FILE: &str = "whois.vp";
+
+/// A [`DidStore`] keeping each DID's files in `<root>/<scid>/`.
+///
+/// Commits stage both files as temporary files, then rename
+/// `did-witness.json` before `did.jsonl`, so a reader never sees log entries
+/// without their witness proofs. If the log can't be renamed the previous
+/// witness proofs are put back. Commits are serialized within the process; run a
+/// single writer per directory.
+pub struct DirectoryStore {
+    root: PathBuf,
+    write_lock: Mutex<()>,
+}
+
+impl DirectoryStore {
+    /// Use `root` as the store, creating it if needed.
+    pub fn new(root: impl Into<PathBuf>) -> Result<Self, DIDWebVHError> {
+        let root = root.into();
+        std::fs::create_dir_all(&root).map_err(|e| {
+            DIDWebVHError::StoreError(format!("Couldn't create {}: {e}", root.display()))
+        })?;
+        Ok(Self {
+            root,
+            write_lock: Mutex::new(()),
+        })
+    }
+
+    /// The store's root directory.
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Directory holding the files of `scid`.
+    pub fn did_dir(&self, scid: &str) -> Result<PathBuf, DIDWebVHError> {
+        // SCIDs are base58; anything else could escape the root
+        if scid.is_empty() || !scid.chars().all(|c| c.is_ascii_alphanumeric()) {
+            return Err(DIDWebVHError::StoreError(format!("Invalid SCID ({scid})")));
+        }
+        Ok(self.root.join(scid))
+    }
+
+    fn read_log_text(&self, scid: &str) -> Result<String, DIDWebVHError> {
+        let path = self.did_dir(scid)?.join(LOG_FILE);
+        match std::fs::read_to_string(&path) {
+            Ok(text) => Ok(text),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(String::new()),
+            Err(e) => Err(DIDWebVHError::StoreError(format!(
+                "Couldn't read {}: {e}",
+                path.display()
+            ))),
+        }
+    }
+}
+
+impl DidStore for DirectoryStore {
+    fn commit(
+        &self,
+        scid: &str,
+        expected_len: Option<usize>,
+        entries: &[LogEntry],
+        witness_proofs: Option<&WitnessProofCollection>,
+    ) -> Result<(), DIDWebVHError> {
+        let _guard = self
+            .write_lock
+            .lock()
+            .map_err(|_| DIDWebVHError::StoreError("DirectoryStore lock poisoned".to_string()))?;
+
+        let dir = self.did_dir(scid)?;
+        let mut log = self.read_log_text(scid)?;
+        let stored_len = log.lines().filter(|l| !l.trim().is_empty()).count();
+        if let Some(expected) = expected_len
+            && expected != stored_len
+        {
+            return Err(conflict(scid, expected, stored_len));
+        }
+
+        // Serialize everything before touching the filesystem
+        if !entries.is_empty() && !log.is_empty() && !log.ends_with('\n') {
+            log.push('\n');
+        }
+        log.push_str(&DIDWebVHState::entries_jsonl(entries)?);
+        let proofs = witness_proofs.map(DIDWebVHState::proofs_json).transpose()?;
+
+        std::fs::create_dir_all(&dir).map_err(|e| {
+            DIDWebVHError::StoreError(format!("Couldn't create {}: {e}", dir.display()))
+        })?;
+        let (witness_path, log_path) = (dir.join(WITNESS_FILE), dir.join(LOG_FILE));
+        // Stage both files before renaming either
+        let staged_proofs = proofs
+            .map(|proofs| stage(&witness_path, &proofs))
+            .transpose()?;
+        let staged_log = if entries.is_empty() {
+            None
+        } else {
+            Some(stage(&log_path, &log)?)
+        };
+        let Some(staged_log) = staged_log else {
+            return staged_proofs.map_or(Ok(()), |p| persist(p, &witness_path));
+        };
+        let Some(staged_proofs) = staged_proofs else {
+            return persist(staged_log, &log_path);
+        };
+
+        // Keep the current proofs in case the log can't be renamed
+        let old_proofs = match std::fs::read(&witness_path) {
+            Ok(old) => Some(old),
+            Err(e) if e.kind() == ErrorKind::NotFound => None,
+            Err(e) => {
+                return Err(DIDWebVHError::StoreError(format!(
+                    "Couldn't read {}: {e}",
+                    witness_path.display()
+                )));
+            }
+        };
+        persist(staged_proofs, &witness_path)?;
+        if let Err(e) = persist(staged_log, &log_path) {
+            let restored = match old_proofs {
+                Some(old) => write_atomic(&witness_path, &old),
+                None => std::fs::remove_file(&witness_path),
+            };
+            if let Err(restore) = restored {
+                return Err(DIDWebVHError::StoreError(format!(
+                    "{e}; couldn't restore {}: {restore}",
+                    witness_path.display()
+                )));
+            }
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    fn read_log(&self, scid: &str) -> Result<Vec<LogEntry>, DIDWebVHError> {
+        parse_entries(self.read_log_text(scid)?.lines())
+    }
+
+    fn read_witness_proofs(&self, scid: &str) -> Result<WitnessProofCollection, DIDWebVHError> {
+        let path = self.did_dir(scid)?.join(WITNESS_FILE);
+        match std::fs::read_to_string(&path) {
+            Ok(json) => deserialize_proofs(&json),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(WitnessProofCollection::default()),
+            Err(e) => Err(DIDWebVHError::StoreError(format!(
+                "Couldn't read {}: {e}",
+                path.display()
+            ))),
+        }
+    }
+
+    fn read_whois(&self, scid: &str) -> Result<Option<Value>, DIDWebVHError> {
+        let path = self.did_dir(scid)?.join(WHOIS_FILE);
+        match std::fs::read_to_string(&path) {
+            Ok(json) => serde_json::from_str(&json).map(Some).map_err(|e| {
+                DIDWebVHError::StoreError(format!("Couldn't parse {}: {e}", path.display()))
+            }),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(DIDWebVHError::StoreError(format!(
+                "Couldn't read {}: {e}",
+                path.display()
+            ))),
+        }
+    }
+
+    fn write_whois(&self, scid: &str, vp: &Value) -> Result<(), DIDWebVHError> {
+        let dir = self.did_dir(scid)?;
+        let json = serde_json::to_string_pretty(vp)
+            .map_err(|e| DIDWebVHError::StoreError(format!("Couldn't serialize whois.vp: {e}")))?;
+        std::fs::create_dir_all(&dir).map_err(|e| {
+            DIDWebVHError::StoreError(format!("Couldn't create {}: {e}", dir.display()))
+        })?;
+        write_file(&dir.join(WHOIS_FILE), &json)
+    }
+
+    fn list(&self) -> Result<Vec<String>, DIDWebVHError> {
+        let dir = std::fs::read_dir(&self.root).map_err(|e| {
+            DIDWebVHError::StoreError(format!("Couldn't read {}: {e}", self.root.display()))
+        })?;
+        let mut scids = Vec::new();
+        for entry in dir {
+            let entry = entry.map_err(|e| {
+                DIDWebVHError::StoreError(format!("Couldn't read {}: {e}", self.root.display()))
+            })?;
+            if entry.path().join(LOG_FILE).is_file()
+                && let Some(name) = entry.file_name().to_str()
+            {
+                scids.push(name.to_string());
+            }
+        }
+        scids.sort();
+        Ok(scids)
+    }
+}
+
+fn write_file(path: &Path, contents: &str) -> Result<(), DIDWebVHError> {
+    stage(path, contents).and_then(|staged| persist(staged, path))
+}
+
+#[cfg(test)]
+thread_local! {
+    /// File name whose staging fails, so tests can reach commit's error paths
+    static FAIL_STAGING: std::cell::Cell<Option<&'static str>> = const { std::cell::Cell::new(None) };
+}
+
+fn stage(path: &Path, contents: &str) -> Result<StagedFile, DIDWebVHError> {
+    #[cfg(test)]
+    if FAIL_STAGING.get().is_some_and(|name| path.ends_with(name)) {
+        return Err(DIDWebVHError::StoreError(format!(
+            "Couldn't write {}",
+            path.display()
+        )));
+    }
+    StagedFile::new(path, contents.as_bytes())
+        .map_err(|e| DIDWebVHError::StoreError(format!("Couldn't write {}: {e}", path.display())))
+}
+
+fn persist(staged: StagedFile, path: &Path) -> Result<(), DIDWebVHError> {
+    staged
+        .persist()
+        .map_err(|e| DIDWebVHError::StoreError(format!("Couldn't write {}: {e}", path.display())))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DirectoryStore, LOG_FILE, WITNESS_FILE};
+    use crate::{
+        store::{DidStore, tests},
+        witness::proofs::WitnessProofCollection,
+    };
+
+    fn temp_store(name: &str) -> DirectoryStore {
+        let root = std::env::temp_dir().join(format!(
+            "didwebvh-store-{name}-{}",
+            std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap()
+                .as_nanos()
+        ));
+        DirectoryStore::new(root).unwrap()
+    }
+
+    #[tokio::test]
+    async fn round_trip() {
+        let store = temp_store("round-trip");
+        tests::round_trip(&store).await;
+        let scid = &store.list().unwrap()[0];
+        let log = std::fs::read_to_string(store.did_dir(scid).unwrap().join(LOG_FILE)).unwrap();
+        assert_eq!(log.lines().count(), 2);
+        let _ = std::fs::remove_dir_all(store.root());
+    }
+
+    #[tokio::test]
+    async fn diverged_state_rejected() {
+        let store = temp_store("diverged");
+        tests::diverged_state_rejected(&store).await;
+        let _ = std::fs::remove_dir_all(store.root());
+    }
+
+    #[tokio::test]
+    async fn failed_commit_writes_nothing() {
+        let store = temp_store("failed-commit");
+        let state = tests::two_entries().await;
+        let dir = store.did_dir(state.scid()).unwrap();
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join(WITNESS_FILE), "old").unwrap();
+        // The log can't be staged
+        super::FAIL_STAGING.set(Some(LOG_FILE));
+
+        let entries: Vec<_> = state
+            .log_entries()
+            .iter()
+            .map(|e| e.log_entry.clone())
+            .collect();
+        assert!(
+            store
+                .commit(
+                    state.scid(),
+                    None,
+                    &entries,
+                    Some(&WitnessProofCollection::default())
+                )
+                .is_err()
+        );
+        assert_eq!(
+            std::fs::read_to_string(dir.join(WITNESS_FILE)).unwrap(),
+            "old"
+        );
+        assert!(!dir.join(LOG_FILE).exists());
+        super::FAIL_STAGING.set(None);
+        let _ = std::fs::remove_dir_all(store.root());
+    }
+
+    #[test]
+    fn scid_cannot_escape_root() {
+        let store = temp_store("escape");
+        assert!(store.did_dir("../etc").is_err());
+        assert!(store.did_dir("").is_err());
+        assert!(store.read_log("a/b").is_err());
+        let _ = std::fs::remove_dir_all(store.root());
+    }
+}
@@ -0,0 +1,106 @@
+//! In-memory [`DidStore`].
+
+use super::{DidStore, conflict};
+use crate::{DIDWebVHError, log_entry::LogEntry, witness::proofs::WitnessProofCollection};
+use ahash::HashMap;
+use serde_json::Value;
+use std::sync::RwLock;
+
+#[derive(Default)]
+struct StoredDid {
+    log: Vec<LogEntry>,
+    witness_proofs: WitnessProofCollection,
+    whois: Option<Value>,
+}
+
+/// A [`DidStore`] held in process memory. Nothing survives a restart.
+#[derive(Default)]
+pub struct MemoryStore {
+    dids: RwLock<HashMap<String, StoredDid>>,
+}
+
+impl MemoryStore {
+    /// An empty store.
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+fn poisoned<T>(_: T) -> DIDWebVHError {
+    DIDWebVHError::StoreError("MemoryStore lock poisoned".to_string())
+}
+
+impl DidStore for MemoryStore {
+    fn commit(
+        &self,
+        scid: &str,
+        expected_len: Option<usize>,
+        entries: &[LogEntry],
+        witness_proofs: Option<&WitnessProofCollection>,
+    ) -> Result<(), DIDWebVHError> {
+        let mut dids = self.dids.write().map_err(poisoned)?;
+        let stored_len = dids.get(scid).map_or(0, |d| d.log.len());
+        if let Some(expected) = expected_len
+            && expected != stored_len
+        {
+            return Err(conflict(scid, expected, stored_len));
+        }
+        let did = dids.entry(scid.to_string()).or_default();
+        did.log.extend_from_slice(entries);
+        if let Some(proofs) = witness_proofs {
+            did.witness_proofs = proofs.clone();
+        }
+        Ok(())
+    }
+
+    fn read_log(&self, scid: &str) -> Result<Vec<LogEntry>, DIDWebVHError> {
+        let dids = self.dids.read().map_err(poisoned)?;
+        Ok(dids.get(scid).map(|d| d.log.clone()).unwrap_or_default())
+    }
+
+    fn read_witness_proofs(&self, scid: &str) -> Result<WitnessProofCollection, DIDWebVHError> {
+        let dids = self.dids.read().map_err(poisoned)?;
+        Ok(dids
+            .get(scid)
+            .map(|d| d.witness_proofs.clone())
+            .unwrap_or_default())
+    }
+
+    fn read_whois(&self, scid: &str) -> Result<Option<Value>, DIDWebVHError> {
+        let dids = self.dids.read().map_err(poisoned)?;
+        Ok(dids.get(scid).and_then(|d| d.whois.clone()))
+    }
+
+    fn write_whois(&self, scid: &str, vp: &Value) -> Result<(), DIDWebVHError> {
+        let mut dids = self.dids.write().map_err(poisoned)?;
+        dids.entry(scid.to_string()).or_default().whois = Some(vp.clone());
+        Ok(())
+    }
+
+    fn list(&self) -> Result<Vec<String>, DIDWebVHError> {
+        let dids = self.dids.read().map_err(poisoned)?;
+        let mut scids: Vec<String> = dids
+            .iter()
+            .filter(|(_, d)| !d.log.is_empty())
+            .map(|(scid, _)| scid.clone())
+            .collect();
+        scids.sort();
+        Ok(scids)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::MemoryStore;
+    use crate::store::tests;
+
+    #[tokio::test]
+    async fn round_trip() {
+        tests::round_trip(&MemoryStore::new()).await;
+    }
+
+    #[tokio::test]
+    async fn diverged_state_rejected() {
+        tests::diverged_state_rejected(&MemoryStore::new()).await;
+    }
+}
@@ -0,0 +1,309 @@
+/*!
+*   Persistent storage for DID logs and witness proofs.
+*
+*   A [`DidStore`] holds the published log (`did.jsonl`) and the witness
+*   proofs (`did-witness.json`), and optionally the `whois.vp`, of any number
+*   of DIDs. DIDs are keyed by their
+*   SCID, which unlike the DID itself doesn't change when a DID migrates.
+*
+*   Every write goes through [`DidStore::commit`], which appends entries and
+*   replaces the witness proofs in one atomic step, and refuses the write if
+*   the stored log has changed length since the caller read it. That keeps two
+*   controllers racing on the same DID from forking its log.
+*
+*   Backends:
+*   - [`MemoryStore`]: in-process, for tests and caches
+*   - [`DirectoryStore`]: one directory per DID holding `did.jsonl` and
+*     `did-witness.json`, ready to be served as static files
+*   - [`SqliteStore`](sqlite::SqliteStore): a single SQLite database, behind
+*     the `sqlite` feature
+*
+*   # Example
+*
+*   ```ignore
+*   use didwebvh_rs::store::{DidStore, DirectoryStore};
+*
+*   let store = DirectoryStore::new("/srv/dids")?;
+*   create_did(config).await?.persist(&store)?;
+*
+*   let state = store.load_state(scid)?.expect("stored above");
+*   let result = update_did(UpdateDIDConfig::builder().state(state) /* ... */.build()?).await?;
+*   result.persist(&store)?;
+*   ```
+*/
+
+use crate::{
+    DIDWebVHError, DIDWebVHState,
+    create::CreateDIDResult,
+    log_entry::{LogEntry, LogEntryMethods},
+    update::UpdateDIDResult,
+    witness::proofs::WitnessProofCollection,
+};
+use serde_json::Value;
+
+pub mod directory;
+pub mod memory;
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
+
+pub use directory::DirectoryStore;
+pub use memory::MemoryStore;
+
+/// Storage for the published logs and witness proofs of many DIDs.
+///
+/// Implementors provide [`commit`](Self::commit) and the three reads; the
+/// remaining methods are built on those.
+pub trait DidStore: Send + Sync {
+    /// Atomically append `entries` to the log of `scid` and, if given,
+    /// replace its witness proofs. Either everything is written or nothing is.
+    ///
+    /// If `expected_len` is set and the stored log doesn't have exactly that
+    /// many entries, nothing is written and a [`DIDWebVHError::StoreError`] is
+    /// returned.
+    fn commit(
+        &self,
+        scid: &str,
+        expected_len: Option<usize>,
+        entries: &[LogEntry],
+        witness_proofs: Option<&WitnessProofCollection>,
+    ) -> Result<(), DIDWebVHError>;
+
+    /// The published log of `scid`, oldest first. Empty if the DID isn't stored.
+    fn read_log(&self, scid: &str) -> Result<Vec<LogEntry>, DIDWebVHError>;
+
+    /// The witness proofs of `scid`. Empty if there are none.
+    fn read_witness_proofs(&self, scid: &str) -> Result<WitnessProofCollection, DIDWebVHError>;
+
+    /// SCIDs of every stored DID.
+    fn list(&self) -> Result<Vec<String>, DIDWebVHError>;
+
+    /// The entry of `scid` with the given versionId, if stored.
+    fn read_version(
+        &self,
+        scid: &str,
+        version_id: &str,
+    ) -> Result<Option<LogEntry>, DIDWebVHError> {
+        Ok(self
+            .read_log(scid)?
+            .into_iter()
+            .find(|e| e.get_version_id() == version_id))
+    }
+
+    /// The `whois.vp` Verifiable Presentation of `scid`, if stored.
+    fn read_whois(&self, scid: &str) -> Result<Option<Value>, DIDWebVHError> {
+        let _ = scid;
+        Ok(None)
+    }
+
+    /// Store the `whois.vp` Verifiable Presentation of `scid`. Backends that
+    /// don't hold presentations return [`DIDWebVHError::NotImplemented`].
+    fn write_whois(&self, scid: &str, vp: &Value) -> Result<(), DIDWebVHError> {
+        let _ = (scid, vp);
+        Err(DIDWebVHError::NotImplemented(
+            "This DidStore doesn't store whois.vp".to_string(),
+        ))
+    }
+
+    /// Append one entry to the log of `scid`.
+    fn append_entry(&self, scid: &str, entry: &LogEntry) -> Result<(), DIDWebVHError> {
+        self.commit(scid, None, std::slice::from_ref(entry), None)
+    }
+
+    /// Replace the witness proofs of `scid`.
+    fn write_witness_proofs(
+        &self,
+        scid: &str,
+        witness_proofs: &WitnessProofCollection,
+    ) -> Result<(), DIDWebVHError> {
+        self.commit(scid, None, &[], Some(witness_proofs))
+    }
+
+    /// Store the published log and witness proofs of `state`.
+    ///
+    /// Only entries the store doesn't already hold are written, so this is
+    /// cheap to call after every update. Errors without writing if the
+    /// stored log isn't a prefix of the state's log. A pending entry is not
+    /// stored. Returns the number of entries appended.
+    fn store_state(&self, state: &DIDWebVHState) -> Result<usize, DIDWebVHError> {
+        let Some(first) = state.log_entries().first() else {
+            return Err(DIDWebVHError::StoreError(
+                "Can't store a DID state without log entries".to_string(),
+            ));
+        };
+        let scid = first.log_entry.get_scid().unwrap_or(state.scid());
+        let stored = self.read_log(scid)?;
+        let published = state.log_entries();
+        if stored.len() > published.len()
+            || stored
+                .iter()
+                .zip(published)
+                .any(|(s, p)| s.get_version_id() != p.get_version_id())
+        {
+            return Err(DIDWebVHError::StoreError(format!(
+                "Stored log for {scid} has diverged from the state being stored"
+            )));
+        }
+        let new: Vec<LogEntry> = published[stored.len()..]
+            .iter()
+            .map(|e| e.log_entry.clone())
+            .collect();
+        self.commit(scid, Some(stored.len()), &new, Some(state.witness_proofs()))?;
+        Ok(new.len())
+    }
+
+    /// Load and validate the stored DID `scid`. `None` if it isn't stored.
+    /// Errors if any stored entry fails validation.
+    fn load_state(&self, scid: &str) -> Result<Option<DIDWebVHState>, DIDWebVHError> {
+        let log = self.read_log(scid)?;
+        if log.is_empty() {
+            return Ok(None);
+        }
+        let mut state = DIDWebVHState::from_log_entries(log);
+        state.set_witness_proofs(self.read_witness_proofs(scid)?);
+        state.validate()?.assert_complete()?;
+        Ok(Some(state))
+    }
+}
+
+impl CreateDIDResult {
+    /// Store the new DID. Errors if a DID with the same SCID is already stored.
+    pub fn persist<S: DidStore + ?Sized>(&self, store: &S) -> Result<(), DIDWebVHError> {
+        let scid = self
+            .log_entry
+            .get_scid()
+            .ok_or_else(|| DIDWebVHError::StoreError("First log entry has no SCID".to_string()))?;
+        store.commit(
+            scid,
+            Some(0),
+            std::slice::from_ref(&self.log_entry),
+            Some(&self.witness_proofs),
+        )
+    }
+}
+
+impl UpdateDIDResult {
+    /// Store the new entries and witness proofs. See [`DidStore::store_state`].
+    pub fn persist<S: DidStore + ?Sized>(&self, store: &S) -> Result<usize, DIDWebVHError> {
+        store.store_state(self.state())
+    }
+}
+
+/// Error for a commit whose `expected_len` doesn't match the stored log.
+pub(crate) fn conflict(scid: &str, expected: usize, stored: usize) -> DIDWebVHError {
+    DIDWebVHError::StoreError(format!(
+        "Log for {scid} has {stored} entries, expected {expected}; it was changed concurrently"
+    ))
+}
+
+/// Parse log entries serialized one per line (or one per row), threading the
+/// webvh version through as [`LogEntry::load_from_file`] does.
+pub(crate) fn parse_entries<'a>(
+    lines: impl IntoIterator<Item = &'a str>,
+) -> Result<Vec<LogEntry>, DIDWebVHError> {
+    let mut entries = Vec::new();
+    let mut version = None;
+    for line in lines {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let entry = LogEntry::deserialize_string(line, version)?;
+        version = Some(entry.get_webvh_version());
+        entries.push(entry);
+    }
+    Ok(entries)
+}
+
+pub(crate) fn deserialize_proofs(json: &str) -> Result<WitnessProofCollection, DIDWebVHError> {
+    serde_json::from_str(json).map_err(|e| {
+        DIDWebVHError::WitnessProofError(format!("Couldn't deserialize Witness Proofs Data: {e}"))
+    })
+}
+
+/// Tests every backend runs.
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::DidStore;
+    use crate::{
+        DIDWebVHState,
+        create::CreateDIDResult,
+        log_entry::LogEntryMethods,
+        test_utils::{did_doc_with_key, key_and_params},
+        witness::proofs::WitnessProofCollection,
+    };
+    use chrono::{Duration, Utc};
+
+    /// Create a DID with two entries.
+    pub(crate) async fn two_entries() -> DIDWebVHState {
+        let (key, params) = key_and_params();
+        let doc = did_doc_with_key("did:webvh:{SCID}:example.com", &key);
+        let start = Utc::now().fixed_offset() - Duration::hours(1);
+        let mut state = DIDWebVHState::default();
+        state
+            .create_log_entry(Some(start), &doc, &params, &key)
+            .await
+            .unwrap();
+        let doc = state.current_document().unwrap();
+        let params = state.current_parameters().unwrap();
+        state
+            .create_log_entry(Some(start + Duration::minutes(1)), &doc, &params, &key)
+            .await
+            .unwrap();
+        state
+    }
+
+    pub(crate) async fn round_trip(store: &dyn DidStore) {
+        let second = two_entries().await;
+        let scid = second.scid().to_string();
+        assert!(store.read_log(&scid).unwrap().is_empty());
+        assert!(store.load_state(&scid).unwrap().is_none());
+
+        let created = CreateDIDResult {
+            did: String::new(),
+            log_entry: second.log_entries()[0].log_entry.clone(),
+            witness_proofs: WitnessProofCollection::default(),
+        };
+        created.persist(store).unwrap();
+        // Same SCID twice is refused
+        assert!(created.persist(store).is_err());
+
+        assert_eq!(store.store_state(&second).unwrap(), 1);
+        assert_eq!(store.store_state(&second).unwrap(), 0);
+        assert_eq!(store.list().unwrap(), vec![scid.clone()]);
+
+        let version_id = second.log_entries()[1].get_version_id();
+        assert_eq!(
+            store
+                .read_version(&scid, version_id)
+                .unwrap()
+                .unwrap()
+                .get_version_id(),
+            version_id
+        );
+        assert!(store.read_version(&scid, "9-nope").unwrap().is_none());
+
+        let loaded = store.load_state(&scid).unwrap().unwrap();
+        assert_eq!(loaded.log_entries().len(), 2);
+        assert!(loaded.validated());
+
+        assert!(store.read_whois(&scid).unwrap().is_none());
+        let vp = serde_json::json!({"type": ["VerifiablePresentation"]});
+        store.write_whois(&scid, &vp).unwrap();
+        assert_eq!(store.read_whois(&scid).unwrap(), Some(vp));
+
+        // A stale writer can't append
+        let stale = [second.log_entries()[1].log_entry.clone()];
+        assert!(store.commit(&scid, Some(1), &stale, None).is_err());
+        assert_eq!(store.read_log(&scid).unwrap().len(), 2);
+    }
+
+    pub(crate) async fn diverged_state_rejected(store: &dyn DidStore) {
+        let stored = two_entries().await;
+        store.store_state(&stored).unwrap();
+
+        // Same first entry, different second entry
+        let first = stored.log_entries()[0].log_entry.clone();
+        let diverged = DIDWebVHState::from_log_entries(vec![first.clone(), first]);
+        assert!(store.store_state(&diverged).is_err());
+        assert_eq!(store.read_log(stored.scid()).unwrap().len(), 2);
+    }
+}
@@ -0,0 +1,237 @@
+//! [`DidStore`] backed by SQLite, behind the `sqlite` feature.
+//!
+//! Log entries are stored one row each, keyed by SCID and position; witness
+//! proofs and `whois.vp` are one JSON row per DID. Each commit is a single transaction.
+
+use super::{DidStore, conflict, deserialize_proofs, parse_entries};
+use crate::{
+    DIDWebVHError, DIDWebVHState,
+    log_entry::{LogEntry, LogEntryMethods},
+    witness::proofs::WitnessProofCollection,
+};
+use rusqlite::{Connection, OptionalExtension, params};
+use serde_json::Value;
+use std::{path::Path, sync::Mutex};
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS log_entries (
+    scid TEXT NOT NULL,
+    seq INTEGER NOT NULL,
+    version_id TEXT NOT NULL,
+    entry TEXT NOT NULL,
+    PRIMARY KEY (scid, seq)
+);
+CREATE INDEX IF NOT EXISTS log_entries_version_id ON log_entries (scid, version_id);
+CREATE TABLE IF NOT EXISTS witness_proofs (
+    scid TEXT PRIMARY KEY,
+    proofs TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS whois (
+    scid TEXT PRIMARY KEY,
+    vp TEXT NOT NULL
+);
+";
+
+/// A [`DidStore`] in a SQLite database.
+pub struct SqliteStore {
+    conn: Mutex<Connection>,
+}
+
+fn sql_error(e: rusqlite::Error) -> DIDWebVHError {
+    DIDWebVHError::StoreError(format!("SQLite: {e}"))
+}
+
+impl SqliteStore {
+    /// Open (or create) the database at `path`.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, DIDWebVHError> {
+        Self::from_connection(Connection::open(path).map_err(sql_error)?)
+    }
+
+    /// A private in-memory database.
+    pub fn open_in_memory() -> Result<Self, DIDWebVHError> {
+        Self::from_connection(Connection::open_in_memory().map_err(sql_error)?)
+    }
+
+    /// Use an existing connection, creating the tables if needed.
+    pub fn from_connection(conn: Connection) -> Result<Self, DIDWebVHError> {
+        conn.execute_batch(SCHEMA).map_err(sql_error)?;
+        Ok(Self {
+            conn: Mutex::new(conn),
+        })
+    }
+
+    fn conn(&self) -> Result<std::sync::MutexGuard<'_, Connection>, DIDWebVHError> {
+        self.conn
+            .lock()
+            .map_err(|_| DIDWebVHError::StoreError("SqliteStore lock poisoned".to_string()))
+    }
+}
+
+/// Run `sql`, returning the first column of every row as a string.
+fn string_rows(
+    conn: &Connection,
+    sql: &str,
+    params: impl rusqlite::Params,
+) -> Result<Vec<String>, DIDWebVHError> {
+    let mut stmt = conn.prepare_cached(sql).map_err(sql_error)?;
+    stmt.query_map(params, |row| row.get::<_, String>(0))
+        .map_err(sql_error)?
+        .collect::<Result<_, _>>()
+        .map_err(sql_error)
+}
+
+impl DidStore for SqliteStore {
+    fn commit(
+        &self,
+        scid: &str,
+        expected_len: Option<usize>,
+        entries: &[LogEntry],
+        witness_proofs: Option<&WitnessProofCollection>,
+    ) -> Result<(), DIDWebVHError> {
+        let rows = entries
+            .iter()
+            .map(|e| {
+                Ok((
+                    e.get_version_id().to_string(),
+                    DIDWebVHState::entry_json(e)?,
+                ))
+            })
+            .collect::<Result<Vec<_>, DIDWebVHError>>()?;
+        let proofs = witness_proofs.map(DIDWebVHState::proofs_json).transpose()?;
+
+        let mut conn = self.conn()?;
+        let tx = conn.transaction().map_err(sql_error)?;
+        let stored_len: i64 = tx
+            .query_row(
+                "SELECT COUNT(*) FROM log_entries WHERE scid = ?1",
+                params![scid],
+                |row| row.get(0),
+            )
+            .map_err(sql_error)?;
+        let stored_len = usize::try_from(stored_len).unwrap_or_default();
+        if let Some(expected) = expected_len
+            && expected != stored_len
+        {
+            return Err(conflict(scid, expected, stored_len));
+        }
+        for (seq, (version_id, entry)) in (stored_len..).zip(rows) {
+            tx.execute(
+                "INSERT INTO log_entries (scid, seq, version_id, entry) VALUES (?1, ?2, ?3, ?4)",
+                params![
+                    scid,
+                    i64::try_from(seq).unwrap_or(i64::MAX),
+                    version_id,
+                    entry
+                ],
+            )
+            .map_err(sql_error)?;
+        }
+        if let Some(proofs) = proofs {
+            tx.execute(
+                "INSERT INTO witness_proofs (scid, proofs) VALUES (?1, ?2)
+                 ON CONFLICT (scid) DO UPDATE SET proofs = excluded.proofs",
+                params![scid, proofs],
+            )
+            .map_err(sql_error)?;
+        }
+        tx.commit().map_err(sql_error)
+    }
+
+    fn read_log(&self, scid: &str) -> Result<Vec<LogEntry>, DIDWebVHError> {
+        let conn = self.conn()?;
+        let rows = string_rows(
+            &conn,
+            "SELECT entry FROM log_entries WHERE scid = ?1 ORDER BY seq",
+            params![scid],
+        )?;
+        parse_entries(rows.iter().map(String::as_str))
+    }
+
+    fn read_version(
+        &self,
+        scid: &str,
+        version_id: &str,
+    ) -> Result<Option<LogEntry>, DIDWebVHError> {
+        // The first entry is read too: it fixes the webvh version later
+        // entries are parsed with
+        let conn = self.conn()?;
+        let rows = string_rows(
+            &conn,
+            "SELECT entry FROM log_entries WHERE scid = ?1 AND (seq = 0 OR version_id = ?2)
+             ORDER BY seq",
+            params![scid, version_id],
+        )?;
+        Ok(parse_entries(rows.iter().map(String::as_str))?
+            .into_iter()
+            .find(|e| e.get_version_id() == version_id))
+    }
+
+    fn read_witness_proofs(&self, scid: &str) -> Result<WitnessProofCollection, DIDWebVHError> {
+        let conn = self.conn()?;
+        let proofs: Option<String> = conn
+            .query_row(
+                "SELECT proofs FROM witness_proofs WHERE scid = ?1",
+                params![scid],
+                |row| row.get(0),
+            )
+            .optional()
+            .map_err(sql_error)?;
+        proofs.map_or_else(
+            || Ok(WitnessProofCollection::default()),
+            |p| deserialize_proofs(&p),
+        )
+    }
+
+    fn read_whois(&self, scid: &str) -> Result<Option<Value>, DIDWebVHError> {
+        let conn = self.conn()?;
+        let vp: Option<String> = conn
+            .query_row(
+                "SELECT vp FROM whois WHERE scid = ?1",
+                params![scid],
+                |row| row.get(0),
+            )
+            .optional()
+            .map_err(sql_error)?;
+        vp.map(|vp| {
+            serde_json::from_str(&vp)
+                .map_err(|e| DIDWebVHError::StoreError(format!("Couldn't parse whois.vp: {e}")))
+        })
+        .transpose()
+    }
+
+    fn write_whois(&self, scid: &str, vp: &Value) -> Result<(), DIDWebVHError> {
+        let conn = self.conn()?;
+        conn.execute(
+            "INSERT INTO whois (scid, vp) VALUES (?1, ?2)
+             ON CONFLICT (scid) DO UPDATE SET vp = excluded.vp",
+            params![scid, vp.to_string()],
+        )
+        .map_err(sql_error)?;
+        Ok(())
+    }
+
+    fn list(&self) -> Result<Vec<String>, DIDWebVHError> {
+        let conn = self.conn()?;
+        string_rows(
+            &conn,
+            "SELECT DISTINCT scid FROM log_entries ORDER BY scid",
+            [],
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SqliteStore;
+    use crate::store::tests;
+
+    #[tokio::test]
+    async fn round_trip() {
+        tests::round_trip(&SqliteStore::open_in_memory().unwrap()).await;
+    }
+
+    #[tokio::test]
+    async fn diverged_state_rejected() {
+        tests::diverged_state_rejected(&SqliteStore::open_in_memory().unwrap()).await;
+    }
+}
@@ -24,15 +24,19 @@
  */
 
 use crate::{
-    DIDWebVHError, DIDWebVHState, Multibase, Signer, create::sign_witness_proofs,
-    ensure_object_mut, log_entry::LogEntry, parameters::Parameters, url::WebVHURL,
-    witness::Witnesses,
+    DIDWebVHError, DIDWebVHState, EntryOptions, Multibase, Signer,
+    create::sign_witness_proofs_with,
+    log_entry::LogEntry,
+    migrate::{MigrationPreview, MigrationReport, preview_migration},
+    parameters::Parameters,
+    proof_options,
+    witness::{WitnessVerifyOptions, Witnesses},
 };
+use affinidi_data_integrity::DataIntegrityProof;
 use affinidi_secrets_resolver::secrets::Secret;
 use ahash::HashMap;
-use serde_json::Value;
+use serde_json::{Value, json};
 use std::sync::Arc;
-use url::Url;
 
 /// Configuration for updating an existing DID.
 ///
@@ -83,6 +87,7 @@ use url::Url;
 ///     .migrate_to("https://new-domain.example.com/")
 ///     .build()?;
 /// ```
+#[allow(clippy::struct_excessive_bools)] // independent update options
 pub struct UpdateDIDConfig<A: Signer = Secret, W: Signer = Secret> {
     /// The DID WebVH state to update (must have at least one log entry).
     pub state: DIDWebVHState,
@@ -113,14 +118,29 @@ pub struct UpdateDIDConfig<A: Signer = Secret, W: Signer = Secret> {
     /// Explicit versionTime for the new log entry. `None` (default) stamps
     /// `now`. Set this to control the entry timestamp — e.g. to backdate it so a
     /// rapid create-then-update sequence stays strictly increasing and not in the
-    /// future (versionTime serializes at second granularity).
+    /// future (versionTime serializes at second granularity). A deactivation
+    /// that first has to end pre-rotation stamps the teardown entry one second
+    /// earlier, so it must be at least two seconds after the last entry.
     pub version_time: Option<chrono::DateTime<chrono::FixedOffset>>,
+    /// Stamp the new entries' proofs with their versionTime instead of the
+    /// signing clock, so the same inputs always give the same `did.jsonl`.
+    /// Default: `false`.
+    pub deterministic: bool,
+    /// Write `updateKeys`, `nextKeyHashes`, `witness` and `watchers` into the
+    /// new entry even when they don't change, as the didwebvh test suite's
+    /// generator does. A deactivation then keeps the current `updateKeys`
+    /// instead of emptying them. Default: `false`.
+    pub restate_parameters: bool,
+    /// Stage the new entry as pending instead of publishing it. See
+    /// [`crate::pending`].
+    pub stage: bool,
 }
 
 /// Builder for constructing an [`UpdateDIDConfig`].
 ///
 /// Only `state` and `signing_key` are required.
 /// All parameter fields default to `None` (no change).
+#[allow(clippy::struct_excessive_bools)] // independent update options
 pub struct UpdateDIDConfigBuilder<A: Signer = Secret, W: Signer = Secret> {
     state: Option<DIDWebVHState>,
     signing_key: Option<A>,
@@ -135,6 +155,9 @@ pub struct UpdateDIDConfigBuilder<A: Signer = Secret, W: Signer = Secret> {
     migrate_to: Option<String>,
     witness_secrets: HashMap<String, W>,
     version_time: Option<chrono::DateTime<chrono::FixedOffset>>,
+    deterministic: bool,
+    restate_parameters: bool,
+    stage: bool,
 }
 
 impl<A: Signer, W: Signer> UpdateDIDConfigBuilder<A, W> {
@@ -153,6 +176,9 @@ impl<A: Signer, W: Signer> UpdateDIDConfigBuilder<A, W> {
             migrate_to: None,
             witness_secrets: HashMap::default(),
             version_time: None,
+            deterministic: false,
+            restate_parameters: false,
+            stage: false,
         }
     }
 
@@ -217,9 +243,11 @@ impl<A: Signer, W: Signer> UpdateDIDConfigBuilder<A, W> {
         self
     }
 
-    /// Migrate the DID to a new URL or DID address. Requires `portable = true`.
-    /// All DID references in the document are rewritten, and the previous DID
-    /// is added to `alsoKnownAs`.
+    /// Migrate the DID to a new URL, DID address, or path on the current domain
+    /// (e.g. `/dids/alice`). Requires `portable = true`.
+    /// DID references in identifier positions are rewritten (service endpoints
+    /// are not), and the previous DID is added to `alsoKnownAs`. Use
+    /// [`crate::migrate::preview_migration`] to see the result before signing.
     pub fn migrate_to(mut self, address: impl Into<String>) -> Self {
         self.migrate_to = Some(address.into());
         self
@@ -243,6 +271,34 @@ impl<A: Signer, W: Signer> UpdateDIDConfigBuilder<A, W> {
         self
     }
 
+    /// Stamp proofs with their entry's versionTime instead of the signing
+    /// clock, for reproducible output (default: `false`).
+    pub fn deterministic(mut self, deterministic: bool) -> Self {
+        self.deterministic = deterministic;
+        self
+    }
+
+    /// Restate unchanged `updateKeys`, `nextKeyHashes`, `witness` and
+    /// `watchers` in the new entry, like the didwebvh test suite's generator
+    /// (default: `false`).
+    pub fn restate_parameters(mut self, restate: bool) -> Self {
+        self.restate_parameters = restate;
+        self
+    }
+
+    /// Stage the new entry as pending instead of publishing it (see
+    /// [`crate::pending`]). Witnesses with a secret in
+    /// [`witness_secrets`](Self::witness_secrets) sign straight away; collect
+    /// the rest with [`crate::witness::remote::request_witness_proofs`], then
+    /// call [`DIDWebVHState::publish_pending`].
+    ///
+    /// Not supported for a deactivation that first has to turn off pre-rotation,
+    /// since that needs two entries.
+    pub fn stage(mut self) -> Self {
+        self.stage = true;
+        self
+    }
+
     /// Build the [`UpdateDIDConfig`], returning an error if required fields are missing.
     pub fn build(self) -> Result<UpdateDIDConfig<A, W>, DIDWebVHError> {
         let state = self
@@ -272,6 +328,9 @@ impl<A: Signer, W: Signer> UpdateDIDConfigBuilder<A, W> {
             migrate_to: self.migrate_to,
             witness_secrets: self.witness_secrets,
             version_time: self.version_time,
+            deterministic: self.deterministic,
+            restate_parameters: self.restate_parameters,
+            stage: self.stage,
         })
     }
 }
@@ -300,6 +359,8 @@ pub struct UpdateDIDResult {
     log_entry: LogEntry,
     /// The full DID WebVH state with all log entries and witness proofs.
     state: DIDWebVHState,
+    /// What a migration rewrote. `None` unless the update was a migration.
+    migration_report: Option<MigrationReport>,
 }
 
 impl UpdateDIDResult {
@@ -308,7 +369,7 @@ impl UpdateDIDResult {
         &self.did
     }
 
-    /// The newly created log entry.
+    /// The newly created log entry (pending, if the update was staged).
     pub fn log_entry(&self) -> &LogEntry {
         &self.log_entry
     }
@@ -323,6 +384,12 @@ impl UpdateDIDResult {
     pub fn into_state(self) -> DIDWebVHState {
         self.state
     }
+
+    /// For migrations, every DID reference that was rewritten and every
+    /// `did:webvh` reference that was left untouched.
+    pub fn migration_report(&self) -> Option<&MigrationReport> {
+        self.migration_report.as_ref()
+    }
 }
 
 /// Update an existing DID using the provided configuration.
@@ -332,7 +399,8 @@ impl UpdateDIDResult {
 /// 1. **Document updates** — Replace the DID document with a new version
 /// 2. **Key rotation** — Change authorization keys (`update_keys`)
 /// 3. **Parameter changes** — Modify witnesses, watchers, TTL, pre-rotation, portability
-/// 4. **Migration** — Move the DID to a new domain (rewrites identifiers, adds alias)
+/// 4. **Migration** — Move the DID to a new domain or path (rewrites identifier
+///    positions only, adds alias; see [`UpdateDIDResult::migration_report`])
 /// 5. **Deactivation** — Permanently deactivate the DID (with automatic pre-rotation
 ///    teardown if needed)
 /// 6. **Witness signing** — Signs witness proofs for all new log entries
@@ -370,6 +438,7 @@ pub async fn update_did<A: Signer, W: Signer>(
     }
 
     // Standard update: build parameters and create log entry
+    let options = entry_options(&config);
     let last_params = config
         .state
         .log_entries()
@@ -407,74 +476,37 @@ pub async fn update_did<A: Signer, W: Signer>(
         params.portable = Some(portable);
     }
 
-    config
-        .state
-        .create_log_entry(config.version_time, &document, &params, &config.signing_key)
-        .await?;
-
-    // Sign witness proofs
-    sign_new_entry_witnesses(&mut config.state, &config.witness_secrets).await?;
+    append_entry(
+        &mut config.state,
+        &config.signing_key,
+        &config.witness_secrets,
+        config.stage,
+        (config.version_time, options),
+        &document,
+        &params,
+    )
+    .await?;
 
     build_result(config.state)
 }
 
-/// Handle DID migration to a new domain.
+/// Handle DID migration to a new domain or path.
+///
+/// Only identifier positions in the document are rewritten; see [`crate::migrate`].
 async fn do_migrate<A: Signer, W: Signer>(
     mut config: UpdateDIDConfig<A, W>,
     new_address: String,
 ) -> Result<UpdateDIDResult, DIDWebVHError> {
-    let last_entry = config
+    let MigrationPreview { document, report } = preview_migration(&config.state, &new_address)?;
+    let options = entry_options(&config);
+
+    // Build parameters (apply any additional changes from config)
+    let mut params = config
         .state
         .log_entries()
         .last()
+        .map(|e| e.validated_parameters.clone())
         .ok_or_else(|| DIDWebVHError::LogEntryError("No log entries exist".to_string()))?;
-
-    if last_entry.validated_parameters.portable != Some(true) {
-        return Err(DIDWebVHError::ParametersError(
-            "DID must have portable=true to migrate".to_string(),
-        ));
-    }
-
-    let did = last_entry
-        .get_state()
-        .get("id")
-        .and_then(|v| v.as_str())
-        .ok_or_else(|| DIDWebVHError::DIDError("DID not found in log entry state".to_string()))?
-        .to_string();
-
-    let did_url = WebVHURL::parse_did_url(&did)?;
-
-    // Parse the new address
-    let mut new_did_url = if new_address.starts_with("did:") {
-        WebVHURL::parse_did_url(&new_address)?
-    } else {
-        let url = Url::parse(&new_address)
-            .map_err(|e| DIDWebVHError::DIDError(format!("Invalid URL: {e}")))?;
-        WebVHURL::parse_url(&url)?
-    };
-    new_did_url.scid = did_url.scid.clone();
-
-    // Rewrite all DID references in the document
-    let doc_str = serde_json::to_string(last_entry.get_state())
-        .map_err(|e| DIDWebVHError::DIDError(format!("Failed to serialize document: {e}")))?;
-    let new_doc_str = doc_str.replace(&did_url.to_string(), &new_did_url.to_string());
-    let mut new_doc: Value = serde_json::from_str(&new_doc_str)
-        .map_err(|e| DIDWebVHError::DIDError(format!("Failed to parse document: {e}")))?;
-
-    // Add previous DID to alsoKnownAs
-    if let Some(alias) = new_doc.get_mut("alsoKnownAs") {
-        if let Some(arr) = alias.as_array_mut() {
-            arr.push(Value::String(did));
-        }
-    } else {
-        ensure_object_mut(&mut new_doc)?.insert(
-            "alsoKnownAs".to_string(),
-            Value::Array(vec![Value::String(did)]),
-        );
-    }
-
-    // Build parameters (apply any additional changes from config)
-    let mut params = last_entry.validated_parameters.clone();
     if let Some(keys) = config.update_keys {
         params.update_keys = Some(Arc::new(keys));
     }
@@ -491,20 +523,27 @@ async fn do_migrate<A: Signer, W: Signer>(
         params.ttl = Some(ttl);
     }
 
-    config
-        .state
-        .create_log_entry(config.version_time, &new_doc, &params, &config.signing_key)
-        .await?;
-
-    sign_new_entry_witnesses(&mut config.state, &config.witness_secrets).await?;
+    append_entry(
+        &mut config.state,
+        &config.signing_key,
+        &config.witness_secrets,
+        config.stage,
+        (config.version_time, options),
+        &document,
+        &params,
+    )
+    .await?;
 
-    build_result(config.state)
+    let mut result = build_result(config.state)?;
+    result.migration_report = Some(report);
+    Ok(result)
 }
 
 /// Handle DID deactivation, with automatic pre-rotation teardown if needed.
 async fn do_deactivate<A: Signer, W: Signer>(
     mut config: UpdateDIDConfig<A, W>,
 ) -> Result<UpdateDIDResult, DIDWebVHError> {
+    let options = entry_options(&config);
     let last_entry = config
         .state
         .log_entries()
@@ -513,6 +552,13 @@ async fn do_deactivate<A: Signer, W: Signer>(
 
     // If pre-rotation is active, create an intermediate entry to disable it first
     if last_entry.validated_parameters.pre_rotation_active {
+        if config.stage {
+            return Err(DIDWebVHError::LogEntryError(
+                "Can't stage a deactivation while pre-rotation is active: it needs two log \
+                 entries. Disable pre-rotation first."
+                    .to_string(),
+            ));
+        }
         let doc = last_entry.get_state().clone();
         let vm = config.signing_key.verification_method();
         let pk = vm.split('#').next().unwrap_or(vm);
@@ -524,16 +570,40 @@ async fn do_deactivate<A: Signer, W: Signer>(
             ..Default::default()
         };
 
+        // Deactivation appends two entries here (pre-rotation teardown + final).
+        // A caller-supplied `version_time` stamps the final entry, and the
+        // teardown goes one second before it, so it must still come after the
+        // last entry (versionTime serializes at second granularity).
+        let teardown_time = config
+            .version_time
+            .map(|t| t - chrono::Duration::seconds(1));
+        if let Some(teardown_time) = teardown_time
+            && teardown_time.timestamp() <= last_entry.get_version_time().timestamp()
+        {
+            return Err(DIDWebVHError::LogEntryError(format!(
+                "version_time must be at least 2 seconds after the last log entry ({}): \
+                 ending pre-rotation adds an entry one second before the deactivation",
+                last_entry.get_version_time_string()
+            )));
+        }
         config
             .state
-            // Deactivation can append two entries (pre-rotation teardown + final);
-            // a single caller-supplied `version_time` can't keep them distinct, so
-            // this multi-entry path keeps the default per-entry `now()` stamping.
-            .create_log_entry(None, &doc, &disable_params, &config.signing_key)
+            .create_log_entry_with(
+                teardown_time,
+                &doc,
+                &disable_params,
+                &config.signing_key,
+                options,
+            )
             .await?;
 
         // Sign witness proofs for the intermediate entry
-        sign_new_entry_witnesses(&mut config.state, &config.witness_secrets).await?;
+        sign_new_entry_witnesses(
+            &mut config.state,
+            &config.witness_secrets,
+            config.deterministic,
+        )
+        .await?;
     }
 
     // Create the final deactivation entry
@@ -545,38 +615,109 @@ async fn do_deactivate<A: Signer, W: Signer>(
         .get_state()
         .clone();
 
+    let update_keys = if config.restate_parameters {
+        config
+            .state
+            .log_entries()
+            .last()
+            .and_then(|e| e.validated_parameters.update_keys.clone())
+            .unwrap_or_default()
+    } else {
+        Arc::default()
+    };
     let deactivate_params = Parameters {
         deactivated: Some(true),
-        update_keys: Some(Arc::new(Vec::new())),
+        update_keys: Some(update_keys),
         ..Default::default()
     };
 
-    config
-        .state
-        // See the deactivation note above: keep the default `now()` here too.
-        .create_log_entry(None, &doc, &deactivate_params, &config.signing_key)
-        .await?;
-
-    sign_new_entry_witnesses(&mut config.state, &config.witness_secrets).await?;
+    append_entry(
+        &mut config.state,
+        &config.signing_key,
+        &config.witness_secrets,
+        config.stage,
+        (config.version_time, options),
+        &doc,
+        &deactivate_params,
+    )
+    .await?;
 
     build_result(config.state)
 }
 
+/// Create the update's (final) log entry, then either sign its witness proofs
+/// or, when staging, hold it as pending with whatever witness proofs can be
+/// signed locally.
+async fn append_entry<A: Signer, W: Signer>(
+    state: &mut DIDWebVHState,
+    signing_key: &A,
+    witness_secrets: &HashMap<String, W>,
+    stage: bool,
+    (version_time, options): (Option<chrono::DateTime<chrono::FixedOffset>>, EntryOptions),
+    document: &Value,
+    params: &Parameters,
+) -> Result<(), DIDWebVHError> {
+    if !stage {
+        state
+            .create_log_entry_with(version_time, document, params, signing_key, options)
+            .await?;
+        return sign_new_entry_witnesses(state, witness_secrets, options.deterministic).await;
+    }
+
+    let pending = state
+        .stage_log_entry_with(version_time, document, params, signing_key, options)
+        .await?;
+    let version_id = pending.get_version_id().to_string();
+    let sign_options = proof_options(pending.get_version_time(), options.deterministic);
+    let witnesses = pending
+        .get_active_witnesses()
+        .and_then(|w| w.witnesses().map(<[_]>::to_vec))
+        .unwrap_or_default();
+    for witness in witnesses {
+        let Some(secret) = witness_secrets.get(witness.id.as_str()) else {
+            continue;
+        };
+        let proof = DataIntegrityProof::sign(
+            &json!({"versionId": version_id}),
+            secret,
+            sign_options.clone(),
+        )
+        .await
+        .map_err(|e| {
+            DIDWebVHError::WitnessProofError(format!(
+                "Couldn't generate witness proof for {version_id}: {e}"
+            ))
+        })?;
+        state.add_pending_witness_proof(&proof, &WitnessVerifyOptions::new())?;
+    }
+    Ok(())
+}
+
+/// How `config` wants its log entries written.
+fn entry_options<A: Signer, W: Signer>(config: &UpdateDIDConfig<A, W>) -> EntryOptions {
+    EntryOptions {
+        deterministic: config.deterministic,
+        restate_parameters: config.restate_parameters,
+    }
+}
+
 /// Sign witness proofs for the most recent log entry in the state.
 async fn sign_new_entry_witnesses<W: Signer>(
     state: &mut DIDWebVHState,
     witness_secrets: &HashMap<String, W>,
+    deterministic: bool,
 ) -> Result<(), DIDWebVHError> {
     let (log_entries, witness_proofs) = state.log_entries_and_witness_proofs_mut();
     let entry = log_entries
         .last()
         .ok_or_else(|| DIDWebVHError::LogEntryError("No log entries after update".to_string()))?;
 
-    sign_witness_proofs(
+    sign_witness_proofs_with(
         witness_proofs,
         entry,
         &entry.get_active_witnesses(),
         witness_secrets,
+        deterministic,
     )
     .await?;
 
@@ -586,8 +727,7 @@ async fn sign_new_entry_witnesses<W: Signer>(
 /// Build the result from the final state.
 fn build_result(state: DIDWebVHState) -> Result<UpdateDIDResult, DIDWebVHError> {
     let last_entry = state
-        .log_entries()
-        .last()
+        .witness_target()
         .ok_or_else(|| DIDWebVHError::LogEntryError("No log entries after update".to_string()))?;
 
     let did = last_entry
@@ -603,6 +743,7 @@ fn build_result(state: DIDWebVHState) -> Result<UpdateDIDResult, DIDWebVHError>
         did,
         log_entry,
         state,
+        migration_report: None,
     })
 }
 
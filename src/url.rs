@@ -439,6 +439,14 @@ impl WebVHURL {
 }
 
 impl WebVHURL {
+    /// Whether the URL selects a specific version (`versionId`, `versionTime`
+    /// or `versionNumber` query).
+    pub fn has_version_query(&self) -> bool {
+        self.query_version_id.is_some()
+            || self.query_version_time.is_some()
+            || self.query_version_number.is_some()
+    }
+
     /// Returns the base DID string (without query parameters or fragment).
     /// This is the format expected as the top-level `id` in a DID Document.
     pub fn to_did_base(&self) -> String {
@@ -13,7 +13,7 @@ use std::sync::Arc;
 use tracing::{debug, error};
 
 use crate::{
-    DIDWebVHError, DIDWebVHState,
+    CheckError, DIDWebVHError, DIDWebVHState,
     log_entry_state::{LogEntryState, LogEntryValidationStatus},
     witness::WitnessVerifyOptions,
 };
@@ -141,10 +141,20 @@ impl ValidationReport {
             TruncationReason::VerificationFailed {
                 at_version_id,
                 error,
-            } => format!(
-                "Log truncated at {at_version_id}: {error}. Last valid entry: {}.",
-                self.ok_until,
-            ),
+            } => {
+                let msg = format!(
+                    "Log truncated at {at_version_id}: {error}. Last valid entry: {}.",
+                    self.ok_until,
+                );
+                // Keep the failed check visible to callers
+                if let Some(kind) = error.check_kind() {
+                    return Err(CheckError::new(kind, msg)
+                        .with_version_id(at_version_id)
+                        .with_source(error.clone())
+                        .into());
+                }
+                msg
+            }
             TruncationReason::PostDeactivation {
                 deactivated_at,
                 dropped_entries,
@@ -206,10 +216,14 @@ impl DIDWebVHState {
                         });
                         break;
                     }
-                    return Err(DIDWebVHError::validation(
-                        format!("No valid LogEntry found! Reason: {e}"),
-                        entry.version_number,
-                    ));
+                    let msg = format!("No valid LogEntry found! Reason: {e}");
+                    return Err(match e.check_kind() {
+                        Some(kind) => CheckError::new(kind, msg)
+                            .with_version_id(entry.get_version_id())
+                            .with_source(e)
+                            .into(),
+                        None => DIDWebVHError::validation(msg, entry.version_number),
+                    });
                 }
             }
             // Check if this valid LogEntry has been deactivated, if so then ignore any other
@@ -539,6 +553,53 @@ mod tests {
         assert!(err.to_string().contains("past the deactivation entry"));
     }
 
+    /// Tests that a truncated log keeps which check failed, and for which
+    /// entry, through [`ValidationReport::assert_complete`].
+    #[tokio::test]
+    async fn test_truncation_keeps_check_kind() {
+        let base_time = (Utc::now() - Duration::seconds(100)).fixed_offset();
+        let key = generate_signing_key();
+        let params = Parameters {
+            update_keys: Some(Arc::new(vec![Multibase::new(
+                key.get_public_keymultibase().unwrap(),
+            )])),
+            ..Default::default()
+        };
+        let doc = did_doc_with_key("did:webvh:{SCID}:localhost%3A8000", &key);
+
+        let mut state = DIDWebVHState::default();
+        state
+            .create_log_entry(Some(base_time), &doc, &params, &key)
+            .await
+            .unwrap();
+        let actual_doc = state.log_entries.last().unwrap().get_state().clone();
+        state
+            .create_log_entry(
+                Some(base_time + Duration::seconds(1)),
+                &actual_doc,
+                &Parameters::default(),
+                &key,
+            )
+            .await
+            .unwrap();
+
+        // Change the second entry after it was signed
+        let crate::log_entry::LogEntry::Spec1_0(entry) = &mut state.log_entries[1].log_entry else {
+            panic!("expected a 1.0 entry");
+        };
+        entry.state["tampered"] = json!(true);
+        let version_id = entry.version_id.clone();
+        for entry in &mut state.log_entries {
+            entry.validation_status = LogEntryValidationStatus::NotValidated;
+        }
+
+        let err = state.validate().unwrap().assert_complete().unwrap_err();
+        assert_eq!(err.code(), crate::ErrorCode::CheckFailed);
+        assert_eq!(err.check_kind(), Some(crate::CheckKind::SignatureInvalid));
+        assert_eq!(err.version_number(), Some(2));
+        assert_eq!(err.version_id(), Some(version_id.as_str()));
+    }
+
     /// Regression: `verify_scid()` proved `parameters.scid` was the genesis
     /// self-hash but never checked that the SCID embedded in the DID
     /// document's `id` was that same value. An attacker could publish a
@@ -611,6 +672,8 @@ mod tests {
 
         let err = state.validate().unwrap_err();
         assert!(err.to_string().contains("No valid LogEntry found"));
+        assert_eq!(err.check_kind(), Some(crate::CheckKind::ProofMissing));
+        assert_eq!(err.version_id(), Some("1-abc"));
     }
 
     /// Validates TTL behavior by creating a state with the given TTL, validating it,
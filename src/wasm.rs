@@ -0,0 +1,372 @@
+/*!
+*   JavaScript bindings, behind the `wasm-bindgen` feature.
+*
+*   Exposes resolution and verification to JavaScript/TypeScript through
+*   `wasm-bindgen`, so browser wallets and Node services verify a DID
+*   exactly as this crate does:
+*
+*   - `resolveLog(did, jsonl, witnessJson?)` resolves from an in-memory log,
+*     as [`DIDWebVHState::resolve_log`]
+*   - `resolve(did, options?)` fetches the log with `fetch`, as
+*     [`DIDWebVHState::resolve`]
+*   - `validate(jsonl, witnessJson?)` returns the [`ValidationReport`] of a log
+*   - `toWebDid(jsonl, witnessJson?)` exports a fully verified log as a
+*     `did:web` document, as [`DIDWebVHState::to_web_did`]
+*
+*   Failures are thrown as a JavaScript `Error` named `DIDWebVHError` that
+*   also carries `code`, `checkKind`, `versionNumber` and `versionId` (see
+*   [`DIDWebVHError::code`] and [`DIDWebVHError::check`]).
+*
+*   The `wasm/` directory of the repository builds these into an npm package
+*   with TypeScript definitions (`wasm-pack build --target web`).
+*/
+
+use crate::{
+    DIDWebVHError, DIDWebVHState, TruncationReason, ValidationReport,
+    log_entry::{LogEntry, LogEntryMethods, MetaData},
+    resolve::ResolveOptions,
+};
+use serde::Deserialize;
+use serde_json::{Value, json};
+use wasm_bindgen::prelude::*;
+
+#[wasm_bindgen(typescript_custom_section)]
+const TYPES: &str = r#"
+/** Resolution metadata, as `MetaData` in didwebvh-rs. */
+export interface DIDMetadata {
+    versionId: string;
+    versionNumber: number;
+    versionTime: string;
+    created: string;
+    updated: string;
+    scid: string;
+    portable: boolean;
+    deactivated: boolean;
+    witness: { threshold: number; witnesses: { id: string }[] } | {} | null;
+    watchers: string[] | null;
+    canonicalId?: string;
+    equivalentId?: string[];
+}
+
+/** A DID document with the implicit `#files` and `#whois` services. */
+export interface ResolutionResult {
+    didDocument: Record<string, unknown>;
+    metadata: DIDMetadata;
+}
+
+/** Options of `resolve`. */
+export interface ResolveOptions {
+    /** Maximum size of each downloaded file (default: 200 KB). */
+    maxResponseBytes?: number;
+    /** Download witness proofs even when no witnesses are configured. */
+    eagerWitnessDownload?: boolean;
+    /** Resolve a migrated DID from its new location. */
+    followCanonicalId?: boolean;
+}
+
+/** What a thrown `DIDWebVHError` carries besides its message. */
+export interface ErrorDetails {
+    message: string;
+    /** Stable error code, e.g. `not_found` or `check_failed`. */
+    code: string;
+    /** The failed check, e.g. `signature_invalid` or `hash_mismatch`. */
+    checkKind?: string;
+    versionNumber?: number;
+    versionId?: string;
+}
+
+export type TruncationReason =
+    | { reason: "verificationFailed"; atVersionId: string; error: ErrorDetails }
+    | { reason: "postDeactivation"; atVersionId: string; droppedEntries: number };
+
+/** Result of `validate`, as `ValidationReport` in didwebvh-rs. */
+export interface ValidationReport {
+    /** versionId of the last entry that verified. */
+    okUntil: string;
+    /** Set when later entries were dropped. */
+    truncated: TruncationReason | null;
+}
+"#;
+
+#[wasm_bindgen]
+extern "C" {
+    /// `ResolutionResult` in TypeScript.
+    #[wasm_bindgen(typescript_type = "ResolutionResult")]
+    pub type JsResolutionResult;
+
+    /// `ResolveOptions` in TypeScript.
+    #[wasm_bindgen(typescript_type = "ResolveOptions")]
+    pub type JsResolveOptions;
+
+    /// `ValidationReport` in TypeScript.
+    #[wasm_bindgen(typescript_type = "ValidationReport")]
+    pub type JsValidationReport;
+
+    /// A DID document in TypeScript.
+    #[wasm_bindgen(typescript_type = "Record<string, unknown>")]
+    pub type JsDocument;
+}
+
+/// Resolves `did` from an in-memory `did.jsonl` and optional
+/// `did-witness.json`.
+#[wasm_bindgen(js_name = resolveLog)]
+pub async fn resolve_log(
+    did: String,
+    jsonl: String,
+    witness_json: Option<String>,
+) -> Result<JsResolutionResult, JsValue> {
+    let mut state = DIDWebVHState::default();
+    let (entry, metadata) = state
+        .resolve_log(&did, &jsonl, witness_json.as_deref())
+        .await
+        .map_err(to_js_error)?;
+    to_js(&resolution_result(entry, &metadata).map_err(to_js_error)?).map(JsCast::unchecked_into)
+}
+
+/// Resolves `did`, fetching its log and witness proofs.
+#[wasm_bindgen]
+pub async fn resolve(
+    did: String,
+    options: Option<JsResolveOptions>,
+) -> Result<JsResolutionResult, JsValue> {
+    let options = match options {
+        Some(options) => resolve_options(from_js(&options)?).map_err(to_js_error)?,
+        None => ResolveOptions::default(),
+    };
+    let mut state = DIDWebVHState::default();
+    let (entry, metadata) = state.resolve(&did, options).await.map_err(to_js_error)?;
+    to_js(&resolution_result(entry, &metadata).map_err(to_js_error)?).map(JsCast::unchecked_into)
+}
+
+/// Verifies a `did.jsonl` and optional `did-witness.json`. Throws if the
+/// first entry doesn't verify; later failures are reported as `truncated`.
+#[wasm_bindgen]
+pub fn validate(jsonl: &str, witness_json: Option<String>) -> Result<JsValidationReport, JsValue> {
+    let (_, report) = validate_log(jsonl, witness_json.as_deref()).map_err(to_js_error)?;
+    to_js(&validation_report(&report)).map(JsCast::unchecked_into)
+}
+
+/// The `did:web` document of the latest entry of a log. Throws unless the
+/// whole log verifies.
+#[wasm_bindgen(js_name = toWebDid)]
+pub fn to_web_did(jsonl: &str, witness_json: Option<String>) -> Result<JsDocument, JsValue> {
+    to_js(&web_document(jsonl, witness_json.as_deref()).map_err(to_js_error)?)
+        .map(JsCast::unchecked_into)
+}
+
+/// `ResolveOptions` from JavaScript. Network timeouts aren't available to
+/// `fetch` and are not offered.
+#[derive(Default, Deserialize)]
+#[serde(default, rename_all = "camelCase", deny_unknown_fields)]
+struct Options {
+    max_response_bytes: Option<u64>,
+    eager_witness_download: bool,
+    follow_canonical_id: bool,
+}
+
+fn resolve_options(value: Value) -> Result<ResolveOptions, DIDWebVHError> {
+    let options: Options = if value.is_null() {
+        Options::default()
+    } else {
+        serde_json::from_value(value)
+            .map_err(|e| DIDWebVHError::DIDError(format!("Invalid resolve options: {e}")))?
+    };
+    let defaults = ResolveOptions::default();
+    Ok(ResolveOptions {
+        max_response_bytes: options
+            .max_response_bytes
+            .unwrap_or(defaults.max_response_bytes),
+        eager_witness_download: options.eager_witness_download,
+        follow_canonical_id: options.follow_canonical_id,
+        ..defaults
+    })
+}
+
+fn resolution_result(entry: &LogEntry, metadata: &MetaData) -> Result<Value, DIDWebVHError> {
+    Ok(json!({
+        "didDocument": entry.get_did_document()?,
+        "metadata": metadata,
+    }))
+}
+
+/// A state holding `jsonl`, validated.
+fn validate_log(
+    jsonl: &str,
+    witness_json: Option<&str>,
+) -> Result<(DIDWebVHState, ValidationReport), DIDWebVHError> {
+    let mut state = DIDWebVHState {
+        log_entries: DIDWebVHState::parse_log_entries(jsonl)?,
+        ..Default::default()
+    };
+    if state.log_entries.is_empty() {
+        return Err(DIDWebVHError::NotFound(
+            "The log has no entries".to_string(),
+        ));
+    }
+    if let Some(raw) = witness_json {
+        state.witness_proofs = DIDWebVHState::parse_witness_proofs(raw)?;
+    }
+    let report = state.validate()?;
+    Ok((state, report))
+}
+
+fn web_document(jsonl: &str, witness_json: Option<&str>) -> Result<Value, DIDWebVHError> {
+    let (state, report) = validate_log(jsonl, witness_json)?;
+    report.assert_complete()?;
+    state.to_web_did()
+}
+
+fn validation_report(report: &ValidationReport) -> Value {
+    let truncated = match &report.truncated {
+        None => Value::Null,
+        Some(TruncationReason::VerificationFailed {
+            at_version_id,
+            error,
+        }) => json!({
+            "reason": "verificationFailed",
+            "atVersionId": at_version_id,
+            "error": error_details(error),
+        }),
+        Some(TruncationReason::PostDeactivation {
+            deactivated_at,
+            dropped_entries,
+        }) => json!({
+            "reason": "postDeactivation",
+            "atVersionId": deactivated_at,
+            "droppedEntries": dropped_entries,
+        }),
+    };
+    json!({ "okUntil": report.ok_until, "truncated": truncated })
+}
+
+/// The machine-readable fields of `e`, see `ErrorDetails` above.
+fn error_details(e: &DIDWebVHError) -> Value {
+    let mut details = json!({ "message": e.to_string(), "code": e.code().as_str() });
+    if let Some(check) = e.check() {
+        details["checkKind"] = json!(check.kind.as_str());
+        if let Some(version_number) = check.version_number {
+            details["versionNumber"] = json!(version_number);
+        }
+        if let Some(version_id) = &check.version_id {
+            details["versionId"] = json!(version_id);
+        }
+    }
+    details
+}
+
+fn to_js(value: &Value) -> Result<JsValue, JsValue> {
+    js_sys::JSON::parse(&value.to_string())
+}
+
+fn from_js(value: &JsValue) -> Result<Value, JsValue> {
+    if value.is_undefined() || value.is_null() {
+        return Ok(Value::Null);
+    }
+    let raw: String = js_sys::JSON::stringify(value)?.into();
+    serde_json::from_str(&raw).map_err(|e| JsValue::from_str(&e.to_string()))
+}
+
+/// An `Error` named `DIDWebVHError` with the fields of [`error_details`].
+fn to_js_error(e: DIDWebVHError) -> JsValue {
+    let error = js_sys::Error::new(&e.to_string());
+    error.set_name("DIDWebVHError");
+    if let Value::Object(details) = error_details(&e) {
+        for (key, value) in details.iter().filter(|(key, _)| *key != "message") {
+            if let Ok(value) = to_js(value) {
+                let _ = js_sys::Reflect::set(&error, &JsValue::from_str(key), &value);
+            }
+        }
+    }
+    error.into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{resolve_options, validate_log, validation_report, web_document};
+    use crate::{
+        DIDWebVHState,
+        log_entry::LogEntry,
+        parameters::Parameters,
+        test_utils::{did_doc_with_key, key_and_params},
+    };
+    use chrono::{Duration, Utc};
+    use serde_json::json;
+
+    /// A two-entry log, as `did.jsonl`.
+    async fn jsonl() -> String {
+        let (key, params) = key_and_params();
+        let doc = did_doc_with_key("did:webvh:{SCID}:example.com", &key);
+        let created = (Utc::now() - Duration::seconds(10)).fixed_offset();
+        let mut state = DIDWebVHState::default();
+        state
+            .create_log_entry(Some(created), &doc, &params, &key)
+            .await
+            .unwrap();
+        let mut doc = state.log_entries()[0].get_state().clone();
+        doc["alsoKnownAs"] = json!(["https://example.com"]);
+        state
+            .create_log_entry(
+                Some(created + Duration::seconds(1)),
+                &doc,
+                &Parameters::default(),
+                &key,
+            )
+            .await
+            .unwrap();
+        state
+            .log_entries()
+            .iter()
+            .map(|e| serde_json::to_string(&e.log_entry).unwrap() + "\n")
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn validate_reports_truncation() {
+        let jsonl = jsonl().await;
+        let (_, report) = validate_log(&jsonl, None).unwrap();
+        let report = validation_report(&report);
+        assert!(report["okUntil"].as_str().unwrap().starts_with("2-"));
+        assert!(report["truncated"].is_null());
+
+        // Tamper with the second entry
+        let mut lines: Vec<&str> = jsonl.lines().collect();
+        let mut second: LogEntry = serde_json::from_str(lines[1]).unwrap();
+        let LogEntry::Spec1_0(entry) = &mut second else {
+            panic!("expected a 1.0 entry");
+        };
+        entry.state["alsoKnownAs"] = json!(["https://attacker.example"]);
+        let tampered = serde_json::to_string(&second).unwrap();
+        lines[1] = &tampered;
+
+        let (_, report) = validate_log(&lines.join("\n"), None).unwrap();
+        let report = validation_report(&report);
+        assert!(report["okUntil"].as_str().unwrap().starts_with("1-"));
+        let truncated = &report["truncated"];
+        assert_eq!(truncated["reason"], "verificationFailed");
+        assert_eq!(truncated["error"]["code"], "check_failed");
+        assert_eq!(truncated["error"]["checkKind"], "signature_invalid");
+        assert_eq!(truncated["error"]["versionNumber"], 2);
+
+        // toWebDid needs the whole log to verify
+        assert!(web_document(&lines.join("\n"), None).is_err());
+        let web = web_document(&jsonl, None).unwrap();
+        assert!(
+            web["id"]
+                .as_str()
+                .unwrap()
+                .starts_with("did:web:example.com")
+        );
+        assert!(validate_log("", None).is_err());
+    }
+
+    #[test]
+    fn options_from_js() {
+        let options = resolve_options(json!({"followCanonicalId": true})).unwrap();
+        assert!(options.follow_canonical_id);
+        assert_eq!(
+            resolve_options(json!(null)).unwrap().max_response_bytes,
+            crate::resolve::DEFAULT_MAX_RESPONSE_BYTES
+        );
+        assert!(resolve_options(json!({"timeout": 5})).is_err());
+    }
+}
@@ -0,0 +1,281 @@
+/*!
+*   Witness diagnostics.
+*
+*   Validation stops at the first entry that misses its witness threshold
+*   and reports a single error. [`WitnessDiagnostics`] instead looks at every
+*   published entry: which witnesses are active, which have a valid proof
+*   (directly, or transitively through a proof for a later entry), how far
+*   short of the threshold the entry is, and which witnesses need to sign
+*   which versionId for the whole log to resolve.
+*/
+
+use crate::{
+    DIDWebVHState,
+    witness::{
+        WitnessVerifyOptions,
+        validate::{EntryWitnessStatus, WitnessProofStatus},
+    },
+};
+use ahash::HashMap;
+use std::fmt;
+
+/// Witness status of a whole log. See [`DIDWebVHState::witness_diagnostics`].
+#[derive(Clone, Debug)]
+pub struct WitnessDiagnostics {
+    /// Status of every published entry, oldest first.
+    pub entries: Vec<EntryWitnessStatus>,
+    /// Signatures that would make every entry meet its threshold.
+    pub next_signatures: Vec<RequiredSignature>,
+}
+
+/// A witness proof still needed for the log to resolve.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RequiredSignature {
+    /// Witness DID (`did:key:...`).
+    pub witness: String,
+    /// versionId the witness should sign. This is the latest entry the
+    /// witness is active for, so one proof covers every earlier entry too.
+    pub version_id: String,
+}
+
+impl EntryWitnessStatus {
+    /// Number of witnesses with a valid proof covering this entry.
+    pub fn valid_count(&self) -> usize {
+        self.witnesses
+            .iter()
+            .filter(|c| matches!(c.status, WitnessProofStatus::Valid { .. }))
+            .count()
+    }
+
+    /// How many more valid proofs this entry needs to meet its threshold.
+    pub fn shortfall(&self) -> usize {
+        self.threshold
+            .map_or(0, |t| (t as usize).saturating_sub(self.valid_count()))
+    }
+
+    /// Witnesses that don't have a valid proof covering this entry.
+    pub fn missing(&self) -> impl Iterator<Item = &str> {
+        self.witnesses
+            .iter()
+            .filter(|c| !matches!(c.status, WitnessProofStatus::Valid { .. }))
+            .map(|c| c.witness.as_str())
+    }
+
+    /// Witnesses covered only through a proof for a later entry.
+    pub fn transitive(&self) -> impl Iterator<Item = &str> {
+        self.witnesses
+            .iter()
+            .filter(|c| {
+                matches!(&c.status, WitnessProofStatus::Valid { version_id } if *version_id != self.version_id)
+            })
+            .map(|c| c.witness.as_str())
+    }
+}
+
+impl WitnessDiagnostics {
+    /// Whether every entry meets its witness threshold.
+    pub fn is_resolvable(&self) -> bool {
+        self.entries.iter().all(|e| e.threshold_met)
+    }
+
+    /// Build from per-entry statuses (as returned by
+    /// [`verify_all`](crate::witness::proofs::WitnessProofCollection::verify_all)).
+    pub fn from_entries(entries: Vec<EntryWitnessStatus>) -> Self {
+        // The latest entry each witness is active for. A proof there covers
+        // every earlier entry the witness is active for.
+        let mut latest_active: HashMap<&str, &str> = HashMap::default();
+        for entry in &entries {
+            for check in &entry.witnesses {
+                latest_active.insert(&check.witness, &entry.version_id);
+            }
+        }
+
+        // Walk newest to oldest so a witness chosen for a later entry is
+        // reused for the earlier entries its proof also covers.
+        let mut chosen: Vec<RequiredSignature> = Vec::new();
+        for entry in entries.iter().rev() {
+            let mut shortfall = entry.shortfall();
+            if shortfall == 0 {
+                continue;
+            }
+            let missing: Vec<&str> = entry.missing().collect();
+            shortfall = shortfall.saturating_sub(
+                missing
+                    .iter()
+                    .filter(|w| chosen.iter().any(|c| c.witness == **w))
+                    .count(),
+            );
+            for witness in missing {
+                if shortfall == 0 {
+                    break;
+                }
+                if chosen.iter().any(|c| c.witness == witness) {
+                    continue;
+                }
+                chosen.push(RequiredSignature {
+                    witness: witness.to_string(),
+                    version_id: latest_active
+                        .get(witness)
+                        .copied()
+                        .unwrap_or(&entry.version_id)
+                        .to_string(),
+                });
+                shortfall -= 1;
+            }
+        }
+
+        Self {
+            entries,
+            next_signatures: chosen,
+        }
+    }
+}
+
+impl DIDWebVHState {
+    /// Report the witness status of every published entry, and which
+    /// witnesses still need to sign for the log to resolve.
+    ///
+    /// The state must have been created or validated so each entry's active
+    /// witnesses are known.
+    pub fn witness_diagnostics(&self, options: &WitnessVerifyOptions) -> WitnessDiagnostics {
+        WitnessDiagnostics::from_entries(self.witness_proofs().verify_all(self, options))
+    }
+}
+
+impl fmt::Display for WitnessDiagnostics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for entry in &self.entries {
+            let Some(threshold) = entry.threshold else {
+                writeln!(f, "{}: not witnessed", entry.version_id)?;
+                continue;
+            };
+            writeln!(
+                f,
+                "{}: {}/{threshold} valid{}",
+                entry.version_id,
+                entry.valid_count(),
+                if entry.threshold_met {
+                    String::new()
+                } else {
+                    format!(", {} short", entry.shortfall())
+                }
+            )?;
+            for check in &entry.witnesses {
+                let status = match &check.status {
+                    WitnessProofStatus::Valid { version_id } if *version_id == entry.version_id => {
+                        "valid".to_string()
+                    }
+                    WitnessProofStatus::Valid { version_id } => format!("valid via {version_id}"),
+                    WitnessProofStatus::Invalid { version_id, reason } => {
+                        format!("invalid proof for {version_id}: {reason}")
+                    }
+                    WitnessProofStatus::Outdated { version_id } => {
+                        format!("outdated (latest proof is for {version_id})")
+                    }
+                    WitnessProofStatus::Missing => "missing".to_string(),
+                };
+                writeln!(f, "  {}: {status}", check.witness)?;
+            }
+        }
+        if !self.next_signatures.is_empty() {
+            writeln!(f, "Needed to resolve:")?;
+            for s in &self.next_signatures {
+                writeln!(f, "  {} must sign {}", s.witness, s.version_id)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{RequiredSignature, WitnessDiagnostics};
+    use crate::witness::validate::{EntryWitnessStatus, WitnessCheck, WitnessProofStatus};
+
+    fn entry(
+        version_id: &str,
+        threshold: u32,
+        checks: &[(&str, WitnessProofStatus)],
+    ) -> EntryWitnessStatus {
+        let witnesses: Vec<WitnessCheck> = checks
+            .iter()
+            .map(|(w, status)| WitnessCheck {
+                witness: (*w).to_string(),
+                status: status.clone(),
+            })
+            .collect();
+        let valid = witnesses
+            .iter()
+            .filter(|c| matches!(c.status, WitnessProofStatus::Valid { .. }))
+            .count();
+        EntryWitnessStatus {
+            version_id: version_id.to_string(),
+            threshold: Some(threshold),
+            witnesses,
+            threshold_met: valid >= threshold as usize,
+        }
+    }
+
+    fn valid(version_id: &str) -> WitnessProofStatus {
+        WitnessProofStatus::Valid {
+            version_id: version_id.to_string(),
+        }
+    }
+
+    #[test]
+    fn one_later_proof_covers_earlier_entries() {
+        let diagnostics = WitnessDiagnostics::from_entries(vec![
+            entry(
+                "1-a",
+                2,
+                &[("A", valid("1-a")), ("B", WitnessProofStatus::Missing)],
+            ),
+            entry(
+                "2-b",
+                2,
+                &[("A", valid("2-b")), ("B", WitnessProofStatus::Missing)],
+            ),
+        ]);
+        assert!(!diagnostics.is_resolvable());
+        assert_eq!(diagnostics.entries[0].shortfall(), 1);
+        // B signing 2-b covers 1-a as well
+        assert_eq!(
+            diagnostics.next_signatures,
+            vec![RequiredSignature {
+                witness: "B".to_string(),
+                version_id: "2-b".to_string(),
+            }]
+        );
+    }
+
+    #[test]
+    fn witness_asked_for_its_latest_active_entry() {
+        // C was replaced by D in 2-b, so C can only cover 1-a
+        let diagnostics = WitnessDiagnostics::from_entries(vec![
+            entry("1-a", 1, &[("C", WitnessProofStatus::Missing)]),
+            entry("2-b", 1, &[("D", valid("2-b"))]),
+        ]);
+        assert_eq!(
+            diagnostics.next_signatures,
+            vec![RequiredSignature {
+                witness: "C".to_string(),
+                version_id: "1-a".to_string(),
+            }]
+        );
+        let text = diagnostics.to_string();
+        assert!(text.contains("1-a: 0/1 valid, 1 short"), "{text}");
+        assert!(text.contains("C must sign 1-a"), "{text}");
+    }
+
+    #[test]
+    fn transitive_proofs_reported() {
+        let status = entry(
+            "1-a",
+            1,
+            &[("A", valid("3-c")), ("B", WitnessProofStatus::Missing)],
+        );
+        assert_eq!(status.transitive().collect::<Vec<_>>(), vec!["A"]);
+        assert_eq!(status.missing().collect::<Vec<_>>(), vec!["B"]);
+        assert_eq!(status.shortfall(), 0);
+    }
+}
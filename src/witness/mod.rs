@@ -10,7 +10,10 @@ use serde::{
 };
 use std::fmt::Display;
 
+pub mod diagnostics;
 pub mod proofs;
+pub mod remote;
+pub mod service;
 pub mod validate;
 
 /// Runtime options for verifying witness proofs.
@@ -75,6 +78,7 @@ impl WitnessVerifyOptions {
 
 /// Witness nodes
 #[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 #[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 #[serde(untagged)]
 pub enum Witnesses {
@@ -191,9 +195,11 @@ impl Witnesses {
 /// by spec-compliant implementations round-trip byte-for-byte and their
 /// `entryHash` continues to verify.
 #[derive(Clone, Debug, Eq, Hash, PartialEq)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 #[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub struct Witness {
     /// `did:key` identifier of this witness node.
+    #[cfg_attr(feature = "schema", schemars(length(min = 1)))]
     pub id: Multibase,
 }
 
@@ -6,7 +6,10 @@
 */
 use std::{fs::File, sync::Arc};
 
-use crate::DIDWebVHError;
+use crate::{
+    DIDWebVHError,
+    witness::{WitnessVerifyOptions, validate::verify_witness_proof},
+};
 use affinidi_data_integrity::DataIntegrityProof;
 use ahash::HashMap;
 use serde::{Deserialize, Serialize};
@@ -18,16 +21,22 @@ use tracing::warn;
 
 /// WebVH witness proof file format
 #[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 #[serde(rename_all = "camelCase")]
 pub struct WitnessProofShadow(Vec<WitnessProof>);
 
 /// Record of each LogEntry that requires witnessing
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 #[serde(rename_all = "camelCase")]
 pub struct WitnessProof {
     /// versionId of the DID Log Entry to which witness proofs apply.
     pub version_id: Arc<String>,
     /// Array of DataIntegrity Proofs from each Witness
+    #[cfg_attr(
+        feature = "schema",
+        schemars(with = "Vec<crate::schema::DataIntegrityProofSchema>")
+    )]
     pub proof: Vec<Arc<DataIntegrityProof>>,
 
     /// Internally used for partial proofs
@@ -133,15 +142,28 @@ impl WitnessProofCollection {
             version_id
         };
 
-        // Update the pointer to latest witness version proof
-        self.witness_version.insert(
-            proof.verification_method.clone(),
-            (version_id, id, rc_proof),
-        );
+        // Update the pointer to latest witness version proof. A proof for an
+        // unpublished entry doesn't supersede the witness's published proof yet.
+        if !future_entry {
+            self.witness_version.insert(
+                proof.verification_method.clone(),
+                (version_id, id, rc_proof),
+            );
+        }
 
         Ok(())
     }
 
+    /// Mark the proofs for a versionId as belonging to an unpublished (or now
+    /// published) LogEntry.
+    pub(crate) fn set_future_entry(&mut self, version_id: &str, future_entry: bool) {
+        for record in self.proofs.0.iter_mut() {
+            if *record.version_id == version_id {
+                record.future_entry = future_entry;
+            }
+        }
+    }
+
     /// Completely remove all proofs relating to a versionId
     pub fn remove_version_id(&mut self, version_id: &str) {
         self.proofs.0.retain(|p| *p.version_id != version_id);
@@ -179,6 +201,9 @@ impl WitnessProofCollection {
 
     /// Save proofs to a file. Returns the number of bytes written.
     ///
+    /// The file is replaced atomically (temporary file, fsync, rename), so a
+    /// crash never leaves a half-written proofs file behind.
+    ///
     /// `u64` rather than `u32` because pruned-but-large witness-proof
     /// files (aggregating proofs across many versionIds) can exceed
     /// `u32::MAX` on disk in principle; using `u64` removes the
@@ -188,11 +213,13 @@ impl WitnessProofCollection {
             DIDWebVHError::WitnessProofError(format!("Couldn't serialize Witness Proofs Data: {e}"))
         })?;
         let bytes = json_data.len() as u64;
-        std::fs::write(file_path, json_data).map_err(|e| {
-            DIDWebVHError::WitnessProofError(format!(
-                "Couldn't write to Witness Proofs file ({file_path}): {e}",
-            ))
-        })?;
+        crate::write_atomic(std::path::Path::new(file_path), json_data.as_bytes()).map_err(
+            |e| {
+                DIDWebVHError::WitnessProofError(format!(
+                    "Couldn't write to Witness Proofs file ({file_path}): {e}",
+                ))
+            },
+        )?;
         Ok(bytes)
     }
 
@@ -321,6 +348,146 @@ impl WitnessProofCollection {
     pub fn get_total_count(&self) -> usize {
         self.proofs.0.iter().map(|p| p.proof.len()).sum()
     }
+
+    /// Iterate over every `(versionId, proof)` pair in the collection.
+    pub fn iter_proofs(&self) -> impl Iterator<Item = (&str, &DataIntegrityProof)> {
+        self.proofs
+            .0
+            .iter()
+            .flat_map(|v| v.proof.iter().map(|p| (v.version_id.as_str(), p.as_ref())))
+    }
+
+    /// Merge proofs from another collection (e.g. proof files gathered from
+    /// several witnesses) into this one.
+    ///
+    /// Each incoming proof is checked against the versionId it claims to
+    /// cover; proofs that fail are dropped and listed in the report. A proof
+    /// for a (versionId, witness) pair already held is a duplicate, and a proof
+    /// older than one already held from the same witness is superseded; both
+    /// are skipped. Older proofs made redundant by merged-in ones are pruned.
+    ///
+    /// `published_version` is the versionNumber of the last published entry.
+    /// Proofs for later versions cover pending entries: they are kept next to
+    /// the witness's published proofs instead of replacing them.
+    pub fn merge(
+        &mut self,
+        other: &WitnessProofCollection,
+        published_version: u32,
+        options: &WitnessVerifyOptions,
+    ) -> Result<MergeReport, DIDWebVHError> {
+        let mut report = MergeReport::default();
+
+        // Highest published versionNumber held per witness
+        let mut highest: HashMap<String, u32> = HashMap::default();
+        for (version_id, proof) in self.iter_proofs() {
+            if let Ok(n) = version_number(version_id)
+                && n <= published_version
+            {
+                let entry = highest
+                    .entry(proof.verification_method.clone())
+                    .or_default();
+                *entry = (*entry).max(n);
+            }
+        }
+
+        for (version_id, proof) in other.iter_proofs() {
+            let reject = |reason: String| RejectedProof {
+                version_id: version_id.to_string(),
+                verification_method: proof.verification_method.clone(),
+                reason,
+            };
+
+            let n = match version_number(version_id) {
+                Ok(n) => n,
+                Err(e) => {
+                    report.rejected.push(reject(e.to_string()));
+                    continue;
+                }
+            };
+            let held = self.get_proofs(version_id).is_some_and(|v| {
+                v.proof
+                    .iter()
+                    .any(|p| p.verification_method == proof.verification_method)
+            });
+            if held {
+                report.duplicates += 1;
+                continue;
+            }
+            if highest
+                .get(&proof.verification_method)
+                .is_some_and(|h| *h > n)
+            {
+                report.superseded += 1;
+                continue;
+            }
+            if let Err(e) = verify_witness_proof(version_id, proof, options) {
+                warn!(
+                    "Dropping witness proof from {} for {version_id}: {e}",
+                    proof.verification_method
+                );
+                report.rejected.push(reject(e.to_string()));
+                continue;
+            }
+
+            let pending = n > published_version;
+            self.add_proof(version_id, proof, pending)?;
+            if !pending {
+                highest.insert(proof.verification_method.clone(), n);
+            }
+            report.added += 1;
+        }
+
+        // Proofs already held for pending entries must survive pruning too
+        let pending: Vec<Arc<String>> = self
+            .proofs
+            .0
+            .iter()
+            .filter(|v| version_number(&v.version_id).is_ok_and(|n| n > published_version))
+            .map(|v| v.version_id.clone())
+            .collect();
+        for version_id in pending {
+            self.set_future_entry(&version_id, true);
+        }
+
+        self.write_optimise_records()?;
+        Ok(report)
+    }
+}
+
+/// Parse the versionNumber out of an `n-hash` versionId.
+fn version_number(version_id: &str) -> Result<u32, DIDWebVHError> {
+    version_id
+        .split_once('-')
+        .and_then(|(n, _)| n.parse().ok())
+        .ok_or_else(|| {
+            DIDWebVHError::WitnessProofError(format!(
+                "Invalid versionID ({version_id}) in witness proofs! Expected n-hash"
+            ))
+        })
+}
+
+/// Outcome of [`WitnessProofCollection::merge`].
+#[derive(Clone, Debug, Default)]
+pub struct MergeReport {
+    /// Proofs verified and added.
+    pub added: usize,
+    /// Proofs already held for the same versionId and witness.
+    pub duplicates: usize,
+    /// Proofs older than one already held from the same witness.
+    pub superseded: usize,
+    /// Proofs dropped because they are malformed or don't verify.
+    pub rejected: Vec<RejectedProof>,
+}
+
+/// A proof dropped by [`WitnessProofCollection::merge`].
+#[derive(Clone, Debug)]
+pub struct RejectedProof {
+    /// versionId the proof claimed to cover.
+    pub version_id: String,
+    /// Witness verification method (`did:key:z...#z...`).
+    pub verification_method: String,
+    /// Why it was rejected.
+    pub reason: String,
 }
 
 #[cfg(test)]
@@ -609,6 +776,12 @@ mod tests {
             1,
             "Expected future entry proof to be preserved after optimisation"
         );
+        // ...and must not supersede the published entry's proof
+        assert_eq!(
+            proofs.get_proof_count("1-abcd"),
+            1,
+            "Expected published proof to survive a future proof from the same witness"
+        );
     }
 
     /// Tests that `generate_proof_state` returns an error for malformed version IDs.
@@ -908,4 +1081,123 @@ mod tests {
             "Expected 2 proofs after v2 replaced v1 for witness-b"
         );
     }
+
+    // ===== Merging =====
+
+    /// Sign `{"versionId": version_id}` with a fresh witness key.
+    async fn signed_proof(
+        secret: &affinidi_secrets_resolver::secrets::Secret,
+        version_id: &str,
+    ) -> affinidi_data_integrity::DataIntegrityProof {
+        affinidi_data_integrity::DataIntegrityProof::sign(
+            &serde_json::json!({ "versionId": version_id }),
+            secret,
+            affinidi_data_integrity::SignOptions::new(),
+        )
+        .await
+        .unwrap()
+    }
+
+    /// Tests that merging proof files from several witnesses keeps one copy of
+    /// each proof, drops proofs whose signature doesn't match the versionId
+    /// they claim, and skips proofs older than one already held.
+    #[tokio::test]
+    async fn test_merge_dedups_and_verifies() {
+        use crate::{test_utils::generate_signing_key, witness::WitnessVerifyOptions};
+
+        let (a, b) = (generate_signing_key(), generate_signing_key());
+        let options = WitnessVerifyOptions::new();
+
+        let mut ours = WitnessProofCollection::default();
+        ours.add_proof("2-abcd", &signed_proof(&a, "2-abcd").await, false)
+            .unwrap();
+
+        let mut theirs = WitnessProofCollection::default();
+        // Duplicate of what we already hold
+        theirs
+            .add_proof("2-abcd", &signed_proof(&a, "2-abcd").await, false)
+            .unwrap();
+        // Older than what we hold from the same witness
+        theirs
+            .add_proof("1-abcd", &signed_proof(&a, "1-abcd").await, false)
+            .unwrap();
+        // New and valid
+        theirs
+            .add_proof("2-abcd", &signed_proof(&b, "2-abcd").await, false)
+            .unwrap();
+        // Signed for a different versionId than it claims
+        let mut forged = WitnessProofCollection::default();
+        forged
+            .add_proof("3-abcd", &signed_proof(&b, "2-abcd").await, false)
+            .unwrap();
+
+        let report = ours.merge(&theirs, 3, &options).unwrap();
+        assert_eq!(report.added, 1);
+        assert_eq!(report.duplicates, 1);
+        assert_eq!(report.superseded, 1);
+        assert!(report.rejected.is_empty());
+        assert_eq!(ours.get_proof_count("2-abcd"), 2);
+
+        let report = ours.merge(&forged, 3, &options).unwrap();
+        assert_eq!(report.added, 0);
+        assert_eq!(report.rejected.len(), 1);
+        assert_eq!(report.rejected[0].version_id, "3-abcd");
+        assert_eq!(ours.get_total_count(), 2);
+    }
+
+    /// Tests that a merged-in later proof replaces the same witness's older one.
+    #[tokio::test]
+    async fn test_merge_prunes_older_proofs() {
+        use crate::{test_utils::generate_signing_key, witness::WitnessVerifyOptions};
+
+        let a = generate_signing_key();
+        let mut ours = WitnessProofCollection::default();
+        ours.add_proof("1-abcd", &signed_proof(&a, "1-abcd").await, false)
+            .unwrap();
+        let mut theirs = WitnessProofCollection::default();
+        theirs
+            .add_proof("2-abcd", &signed_proof(&a, "2-abcd").await, false)
+            .unwrap();
+
+        let report = ours
+            .merge(&theirs, 2, &WitnessVerifyOptions::new())
+            .unwrap();
+        assert_eq!(report.added, 1);
+        assert_eq!(ours.get_proof_count("1-abcd"), 0);
+        assert_eq!(ours.get_proof_count("2-abcd"), 1);
+    }
+
+    /// Tests that a proof for a pending entry doesn't prune the same
+    /// witness's proof for the last published entry.
+    #[tokio::test]
+    async fn test_merge_keeps_published_proof_under_pending() {
+        use crate::{test_utils::generate_signing_key, witness::WitnessVerifyOptions};
+
+        let a = generate_signing_key();
+        let mut ours = WitnessProofCollection::default();
+        ours.add_proof("2-abcd", &signed_proof(&a, "2-abcd").await, false)
+            .unwrap();
+        let mut theirs = WitnessProofCollection::default();
+        theirs
+            .add_proof("3-abcd", &signed_proof(&a, "3-abcd").await, false)
+            .unwrap();
+
+        let report = ours
+            .merge(&theirs, 2, &WitnessVerifyOptions::new())
+            .unwrap();
+        assert_eq!(report.added, 1);
+        assert_eq!(ours.get_proof_count("2-abcd"), 1);
+        assert_eq!(ours.get_proof_count("3-abcd"), 1);
+
+        // Merging again, with the pending proof already held, keeps both
+        let report = ours
+            .merge(
+                &WitnessProofCollection::default(),
+                2,
+                &WitnessVerifyOptions::new(),
+            )
+            .unwrap();
+        assert_eq!(report.added, 0);
+        assert_eq!(ours.get_total_count(), 2);
+    }
 }
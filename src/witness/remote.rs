@@ -0,0 +1,646 @@
+/*!
+*   Requesting witness proofs from remote witnesses.
+*
+*   [`sign_witness_proofs`](crate::create::sign_witness_proofs) needs every
+*   witness key in-process. In production, witnesses are usually independent
+*   parties, so this module defines the exchange instead:
+*
+*   - a [`WitnessRequest`] carries the new log entry, the log before it with
+*     its witness proofs, and the `versionId` to sign, so the witness can
+*     check the entry before attesting to it
+*   - a [`WitnessResponse`] carries the witness's `DataIntegrityProof` over
+*     `{"versionId": ...}`
+*   - a [`WitnessClient`] delivers a request to one witness, however that
+*     witness is reached (HTTP, a message queue, an in-process key, ...)
+*
+*   [`request_witness_proofs`] fans a request out to every active witness
+*   concurrently, verifies each returned proof, and stops as soon as the
+*   threshold is met. Accepted proofs are merged into the state's
+*   [`WitnessProofCollection`](crate::witness::proofs::WitnessProofCollection).
+*
+*   With the `network` feature, [`HttpWitnessClient`] POSTs the request as
+*   JSON to a per-witness URL.
+*/
+
+use crate::{
+    DIDWebVHError, DIDWebVHState, Signer,
+    log_entry::LogEntry,
+    witness::{Witness, WitnessVerifyOptions, proofs::WitnessProofCollection},
+};
+use affinidi_data_integrity::{DataIntegrityProof, SignOptions};
+use ahash::HashMap;
+use async_trait::async_trait;
+use futures_util::{StreamExt, stream::FuturesUnordered};
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use tracing::{debug, warn};
+
+/// What a witness is asked to sign.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WitnessRequest {
+    /// versionId of the entry to witness.
+    pub version_id: String,
+    /// The entry to witness.
+    pub log_entry: LogEntry,
+    /// Every published entry before `log_entry`, oldest first.
+    pub previous_log: Vec<LogEntry>,
+    /// Witness proofs for `previous_log` (did-witness.json), so the witness
+    /// can check it resolves before extending it.
+    #[serde(default)]
+    pub witness_proofs: WitnessProofCollection,
+}
+
+impl WitnessRequest {
+    /// Request a witness proof for the pending entry in `state`, or if
+    /// nothing is pending, its latest published entry.
+    pub fn from_state(state: &DIDWebVHState) -> Result<Self, DIDWebVHError> {
+        let (target, previous) = match (state.pending_entry(), state.log_entries()) {
+            (Some(pending), published) => (pending, published),
+            (None, [previous @ .., last]) => (last, previous),
+            (None, []) => {
+                return Err(DIDWebVHError::WitnessProofError(
+                    "Can't request witness proofs for an empty DID state".to_string(),
+                ));
+            }
+        };
+        Ok(Self {
+            version_id: target.get_version_id().to_string(),
+            log_entry: target.log_entry.clone(),
+            previous_log: previous.iter().map(|e| e.log_entry.clone()).collect(),
+            witness_proofs: state.witness_proofs().clone(),
+        })
+    }
+
+    /// The document a witness signs: `{"versionId": ...}`.
+    pub fn signing_payload(&self) -> Value {
+        json!({ "versionId": self.version_id })
+    }
+}
+
+/// A witness's answer to a [`WitnessRequest`].
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WitnessResponse {
+    /// Proof over [`WitnessRequest::signing_payload`], made with the witness's `did:key`.
+    pub proof: DataIntegrityProof,
+}
+
+/// Delivers a [`WitnessRequest`] to a single witness.
+///
+/// Implementations only transport; proofs are verified by
+/// [`request_witness_proofs`], so a client doesn't need to trust the witness.
+#[async_trait]
+pub trait WitnessClient: Send + Sync {
+    /// Ask `witness` to sign `request`.
+    async fn request_proof(
+        &self,
+        witness: &Witness,
+        request: &WitnessRequest,
+    ) -> Result<WitnessResponse, DIDWebVHError>;
+}
+
+/// A [`WitnessClient`] backed by in-process witness keys, keyed by witness DID.
+///
+/// Useful in tests, or to mix locally held witness keys with remote ones.
+pub struct LocalWitnessClient<W: Signer> {
+    secrets: HashMap<String, W>,
+}
+
+impl<W: Signer> LocalWitnessClient<W> {
+    /// Create a client from witness signers keyed by witness DID (`did:key:...`).
+    pub fn new(secrets: HashMap<String, W>) -> Self {
+        Self { secrets }
+    }
+}
+
+#[async_trait]
+impl<W: Signer> WitnessClient for LocalWitnessClient<W> {
+    async fn request_proof(
+        &self,
+        witness: &Witness,
+        request: &WitnessRequest,
+    ) -> Result<WitnessResponse, DIDWebVHError> {
+        let Some(signer) = self.secrets.get(&witness.as_did()) else {
+            return Err(DIDWebVHError::WitnessProofError(format!(
+                "Couldn't find secret for witness ({})",
+                witness.id
+            )));
+        };
+        let proof =
+            DataIntegrityProof::sign(&request.signing_payload(), signer, SignOptions::new())
+                .await
+                .map_err(|e| {
+                    DIDWebVHError::WitnessProofError(format!(
+                        "Couldn't generate witness proof for {}: {e}",
+                        request.version_id
+                    ))
+                })?;
+        Ok(WitnessResponse { proof })
+    }
+}
+
+/// A witness that didn't contribute a valid proof.
+#[derive(Clone, Debug)]
+pub struct WitnessFailure {
+    /// Witness DID.
+    pub witness: String,
+    /// Why the request failed or the proof was rejected.
+    pub reason: String,
+}
+
+/// Result of [`request_witness_proofs`].
+#[derive(Clone, Debug, Default)]
+pub struct WitnessCollection {
+    /// versionId that was witnessed.
+    pub version_id: String,
+    /// Witnesses whose proofs were verified and added.
+    pub accepted: Vec<String>,
+    /// Witnesses that failed before the threshold was met.
+    pub failed: Vec<WitnessFailure>,
+}
+
+/// Request proofs for the pending entry of `state` (or if nothing is pending,
+/// its latest entry) from its active witnesses.
+///
+/// Requests are sent concurrently. Each response must be signed by the
+/// witness it was sent to, pass [`WitnessVerifyOptions::check_proof_shape`]
+/// and verify against the entry's `versionId`. Collection stops once the
+/// threshold is met; outstanding requests are dropped, and active witnesses
+/// whose valid proof for the entry is already held aren't asked again. Accepted proofs are merged
+/// into the state's witness proofs. For a pending entry they are kept even
+/// if the threshold isn't met yet, so collection can be retried.
+///
+/// Returns `Ok` with an empty [`WitnessCollection::accepted`] if the entry has
+/// no active witnesses, and an error if the threshold can't be met.
+pub async fn request_witness_proofs<C: WitnessClient + ?Sized>(
+    client: &C,
+    state: &mut DIDWebVHState,
+    options: &WitnessVerifyOptions,
+) -> Result<WitnessCollection, DIDWebVHError> {
+    let request = WitnessRequest::from_state(state)?;
+    let mut outcome = WitnessCollection {
+        version_id: request.version_id.clone(),
+        ..Default::default()
+    };
+
+    let is_pending = state.pending_entry().is_some();
+    let Some(entry) = state.witness_target() else {
+        return Err(DIDWebVHError::WitnessProofError(
+            "Can't request witness proofs for an empty DID state".to_string(),
+        ));
+    };
+    let Some(witnesses) = entry.get_active_witnesses() else {
+        return Ok(outcome);
+    };
+    let (Some(threshold), Some(nodes)) = (witnesses.threshold(), witnesses.witnesses()) else {
+        return Ok(outcome);
+    };
+
+    // Active witnesses that already hold a valid proof for this entry aren't
+    // asked again. Other held proofs don't count towards the threshold.
+    let held_proofs = state.witness_proofs().get_proofs(&request.version_id);
+    let held: Vec<String> = nodes
+        .iter()
+        .filter(|witness| {
+            held_proofs.is_some_and(|v| {
+                v.proof
+                    .iter()
+                    .any(|proof| verify_response(witness, &entry.log_entry, proof, options).is_ok())
+            })
+        })
+        .map(Witness::as_did_key)
+        .collect();
+    let needed = (threshold as usize).saturating_sub(held.len());
+
+    let mut pending: FuturesUnordered<_> = nodes
+        .iter()
+        .filter(|witness| !held.contains(&witness.as_did_key()))
+        .map(|witness| {
+            let request = &request;
+            async move { (witness, client.request_proof(witness, request).await) }
+        })
+        .collect();
+
+    let mut accepted = Vec::new();
+    while accepted.len() < needed
+        && let Some((witness, result)) = pending.next().await
+    {
+        let checked = result.and_then(|response| {
+            verify_response(witness, &entry.log_entry, &response.proof, options)
+                .map(|()| response.proof)
+        });
+        match checked {
+            Ok(proof) => {
+                debug!(
+                    "{}: witness proof from {} accepted",
+                    request.version_id, witness.id
+                );
+                outcome.accepted.push(witness.as_did());
+                accepted.push(proof);
+            }
+            Err(e) => {
+                warn!("{}: witness {} failed: {e}", request.version_id, witness.id);
+                outcome.failed.push(WitnessFailure {
+                    witness: witness.as_did(),
+                    reason: e.to_string(),
+                });
+            }
+        }
+    }
+    drop(pending);
+    let collected = held.len() + accepted.len();
+
+    // A pending entry keeps partial progress; a published one is all or nothing
+    if is_pending || collected >= threshold as usize {
+        let witness_proofs = state.witness_proofs_mut();
+        for proof in &accepted {
+            witness_proofs
+                .add_proof(&request.version_id, proof, is_pending)
+                .map_err(|e| {
+                    DIDWebVHError::WitnessProofError(format!("Error adding proof: {e}"))
+                })?;
+        }
+        witness_proofs.write_optimise_records()?;
+    }
+
+    if collected < threshold as usize {
+        let reasons: Vec<String> = outcome
+            .failed
+            .iter()
+            .map(|f| format!("{}: {}", f.witness, f.reason))
+            .collect();
+        return Err(DIDWebVHError::WitnessProofError(format!(
+            "Witness proof threshold ({threshold}) was not met for {}. Only ({collected}) proofs \
+             were collected. Failures: [{}]",
+            request.version_id,
+            reasons.join("; ")
+        )));
+    }
+
+    Ok(outcome)
+}
+
+/// A proof is only accepted from the witness it was requested from, in the
+/// spec-mandated shape, and over this entry's `versionId`.
+fn verify_response(
+    witness: &Witness,
+    log_entry: &LogEntry,
+    proof: &DataIntegrityProof,
+    options: &WitnessVerifyOptions,
+) -> Result<(), DIDWebVHError> {
+    if proof.verification_method != witness.as_did_key() {
+        return Err(DIDWebVHError::WitnessProofError(format!(
+            "Proof was signed by {}, expected {}",
+            proof.verification_method,
+            witness.as_did_key()
+        )));
+    }
+    options.check_proof_shape(proof)?;
+    log_entry.validate_witness_proof(proof, options).map(|_| ())
+}
+
+/// Sends [`WitnessRequest`]s as JSON `POST`s to a per-witness URL and expects
+/// a JSON [`WitnessResponse`] back.
+#[cfg(feature = "network")]
+pub struct HttpWitnessClient {
+    client: reqwest::Client,
+    endpoints: HashMap<String, url::Url>,
+    max_response_bytes: usize,
+}
+
+#[cfg(feature = "network")]
+impl HttpWitnessClient {
+    /// Create a client. `endpoints` maps witness DID (`did:key:...`) to the URL
+    /// its witness service accepts requests on.
+    pub fn new(client: reqwest::Client, endpoints: HashMap<String, url::Url>) -> Self {
+        Self {
+            client,
+            endpoints,
+            max_response_bytes: 16 * 1024,
+        }
+    }
+
+    /// Reject responses larger than `bytes` (default: 16 KB).
+    pub fn max_response_bytes(mut self, bytes: usize) -> Self {
+        self.max_response_bytes = bytes;
+        self
+    }
+}
+
+#[cfg(feature = "network")]
+#[async_trait]
+impl WitnessClient for HttpWitnessClient {
+    async fn request_proof(
+        &self,
+        witness: &Witness,
+        request: &WitnessRequest,
+    ) -> Result<WitnessResponse, DIDWebVHError> {
+        let Some(url) = self.endpoints.get(&witness.as_did()) else {
+            return Err(DIDWebVHError::WitnessProofError(format!(
+                "No endpoint configured for witness ({})",
+                witness.id
+            )));
+        };
+        let network_error =
+            |status_code: Option<u16>, message: String| DIDWebVHError::NetworkError {
+                url: url.to_string(),
+                status_code,
+                message,
+            };
+
+        let body = serde_json::to_string(request).map_err(|e| {
+            DIDWebVHError::WitnessProofError(format!("Couldn't serialize witness request: {e}"))
+        })?;
+        let mut response = self
+            .client
+            .post(url.clone())
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .body(body)
+            .send()
+            .await
+            .map_err(|e| network_error(None, format!("Request failed: {e}")))?;
+
+        let status = response.status();
+        if !status.is_success() {
+            return Err(network_error(
+                Some(status.as_u16()),
+                format!("HTTP {}", status.as_u16()),
+            ));
+        }
+        // Reject on Content-Length, then enforce the limit as chunks arrive
+        let max_bytes = self.max_response_bytes as u64;
+        let too_large = || DIDWebVHError::ResponseTooLarge {
+            url: url.to_string(),
+            max_bytes,
+        };
+        if response.content_length().is_some_and(|len| len > max_bytes) {
+            return Err(too_large());
+        }
+        let mut bytes = Vec::new();
+        while let Some(chunk) = response.chunk().await.map_err(|e| {
+            network_error(Some(status.as_u16()), format!("Failed to read body: {e}"))
+        })? {
+            if (bytes.len() + chunk.len()) as u64 > max_bytes {
+                return Err(too_large());
+            }
+            bytes.extend_from_slice(&chunk);
+        }
+        serde_json::from_slice(&bytes).map_err(|e| {
+            DIDWebVHError::WitnessProofError(format!(
+                "Witness ({}) returned an invalid response: {e}",
+                witness.id
+            ))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        LocalWitnessClient, WitnessClient, WitnessRequest, WitnessResponse, request_witness_proofs,
+    };
+    use crate::{
+        DIDWebVHError, DIDWebVHState,
+        parameters::Parameters,
+        test_utils::{did_doc_with_key, generate_signing_key, key_and_params},
+        witness::{Witness, WitnessVerifyOptions, Witnesses},
+    };
+    use affinidi_secrets_resolver::secrets::Secret;
+    use ahash::HashMap;
+    use async_trait::async_trait;
+    use std::sync::Arc;
+
+    /// Create a DID witnessed by `witnesses` with the given threshold.
+    async fn witnessed_state(witnesses: &[Secret], threshold: u32) -> DIDWebVHState {
+        let (key, params) = key_and_params();
+        let params = Parameters {
+            witness: Some(Arc::new(Witnesses::Value {
+                threshold,
+                witnesses: witnesses
+                    .iter()
+                    .map(|w| Witness::new(w.get_public_keymultibase().unwrap()))
+                    .collect(),
+            })),
+            ..params
+        };
+        let doc = did_doc_with_key("did:webvh:{SCID}:example.com", &key);
+        let mut state = DIDWebVHState::default();
+        state
+            .create_log_entry(None, &doc, &params, &key)
+            .await
+            .unwrap();
+        state
+    }
+
+    fn by_did(secrets: &[Secret]) -> HashMap<String, Secret> {
+        secrets
+            .iter()
+            .map(|s| {
+                let did = s.id.split('#').next().unwrap().to_string();
+                (did, s.clone())
+            })
+            .collect()
+    }
+
+    /// Answers every request with a proof from the wrong key.
+    struct ImpostorClient(Secret);
+
+    #[async_trait]
+    impl WitnessClient for ImpostorClient {
+        async fn request_proof(
+            &self,
+            _witness: &Witness,
+            request: &WitnessRequest,
+        ) -> Result<WitnessResponse, DIDWebVHError> {
+            LocalWitnessClient::new(by_did(std::slice::from_ref(&self.0)))
+                .request_proof(
+                    &Witness::new(self.0.get_public_keymultibase().unwrap()),
+                    request,
+                )
+                .await
+        }
+    }
+
+    #[tokio::test]
+    async fn threshold_met_and_proofs_validate() {
+        let witnesses: Vec<Secret> = (0..3).map(|_| generate_signing_key()).collect();
+        let mut state = witnessed_state(&witnesses, 2).await;
+
+        // Only two of the three witnesses are reachable
+        let client = LocalWitnessClient::new(by_did(&witnesses[..2]));
+        let outcome = request_witness_proofs(&client, &mut state, &WitnessVerifyOptions::new())
+            .await
+            .unwrap();
+        assert_eq!(outcome.accepted.len(), 2);
+        assert_eq!(state.witness_proofs().get_total_count(), 2);
+
+        let request = WitnessRequest::from_state(&state).unwrap();
+        assert!(request.previous_log.is_empty());
+        assert_eq!(request.version_id, outcome.version_id);
+
+        let mut resolved = DIDWebVHState::default();
+        let log = serde_json::to_string(&state.log_entries()[0].log_entry).unwrap();
+        let proofs = serde_json::to_string(state.witness_proofs()).unwrap();
+        let did = state.log_entries()[0].get_state()["id"]
+            .as_str()
+            .unwrap()
+            .to_string();
+        resolved
+            .resolve_log(&did, &log, Some(&proofs))
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn threshold_not_met_reports_failures() {
+        let witnesses: Vec<Secret> = (0..2).map(|_| generate_signing_key()).collect();
+        let mut state = witnessed_state(&witnesses, 2).await;
+
+        let client = LocalWitnessClient::new(by_did(&witnesses[..1]));
+        let err = request_witness_proofs(&client, &mut state, &WitnessVerifyOptions::new())
+            .await
+            .unwrap_err();
+        assert!(
+            err.to_string().contains("threshold (2) was not met"),
+            "{err}"
+        );
+        assert_eq!(state.witness_proofs().get_total_count(), 0);
+    }
+
+    #[tokio::test]
+    async fn held_proofs_count_only_if_valid_and_active() {
+        use affinidi_data_integrity::{DataIntegrityProof, SignOptions};
+
+        let witnesses: Vec<Secret> = (0..2).map(|_| generate_signing_key()).collect();
+        let mut state = witnessed_state(&witnesses, 2).await;
+        let version_id = WitnessRequest::from_state(&state).unwrap().version_id;
+        let sign = |secret: Secret, signed_id: &str| {
+            let data = serde_json::json!({ "versionId": signed_id });
+            async move {
+                DataIntegrityProof::sign(&data, &secret, SignOptions::new())
+                    .await
+                    .unwrap()
+            }
+        };
+        // A valid proof from a key that isn't a witness, and a witness's
+        // proof over another versionId
+        let outsider = sign(generate_signing_key(), &version_id).await;
+        let misdirected = sign(witnesses[0].clone(), "1-other").await;
+        for proof in [&outsider, &misdirected] {
+            state
+                .witness_proofs_mut()
+                .add_proof(&version_id, proof, false)
+                .unwrap();
+        }
+
+        let client = LocalWitnessClient::new(by_did(&witnesses[..1]));
+        let err = request_witness_proofs(&client, &mut state, &WitnessVerifyOptions::new())
+            .await
+            .unwrap_err();
+        assert!(
+            err.to_string().contains("threshold (2) was not met"),
+            "{err}"
+        );
+
+        let client = LocalWitnessClient::new(by_did(&witnesses));
+        let outcome = request_witness_proofs(&client, &mut state, &WitnessVerifyOptions::new())
+            .await
+            .unwrap();
+        assert_eq!(outcome.accepted.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn proof_from_wrong_key_rejected() {
+        let witnesses = vec![generate_signing_key()];
+        let mut state = witnessed_state(&witnesses, 1).await;
+
+        let client = ImpostorClient(generate_signing_key());
+        let err = request_witness_proofs(&client, &mut state, &WitnessVerifyOptions::new())
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("expected did:key:"), "{err}");
+    }
+
+    #[tokio::test]
+    async fn unwitnessed_entry_needs_no_proofs() {
+        let (key, params) = key_and_params();
+        let doc = did_doc_with_key("did:webvh:{SCID}:example.com", &key);
+        let mut state = DIDWebVHState::default();
+        state
+            .create_log_entry(None, &doc, &params, &key)
+            .await
+            .unwrap();
+
+        let client = LocalWitnessClient::<Secret>::new(HashMap::default());
+        let outcome = request_witness_proofs(&client, &mut state, &WitnessVerifyOptions::new())
+            .await
+            .unwrap();
+        assert!(outcome.accepted.is_empty());
+    }
+
+    #[cfg(feature = "network")]
+    #[tokio::test]
+    async fn http_client_round_trip() {
+        use super::HttpWitnessClient;
+        use wiremock::{
+            Mock, MockServer, ResponseTemplate,
+            matchers::{method, path},
+        };
+
+        let witness = generate_signing_key();
+        let mut state = witnessed_state(std::slice::from_ref(&witness), 1).await;
+
+        // The mock witness signs ahead of time; it has no way to see the request
+        let request = WitnessRequest::from_state(&state).unwrap();
+        let response = LocalWitnessClient::new(by_did(std::slice::from_ref(&witness)))
+            .request_proof(
+                &Witness::new(witness.get_public_keymultibase().unwrap()),
+                &request,
+            )
+            .await
+            .unwrap();
+
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/witness"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(&response))
+            .mount(&server)
+            .await;
+
+        let did = by_did(std::slice::from_ref(&witness))
+            .into_keys()
+            .next()
+            .unwrap();
+        let endpoints = [(
+            did,
+            url::Url::parse(&format!("{}/witness", server.uri())).unwrap(),
+        )]
+        .into_iter()
+        .collect();
+        let client = HttpWitnessClient::new(reqwest::Client::new(), endpoints);
+        let outcome = request_witness_proofs(&client, &mut state, &WitnessVerifyOptions::new())
+            .await
+            .unwrap();
+        assert_eq!(outcome.accepted.len(), 1);
+
+        let tiny = HttpWitnessClient::new(
+            reqwest::Client::new(),
+            [(
+                outcome.accepted[0].clone(),
+                url::Url::parse(&format!("{}/witness", server.uri())).unwrap(),
+            )]
+            .into_iter()
+            .collect(),
+        )
+        .max_response_bytes(8);
+        let err = tiny
+            .request_proof(
+                &Witness::new(witness.get_public_keymultibase().unwrap()),
+                &request,
+            )
+            .await
+            .unwrap_err();
+        assert!(matches!(err, DIDWebVHError::ResponseTooLarge { .. }));
+    }
+}
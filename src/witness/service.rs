@@ -0,0 +1,594 @@
+/*!
+*   An embeddable witness service.
+*
+*   A witness's signature says "I saw this entry and it was valid". Signing
+*   `{"versionId": ...}` blindly would let a compromised controller (or anyone
+*   who can reach the witness) collect attestations for entries that don't
+*   verify. [`WitnessService`] checks a [`WitnessRequest`] before signing:
+*
+*   1. the previous log is validated the way a resolver would
+*      ([`DIDWebVHState::validate_with`]): signatures, hash chain, SCID,
+*      parameters, pre-rotation and the witness proofs sent along with it. A
+*      log that only partly validates or is already deactivated is refused
+*   2. the proposed entry must verify on top of the last validated entry
+*   3. this witness must be one of the proposed entry's active witnesses
+*   4. the witness-side [`WitnessPolicy`] is applied: `versionTime` must be
+*      recent, signing is rate limited per DID, and an entry that drops this
+*      witness from the witness list is refused unless the operator was told
+*      to expect it ([`WitnessService::expect_removal`])
+*
+*   The service is transport-agnostic: call [`WitnessService::handle`] from
+*   whatever server receives requests. It also implements
+*   [`WitnessClient`], so it can be driven in-process with
+*   [`request_witness_proofs`](crate::witness::remote::request_witness_proofs).
+*/
+
+use crate::{
+    DIDWebVHError, DIDWebVHState, Signer,
+    log_entry::LogEntryMethods,
+    log_entry_state::{LogEntryState, LogEntryValidationStatus},
+    parameters::Parameters,
+    validate::ValidationReport,
+    witness::{
+        Witness, WitnessVerifyOptions,
+        remote::{WitnessClient, WitnessRequest, WitnessResponse},
+    },
+};
+use affinidi_data_integrity::{DataIntegrityProof, SignOptions};
+use ahash::{HashMap, HashSet};
+use async_trait::async_trait;
+use chrono::{DateTime, FixedOffset, Utc};
+use std::{collections::VecDeque, sync::Mutex, time::Duration};
+use tracing::{info, warn};
+
+/// Witness-side rules applied before signing.
+#[derive(Clone, Debug)]
+pub struct WitnessPolicy {
+    /// Oldest acceptable `versionTime`, relative to now. Default: 10 minutes.
+    pub max_entry_age: Duration,
+    /// How far in the future `versionTime` may be, to allow for clock skew. Default: 1 minute.
+    pub max_clock_skew: Duration,
+    /// At most `max_signatures` per DID within `window`. `None` disables rate limiting.
+    pub rate_limit: Option<RateLimit>,
+    /// Options for checking the witness proofs of the previous log.
+    pub verify_options: WitnessVerifyOptions,
+}
+
+impl Default for WitnessPolicy {
+    fn default() -> Self {
+        Self {
+            max_entry_age: Duration::from_mins(10),
+            max_clock_skew: Duration::from_mins(1),
+            rate_limit: Some(RateLimit {
+                max_signatures: 10,
+                window: Duration::from_hours(1),
+            }),
+            verify_options: WitnessVerifyOptions::new(),
+        }
+    }
+}
+
+impl WitnessPolicy {
+    /// Default policy.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the oldest acceptable `versionTime`.
+    #[must_use]
+    pub fn max_entry_age(mut self, age: Duration) -> Self {
+        self.max_entry_age = age;
+        self
+    }
+
+    /// Set how far in the future `versionTime` may be.
+    #[must_use]
+    pub fn max_clock_skew(mut self, skew: Duration) -> Self {
+        self.max_clock_skew = skew;
+        self
+    }
+
+    /// Set (or with `None`, disable) the per-DID rate limit.
+    #[must_use]
+    pub fn rate_limit(mut self, limit: Option<RateLimit>) -> Self {
+        self.rate_limit = limit;
+        self
+    }
+
+    /// Set the options used to check the previous log's witness proofs.
+    #[must_use]
+    pub fn verify_options(mut self, options: WitnessVerifyOptions) -> Self {
+        self.verify_options = options;
+        self
+    }
+}
+
+/// Per-DID signing rate limit.
+#[derive(Clone, Copy, Debug)]
+pub struct RateLimit {
+    /// Signatures allowed per DID within `window`.
+    pub max_signatures: u32,
+    /// Sliding window the limit applies to.
+    pub window: Duration,
+}
+
+/// A witness that validates entries before signing them.
+pub struct WitnessService<W: Signer> {
+    signer: W,
+    did: String,
+    policy: WitnessPolicy,
+    /// SCIDs whose controllers gave notice that this witness will be removed.
+    expected_removals: Mutex<HashSet<String>>,
+    /// Recent signing times per SCID, for the rate limit.
+    signed: Mutex<HashMap<String, VecDeque<DateTime<FixedOffset>>>>,
+}
+
+impl<W: Signer> WitnessService<W> {
+    /// Create a service signing with `signer`, whose verification method must
+    /// be the witness's `did:key:z...#z...`.
+    pub fn new(signer: W, policy: WitnessPolicy) -> Result<Self, DIDWebVHError> {
+        let did = match signer.verification_method().split_once('#') {
+            Some((did, fragment)) if did.strip_prefix("did:key:") == Some(fragment) => {
+                did.to_string()
+            }
+            _ => {
+                return Err(DIDWebVHError::WitnessProofError(format!(
+                    "Witness signer verification method ({}) must be did:key:<key>#<key>",
+                    signer.verification_method()
+                )));
+            }
+        };
+        Ok(Self {
+            signer,
+            did,
+            policy,
+            expected_removals: Mutex::new(HashSet::default()),
+            signed: Mutex::new(HashMap::default()),
+        })
+    }
+
+    /// This witness's DID (`did:key:...`).
+    pub fn did(&self) -> &str {
+        &self.did
+    }
+
+    /// Accept one entry for the DID with this SCID that removes this witness
+    /// from the witness list. The notice is consumed once that entry is signed.
+    pub fn expect_removal(&self, scid: &str) {
+        self.expected_removals
+            .lock()
+            .expect("expected_removals lock poisoned")
+            .insert(scid.to_string());
+    }
+
+    /// Validate `request` and, if it passes, sign its versionId.
+    pub async fn handle(&self, request: &WitnessRequest) -> Result<WitnessResponse, DIDWebVHError> {
+        self.handle_at(request, Utc::now().fixed_offset()).await
+    }
+
+    async fn handle_at(
+        &self,
+        request: &WitnessRequest,
+        now: DateTime<FixedOffset>,
+    ) -> Result<WitnessResponse, DIDWebVHError> {
+        if request.log_entry.get_version_id() != request.version_id {
+            return Err(DIDWebVHError::WitnessProofError(format!(
+                "Request asks to sign {}, but the log entry is {}",
+                request.version_id,
+                request.log_entry.get_version_id()
+            )));
+        }
+
+        let (scid, removes_self) = self.check_entry(request, now)?;
+        self.check_rate_limit(&scid, now, false)?;
+
+        let proof =
+            DataIntegrityProof::sign(&request.signing_payload(), &self.signer, SignOptions::new())
+                .await
+                .map_err(|e| {
+                    DIDWebVHError::WitnessProofError(format!(
+                        "Couldn't generate witness proof for {}: {e}",
+                        request.version_id
+                    ))
+                })?;
+        // Checked again now the proof exists, so concurrent requests can't
+        // overshoot the limit and failed ones don't use it up
+        self.check_rate_limit(&scid, now, true)?;
+
+        if removes_self {
+            self.expected_removals
+                .lock()
+                .expect("expected_removals lock poisoned")
+                .remove(&scid);
+        }
+        info!("Witnessed {} ({scid})", request.version_id);
+
+        Ok(WitnessResponse { proof })
+    }
+
+    /// Verify the extended log and apply the entry-level policy. Returns the
+    /// SCID and whether the entry removes this witness.
+    fn check_entry(
+        &self,
+        request: &WitnessRequest,
+        now: DateTime<FixedOffset>,
+    ) -> Result<(String, bool), DIDWebVHError> {
+        let mut state = DIDWebVHState::from_log_entries(request.previous_log.clone());
+        if !request.previous_log.is_empty() {
+            state.set_witness_proofs(request.witness_proofs.clone());
+            state
+                .validate_with(&self.policy.verify_options)
+                .and_then(ValidationReport::assert_complete)
+                .map_err(|e| {
+                    DIDWebVHError::WitnessProofError(format!(
+                        "Refusing to witness {}: previous log doesn't validate: {e}",
+                        request.version_id
+                    ))
+                })?;
+            if state.deactivated() {
+                return Err(DIDWebVHError::WitnessProofError(format!(
+                    "Refusing to witness {}: the DID is deactivated",
+                    request.version_id
+                )));
+            }
+        }
+
+        let mut entry = LogEntryState {
+            log_entry: request.log_entry.clone(),
+            version_number: request
+                .log_entry
+                .get_version_id_fields()
+                .map_or(0, |(number, _)| number),
+            validated_parameters: Parameters::default(),
+            validation_status: LogEntryValidationStatus::NotValidated,
+        };
+        entry
+            .verify_log_entry(state.log_entries().last())
+            .map_err(|e| {
+                DIDWebVHError::WitnessProofError(format!(
+                    "Refusing to witness {}: log doesn't verify at {}: {e}",
+                    request.version_id,
+                    entry.get_version_id()
+                ))
+            })?;
+
+        let is_active = entry
+            .get_active_witnesses()
+            .and_then(|w| {
+                w.witnesses()
+                    .map(|w| w.iter().any(|w| w.as_did() == self.did))
+            })
+            .unwrap_or(false);
+        if !is_active {
+            return Err(DIDWebVHError::WitnessProofError(format!(
+                "Refusing to witness {}: {} is not an active witness for this entry",
+                request.version_id, self.did
+            )));
+        }
+
+        let version_time = entry.log_entry.get_version_time();
+        let too_old = chrono::Duration::from_std(self.policy.max_entry_age)
+            .is_ok_and(|age| version_time < now - age);
+        let too_new = chrono::Duration::from_std(self.policy.max_clock_skew)
+            .is_ok_and(|skew| version_time > now + skew);
+        if too_old || too_new {
+            return Err(DIDWebVHError::WitnessProofError(format!(
+                "Refusing to witness {}: versionTime ({}) is not recent",
+                request.version_id,
+                entry.log_entry.get_version_time_string()
+            )));
+        }
+
+        let scid = entry
+            .validated_parameters
+            .scid
+            .as_ref()
+            .map(ToString::to_string)
+            .ok_or_else(|| {
+                DIDWebVHError::WitnessProofError(format!(
+                    "Refusing to witness {}: no SCID in log",
+                    request.version_id
+                ))
+            })?;
+
+        let still_witness = entry
+            .validated_parameters
+            .witness
+            .as_ref()
+            .and_then(|w| {
+                w.witnesses()
+                    .map(|w| w.iter().any(|w| w.as_did() == self.did))
+            })
+            .unwrap_or(false);
+        if !still_witness
+            && !self
+                .expected_removals
+                .lock()
+                .expect("expected_removals lock poisoned")
+                .contains(&scid)
+        {
+            warn!(
+                "{}: entry removes witness {} without notice",
+                request.version_id, self.did
+            );
+            return Err(DIDWebVHError::WitnessProofError(format!(
+                "Refusing to witness {}: it removes this witness without notice",
+                request.version_id
+            )));
+        }
+
+        Ok((scid, !still_witness))
+    }
+
+    /// Check that another signature for `scid` fits the rate limit, and with
+    /// `record`, count it.
+    fn check_rate_limit(
+        &self,
+        scid: &str,
+        now: DateTime<FixedOffset>,
+        record: bool,
+    ) -> Result<(), DIDWebVHError> {
+        let Some(limit) = self.policy.rate_limit else {
+            return Ok(());
+        };
+        let mut signed = self.signed.lock().expect("signed lock poisoned");
+        let history = signed.entry(scid.to_string()).or_default();
+        if let Ok(window) = chrono::Duration::from_std(limit.window) {
+            while history.front().is_some_and(|t| *t <= now - window) {
+                history.pop_front();
+            }
+        }
+        if history.len() >= limit.max_signatures as usize {
+            return Err(DIDWebVHError::WitnessProofError(format!(
+                "Refusing to witness: rate limit ({} per {:?}) reached for {scid}",
+                limit.max_signatures, limit.window
+            )));
+        }
+        if record {
+            history.push_back(now);
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl<W: Signer> WitnessClient for WitnessService<W> {
+    async fn request_proof(
+        &self,
+        witness: &Witness,
+        request: &WitnessRequest,
+    ) -> Result<WitnessResponse, DIDWebVHError> {
+        if witness.as_did() != self.did {
+            return Err(DIDWebVHError::WitnessProofError(format!(
+                "This service is witness {}, not {}",
+                self.did,
+                witness.as_did()
+            )));
+        }
+        self.handle(request).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{RateLimit, WitnessPolicy, WitnessService};
+    use crate::{
+        DIDWebVHState,
+        parameters::Parameters,
+        test_utils::{did_doc_with_key, generate_signing_key, key_and_params},
+        witness::{
+            Witness, WitnessVerifyOptions, Witnesses,
+            remote::{WitnessRequest, request_witness_proofs},
+        },
+    };
+    use affinidi_secrets_resolver::secrets::Secret;
+    use chrono::{DateTime, Duration, FixedOffset, Utc};
+    use std::sync::Arc;
+
+    fn witnesses_param(witnesses: &[&Secret]) -> Arc<Witnesses> {
+        Arc::new(Witnesses::Value {
+            threshold: 1,
+            witnesses: witnesses
+                .iter()
+                .map(|w| Witness::new(w.get_public_keymultibase().unwrap()))
+                .collect(),
+        })
+    }
+
+    /// A DID witnessed by `witness`, created at `t0`, plus the key that controls it.
+    async fn witnessed_state(
+        witness: &Secret,
+        t0: DateTime<FixedOffset>,
+    ) -> (DIDWebVHState, Secret) {
+        let (key, params) = key_and_params();
+        let params = Parameters {
+            witness: Some(witnesses_param(&[witness])),
+            ..params
+        };
+        let doc = did_doc_with_key("did:webvh:{SCID}:example.com", &key);
+        let mut state = DIDWebVHState::default();
+        state
+            .create_log_entry(Some(t0), &doc, &params, &key)
+            .await
+            .unwrap();
+        (state, key)
+    }
+
+    /// Append an entry at `time` with the given witness parameter.
+    async fn append(
+        state: &mut DIDWebVHState,
+        key: &Secret,
+        time: DateTime<FixedOffset>,
+        witness: Option<Arc<Witnesses>>,
+    ) {
+        let doc = state.log_entries().last().unwrap().get_state().clone();
+        let params = Parameters {
+            witness,
+            ..Default::default()
+        };
+        state
+            .create_log_entry(Some(time), &doc, &params, key)
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn signs_valid_entry() {
+        let witness = generate_signing_key();
+        let service = WitnessService::new(witness.clone(), WitnessPolicy::new()).unwrap();
+        let (mut state, _) = witnessed_state(&witness, Utc::now().fixed_offset()).await;
+
+        let outcome = request_witness_proofs(&service, &mut state, &WitnessVerifyOptions::new())
+            .await
+            .unwrap();
+        assert_eq!(outcome.accepted, vec![service.did().to_string()]);
+    }
+
+    #[tokio::test]
+    async fn refuses_tampered_entry() {
+        let witness = generate_signing_key();
+        let service = WitnessService::new(witness.clone(), WitnessPolicy::new()).unwrap();
+        let (state, _) = witnessed_state(&witness, Utc::now().fixed_offset()).await;
+
+        let mut request = WitnessRequest::from_state(&state).unwrap();
+        let mut entry = serde_json::to_value(&request.log_entry).unwrap();
+        entry["state"]["alsoKnownAs"] = serde_json::json!(["did:web:evil.example"]);
+        request.log_entry = serde_json::from_value(entry).unwrap();
+
+        let err = service.handle(&request).await.unwrap_err();
+        assert!(err.to_string().contains("doesn't verify"), "{err}");
+    }
+
+    #[tokio::test]
+    async fn refuses_non_member_and_stale_entries() {
+        let witness = generate_signing_key();
+        let service = WitnessService::new(witness.clone(), WitnessPolicy::new()).unwrap();
+
+        let other = generate_signing_key();
+        let (state, _) = witnessed_state(&other, Utc::now().fixed_offset()).await;
+        let err = service
+            .handle(&WitnessRequest::from_state(&state).unwrap())
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("not an active witness"), "{err}");
+
+        let stale = Utc::now().fixed_offset() - Duration::hours(1);
+        let (state, _) = witnessed_state(&witness, stale).await;
+        let err = service
+            .handle(&WitnessRequest::from_state(&state).unwrap())
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("not recent"), "{err}");
+    }
+
+    #[tokio::test]
+    async fn removal_needs_notice() {
+        let witness = generate_signing_key();
+        let replacement = generate_signing_key();
+        let service = WitnessService::new(witness.clone(), WitnessPolicy::new()).unwrap();
+        let t0 = Utc::now().fixed_offset() - Duration::minutes(5);
+        let (mut state, key) = witnessed_state(&witness, t0).await;
+        request_witness_proofs(&service, &mut state, &WitnessVerifyOptions::new())
+            .await
+            .unwrap();
+        append(
+            &mut state,
+            &key,
+            t0 + Duration::minutes(1),
+            Some(witnesses_param(&[&replacement])),
+        )
+        .await;
+
+        let request = WitnessRequest::from_state(&state).unwrap();
+        let err = service.handle(&request).await.unwrap_err();
+        assert!(err.to_string().contains("without notice"), "{err}");
+
+        service.expect_removal(
+            state.log_entries()[0]
+                .validated_parameters
+                .scid
+                .as_deref()
+                .unwrap(),
+        );
+        service.handle(&request).await.unwrap();
+        // The notice covers a single entry
+        assert!(service.handle(&request).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn refuses_unwitnessed_or_deactivated_log() {
+        let witness = generate_signing_key();
+        let service = WitnessService::new(witness.clone(), WitnessPolicy::new()).unwrap();
+        let t0 = Utc::now().fixed_offset() - Duration::minutes(5);
+        let (mut state, key) = witnessed_state(&witness, t0).await;
+        append(&mut state, &key, t0 + Duration::minutes(1), None).await;
+
+        // The genesis entry was never witnessed, so the log doesn't resolve
+        let request = WitnessRequest::from_state(&state).unwrap();
+        let err = service.handle(&request).await.unwrap_err();
+        assert!(err.to_string().contains("previous log"), "{err}");
+
+        let (mut state, key) = witnessed_state(&witness, t0).await;
+        request_witness_proofs(&service, &mut state, &WitnessVerifyOptions::new())
+            .await
+            .unwrap();
+        let doc = state.log_entries()[0].get_state().clone();
+        let params = Parameters {
+            deactivated: Some(true),
+            ..Default::default()
+        };
+        state
+            .create_log_entry(Some(t0 + Duration::minutes(1)), &doc, &params, &key)
+            .await
+            .unwrap();
+        request_witness_proofs(&service, &mut state, &WitnessVerifyOptions::new())
+            .await
+            .unwrap();
+
+        // Nothing can be appended after deactivation, so resubmit the
+        // deactivating entry on top of itself
+        let mut request = WitnessRequest::from_state(&state).unwrap();
+        request.previous_log.push(request.log_entry.clone());
+        let err = service.handle(&request).await.unwrap_err();
+        assert!(err.to_string().contains("deactivated"), "{err}");
+    }
+
+    #[tokio::test]
+    async fn rate_limited_per_did() {
+        let witness = generate_signing_key();
+        let policy = WitnessPolicy::new().rate_limit(Some(RateLimit {
+            max_signatures: 2,
+            window: std::time::Duration::from_mins(1),
+        }));
+        let service = WitnessService::new(witness.clone(), policy).unwrap();
+        let now = Utc::now().fixed_offset();
+        let (state, _) = witnessed_state(&witness, now).await;
+        let request = WitnessRequest::from_state(&state).unwrap();
+
+        service.handle_at(&request, now).await.unwrap();
+        service.handle_at(&request, now).await.unwrap();
+        let err = service.handle_at(&request, now).await.unwrap_err();
+        assert!(err.to_string().contains("rate limit"), "{err}");
+
+        // Refused requests don't use up the limit
+        let (other, _) = witnessed_state(&witness, now).await;
+        let stale = now + Duration::hours(1);
+        let other_request = WitnessRequest::from_state(&other).unwrap();
+        for _ in 0..3 {
+            assert!(service.handle_at(&other_request, stale).await.is_err());
+        }
+
+        // Another DID isn't affected
+        service
+            .handle_at(&WitnessRequest::from_state(&other).unwrap(), now)
+            .await
+            .unwrap();
+
+        // The window slides
+        service
+            .handle_at(&request, now + Duration::seconds(61))
+            .await
+            .unwrap();
+    }
+}
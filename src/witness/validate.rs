@@ -35,16 +35,180 @@
 */
 
 use crate::{
-    DIDWebVHError,
+    CheckError, CheckKind, DIDWebVHError, DIDWebVHState,
     log_entry::{PublicKey, enforce_witness_proof_shape},
     log_entry_state::LogEntryState,
     witness::{WitnessVerifyOptions, proofs::WitnessProofCollection},
 };
-use affinidi_data_integrity::VerifyOptions;
+use affinidi_data_integrity::{DataIntegrityProof, VerifyOptions};
+use ahash::HashSet;
 use serde_json::json;
 use tracing::{debug, warn};
 
+/// Check a witness proof's shape and its signature over `{"versionId": version_id}`.
+pub(crate) fn verify_witness_proof(
+    version_id: &str,
+    proof: &DataIntegrityProof,
+    options: &WitnessVerifyOptions,
+) -> Result<(), DIDWebVHError> {
+    enforce_witness_proof_shape(proof, options)?;
+    proof
+        .verify_with_public_key(
+            &json!({ "versionId": version_id }),
+            proof.get_public_key_bytes()?.as_slice(),
+            VerifyOptions::new(),
+        )
+        .map_err(|e| {
+            DIDWebVHError::WitnessProofError(format!(
+                "Witness proof for {version_id} failed verification: {e}"
+            ))
+        })?;
+    Ok(())
+}
+
+/// Witness status of one log entry, from [`WitnessProofCollection::verify_all`].
+#[derive(Clone, Debug)]
+pub struct EntryWitnessStatus {
+    /// versionId of the log entry.
+    pub version_id: String,
+    /// Witness threshold for this entry, `None` if it isn't witnessed.
+    pub threshold: Option<u32>,
+    /// Status of each active witness, in configuration order.
+    pub witnesses: Vec<WitnessCheck>,
+    /// Whether enough witnesses have a [`WitnessProofStatus::Valid`] proof.
+    pub threshold_met: bool,
+}
+
+/// One witness's status for one log entry.
+#[derive(Clone, Debug)]
+pub struct WitnessCheck {
+    /// Witness DID (`did:key:...`).
+    pub witness: String,
+    /// What was found for this witness.
+    pub status: WitnessProofStatus,
+}
+
+/// Whether a witness has a proof that covers a log entry.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum WitnessProofStatus {
+    /// A proof for this entry, or a later published one, verifies.
+    Valid {
+        /// versionId the proof signs.
+        version_id: String,
+    },
+    /// Proofs covering this entry exist, but none verify.
+    Invalid {
+        /// versionId of the last proof tried.
+        version_id: String,
+        /// Why it failed.
+        reason: String,
+    },
+    /// The witness only has proofs for earlier entries, which don't count.
+    Outdated {
+        /// versionId of the witness's latest proof.
+        version_id: String,
+    },
+    /// No proof from this witness for a published entry at or after this one.
+    Missing,
+}
+
 impl WitnessProofCollection {
+    /// Report, for every log entry in `state`, which active witnesses have a
+    /// valid proof covering it.
+    ///
+    /// Unlike validation this doesn't stop at the first problem, so it can be
+    /// used to see what is missing before publishing. Proofs count for their
+    /// own entry and every earlier one ("current or any later"); proofs for
+    /// versionIds not in the log are ignored. `state` must have been created
+    /// or validated so each entry's active witnesses are known.
+    pub fn verify_all(
+        &self,
+        state: &DIDWebVHState,
+        options: &WitnessVerifyOptions,
+    ) -> Vec<EntryWitnessStatus> {
+        let published: HashSet<&str> = state
+            .log_entries()
+            .iter()
+            .map(|e| e.get_version_id())
+            .collect();
+        let mut proofs: Vec<(u32, &str, &DataIntegrityProof)> = self
+            .iter_proofs()
+            .filter(|(version_id, _)| published.contains(version_id))
+            .filter_map(|(version_id, proof)| {
+                let n = version_id.split_once('-')?.0.parse().ok()?;
+                Some((n, version_id, proof))
+            })
+            .collect();
+        proofs.sort_by_key(|(n, _, _)| *n);
+
+        state
+            .log_entries()
+            .iter()
+            .map(|entry| {
+                let witnesses = entry.get_active_witnesses();
+                let (Some(threshold), Some(nodes)) = (
+                    witnesses.as_ref().and_then(|w| w.threshold()),
+                    witnesses.as_ref().and_then(|w| w.witnesses()),
+                ) else {
+                    return EntryWitnessStatus {
+                        version_id: entry.get_version_id().to_string(),
+                        threshold: None,
+                        witnesses: Vec::new(),
+                        threshold_met: true,
+                    };
+                };
+
+                let checks: Vec<WitnessCheck> = nodes
+                    .iter()
+                    .map(|w| {
+                        let vm = w.as_did_key();
+                        let mine = proofs
+                            .iter()
+                            .filter(|(_, _, p)| p.verification_method == vm);
+                        let mut status = WitnessProofStatus::Missing;
+                        for (n, version_id, proof) in mine {
+                            if *n < entry.version_number {
+                                status = WitnessProofStatus::Outdated {
+                                    version_id: (*version_id).to_string(),
+                                };
+                                continue;
+                            }
+                            match verify_witness_proof(version_id, proof, options) {
+                                Ok(()) => {
+                                    status = WitnessProofStatus::Valid {
+                                        version_id: (*version_id).to_string(),
+                                    };
+                                    break;
+                                }
+                                Err(e) => {
+                                    status = WitnessProofStatus::Invalid {
+                                        version_id: (*version_id).to_string(),
+                                        reason: e.to_string(),
+                                    };
+                                }
+                            }
+                        }
+                        WitnessCheck {
+                            witness: w.as_did(),
+                            status,
+                        }
+                    })
+                    .collect();
+
+                let valid = checks
+                    .iter()
+                    .filter(|c| matches!(c.status, WitnessProofStatus::Valid { .. }))
+                    .count();
+                EntryWitnessStatus {
+                    version_id: entry.get_version_id().to_string(),
+                    threshold: Some(threshold),
+                    witnesses: checks,
+                    threshold_met: valid >= threshold as usize,
+                }
+            })
+            .collect()
+    }
+
     /// Validates if a LogEntry was correctly witnessed
     /// highest_version_number is required so we don't mistakenly use future witness proofs
     /// for unpublished LogEntries.
@@ -116,12 +280,17 @@ impl WitnessProofCollection {
                             VerifyOptions::new(),
                         )
                         .map_err(|e| {
-                            DIDWebVHError::WitnessProofError(format!(
-                                "LogEntry ({}): Witness proof for later version ({}) failed verification: {}",
-                                log_entry.get_version_id(),
-                                proof_version_id,
-                                e
-                            ))
+                            CheckError::new(
+                                CheckKind::WitnessProofInvalid,
+                                format!(
+                                    "LogEntry ({}): Witness proof for later version ({}) failed verification: {}",
+                                    log_entry.get_version_id(),
+                                    proof_version_id,
+                                    e
+                                ),
+                            )
+                            .with_version_id(log_entry.get_version_id())
+                            .with_source(e)
                         })?;
                     debug!(
                         "LogEntry ({}): later witness proof from {} (for {oldest_id}) verified ok",
@@ -138,11 +307,16 @@ impl WitnessProofCollection {
                         .log_entry
                         .validate_witness_proof(proof, options)
                         .map_err(|e| {
-                            DIDWebVHError::WitnessProofError(format!(
-                                "LogEntry ({}): Witness proof validation failed: {}",
-                                log_entry.get_version_id(),
-                                e
-                            ))
+                            CheckError::new(
+                                CheckKind::WitnessProofInvalid,
+                                format!(
+                                    "LogEntry ({}): Witness proof validation failed: {}",
+                                    log_entry.get_version_id(),
+                                    e
+                                ),
+                            )
+                            .with_version_id(log_entry.get_version_id())
+                            .with_source(e)
                         })?;
                     valid_proofs += 1;
                     debug!(
@@ -180,9 +354,12 @@ impl WitnessProofCollection {
 
         let Some(threshold) = witnesses.threshold() else {
             // No threshold set, so we consider this as a state error
-            return Err(DIDWebVHError::ValidationError(
-                "Witness threshold not defined when witnessing seems to be enabled!".to_string(),
-            ));
+            return Err(CheckError::new(
+                CheckKind::WitnessThreshold,
+                "Witness threshold not defined when witnessing seems to be enabled!",
+            )
+            .with_version_id(log_entry.get_version_id())
+            .into());
         };
 
         if valid_proofs < threshold {
@@ -191,9 +368,14 @@ impl WitnessProofCollection {
                 "LogEntry ({}): Witness threshold ({threshold}) not met. Only ({valid_proofs} valid proofs!",
                 log_entry.get_version_id(),
             );
-            Err(DIDWebVHError::WitnessProofError(format!(
-                "Witness proof threshold ({threshold}) was not met. Only ({valid_proofs}) proofs were validated",
-            )))
+            Err(CheckError::new(
+                CheckKind::WitnessThreshold,
+                format!(
+                    "Witness proof threshold ({threshold}) was not met. Only ({valid_proofs}) proofs were validated",
+                ),
+            )
+            .with_version_id(log_entry.get_version_id())
+            .into())
         } else {
             debug!(
                 "LogEntry ({}): Witness proofs fully passed",
@@ -559,4 +741,101 @@ mod tests {
             .validate_log_entry(&entry, 1, &WitnessVerifyOptions::new())
             .expect("0 threshold with 0 proofs should pass");
     }
+
+    /// Tests that `verify_all` reports each witness's status per log entry
+    /// without stopping at the first entry that misses its threshold.
+    ///
+    /// Two witnesses with a threshold of 2 are configured on a two-entry log.
+    /// Witness A signs entry 2, which also covers entry 1. Witness B signs
+    /// only entry 1, so it is outdated for entry 2. A third proof, for a
+    /// versionId that isn't in the log, is ignored.
+    #[tokio::test]
+    async fn test_verify_all_reports_per_entry_status() {
+        use super::WitnessProofStatus;
+        use crate::{
+            DIDWebVHState,
+            test_utils::{did_doc_with_key, generate_signing_key, key_and_params},
+        };
+        use chrono::Duration;
+
+        let (a, b) = (generate_signing_key(), generate_signing_key());
+        let (key, params) = key_and_params();
+        let params = Parameters {
+            witness: Some(Arc::new(Witnesses::Value {
+                threshold: 2,
+                witnesses: vec![
+                    Witness::new(a.get_public_keymultibase().unwrap()),
+                    Witness::new(b.get_public_keymultibase().unwrap()),
+                ],
+            })),
+            ..params
+        };
+        let t0 = Utc::now().fixed_offset() - Duration::hours(1);
+        let doc = did_doc_with_key("did:webvh:{SCID}:example.com", &key);
+        let mut state = DIDWebVHState::default();
+        state
+            .create_log_entry(Some(t0), &doc, &params, &key)
+            .await
+            .unwrap();
+        let doc = state.log_entries()[0].get_state().clone();
+        state
+            .create_log_entry(
+                Some(t0 + Duration::minutes(1)),
+                &doc,
+                &Parameters::default(),
+                &key,
+            )
+            .await
+            .unwrap();
+        let v1 = state.log_entries()[0].get_version_id().to_string();
+        let v2 = state.log_entries()[1].get_version_id().to_string();
+
+        let sign = |secret, version_id: String| async move {
+            DataIntegrityProof::sign(
+                &json!({ "versionId": version_id }),
+                secret,
+                SignOptions::new(),
+            )
+            .await
+            .unwrap()
+        };
+        let mut proofs = WitnessProofCollection::default();
+        proofs
+            .add_proof(&v2, &sign(&a, v2.clone()).await, false)
+            .unwrap();
+        proofs
+            .add_proof(&v1, &sign(&b, v1.clone()).await, false)
+            .unwrap();
+        proofs
+            .add_proof(
+                "3-unpublished",
+                &sign(&b, "3-unpublished".into()).await,
+                true,
+            )
+            .unwrap();
+
+        let report = proofs.verify_all(&state, &WitnessVerifyOptions::new());
+        assert_eq!(report.len(), 2);
+
+        assert!(report[0].threshold_met);
+        assert_eq!(
+            report[0].witnesses[0].status,
+            WitnessProofStatus::Valid {
+                version_id: v2.clone()
+            }
+        );
+        assert_eq!(
+            report[0].witnesses[1].status,
+            WitnessProofStatus::Valid {
+                version_id: v1.clone()
+            }
+        );
+
+        assert!(!report[1].threshold_met);
+        assert_eq!(report[1].threshold, Some(2));
+        assert_eq!(
+            report[1].witnesses[1].status,
+            WitnessProofStatus::Outdated { version_id: v1 }
+        );
+    }
 }
@@ -0,0 +1,221 @@
+//! End-to-end tests for the `didwebvh` binary (`bin` feature): a DID is
+//! created, updated, migrated and verified through the command line only,
+//! checking JSON output and exit codes.
+
+#![cfg(feature = "bin")]
+
+use serde_json::Value;
+use std::{
+    path::{Path, PathBuf},
+    process::{Command, Output},
+};
+
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!(
+        "didwebvh-bin-{name}-{}",
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_nanos()
+    ));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn didwebvh(dir: &Path, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_didwebvh"))
+        .current_dir(dir)
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+fn json(output: &Output) -> Value {
+    serde_json::from_slice(&output.stdout).unwrap_or_else(|e| {
+        panic!(
+            "{e}: {}{}",
+            String::from_utf8_lossy(&output.stdout),
+            String::from_utf8_lossy(&output.stderr)
+        )
+    })
+}
+
+#[test]
+fn create_update_migrate_verify() {
+    let dir = temp_dir("lifecycle");
+    let created = didwebvh(
+        &dir,
+        &[
+            "create",
+            "--json",
+            "--address",
+            "https://example.com/dids/alice",
+            "--key-out",
+            "key.json",
+            "--portable",
+        ],
+    );
+    assert_eq!(created.status.code(), Some(0));
+    let did = json(&created)["did"].as_str().unwrap().to_string();
+    assert!(did.ends_with(":example.com:dids:alice"));
+
+    // Flags from a config file
+    std::fs::write(
+        dir.join("update.json"),
+        r#"{"key": "key.json", "ttl": 600}"#,
+    )
+    .unwrap();
+    let updated = didwebvh(&dir, &["update", "--config", "update.json", "--json"]);
+    assert_eq!(updated.status.code(), Some(0), "{}", json(&updated));
+    assert!(
+        json(&updated)["versionId"]
+            .as_str()
+            .unwrap()
+            .starts_with("2-")
+    );
+
+    let migrated = didwebvh(&dir, &["migrate", "--key", "key.json", "--to", "/dids/bob"]);
+    assert_eq!(migrated.status.code(), Some(0));
+
+    let verified = didwebvh(&dir, &["verify", "did.jsonl", "--json"]);
+    assert_eq!(verified.status.code(), Some(0));
+    let verified = json(&verified);
+    assert_eq!(verified["valid"], true);
+    assert_eq!(verified["entries"], 3);
+    assert!(verified["did"].as_str().unwrap().ends_with(":dids:bob"));
+
+    let web = json(&didwebvh(&dir, &["export-web", "did.jsonl", "--json"]));
+    assert_eq!(web["id"], "did:web:example.com:dids:bob");
+
+    // Lint findings warn unless denied
+    let linted = didwebvh(&dir, &["lint", "did.jsonl", "--json"]);
+    assert_eq!(linted.status.code(), Some(0));
+    let codes: Vec<String> = json(&linted)["findings"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|f| f["code"].as_str().unwrap().to_string())
+        .collect();
+    assert!(
+        codes.contains(&"pre-rotation-disabled".to_string()),
+        "{codes:?}"
+    );
+    let denied = didwebvh(
+        &dir,
+        &[
+            "lint",
+            "did.jsonl",
+            "--json",
+            "--deny",
+            "all",
+            "--allow",
+            "watchers-unset",
+        ],
+    );
+    assert_eq!(denied.status.code(), Some(3));
+    assert_eq!(json(&denied)["errors"], true);
+
+    let _ = std::fs::remove_dir_all(dir);
+}
+
+#[test]
+fn exit_codes() {
+    let dir = temp_dir("exit-codes");
+    assert_eq!(didwebvh(&dir, &["bogus"]).status.code(), Some(2));
+    // Missing log
+    let missing = didwebvh(&dir, &["verify", "did.jsonl", "--json"]);
+    assert_eq!(missing.status.code(), Some(1));
+    assert!(json(&missing)["error"].is_string());
+
+    assert!(
+        didwebvh(
+            &dir,
+            &[
+                "create",
+                "--address",
+                "https://example.com",
+                "--key-out",
+                "key.json"
+            ]
+        )
+        .status
+        .success()
+    );
+    // Tampered log doesn't verify
+    let log = std::fs::read_to_string(dir.join("did.jsonl")).unwrap();
+    std::fs::write(
+        dir.join("did.jsonl"),
+        log.replace("example.com", "example.org"),
+    )
+    .unwrap();
+    let tampered = didwebvh(&dir, &["verify", "did.jsonl", "--json"]);
+    assert_eq!(tampered.status.code(), Some(3));
+    assert_eq!(json(&tampered)["valid"], false);
+
+    // An existing log is only replaced with --force
+    let recreate = [
+        "create",
+        "--address",
+        "https://example.com",
+        "--key",
+        "key.json",
+    ];
+    assert_eq!(didwebvh(&dir, &recreate).status.code(), Some(1));
+    assert!(
+        didwebvh(&dir, &[&recreate[..], &["--force"]].concat())
+            .status
+            .success()
+    );
+    let recreated = didwebvh(&dir, &["verify", "did.jsonl", "--json"]);
+    assert_eq!(json(&recreated)["valid"], true);
+
+    let _ = std::fs::remove_dir_all(dir);
+}
+
+#[test]
+fn command_line_overrides_config() {
+    let dir = temp_dir("config-override");
+    assert!(
+        didwebvh(
+            &dir,
+            &[
+                "create",
+                "--address",
+                "https://example.com",
+                "--key-out",
+                "key.json"
+            ]
+        )
+        .status
+        .success()
+    );
+    std::fs::write(
+        dir.join("update.json"),
+        r#"{"key": "key.json", "ttl": 600, "watcher": ["https://a.example.com"]}"#,
+    )
+    .unwrap();
+    // Both flags are in the file too: the command line replaces them
+    let updated = didwebvh(
+        &dir,
+        &[
+            "update",
+            "--config",
+            "update.json",
+            "--ttl",
+            "300",
+            "--watcher=https://b.example.com",
+            "--json",
+        ],
+    );
+    assert_eq!(updated.status.code(), Some(0), "{}", json(&updated));
+
+    let inspected = json(&didwebvh(&dir, &["inspect", "did.jsonl", "--json"]));
+    let parameters = &inspected["entries"][1]["parameters"];
+    assert_eq!(parameters["ttl"], 300);
+    assert_eq!(
+        parameters["watchers"],
+        serde_json::json!(["https://b.example.com"])
+    );
+
+    let _ = std::fs::remove_dir_all(dir);
+}
@@ -0,0 +1,149 @@
+/*!
+ * Deterministic mode: keys derived from a seed, explicit versionTimes and
+ * `deterministic(true)` must give byte-identical did.jsonl and
+ * did-witness.json on every run.
+ */
+
+use affinidi_secrets_resolver::secrets::{KeyType, Secret};
+use chrono::{DateTime, Duration, FixedOffset};
+use didwebvh_rs::{
+    DIDWebVHState, Multibase,
+    create::{CreateDIDConfig, create_did},
+    did_key::derive_did_key,
+    parameters::Parameters,
+    update::{UpdateDIDConfig, update_did},
+    witness::{Witness, Witnesses},
+};
+use serde_json::json;
+use std::{path::PathBuf, sync::Arc};
+
+const SEED: &[u8] = b"didwebvh-rs golden files";
+
+fn key(label: &str) -> (Multibase, Secret) {
+    let (_, secret) = derive_did_key(KeyType::Ed25519, SEED, label).unwrap();
+    (
+        Multibase::new(secret.get_public_keymultibase().unwrap()),
+        secret,
+    )
+}
+
+/// Create a witnessed DID, rotate its key and deactivate it, writing the
+/// files into a fresh directory.
+async fn generate(run: &str) -> PathBuf {
+    let start = DateTime::<FixedOffset>::parse_from_rfc3339("2025-01-01T00:00:00Z").unwrap();
+    let (auth_pk, auth) = key("auth-1");
+    let (next_pk, next) = key("auth-2");
+    let witnesses: Vec<_> = ["witness-b", "witness-a"].iter().map(|l| key(l)).collect();
+
+    let config = CreateDIDConfig::builder()
+        .address("https://example.com/dids/golden")
+        .authorization_key(auth.clone())
+        .did_document(json!({
+            "@context": ["https://www.w3.org/ns/did/v1"],
+            "id": "{DID}",
+            "verificationMethod": [{
+                "id": "{DID}#key-0",
+                "type": "Multikey",
+                "controller": "{DID}",
+                "publicKeyMultibase": auth_pk.as_str(),
+            }],
+            "authentication": ["{DID}#key-0"],
+        }))
+        .parameters(Parameters {
+            update_keys: Some(Arc::new(vec![auth_pk])),
+            witness: Some(Arc::new(Witnesses::Value {
+                threshold: 2,
+                witnesses: witnesses
+                    .iter()
+                    .map(|(pk, _)| Witness::new(pk.as_str()))
+                    .collect(),
+            })),
+            ..Default::default()
+        })
+        .witness_secrets(
+            witnesses
+                .iter()
+                .map(|(pk, secret)| (format!("did:key:{pk}"), secret.clone()))
+                .collect(),
+        )
+        .version_time(start)
+        .deterministic(true)
+        .build()
+        .unwrap();
+    let witness_secrets = config.witness_secrets.clone();
+    let created = create_did(config).await.unwrap();
+    let mut state = DIDWebVHState::from_log_entries(vec![created.log_entry().clone()]);
+    state.set_witness_proofs(created.witness_proofs().clone());
+    state.validate().unwrap().assert_complete().unwrap();
+
+    let state = update_did(
+        UpdateDIDConfig::builder()
+            .state(state)
+            .signing_key(auth)
+            .update_keys(vec![next_pk])
+            .witness_secrets(witness_secrets.clone())
+            .version_time(start + Duration::days(1))
+            .deterministic(true)
+            .build()
+            .unwrap(),
+    )
+    .await
+    .unwrap()
+    .into_state();
+    let state = update_did(
+        UpdateDIDConfig::builder()
+            .state(state)
+            .signing_key(next)
+            .deactivate(true)
+            .witness_secrets(witness_secrets)
+            .version_time(start + Duration::days(2))
+            .deterministic(true)
+            .build()
+            .unwrap(),
+    )
+    .await
+    .unwrap()
+    .into_state();
+
+    let dir = std::env::temp_dir().join(format!(
+        "didwebvh_test_reproducible_{run}_{}",
+        std::process::id()
+    ));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    for entry in state.log_entries() {
+        entry
+            .log_entry
+            .save_to_file(dir.join("did.jsonl").to_str().unwrap())
+            .unwrap();
+    }
+    state
+        .witness_proofs()
+        .save_to_file(dir.join("did-witness.json").to_str().unwrap())
+        .unwrap();
+    dir
+}
+
+#[tokio::test]
+async fn same_inputs_give_identical_files() {
+    let first = generate("a").await;
+    let second = generate("b").await;
+    for file in ["did.jsonl", "did-witness.json"] {
+        let a = std::fs::read(first.join(file)).unwrap();
+        let b = std::fs::read(second.join(file)).unwrap();
+        assert_eq!(a, b, "{file} differs between runs");
+    }
+
+    // Witness proofs follow the order the witnesses are listed in.
+    let proofs: serde_json::Value =
+        serde_json::from_slice(&std::fs::read(first.join("did-witness.json")).unwrap()).unwrap();
+    let (witness_b, _) = key("witness-b");
+    assert_eq!(
+        proofs[0]["proof"][0]["verificationMethod"],
+        format!("did:key:{witness_b}#{witness_b}")
+    );
+    assert_eq!(proofs[0]["proof"][0]["created"], "2025-01-03T00:00:00Z");
+
+    std::fs::remove_dir_all(first).unwrap();
+    std::fs::remove_dir_all(second).unwrap();
+}
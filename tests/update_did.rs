@@ -8,9 +8,10 @@
 use affinidi_secrets_resolver::secrets::Secret;
 use didwebvh_rs::{
     DIDWebVHState, Multibase,
+    log_entry::LogEntryMethods,
     parameters::Parameters,
     update::{UpdateDIDConfig, update_did},
-    witness::Witnesses,
+    witness::{WitnessVerifyOptions, Witnesses},
 };
 use serde_json::json;
 use std::sync::Arc;
@@ -174,6 +175,69 @@ async fn deactivate_did() {
     assert_eq!(result.state().log_entries().len(), 2);
 }
 
+/// Deactivating with pre-rotation active ends it first: the teardown entry is
+/// stamped one second before `version_time`, which stamps the deactivation.
+/// A `version_time` that leaves no room for the teardown is rejected.
+#[tokio::test]
+async fn deactivate_with_pre_rotation_honours_version_time() {
+    let t_create = chrono::Utc::now().fixed_offset() - chrono::Duration::hours(1);
+    let k1 = generate_signing_key();
+    let k2 = generate_signing_key();
+    let pk1 = k1.get_public_keymultibase().unwrap();
+    let pk2 = k2.get_public_keymultibase().unwrap();
+    let doc = json!({
+        "id": "did:webvh:{SCID}:example.com",
+        "@context": ["https://www.w3.org/ns/did/v1"],
+    });
+    let params = Parameters {
+        update_keys: Some(Arc::new(vec![Multibase::new(pk1)])),
+        next_key_hashes: Some(Arc::new(vec![Multibase::new(
+            Secret::base58_hash_string(&pk2).unwrap(),
+        )])),
+        ..Default::default()
+    };
+    let mut state = DIDWebVHState::default();
+    state
+        .create_log_entry(Some(t_create), &doc, &params, &k1)
+        .await
+        .unwrap();
+
+    let genesis = state.log_entries()[0].log_entry.clone();
+    let deactivate = |seconds: i64| {
+        let mut state = DIDWebVHState::from_log_entries(vec![genesis.clone()]);
+        state.validate().unwrap().assert_complete().unwrap();
+        UpdateDIDConfig::<_, Secret>::builder()
+            .state(state)
+            .signing_key(k2.clone())
+            .deactivate(true)
+            .version_time(t_create + chrono::Duration::seconds(seconds))
+            .build()
+            .unwrap()
+    };
+    let err = update_did(deactivate(1)).await.unwrap_err();
+    assert!(err.to_string().contains("at least 2 seconds"), "{err}");
+
+    let result = update_did(deactivate(2)).await.unwrap();
+    let entries: Vec<_> = result
+        .state()
+        .log_entries()
+        .iter()
+        .map(|e| e.log_entry.clone())
+        .collect();
+    assert_eq!(entries.len(), 3);
+    let times: Vec<_> = entries
+        .iter()
+        .map(|e| e.get_version_time().timestamp())
+        .collect();
+    assert_eq!(times[1], t_create.timestamp() + 1);
+    assert_eq!(times[2], t_create.timestamp() + 2);
+
+    // The published chain validates from scratch
+    let mut state = DIDWebVHState::from_log_entries(entries);
+    state.validate().unwrap().assert_complete().unwrap();
+    assert!(state.deactivated());
+}
+
 #[tokio::test]
 async fn migrate_requires_portable() {
     let (state, key, _) = create_test_did(false).await;
@@ -211,6 +275,62 @@ async fn migrate_portable_did() {
     assert_eq!(result.state().log_entries().len(), 2);
 }
 
+#[tokio::test]
+async fn migrate_leaves_service_endpoints_alone() {
+    let (state, key, old_did) = create_test_did(true).await;
+    let mut doc = state.log_entries().last().unwrap().get_state().clone();
+    doc["service"] = json!([{
+        "id": format!("{old_did}#files"),
+        "type": "LinkedDomains",
+        "serviceEndpoint": format!("https://example.com/?did={old_did}"),
+    }]);
+    let config = UpdateDIDConfig::builder()
+        .state(state)
+        .signing_key(key.clone())
+        .document(doc)
+        .build()
+        .unwrap();
+    let state = update_did(config).await.unwrap().into_state();
+
+    let config = UpdateDIDConfig::builder()
+        .state(state)
+        .signing_key(key)
+        .migrate_to("/dids/alice")
+        .build()
+        .unwrap();
+    let result = update_did(config).await.unwrap();
+
+    let new_did = result.did().to_string();
+    assert!(new_did.ends_with(":example.com:dids:alice"), "{new_did}");
+    let doc = result.state().log_entries().last().unwrap().get_state();
+    assert_eq!(doc["service"][0]["id"], format!("{new_did}#files"));
+    assert_eq!(
+        doc["service"][0]["serviceEndpoint"],
+        format!("https://example.com/?did={old_did}")
+    );
+
+    let report = result.migration_report().unwrap();
+    assert_eq!(report.old_did, old_did);
+    assert_eq!(report.new_did, new_did);
+    assert!(report.rewritten.iter().any(|r| r.path == "/service/0/id"));
+    assert_eq!(report.untouched.len(), 1);
+    assert_eq!(report.untouched[0].path, "/service/0/serviceEndpoint");
+}
+
+#[tokio::test]
+async fn migrate_to_current_location_errors() {
+    let (state, key, _) = create_test_did(true).await;
+
+    let config = UpdateDIDConfig::builder()
+        .state(state)
+        .signing_key(key)
+        .migrate_to("https://example.com/")
+        .build()
+        .unwrap();
+
+    assert!(update_did(config).await.is_err());
+}
+
 #[tokio::test]
 async fn update_witnesses() {
     let (state, key, _) = create_test_did(false).await;
@@ -232,6 +352,95 @@ async fn update_witnesses() {
     assert_eq!(result.state().log_entries().len(), 2);
 }
 
+#[tokio::test]
+async fn staged_update_waits_for_witnesses() {
+    let (state, key, _) = create_test_did(false).await;
+    let witness = generate_signing_key();
+    let witness_did = witness.id.split('#').next().unwrap().to_string();
+
+    let witnesses = Witnesses::builder()
+        .threshold(1)
+        .witness(Multibase::new(witness_did.clone()))
+        .build()
+        .unwrap();
+    let result = update_did(
+        UpdateDIDConfig::builder()
+            .state(state)
+            .signing_key(key.clone())
+            .witness(witnesses)
+            .build()
+            .unwrap(),
+    )
+    .await
+    .unwrap();
+
+    // Without the witness's secret the entry stays pending
+    let result = update_did(
+        UpdateDIDConfig::builder()
+            .state(result.into_state())
+            .signing_key(key.clone())
+            .ttl(300)
+            .stage()
+            .build()
+            .unwrap(),
+    )
+    .await
+    .unwrap();
+    let options = WitnessVerifyOptions::new();
+    let state = result.into_state();
+    assert_eq!(state.log_entries().len(), 2);
+    let status = state.pending_status(&options).unwrap();
+    assert!(!status.ready);
+
+    // A second update can't be stacked on top of it
+    let err = update_did(
+        UpdateDIDConfig::builder()
+            .state(state)
+            .signing_key(key.clone())
+            .ttl(600)
+            .build()
+            .unwrap(),
+    )
+    .await;
+    let Err(err) = err else {
+        panic!("update over a pending entry should fail");
+    };
+    assert!(err.to_string().contains("is pending"), "{err}");
+
+    // With the secret it is witnessed straight away
+    let (state, key, _) = create_test_did(false).await;
+    let result = update_did(
+        UpdateDIDConfig::builder()
+            .state(state)
+            .signing_key(key.clone())
+            .witness(
+                Witnesses::builder()
+                    .threshold(1)
+                    .witness(Multibase::new(witness_did.clone()))
+                    .build()
+                    .unwrap(),
+            )
+            .build()
+            .unwrap(),
+    )
+    .await
+    .unwrap();
+    let result = update_did(
+        UpdateDIDConfig::builder()
+            .state(result.into_state())
+            .signing_key(key)
+            .ttl(300)
+            .witness_secret(witness_did, witness)
+            .stage()
+            .build()
+            .unwrap(),
+    )
+    .await
+    .unwrap();
+    assert!(result.state().pending_status(&options).unwrap().ready);
+    assert!(result.into_state().discard_pending().is_some());
+}
+
 #[tokio::test]
 async fn multiple_sequential_updates() {
     let (state, key, _) = create_test_did(false).await;
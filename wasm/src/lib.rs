@@ -0,0 +1,4 @@
+//! The didwebvh-rs JavaScript bindings as a `cdylib` for `wasm-pack`. The
+//! exports are defined in `didwebvh_rs::wasm`.
+
+pub use didwebvh_rs::wasm::*;